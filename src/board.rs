@@ -1,8 +1,16 @@
+use crate::format::ParseError;
 use crate::util;
 use csv;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{btree_set, BTreeSet};
+use std::convert::TryFrom;
+use std::error::Error;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::io;
+use std::sync::{mpsc, Mutex, PoisonError};
 
 fn create_constraint_list(num: usize) -> Vec<ConstraintList> {
     let mut v = Vec::with_capacity(num);
@@ -12,28 +20,23 @@ fn create_constraint_list(num: usize) -> Vec<ConstraintList> {
     v
 }
 
-fn get_constraint_bounds(ls: &ConstraintList, index: usize) -> (usize, usize) {
-    let mut left = index;
-    let mut right = ls.len() - index - 1;
-    for (i, value) in ls.iter().enumerate() {
-        if i < index {
-            left += value.get_length() as usize;
-        } else if i > index {
-            right += value.get_length() as usize;
-        }
-    }
-    (left, right)
-}
-
-/**
+/*
  * Remember, and do not forget:
  * Ordering should always be (x, y)!
  * This means (width, height) and (column, row)!
  */
 
+/// An index into a board's Palette.
+/// Color 0 is the default fill color, and is represented by Cell::Filled.
+pub type Color = u8;
+
+/// Glyphs used to display filled cells, indexed by color
+pub(crate) const COLOR_GLYPHS: &[u8] = b"X123456789abcdefghijklmnopqrstuvwxyz";
+
 /// A single Cell.
 /// Can either be empty, filled, or undetermined.
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Cell {
     /// An undetermined Cell
     Unknown,
@@ -41,18 +44,53 @@ pub enum Cell {
     Empty,
     /// A filled Cell
     Filled,
+    /// A Cell filled with a color other than the default.
+    /// Use Cell::with_color to construct these, since color 0 is always Cell::Filled.
+    Colored(Color),
 }
 
 impl Cell {
+    /// Create a filled Cell of the given color
+    pub fn with_color(color: Color) -> Cell {
+        if color == 0 {
+            Cell::Filled
+        } else {
+            Cell::Colored(color)
+        }
+    }
+
+    /// Get this Cell with Cell::Colored(0) replaced by the Cell::Filled it stands for
+    pub fn normalized(self) -> Cell {
+        match self {
+            Cell::Colored(0) => Cell::Filled,
+            cell => cell,
+        }
+    }
+
+    /// Get the color of this Cell, or None if this Cell is not filled
+    pub fn get_color(&self) -> Option<Color> {
+        match *self {
+            Cell::Filled => Some(0),
+            Cell::Colored(color) => Some(color),
+            Cell::Unknown | Cell::Empty => None,
+        }
+    }
+
+    /// Returns true if this Cell is filled with any color
+    pub fn is_filled(&self) -> bool {
+        self.get_color().is_some()
+    }
+
     /// Convert an int to a Cell.
     /// 0 represents an empty Cell,
     /// 1 represents a filled Cell
     /// -1 represents an undetermined cell
+    /// Values above 1 represent colored cells, starting at color 1
     pub fn from_i64(value: i64) -> Option<Cell> {
         match value {
             0 => Some(Cell::Empty),
-            1 => Some(Cell::Filled),
             -1 => Some(Cell::Unknown),
+            v if v >= 1 && v <= Color::MAX as i64 + 1 => Some(Cell::with_color((v - 1) as Color)),
             _ => None,
         }
     }
@@ -62,70 +100,471 @@ impl Cell {
         match *self {
             Cell::Empty => 0,
             Cell::Filled => 1,
+            Cell::Colored(color) => color as i64 + 1,
             Cell::Unknown => -1,
         }
     }
 
-    pub fn get_format(&self) -> (&str,&str)
+    /// Convert a glyph, as printed by this Cell's Display implementation, back into a Cell
+    pub fn from_glyph(glyph: char) -> Option<Cell> {
+        match glyph {
+            '?' => Some(Cell::Unknown),
+            '.' => Some(Cell::Empty),
+            _ => COLOR_GLYPHS
+                .iter()
+                .position(|&x| x as char == glyph)
+                .map(|color| Cell::with_color(color as Color)),
+        }
+    }
+
+    /// Get the ANSI escape codes to write before and after this cell on a terminal.
+    /// Filled cells are drawn on the background of their color in the given palette, if any;
+    /// otherwise they are left to their glyph alone.
+    pub fn get_format(&self, palette: Option<&Palette>) -> (String, &str)
     {
         match *self {
-            Cell::Unknown => ("\x1B[41m", "\x1B[0m"),
-            Cell::Empty => ("", ""),
-            Cell::Filled => ("", ""),
+            Cell::Unknown => ("\x1B[41m".to_string(), "\x1B[0m"),
+            Cell::Empty => (String::new(), ""),
+            Cell::Filled | Cell::Colored(_) => {
+                let color = palette.and_then(|palette| palette.get(self.get_color().unwrap()));
+                match color {
+                    Some(color) => (color.ansi_format(), "\x1B[0m"),
+                    None => (String::new(), ""),
+                }
+            }
         }
     }
 }
 
+// Cell::Colored(0) is the same cell as Cell::Filled, which to_i64 already accounts for
+impl PartialEq for Cell {
+    fn eq(&self, other: &Cell) -> bool {
+        self.to_i64() == other.to_i64()
+    }
+}
+
+impl Hash for Cell {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.to_i64().hash(state)
+    }
+}
+
 impl fmt::Display for Cell {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match *self {
-                Cell::Unknown => "?",
-                Cell::Empty => ".",
-                Cell::Filled => "X",
+        match *self {
+            Cell::Unknown => write!(f, "?"),
+            Cell::Empty => write!(f, "."),
+            Cell::Filled | Cell::Colored(_) => {
+                let color = self.get_color().unwrap() as usize;
+                let glyph = COLOR_GLYPHS.get(color).copied().unwrap_or(b'#');
+                write!(f, "{}", glyph as char)
             }
-        )
+        }
+    }
+}
+
+/// The characters used to print cells, and to read them back in.
+/// Colored cells other than the default color always use the same glyphs as Cell's Display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CharMap {
+    /// Character for cells filled with the default color
+    pub filled: char,
+    /// Character for empty cells
+    pub empty: char,
+    /// Character for unknown cells
+    pub unknown: char,
+}
+
+impl CharMap {
+    /// Create a CharMap from three characters: filled, empty, then unknown, such as "#-*".
+    /// Returns None if the string is not exactly three different characters.
+    pub fn parse(value: &str) -> Option<CharMap> {
+        let chars: Vec<char> = value.chars().collect();
+        match chars[..] {
+            [filled, empty, unknown]
+                if filled != empty && filled != unknown && empty != unknown =>
+            {
+                Some(CharMap {
+                    filled,
+                    empty,
+                    unknown,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the character used for the given cell
+    pub fn get_char(&self, cell: Cell) -> char {
+        match cell {
+            Cell::Unknown => self.unknown,
+            Cell::Empty => self.empty,
+            Cell::Filled => self.filled,
+            cell => cell.to_string().chars().next().unwrap(),
+        }
+    }
+
+    /// Get the cell a character represents, or None if it is not a cell character
+    pub fn get_cell(&self, glyph: char) -> Option<Cell> {
+        if glyph == self.filled {
+            Some(Cell::Filled)
+        } else if glyph == self.empty {
+            Some(Cell::Empty)
+        } else if glyph == self.unknown {
+            Some(Cell::Unknown)
+        } else {
+            Cell::from_glyph(glyph).filter(|cell| cell.get_color().is_some_and(|c| c > 0))
+        }
+    }
+}
+
+impl Default for CharMap {
+    fn default() -> CharMap {
+        CharMap {
+            filled: 'X',
+            empty: '.',
+            unknown: '?',
+        }
+    }
+}
+
+/// A single color in a Palette
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PaletteColor {
+    /// A human readable name for this color
+    pub name: String,
+    /// The (red, green, blue) value of this color
+    pub rgb: (u8, u8, u8),
+}
+
+/// The levels of each channel in the 6x6x6 color cube of the 256 color ANSI palette
+const ANSI_CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl PaletteColor {
+    /// Get the closest of the 256 colors of an ANSI terminal to this color: either one from
+    /// the 6x6x6 color cube (16 to 231), or one of the 24 shades of gray (232 to 255)
+    pub fn ansi_color(&self) -> u8 {
+        let (r, g, b) = self.rgb;
+        let distance = |(r2, g2, b2): (u8, u8, u8)| {
+            let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+            d(r, r2) + d(g, g2) + d(b, b2)
+        };
+        let nearest_level = |x: u8| {
+            (0..ANSI_CUBE_LEVELS.len())
+                .min_by_key(|&i| (ANSI_CUBE_LEVELS[i] as i32 - x as i32).abs())
+                .unwrap()
+        };
+        let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+        let cube = (ANSI_CUBE_LEVELS[ri], ANSI_CUBE_LEVELS[gi], ANSI_CUBE_LEVELS[bi]);
+        // the shades of gray go from 8 to 238 in steps of 10
+        let average = (r as u32 + g as u32 + b as u32) / 3;
+        let shade = (average.saturating_sub(3) / 10).min(23) as u8;
+        let gray = 8 + shade * 10;
+        if distance((gray, gray, gray)) < distance(cube) {
+            232 + shade
+        } else {
+            16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+        }
+    }
+
+    /// Get the ANSI escape codes that draw text on this color's background, in black or white,
+    /// whichever is easier to read on it
+    pub fn ansi_format(&self) -> String {
+        let (r, g, b) = self.rgb;
+        let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+        let foreground = if luma < 128_000 { 97 } else { 30 };
+        format!("\x1B[48;5;{}m\x1B[{}m", self.ansi_color(), foreground)
+    }
+}
+
+/// The list of colors that a board's cells may be filled with.
+/// Color 0 is always present; black and white puzzles only ever use that color.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Palette {
+    colors: Vec<PaletteColor>,
+}
+
+impl Palette {
+    /// Create a palette containing only the default black fill color
+    pub fn new() -> Palette {
+        Palette {
+            colors: vec![PaletteColor {
+                name: "black".to_string(),
+                rgb: (0, 0, 0),
+            }],
+        }
+    }
+
+    /// Create a palette with the given color as color 0
+    pub fn with_default(name: &str, rgb: (u8, u8, u8)) -> Palette {
+        Palette {
+            colors: vec![PaletteColor {
+                name: name.to_string(),
+                rgb,
+            }],
+        }
+    }
+
+    /// Add a color to this palette, returning its index.
+    /// Returns None if the palette is full.
+    pub fn add(&mut self, name: &str, rgb: (u8, u8, u8)) -> Option<Color> {
+        if self.colors.len() > Color::MAX as usize {
+            return None;
+        }
+        self.colors.push(PaletteColor {
+            name: name.to_string(),
+            rgb,
+        });
+        Some((self.colors.len() - 1) as Color)
+    }
+
+    /// Get the color with the given index
+    pub fn get(&self, color: Color) -> Option<&PaletteColor> {
+        self.colors.get(color as usize)
+    }
+
+    /// Find the index of the color with the given name
+    pub fn find(&self, name: &str) -> Option<Color> {
+        self.colors
+            .iter()
+            .position(|x| x.name == name)
+            .map(|i| i as Color)
+    }
+
+    /// Get the number of colors in this palette
+    pub fn num_colors(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// Returns true if this palette only contains a single color
+    pub fn is_monochrome(&self) -> bool {
+        self.colors.len() == 1
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new()
     }
 }
 
 /// A type used to represent lengths on a board.
 /// This includes the board's size, and constraint lengths.
+/// Enable the `wide-units` feature for boards larger than 65535 in either dimension.
+#[cfg(not(feature = "wide-units"))]
 pub type Unit = u16;
+#[cfg(feature = "wide-units")]
+pub type Unit = u32;
+
+/// Convert a length to a Unit, panicking if the length does not fit
+pub(crate) fn to_unit(value: usize) -> Unit {
+    match Unit::try_from(value) {
+        Ok(v) => v,
+        Err(_) => panic!(
+            "{} is too large for a board dimension (maximum is {})",
+            value,
+            Unit::MAX
+        ),
+    }
+}
+
+/// Get the number of cells in a board of the given size.
+/// Sizes are multiplied as usize so that they can not overflow Unit.
+fn get_cell_count(width: Unit, height: Unit) -> usize {
+    (width as usize)
+        .checked_mul(height as usize)
+        .expect("board has too many cells")
+}
 
 /// A single Constraint (or hint) for the board.
-#[derive(Copy, Clone, PartialEq, Eq)]
+/// Constraints usually have an exact length, but may instead have a range of
+/// possible lengths, or an entirely unknown length (for "blotted" puzzles).
+/// A total constraint gives only how many cells of its line are filled, in any blocks,
+/// and is the only constraint in its line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Constraint {
     length: Unit,
+    #[cfg_attr(feature = "serde", serde(default))]
+    max_length: Option<Unit>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    color: Color,
+    #[cfg_attr(feature = "serde", serde(default))]
+    total: bool,
 }
 
 impl Constraint {
     /// Create a new constraint with the given length
     pub fn new(value: Unit) -> Constraint {
-        Constraint { length: value }
+        Constraint {
+            length: value,
+            max_length: None,
+            color: 0,
+            total: false,
+        }
+    }
+    /// Create a new constraint with the given length and color
+    pub fn new_colored(value: Unit, color: Color) -> Constraint {
+        Constraint {
+            length: value,
+            max_length: None,
+            color,
+            total: false,
+        }
+    }
+    /// Create a new constraint whose length is anywhere from min to max (inclusive)
+    pub fn new_range(min: Unit, max: Unit) -> Constraint {
+        Constraint {
+            length: min,
+            max_length: if min == max { None } else { Some(max) },
+            color: 0,
+            total: false,
+        }
+    }
+    /// Create a new constraint whose length is not known
+    pub fn new_unknown() -> Constraint {
+        Constraint::new_range(1, Unit::MAX)
     }
-    /// Get this constraint's length
+    /// Create a new constraint for the total number of filled cells in a line
+    pub fn new_total(count: Unit) -> Constraint {
+        Constraint {
+            length: count,
+            max_length: None,
+            color: 0,
+            total: true,
+        }
+    }
+    /// Get this constraint's length.
+    /// If this constraint is not exact, this is its minimum length.
     pub fn get_length(&self) -> Unit {
         self.length
     }
+    /// Get this constraint's maximum length
+    pub fn get_max_length(&self) -> Unit {
+        self.max_length.unwrap_or(self.length)
+    }
+    /// Returns true if this constraint is a single block with a known length
+    pub fn is_exact(&self) -> bool {
+        self.max_length.is_none() && !self.total
+    }
+    /// Returns true if this constraint is the total number of filled cells in its line
+    pub fn is_total(&self) -> bool {
+        self.total
+    }
+    /// Returns true if this constraint's length is entirely unknown
+    pub fn is_unknown(&self) -> bool {
+        self.length <= 1 && self.max_length == Some(Unit::MAX)
+    }
+    /// Get this constraint's color
+    pub fn get_color(&self) -> Color {
+        self.color
+    }
+    /// Get a copy of this constraint with the given color
+    pub fn with_color(self, color: Color) -> Constraint {
+        Constraint { color, ..self }
+    }
+    /// Get the number of columns it takes to print this constraint
+    fn get_print_width(&self) -> usize {
+        if self.is_unknown() {
+            1
+        } else if self.is_total() {
+            1 + get_print_width(self.length)
+        } else if self.is_exact() {
+            get_print_width(self.length)
+        } else {
+            get_print_width(self.length) + 1 + get_print_width(self.get_max_length())
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = if self.is_unknown() {
+            "?".to_string()
+        } else if self.is_total() {
+            format!("={}", self.length)
+        } else if self.is_exact() {
+            self.length.to_string()
+        } else {
+            format!("{}-{}", self.length, self.get_max_length())
+        };
+        f.pad(&s)
+    }
+}
+
+/// Parse a constraint from a string.
+/// Accepts a length ("3"), a range of lengths ("3-5"), an unknown length ("?"),
+/// or the total number of filled cells in the line ("=7").
+pub fn parse_constraint(value: &str) -> Option<Constraint> {
+    let value = value.trim();
+    if value == "?" {
+        return Some(Constraint::new_unknown());
+    }
+    if let Some(count) = value.strip_prefix('=') {
+        return count.trim().parse::<Unit>().ok().map(Constraint::new_total);
+    }
+    if let Some(dash) = value.find('-') {
+        let min = value[..dash].trim().parse::<Unit>().ok()?;
+        let max = value[dash + 1..].trim().parse::<Unit>().ok()?;
+        if min > max {
+            return None;
+        }
+        return Some(Constraint::new_range(min, max));
+    }
+    value.parse::<Unit>().ok().map(Constraint::new)
+}
+
+/// Convert an error from the CSV reader, keeping the line it happened on
+fn csv_parse_error(e: csv::Error) -> ParseError {
+    let line = e.position().map_or(1, |pos| pos.line() as usize);
+    let message = e.to_string();
+    match e.into_kind() {
+        csv::ErrorKind::Io(e) => ParseError::Io(e),
+        _ => ParseError::syntax(line, message),
+    }
+}
+
+/// Parse a single solution field, such as b"1", without copying it
+fn parse_cell_field(field: &[u8]) -> Option<Cell> {
+    let value = std::str::from_utf8(field).ok()?.parse::<i64>().ok()?;
+    Cell::from_i64(value)
+}
+
+/// Write one line of comma separated constraints per list
+fn write_csv_constraints<W: io::Write>(handle: &mut W, lists: &[ConstraintList]) -> io::Result<()> {
+    for list in lists {
+        for (i, constraint) in list.iter().enumerate() {
+            if constraint.get_color() != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "puzzle files can not represent colored constraints",
+                ));
+            }
+            if i > 0 {
+                write!(handle, ",")?;
+            }
+            write!(handle, "{}", constraint)?;
+        }
+        writeln!(handle)?;
+    }
+    Ok(())
 }
 
 /// Given a list of individual nodes,
 /// find all nodes which can be used to reach from start to end.
-fn find_full_paths<T>(
+#[allow(clippy::too_many_arguments)]
+fn find_full_paths(
     i: usize,
     j: usize,
     w: usize,
     h: usize,
     nodelist: &util::NodeList<bool>,
     determined: &mut util::NodeList<Option<bool>>,
-    c: &ConstraintList,
-    line: &T,
-) -> bool
-where
-    T: LineRef,
-{
+    bounds: &ConstraintBounds,
+    masks: &LineMasks,
+) -> bool {
     // Each node will be determined at most once, so this is guaranteed at most O(n^2)
     if let Some(value) = *determined.get(i, j) {
         // this node has already been determined.
@@ -139,10 +578,8 @@ where
             } else {
                 let mut v = false;
                 // determine if any children reach end
-                for k in j..h {
-                    if determine_edge(i, j, k, c, line) {
-                        v |= find_full_paths(i + 1, k, w, h, nodelist, determined, c, line);
-                    }
+                for k in j..=last_edge(i, j, h, bounds, masks) {
+                    v |= find_full_paths(i + 1, k, w, h, nodelist, determined, bounds, masks);
                 }
                 determined.set(i, j, Some(v));
                 v
@@ -158,70 +595,327 @@ where
 /// A type used to represent a list of constraints on a row or column
 pub type ConstraintList = Vec<Constraint>;
 
+/// Working memory for solving lines. It grows to fit the largest line it is used with, so a
+/// single LineScratch can be shared by every line of a board without allocating for each solve.
+#[derive(Default)]
+pub struct LineScratch {
+    /// Whether each node can be placed (see LineRef::is_solvable)
+    nodes: util::NodeList<bool>,
+    /// Whether each node is part of a full path (see find_full_paths)
+    determined: util::NodeList<Option<bool>>,
+    /// Whether each cell can be (empty, filled)
+    node_values: Vec<(bool, bool)>,
+    /// Constraint positions, used while enumerating arrangements
+    starts: Vec<usize>,
+    /// State tables for LineMut::try_solve_line_automaton
+    reachable: Vec<bool>,
+    accepted: Vec<bool>,
+    /// The cells of the line being solved
+    masks: LineMasks,
+    /// Bits for whether each cell can be empty or filled, used by try_solve_line_complete
+    can_be_empty: Vec<u64>,
+    can_be_filled: Vec<u64>,
+    /// The constraints of the line being solved, summed
+    bounds: ConstraintBounds,
+}
+
+/// Running totals of a line's constraint lengths, so that the fewest cells needed before and
+/// after each constraint are found without summing the whole list each time
+#[derive(Clone, Debug, Default)]
+pub struct ConstraintBounds {
+    /// sums[i] is the total length of the constraints before constraint i
+    sums: Vec<usize>,
+}
+
+impl ConstraintBounds {
+    /// Sum the lengths of the given constraints, reusing this value's memory
+    pub fn load(&mut self, ls: &ConstraintList) {
+        self.sums.clear();
+        self.sums.push(0);
+        let mut total = 0;
+        for constraint in ls {
+            total += constraint.get_length() as usize;
+            self.sums.push(total);
+        }
+    }
+    /// Get the length of the constraint at the given index
+    pub fn length(&self, index: usize) -> usize {
+        self.sums[index + 1] - self.sums[index]
+    }
+    /// Get the total length of every constraint
+    pub fn total(&self) -> usize {
+        self.sums[self.sums.len() - 1]
+    }
+    /// Get the fewest cells needed to the left and to the right of the constraint at the
+    /// given index, counting a gap between each pair of constraints
+    pub fn get(&self, index: usize) -> (usize, usize) {
+        let num = self.sums.len() - 1;
+        let left = index + self.sums[index];
+        let right = num - index - 1 + self.total() - self.sums[index + 1];
+        (left, right)
+    }
+}
+
+/// A line's known cells packed into bits, 64 cells per word, so that checking a range of
+/// cells takes a few word operations rather than a look at every cell.
+#[derive(Clone, Debug, Default)]
+pub struct LineMasks {
+    /// Bit i is set if cell i is filled
+    filled: Vec<u64>,
+    /// Bit i is set if cell i is empty or masked
+    empty: Vec<u64>,
+    size: usize,
+}
+
+impl LineMasks {
+    /// Pack the cells of the given line
+    pub fn new<T: LineRef>(line: &T) -> LineMasks {
+        let mut masks = LineMasks::default();
+        masks.load(line);
+        masks
+    }
+    /// Pack the cells of the given line, reusing this value's memory
+    pub fn load<T: LineRef>(&mut self, line: &T) {
+        self.size = line.size() as usize;
+        let num_words = self.size.div_ceil(64);
+        self.filled.clear();
+        self.filled.resize(num_words, 0);
+        self.empty.clear();
+        self.empty.resize(num_words, 0);
+        for i in 0..self.size {
+            let cell = line.get_cell(i as Unit);
+            let bit = 1 << (i % 64);
+            if cell.is_filled() {
+                self.filled[i / 64] |= bit;
+            } else if cell == Cell::Empty || line.is_masked(i as Unit) {
+                self.empty[i / 64] |= bit;
+            }
+        }
+    }
+    /// Get the number of cells in the line
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    /// Returns true if the given cell is filled
+    pub fn is_filled(&self, index: usize) -> bool {
+        self.filled[index / 64] & (1 << (index % 64)) != 0
+    }
+    /// Returns true if the given cell is empty or masked
+    pub fn is_empty(&self, index: usize) -> bool {
+        self.empty[index / 64] & (1 << (index % 64)) != 0
+    }
+    /// Get the first filled cell at or after the given cell, or the line's size if there is none
+    pub fn next_filled(&self, from: usize) -> usize {
+        let mut index = from / 64;
+        if index >= self.filled.len() {
+            return self.size;
+        }
+        let mut word = self.filled[index] & (!0u64 << (from % 64));
+        while word == 0 {
+            index += 1;
+            if index >= self.filled.len() {
+                return self.size;
+            }
+            word = self.filled[index];
+        }
+        index * 64 + word.trailing_zeros() as usize
+    }
+    /// Returns true if any cell in [start, end) is filled
+    pub fn any_filled(&self, start: usize, end: usize) -> bool {
+        any_bit_in_range(&self.filled, start, end)
+    }
+    /// Returns true if any cell in [start, end) is empty or masked
+    pub fn any_empty(&self, start: usize, end: usize) -> bool {
+        any_bit_in_range(&self.empty, start, end)
+    }
+    /// Same as LineRef::can_fit_constraint: whether a constraint of the given length can be
+    /// placed starting at pos, with no filled cells directly on either side of it
+    pub fn can_fit_constraint(&self, pos: usize, len: usize) -> bool {
+        let end = pos + len;
+        if end > self.size {
+            panic!("OOB???? {}:{} [{}]", pos, len, self.size)
+        }
+        let left_filled = pos > 0 && self.is_filled(pos - 1);
+        let right_filled = end < self.size && self.is_filled(end);
+        !left_filled && !right_filled && !self.any_empty(pos, end)
+    }
+}
+
+/// Resize a list of words to hold the given number of bits, all cleared
+fn reset_bits(words: &mut Vec<u64>, size: usize) {
+    words.clear();
+    words.resize(size.div_ceil(64), 0);
+}
+
+fn is_bit_set(words: &[u64], index: usize) -> bool {
+    words[index / 64] & (1 << (index % 64)) != 0
+}
+
+/// Set every bit in [start, end) of the given words
+fn set_bit_range(words: &mut [u64], start: usize, end: usize) {
+    if start >= end {
+        return;
+    }
+    let first = start / 64;
+    let last = (end - 1) / 64;
+    let low = !0u64 << (start % 64);
+    let high = !0u64 >> (63 - (end - 1) % 64);
+    if first == last {
+        words[first] |= low & high;
+        return;
+    }
+    words[first] |= low;
+    for word in words[first + 1..last].iter_mut() {
+        *word = !0;
+    }
+    words[last] |= high;
+}
+
+/// Returns true if any bit in [start, end) of the given words is set
+fn any_bit_in_range(words: &[u64], start: usize, end: usize) -> bool {
+    if start >= end {
+        return false;
+    }
+    let first = start / 64;
+    let last = (end - 1) / 64;
+    // bits at or after start in the first word, and bits before end in the last word
+    let low = !0u64 << (start % 64);
+    let high = !0u64 >> (63 - (end - 1) % 64);
+    if first == last {
+        return words[first] & low & high != 0;
+    }
+    words[first] & low != 0
+        || words[first + 1..last].iter().any(|word| *word != 0)
+        || words[last] & high != 0
+}
+
+impl LineScratch {
+    pub fn new() -> LineScratch {
+        LineScratch::default()
+    }
+}
+
+/// Resize a list of cell possibilities to the given number of cells, all set to value
+fn reset_node_values(node_values: &mut Vec<(bool, bool)>, size: usize, value: (bool, bool)) {
+    node_values.clear();
+    node_values.resize(size, value);
+}
+
 /// A mutable reference on a board's row or column
 pub trait LineMut: LineRef {
     /// Set a cell's value on this line
     fn set_cell(&mut self, index: Unit, value: Cell);
+    /// Get the column and row of a cell on this line
+    fn get_coordinate(&self, index: Unit) -> (Unit, Unit);
+    /// Get the changes made by solving the given cells, which were previously Unknown
+    fn record_changes(&self, indices: &[Unit]) -> ChangeSet {
+        let mut changes = ChangeSet::new();
+        for &index in indices {
+            let (col, row) = self.get_coordinate(index);
+            changes.record(Change::new(col, row, Cell::Unknown, self.get_cell(index)));
+        }
+        changes
+    }
+    /// Same as try_solve_line_complete, but returns the modified cells as a ChangeSet
+    fn try_solve_line_changes(&mut self, scratch: &mut LineScratch) -> Option<ChangeSet> {
+        let modified = self.try_solve_line_complete(scratch)?;
+        Some(self.record_changes(&modified))
+    }
     /// Solve this line to its fullest degree possible.
-    /// Returns None if a contradiction was found.
+    /// Returns None if a contradiction was found, in which case the line is left unchanged.
     /// Otherwise, returns Some(Vec<Unit>) with a list of cells that were modified.
     /// Uses a similar technique as LineRef::is_solvable, by treating constraints as
     /// a graph of nodes (valid placements for each constraint) connected by edges (the gaps between constraints).
-    fn try_solve_line_complete(
-        &mut self,
-        nodelist: &mut util::NodeList<bool>,
-    ) -> Option<Vec<Unit>> {
+    fn try_solve_line_complete(&mut self, scratch: &mut LineScratch) -> Option<Vec<Unit>> {
         let c = self.get_constraints();
         let mut ret = Vec::new();
+        // fast path: a completed line only needs checking against its constraints
+        if self.is_completed() {
+            return if self.matches_constraints() {
+                Some(ret)
+            } else {
+                None
+            };
+        }
         // special case: no constraints
-        if c.len() == 0 {
+        if c.is_empty() {
             // Every cell must be empty
+            if (0..self.size()).any(|i| self.get_cell(i).is_filled()) {
+                return None;
+            }
             for i in 0..self.size() {
-                match self.get_cell(i) {
-                    Cell::Unknown => {
-                        ret.push(i);
-                        self.set_cell(i, Cell::Empty);
-                    }
-                    Cell::Filled => {
-                        return None;
-                    }
-                    Cell::Empty => {}
+                if self.get_cell(i) == Cell::Unknown {
+                    ret.push(i);
+                    self.set_cell(i, Cell::Empty);
                 }
             }
             return Some(ret);
         }
-        let c_sum: usize = c.iter().map(|x| x.get_length() as usize).sum();
-        let extra_space = self.size() as usize + 1 - c_sum - c.len();
+        // special case: a total number of filled cells, anywhere in the line
+        if get_line_total(c).is_some() {
+            let cells = find_total_cells(self)?;
+            return Some(set_known_cells(self, cells));
+        }
+        // special case: lines that wrap around
+        if self.is_toroidal() {
+            return self.try_solve_line_toroidal(scratch);
+        }
+        // special case: colored constraints
+        if self.is_colored() {
+            return self.try_solve_line_colored();
+        }
+        // special case: constraints without exact lengths
+        if c.iter().any(|x| !x.is_exact()) {
+            let node_values = find_ranged_possibilities(self)?;
+            apply_cell_possibilities(self, &node_values, &mut ret)?;
+            return Some(ret);
+        }
+        // fast path: short lines with little slack have few enough arrangements to list them all
+        let slack = self.slack()?;
+        if self.size() as usize <= ENUMERATION_MAX_LENGTH && slack <= ENUMERATION_MAX_SLACK {
+            if !enumerate_possibilities(self, scratch) {
+                return None;
+            }
+            apply_cell_possibilities(self, &scratch.node_values, &mut ret)?;
+            return Some(ret);
+        }
+        let LineScratch {
+            nodes: nodelist,
+            determined,
+            node_values,
+            masks,
+            can_be_empty,
+            can_be_filled,
+            bounds,
+            ..
+        } = scratch;
+        bounds.load(c);
+        let extra_space = self.size() as usize + 1 - bounds.total() - c.len();
         let num_nodes_width = c.len();
         let num_nodes_height = extra_space + 1;
+        masks.load(self);
+        nodelist.reset(num_nodes_width, num_nodes_height);
         // For each node NODE[i, j]:
         // [i] is the constraint index
         // [j] is the permutation
         // NODE[i, 0] represents the first possible position that the constraint 'j' can be placed.
         // Determine whether each node can be placed on the board.
         for i in 0..num_nodes_width {
-            let (left, _right) = get_constraint_bounds(&c, i);
-            let value = c[i].get_length();
+            let (left, _right) = bounds.get(i);
+            let value = bounds.length(i);
             for j in 0..num_nodes_height {
-                let mut nodevalue = self.can_fit_constraint((left + j) as Unit, value);
+                let mut nodevalue = masks.can_fit_constraint(left + j, value);
                 // If first node, check that everything to left can be 0
-                if nodevalue && i == 0 && j > 1 {
-                    for q in 0..(j - 1) {
-                        if self.get_cell(q as Unit) == Cell::Filled {
-                            nodevalue = false;
-                            break;
-                        }
-                    }
+                if nodevalue && i == 0 && j > 1 && masks.any_filled(0, j - 1) {
+                    nodevalue = false;
                 }
                 // If last node, check that everything to right can be 0
-                if nodevalue && i == num_nodes_width - 1 && j + 2 < num_nodes_height {
-                    for q in (self.size() as usize - num_nodes_height + j + 2)..self.size() as usize
-                    {
-                        if self.get_cell(q as Unit) == Cell::Filled {
-                            nodevalue = false;
-                            break;
-                        }
-                    }
+                if nodevalue
+                    && i == num_nodes_width - 1
+                    && j + 2 < num_nodes_height
+                    && masks.any_filled(masks.size() - num_nodes_height + j + 2, masks.size())
+                {
+                    nodevalue = false;
                 }
                 // set value
                 nodelist.set(i, j, nodevalue);
@@ -232,7 +926,7 @@ pub trait LineMut: LineRef {
         // every node between them is marked as Some(true).
         // Nodes that can not be used as a full path are marked as Some(false),
         // and nodes that are never visited are marked as None.
-        let mut determined = self.make_empty_node_list::<Option<bool>>();
+        determined.reset(num_nodes_width, num_nodes_height);
         for j in 0..num_nodes_height {
             // Try to find all full paths from NODE[0, j] to some end node
             find_full_paths(
@@ -240,132 +934,911 @@ pub trait LineMut: LineRef {
                 j,
                 num_nodes_width,
                 num_nodes_height,
-                &nodelist,
-                &mut determined,
-                c,
-                self,
+                nodelist,
+                determined,
+                bounds,
+                masks,
             );
         }
-        // determine which cells can be set to certain values
-        let mut node_values = vec![(false, false); self.size() as usize];
+        // determine which cells can be set to certain values.
+        // Whole ranges of cells are marked at once, as bits.
+        let size = self.size() as usize;
+        reset_bits(can_be_empty, size);
+        reset_bits(can_be_filled, size);
         // Iterate through each valid node
         for i in 0..num_nodes_width {
             for j in 0..num_nodes_height {
                 if let Some(true) = *determined.get(i, j) {
                     // find the range of cells for this node
-                    let (start, end) = get_node_range(i, j, &c);
+                    let (start, end) = get_node_range(i, j, bounds);
                     if i == 0 {
                         // If this is the first constraint, then mark every cell
                         // to the left of it as able to be empty
-                        for k in 0..start {
-                            node_values[k].0 = true;
-                        }
+                        set_bit_range(can_be_empty, 0, start);
                     } else if start > 0 {
                         // otherwise, mark the cell immediately before this
                         // constraint as able to be empty.
-                        node_values[start - 1].0 = true;
+                        set_bit_range(can_be_empty, start - 1, start);
                     }
                     if i == num_nodes_width - 1 {
                         // If this is the last constraint,
                         // mark every cell to the right of it as able to be empty.
-                        for k in end..self.size() as usize {
-                            node_values[k].0 = true;
-                        }
-                    } else if end < self.size() as usize {
+                        set_bit_range(can_be_empty, end, size);
+                    } else if end < size {
                         // otherwise, mark the cell immediately after this
                         // constraint as able to be empty.
-                        node_values[end].0 = true;
+                        set_bit_range(can_be_empty, end, end + 1);
                     }
                     // Mark every cell in the constraint as able to be filled.
-                    for k in start..end {
-                        node_values[k].1 = true;
-                    }
+                    set_bit_range(can_be_filled, start, end);
                     if i < num_nodes_width - 1 {
                         // If this is not the last constraint, find the following valid constraint with the longest edge.
                         let k = (j..num_nodes_height)
-                            .filter(|k| *determined.get(i + 1, *k) == Some(true))
-                            .max()
+                            .rev()
+                            .find(|k| *determined.get(i + 1, *k) == Some(true))
                             .unwrap();
                         // Then, mark every cell between this and the longest edge as able to be empty.
-                        if let Some((estart, eend)) = get_edge_range(i, j, k, c) {
-                            for l in estart..eend {
-                                node_values[l].0 = true;
-                            }
+                        if let Some((estart, eend)) = get_edge_range(i, j, k, bounds) {
+                            set_bit_range(can_be_empty, estart, eend);
                         }
                     }
                 }
             }
         }
-        for (i, (can_be_empty, can_be_filled)) in node_values.iter().enumerate() {
-            if *can_be_empty && !*can_be_filled {
-                match self.get_cell(i as Unit) {
-                    Cell::Empty => {}
-                    // error if can't be filled, but cell is currently filled (probably can't happen)
-                    Cell::Filled => return None, 
-                    Cell::Unknown => {
-                        // Set this cell as empty
-                        self.set_cell(i as Unit, Cell::Empty);
-                        ret.push(i as Unit);
-                    }
+        reset_node_values(node_values, size, (false, false));
+        for (k, value) in node_values.iter_mut().enumerate() {
+            *value = (is_bit_set(can_be_empty, k), is_bit_set(can_be_filled, k));
+        }
+        apply_cell_possibilities(self, node_values, &mut ret)?;
+        Some(ret)
+    }
+    /// Solve this line using the classic left-right overlap method:
+    /// find the leftmost and rightmost valid placements of the constraints, then fill the
+    /// cells that every constraint covers in both, and empty the cells no constraint can reach.
+    /// This is faster than try_solve_line_complete, but may deduce fewer cells.
+    /// Returns None if a contradiction was found, in which case the line is left unchanged.
+    /// Otherwise, returns Some(Vec<Unit>) with a list of cells that were modified.
+    fn try_solve_line_left_right(&mut self, scratch: &mut LineScratch) -> Option<Vec<Unit>> {
+        let c = self.get_constraints();
+        let is_plain = !self.is_colored() && !self.is_toroidal();
+        if c.is_empty() || c.iter().any(|x| !x.is_exact()) || !is_plain {
+            return self.try_solve_line_complete(scratch);
+        }
+        let size = self.size() as usize;
+        let lengths: Vec<usize> = c.iter().map(|x| x.get_length() as usize).collect();
+        let (left, right) = constraint_extents(self, &lengths)?;
+        let node_values = &mut scratch.node_values;
+        reset_node_values(node_values, size, (true, false));
+        for (i, length) in lengths.iter().enumerate() {
+            // every cell between the leftmost start and rightmost end may be filled
+            for value in node_values[left[i]..right[i] + length].iter_mut() {
+                value.1 = true;
+            }
+            // cells covered by both placements can not be empty
+            if right[i] < left[i] + length {
+                for value in node_values[right[i]..left[i] + length].iter_mut() {
+                    value.0 = false;
                 }
-            } else if !*can_be_empty && *can_be_filled {
-                match self.get_cell(i as Unit) {
-                    Cell::Filled => {}
-                    // error if can't be empty, but cell is currently empty (probably can't happen)
-                    Cell::Empty => return None,
-                    Cell::Unknown => {
-                        // Set this cell as filled
-                        self.set_cell(i as Unit, Cell::Filled);
-                        ret.push(i as Unit);
-                    }
+            }
+        }
+        let mut ret = Vec::new();
+        apply_cell_possibilities(self, node_values, &mut ret)?;
+        Some(ret)
+    }
+    /// Solve this line by running the given automaton, made by make_automaton, forwards and
+    /// backwards over the cells. A cell can have a value if some accepted sequence of states
+    /// passes through it with that value.
+    /// Deduces every cell that can be deduced from this line alone, which
+    /// try_solve_line_complete occasionally misses on long lines, and the automaton only
+    /// needs to be built once for each line, no matter how many times the line is solved.
+    /// Returns None if a contradiction was found, in which case the line is left unchanged.
+    /// Otherwise, returns Some(Vec<Unit>) with a list of cells that were modified.
+    fn try_solve_line_automaton(
+        &mut self,
+        automaton: &LineAutomaton,
+        scratch: &mut LineScratch,
+    ) -> Option<Vec<Unit>> {
+        // the automaton only reads cells as empty or filled, in a line with two ends
+        if get_line_total(self.get_constraints()).is_some() {
+            let cells = find_total_cells(self)?;
+            return Some(set_known_cells(self, cells));
+        }
+        if self.is_toroidal() {
+            return self.try_solve_line_toroidal(scratch);
+        }
+        if self.is_colored() {
+            return self.try_solve_line_colored();
+        }
+        let size = self.size() as usize;
+        let LineScratch {
+            reachable,
+            accepted,
+            node_values,
+            ..
+        } = scratch;
+        let num_states = automaton.states.len();
+        let index = |pos: usize, state: usize| pos * num_states + state;
+        let can_be_empty = |i: usize| !self.get_cell(i as Unit).is_filled();
+        let can_be_filled = |i: usize| self.get_cell(i as Unit) != Cell::Empty;
+        // REACHABLE[pos, state] means that the automaton can be in this state after
+        // reading the first pos cells.
+        reachable.clear();
+        reachable.resize((size + 1) * num_states, false);
+        reachable[index(0, 0)] = true;
+        for pos in 0..size {
+            for (i, state) in automaton.states.iter().enumerate() {
+                if !reachable[index(pos, i)] {
+                    continue;
+                }
+                if let Some(next) = state.on_empty.filter(|_| can_be_empty(pos)) {
+                    reachable[index(pos + 1, next)] = true;
+                }
+                if let Some(next) = state.on_filled.filter(|_| can_be_filled(pos)) {
+                    reachable[index(pos + 1, next)] = true;
+                }
+            }
+        }
+        // ACCEPTED[pos, state] means that the automaton will accept the line when in this
+        // state after reading the first pos cells.
+        accepted.clear();
+        accepted.resize((size + 1) * num_states, false);
+        for (i, state) in automaton.states.iter().enumerate() {
+            accepted[index(size, i)] = state.accepting;
+        }
+        reset_node_values(node_values, size, (false, false));
+        for pos in (0..size).rev() {
+            for (i, state) in automaton.states.iter().enumerate() {
+                let empty = state
+                    .on_empty
+                    .filter(|_| can_be_empty(pos))
+                    .is_some_and(|next| accepted[index(pos + 1, next)]);
+                let filled = state
+                    .on_filled
+                    .filter(|_| can_be_filled(pos))
+                    .is_some_and(|next| accepted[index(pos + 1, next)]);
+                accepted[index(pos, i)] = empty || filled;
+                if reachable[index(pos, i)] {
+                    node_values[pos].0 |= empty;
+                    node_values[pos].1 |= filled;
                 }
-            } else if !*can_be_empty && !*can_be_filled {
-                // Error if no possible value for cell
-                return None;
+            }
+        }
+        if !accepted[index(0, 0)] {
+            return None;
+        }
+        let mut ret = Vec::new();
+        apply_cell_possibilities(self, node_values, &mut ret)?;
+        Some(ret)
+    }
+    /// Solve a line with colored constraints, where only constraints of the same color need a
+    /// gap between them, by walking the line one cell at a time (see find_colored_possibilities).
+    /// Only cells left with a single possible value are set, since an Unknown cell can not
+    /// hold which colors it may still be.
+    /// Returns None if a contradiction was found, in which case the line is left unchanged.
+    /// Otherwise, returns Some(Vec<Unit>) with a list of cells that were modified.
+    fn try_solve_line_colored(&mut self) -> Option<Vec<Unit>> {
+        let possibilities = find_colored_possibilities(self)?;
+        let mut ret = Vec::new();
+        for i in 0..self.size() {
+            if self.get_cell(i) != Cell::Unknown {
+                continue;
+            }
+            if let Some(value) = possibilities.only_value(i as usize) {
+                self.set_cell(i, value);
+                ret.push(i);
             }
         }
         Some(ret)
     }
+    /// Solve a line that wraps around, using find_toroidal_cells.
+    /// Returns None if a contradiction was found, in which case the line is left unchanged.
+    /// Otherwise, returns Some(Vec<Unit>) with a list of cells that were modified.
+    fn try_solve_line_toroidal(&mut self, scratch: &mut LineScratch) -> Option<Vec<Unit>> {
+        let cells = find_toroidal_cells(self, scratch)?;
+        Some(set_known_cells(self, cells))
+    }
 }
 
-fn get_node_range(i: usize, j: usize, c: &ConstraintList) -> (usize, usize) {
-    let value = c[i].get_length();
-    let (left, _right) = get_constraint_bounds(&c, i);
-    (left + j, left + j + value as usize)
+/// A single state of a LineAutomaton
+#[derive(Clone, Debug)]
+struct AutomatonState {
+    /// The state to move to when reading an empty cell, if any
+    on_empty: Option<usize>,
+    /// The state to move to when reading a filled cell, if any
+    on_filled: Option<usize>,
+    /// Whether the line may end in this state
+    accepting: bool,
 }
 
-fn get_edge_range(i: usize, j: usize, k: usize, c: &ConstraintList) -> Option<(usize, usize)> {
-    if k <= j + 1 {
-        None
-    } else {
-        let (left, _right) = get_constraint_bounds(&c, i);
-        let i0_value = c[i].get_length() as usize;
-        // let i2 = i1 + 1;
-        // from NODE[i,j] to NODE[i+1,k] where k >= j
-        let pos = left + i0_value + j + 1;
-        // check that gap between A[i,j] and A[i+1,k] is able to be all 0s
-        let width = k - j - 1;
-        Some((pos, pos + width))
+/// A deterministic finite automaton that accepts exactly the cell sequences that satisfy a
+/// line's constraints. See LineRef::make_automaton and LineMut::try_solve_line_automaton.
+#[derive(Clone, Debug)]
+pub struct LineAutomaton {
+    /// State 0 is the starting state
+    states: Vec<AutomatonState>,
+}
+
+impl LineAutomaton {
+    /// Build an automaton for the given constraints on a line of the given size.
+    /// Each constraint becomes one state per cell it may cover, followed by a gap state
+    /// which loops on empty cells.
+    /// A total instead has one state per filled cell counted so far.
+    pub fn new(constraints: &[Constraint], size: Unit) -> LineAutomaton {
+        if let Some(total) = get_line_total(constraints) {
+            let total = total as usize;
+            let states = (0..=total)
+                .map(|count| AutomatonState {
+                    on_empty: Some(count),
+                    on_filled: if count < total { Some(count + 1) } else { None },
+                    accepting: count == total,
+                })
+                .collect();
+            return LineAutomaton { states };
+        }
+        // the leading gap, before the first constraint
+        let mut states = vec![AutomatonState {
+            on_empty: Some(0),
+            on_filled: None,
+            accepting: constraints.is_empty(),
+        }];
+        for (i, constraint) in constraints.iter().enumerate() {
+            let is_last = i + 1 == constraints.len();
+            // an unknown length is limited by the line, which keeps the automaton small
+            let max_length = constraint.get_max_length().min(size) as usize;
+            let min_length = constraint.get_length() as usize;
+            let gap = states.len() + max_length;
+            // the previous gap moves into this constraint
+            let first = states.len();
+            states.last_mut().unwrap().on_filled = Some(first);
+            for length in 1..=max_length {
+                let long_enough = length >= min_length;
+                states.push(AutomatonState {
+                    on_empty: if long_enough { Some(gap) } else { None },
+                    on_filled: if length < max_length { Some(states.len() + 1) } else { None },
+                    accepting: is_last && long_enough,
+                });
+            }
+            states.push(AutomatonState {
+                on_empty: Some(gap),
+                on_filled: None,
+                accepting: is_last,
+            });
+        }
+        LineAutomaton { states }
+    }
+}
+
+/// Find the leftmost placement of constraints with the given lengths that agrees with
+/// the given cells, as the index of the first cell of each constraint.
+/// Returns None if there is no valid placement.
+fn leftmost_starts(cells: &[Cell], lengths: &[usize]) -> Option<Vec<usize>> {
+    let size = cells.len();
+    let num_states = size + 2;
+    let index = |i: usize, p: usize| i * num_states + p;
+    // run[p] is the number of cells starting at p that are not empty
+    let mut run = vec![0; size + 1];
+    for p in (0..size).rev() {
+        if cells[p] != Cell::Empty {
+            run[p] = run[p + 1] + 1;
+        }
+    }
+    // Whether constraint i can be placed starting at cell p
+    let fits = |i: usize, p: usize| {
+        let end = p + lengths[i];
+        end <= size && run[p] >= lengths[i] && (end == size || !cells[end].is_filled())
+    };
+    // FEASIBLE[i, p] means that constraints i.. can be placed in cells [p, size),
+    // leaving every filled cell covered.
+    // The cell after a constraint is a gap, so p goes up to size + 1.
+    let mut feasible = vec![false; (lengths.len() + 1) * num_states];
+    feasible[index(lengths.len(), size + 1)] = true;
+    feasible[index(lengths.len(), size)] = true;
+    for p in (0..size).rev() {
+        feasible[index(lengths.len(), p)] =
+            !cells[p].is_filled() && feasible[index(lengths.len(), p + 1)];
+    }
+    for i in (0..lengths.len()).rev() {
+        for p in (0..size).rev() {
+            feasible[index(i, p)] = (!cells[p].is_filled() && feasible[index(i, p + 1)])
+                || (fits(i, p) && feasible[index(i + 1, p + lengths[i] + 1)]);
+        }
+    }
+    if !feasible[index(0, 0)] {
+        return None;
+    }
+    // Place each constraint as early as possible while the rest can still be placed.
+    // No filled cell is skipped, since the first valid start comes before any filled cell.
+    let mut starts = Vec::with_capacity(lengths.len());
+    let mut pos = 0;
+    for (i, length) in lengths.iter().enumerate() {
+        let start = (pos..size)
+            .find(|&p| fits(i, p) && feasible[index(i + 1, p + length + 1)])
+            .unwrap();
+        starts.push(start);
+        pos = start + length + 1;
+    }
+    Some(starts)
+}
+
+/// Get the leftmost and rightmost start of each of a line's constraints, given their lengths,
+/// over every valid placement of them. Masked cells act exactly like empty cells.
+/// Returns None if the constraints can not be placed.
+fn constraint_extents<T: LineRef>(line: &T, lengths: &[usize]) -> Option<(Vec<usize>, Vec<usize>)> {
+    let size = line.size() as usize;
+    let mut cells: Vec<Cell> = (0..line.size())
+        .map(|i| {
+            if line.is_masked(i) {
+                Cell::Empty
+            } else {
+                line.get_cell(i)
+            }
+        })
+        .collect();
+    let left = leftmost_starts(&cells, lengths)?;
+    // the rightmost placement is the leftmost placement of the reversed line
+    cells.reverse();
+    let reversed_lengths: Vec<usize> = lengths.iter().rev().copied().collect();
+    let right = leftmost_starts(&cells, &reversed_lengths)?
+        .iter()
+        .rev()
+        .zip(lengths.iter())
+        .map(|(start, length)| size - start - length)
+        .collect();
+    Some((left, right))
+}
+
+/// Set every Unknown cell of a line that is known in the given cells.
+/// Returns the cells that were modified.
+fn set_known_cells<T: LineMut>(line: &mut T, cells: Vec<Cell>) -> Vec<Unit> {
+    let mut ret = Vec::new();
+    for (i, value) in cells.into_iter().enumerate() {
+        let i = i as Unit;
+        if value != Cell::Unknown && line.get_cell(i) == Cell::Unknown {
+            line.set_cell(i, value);
+            ret.push(i);
+        }
+    }
+    ret
+}
+
+/// Find the cells of a line whose only constraint is a total. Cells that can not be the
+/// total's color are empty. If the cells of that color already reach the total, every other
+/// cell is empty, and if the total can only be reached by filling every cell that may still be
+/// that color, they are all filled.
+/// Returns None if the total has been passed, can no longer be reached, or the line has cells
+/// of another color.
+fn find_total_cells<T: LineRef>(line: &T) -> Option<Vec<Cell>> {
+    let total = get_line_total(line.get_constraints())? as usize;
+    let color = line.get_constraints()[0].get_color();
+    let mut cells = Vec::with_capacity(line.size() as usize);
+    let mut filled = 0;
+    let mut open = Vec::new();
+    for i in 0..line.size() {
+        let cell = line.get_cell(i);
+        match cell.get_color() {
+            Some(c) if c == color => filled += 1,
+            Some(_) => return None,
+            None if cell == Cell::Unknown && line.can_be_color(i, color) => open.push(i as usize),
+            None => {}
+        }
+        cells.push(if cell == Cell::Unknown && !line.can_be_color(i, color) {
+            Cell::Empty
+        } else {
+            cell
+        });
+    }
+    if filled > total || filled + open.len() < total {
+        return None;
+    }
+    if filled == total || filled + open.len() == total {
+        let value = if filled == total { Cell::Empty } else { Cell::with_color(color) };
+        for i in open {
+            cells[i] = value;
+        }
+    }
+    Some(cells)
+}
+
+/// Given whether each cell in a line can be empty and/or filled,
+/// set every cell which only has one possible value.
+/// Modified cells are appended to `ret`.
+/// Returns None if some cell has no possible values, without modifying the line.
+fn apply_cell_possibilities<T: LineMut>(
+    line: &mut T,
+    node_values: &[(bool, bool)],
+    ret: &mut Vec<Unit>,
+) -> Option<()> {
+    let contradicted = node_values.iter().enumerate().any(|(i, values)| {
+        let cell = line.get_cell(i as Unit);
+        match values {
+            (false, false) => true,
+            (true, false) => cell.is_filled(),
+            (false, true) => cell == Cell::Empty,
+            (true, true) => false,
+        }
+    });
+    if contradicted {
+        return None;
+    }
+    for (i, (can_be_empty, can_be_filled)) in node_values.iter().enumerate() {
+        if *can_be_empty && !*can_be_filled {
+            match line.get_cell(i as Unit) {
+                Cell::Empty => {}
+                // error if can't be filled, but cell is currently filled (probably can't happen)
+                Cell::Filled | Cell::Colored(_) => return None,
+                Cell::Unknown => {
+                    // Set this cell as empty
+                    line.set_cell(i as Unit, Cell::Empty);
+                    ret.push(i as Unit);
+                }
+            }
+        } else if !*can_be_empty && *can_be_filled {
+            match line.get_cell(i as Unit) {
+                Cell::Filled | Cell::Colored(_) => {}
+                // error if can't be empty, but cell is currently empty (probably can't happen)
+                Cell::Empty => return None,
+                Cell::Unknown => {
+                    // Set this cell as filled
+                    line.set_cell(i as Unit, Cell::Filled);
+                    ret.push(i as Unit);
+                }
+            }
+        } else if !*can_be_empty && !*can_be_filled {
+            // Error if no possible value for cell
+            return None;
+        }
+    }
+    Some(())
+}
+
+/// Determine whether each cell in a line can be empty and/or filled,
+/// for lines whose constraints do not all have exact lengths.
+/// Returns None if the line can not be solved.
+///
+/// The node/edge graph used for exact constraints relies on each constraint's
+/// length being fixed, so instead this walks the line one cell at a time.
+/// STATE[i, p] means that the first i constraints fit in cells [0, p),
+/// with cell p-1 (if any) being empty.
+/// A virtual empty cell is placed after the end of the line so that the
+/// final state is always STATE[len(c), size + 1].
+fn find_ranged_possibilities<T: LineRef>(line: &T) -> Option<Vec<(bool, bool)>> {
+    let c = line.get_constraints();
+    let size = line.size() as usize;
+    let num_states = size + 2;
+    let index = |i: usize, p: usize| i * num_states + p;
+    let can_be_empty = |p: usize| p == size || !line.get_cell(p as Unit).is_filled();
+    // Lengths of constraint i that can be placed starting at cell p
+    let placements = |i: usize, p: usize| {
+        let min = c[i].get_length() as usize;
+        let max = (c[i].get_max_length() as usize).min(size - p);
+        (min..=max).filter(move |len| line.can_fit_constraint(p as Unit, *len as Unit))
+    };
+    // Forward pass: which states can be reached from the start of the line
+    let mut forward = vec![false; (c.len() + 1) * num_states];
+    forward[index(0, 0)] = true;
+    for p in 0..=size {
+        for i in 0..=c.len() {
+            if !forward[index(i, p)] {
+                continue;
+            }
+            if can_be_empty(p) {
+                forward[index(i, p + 1)] = true;
+            }
+            if i < c.len() && p < size {
+                for len in placements(i, p) {
+                    forward[index(i + 1, p + len + 1)] = true;
+                }
+            }
+        }
+    }
+    if !forward[index(c.len(), size + 1)] {
+        return None;
+    }
+    // Backward pass: which states can reach the end of the line
+    let mut backward = vec![false; (c.len() + 1) * num_states];
+    backward[index(c.len(), size + 1)] = true;
+    for p in (0..=size).rev() {
+        for i in 0..=c.len() {
+            let mut value = can_be_empty(p) && backward[index(i, p + 1)];
+            if !value && i < c.len() && p < size {
+                value = placements(i, p).any(|len| backward[index(i + 1, p + len + 1)]);
+            }
+            backward[index(i, p)] = value;
+        }
+    }
+    // Every transition between a reachable state and a state that reaches the end
+    // is part of some valid solution, so mark the cells it covers.
+    let mut node_values = vec![(false, false); size];
+    for p in 0..size {
+        for i in 0..=c.len() {
+            if !forward[index(i, p)] {
+                continue;
+            }
+            if can_be_empty(p) && backward[index(i, p + 1)] {
+                node_values[p].0 = true;
+            }
+            if i < c.len() {
+                for len in placements(i, p) {
+                    if backward[index(i + 1, p + len + 1)] {
+                        for value in node_values[p..p + len].iter_mut() {
+                            value.1 = true;
+                        }
+                        if p + len < size {
+                            node_values[p + len].0 = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Some(node_values)
+}
+
+/// The values each cell of a line can have, as found by find_colored_possibilities
+struct ColorPossibilities {
+    /// The distinct colors of the line's constraints
+    colors: Vec<Color>,
+    /// Whether each cell can be empty
+    empty: Vec<bool>,
+    /// Whether cell p can be filled with colors[k], at p * colors.len() + k
+    filled: Vec<bool>,
+}
+
+impl ColorPossibilities {
+    /// Get the only value the given cell can have, or None if it can have several
+    fn only_value(&self, index: usize) -> Option<Cell> {
+        let num_colors = self.colors.len();
+        let filled = &self.filled[index * num_colors..(index + 1) * num_colors];
+        let mut values = filled
+            .iter()
+            .zip(self.colors.iter())
+            .filter(|(can_fill, _)| **can_fill)
+            .map(|(_, color)| Cell::with_color(*color));
+        let empty = if self.empty[index] { Some(Cell::Empty) } else { None };
+        let mut values = empty.into_iter().chain(&mut values);
+        match (values.next(), values.next()) {
+            (Some(value), None) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Determine which values each cell in a line can have, for lines with colored constraints.
+/// Returns None if the line can not be solved.
+///
+/// Works like find_ranged_possibilities, except that only constraints of the same color need
+/// an empty cell between them, so a constraint does not always end with a gap.
+/// STATE[i, p] means that the first i constraints fit in cells [0, p), with every cell
+/// before p either empty or covered by one of them; the final state is STATE[len(c), size].
+fn find_colored_possibilities<T: LineRef>(line: &T) -> Option<ColorPossibilities> {
+    let c = line.get_constraints();
+    let size = line.size() as usize;
+    let mut colors: Vec<Color> = c.iter().map(|x| x.get_color()).collect();
+    colors.sort_unstable();
+    colors.dedup();
+    let num_colors = colors.len();
+    // the index into colors of each constraint's color
+    let color_of: Vec<usize> = c
+        .iter()
+        .map(|x| colors.binary_search(&x.get_color()).unwrap())
+        .collect();
+    let can_be_empty: Vec<bool> = (0..size)
+        .map(|p| !line.get_cell(p as Unit).is_filled())
+        .collect();
+    // run[p * num_colors + k] is the number of cells starting at p that can be colors[k]
+    let mut run = vec![0; (size + 1) * num_colors];
+    for p in (0..size).rev() {
+        for (k, &color) in colors.iter().enumerate() {
+            if line.can_be_color(p as Unit, color) {
+                run[p * num_colors + k] = run[(p + 1) * num_colors + k] + 1;
+            }
+        }
+    }
+    let num_states = size + 1;
+    let index = |i: usize, p: usize| i * num_states + p;
+    // Lengths of constraint i that can be placed starting at cell p
+    let lengths = |i: usize, p: usize| {
+        let min = (c[i].get_length() as usize).max(1);
+        let max = (c[i].get_max_length() as usize)
+            .min(size - p)
+            .min(run[p * num_colors + color_of[i]]);
+        min..=max
+    };
+    // The state after placing constraint i at cell p with the given length, if the cells
+    // after it allow it: the next constraint must be preceded by a gap if it has the same color
+    let target = |i: usize, p: usize, len: usize| {
+        let end = p + len;
+        let needs_gap = c.get(i + 1).is_some_and(|next| next.get_color() == c[i].get_color());
+        if !needs_gap {
+            Some(end)
+        } else if end < size && can_be_empty[end] {
+            Some(end + 1)
+        } else {
+            None
+        }
+    };
+    // Forward pass: which states can be reached from the start of the line
+    let mut forward = vec![false; (c.len() + 1) * num_states];
+    forward[index(0, 0)] = true;
+    for p in 0..size {
+        for i in 0..=c.len() {
+            if !forward[index(i, p)] {
+                continue;
+            }
+            if can_be_empty[p] {
+                forward[index(i, p + 1)] = true;
+            }
+            if i < c.len() {
+                for next in lengths(i, p).filter_map(|len| target(i, p, len)) {
+                    forward[index(i + 1, next)] = true;
+                }
+            }
+        }
+    }
+    if !forward[index(c.len(), size)] {
+        return None;
+    }
+    // Backward pass: which states can reach the end of the line
+    let mut backward = vec![false; (c.len() + 1) * num_states];
+    backward[index(c.len(), size)] = true;
+    for p in (0..size).rev() {
+        for i in 0..=c.len() {
+            let mut value = can_be_empty[p] && backward[index(i, p + 1)];
+            if !value && i < c.len() {
+                value = lengths(i, p)
+                    .filter_map(|len| target(i, p, len))
+                    .any(|next| backward[index(i + 1, next)]);
+            }
+            backward[index(i, p)] = value;
+        }
+    }
+    // Every transition between a reachable state and a state that reaches the end
+    // is part of some valid solution, so mark the cells it covers.
+    let mut empty = vec![false; size];
+    let mut filled = vec![false; size * num_colors];
+    for p in 0..size {
+        for i in 0..=c.len() {
+            if !forward[index(i, p)] {
+                continue;
+            }
+            if can_be_empty[p] && backward[index(i, p + 1)] {
+                empty[p] = true;
+            }
+            if i < c.len() {
+                for len in lengths(i, p) {
+                    let next = match target(i, p, len) {
+                        Some(next) if backward[index(i + 1, next)] => next,
+                        _ => continue,
+                    };
+                    for q in p..p + len {
+                        filled[q * num_colors + color_of[i]] = true;
+                    }
+                    if next > p + len {
+                        empty[p + len] = true;
+                    }
+                }
+            }
+        }
+    }
+    Some(ColorPossibilities {
+        colors,
+        empty,
+        filled,
+    })
+}
+
+/// Split a line that wraps around into ordinary lines, given as their constraints and cells,
+/// one for each way its ends can meet: the last cell empty, the first cell empty and the last
+/// filled, the ends in blocks of different colors, or its last constraint split into a block
+/// at the end and a block at the start, once for every split. Every way of filling the line
+/// is a placement of exactly one of them. Ways that contradict the line's known cells are left
+/// out, and a line that nothing can wrap around is given as a single, unchanged line.
+pub(crate) fn toroidal_cases<T: LineRef>(line: &T) -> Vec<(ConstraintList, Vec<Cell>)> {
+    let size = line.size() as usize;
+    let c = line.get_constraints();
+    let cells: Vec<Cell> = (0..size)
+        .map(|i| if line.is_masked(i as Unit) { Cell::Empty } else { line.get_cell(i as Unit) })
+        .collect();
+    let mut cases = Vec::new();
+    let mut add_case = |constraints: ConstraintList, first: Cell, last: Cell| {
+        let mut case = cells.clone();
+        for (i, value) in [(0, first), (size - 1, last)] {
+            if value != Cell::Unknown {
+                if case[i] != Cell::Unknown && case[i] != value {
+                    return;
+                }
+                case[i] = value;
+            }
+        }
+        cases.push((constraints, case));
+    };
+    let (first, last) = match (c.first(), c.last()) {
+        (Some(first), Some(last)) if size > 0 && get_line_total(c).is_none() => (first, last),
+        _ => {
+            // nothing can wrap around
+            cases.push((c.clone(), cells));
+            return cases;
+        }
+    };
+    let first_value = Cell::with_color(first.get_color());
+    let last_value = Cell::with_color(last.get_color());
+    add_case(c.clone(), Cell::Unknown, Cell::Empty);
+    add_case(c.clone(), Cell::Empty, last_value);
+    // filled ends only stay apart if they are in blocks of different colors,
+    // or are the two ends of a single block filling the whole line
+    if c.len() == 1 || first.get_color() != last.get_color() {
+        add_case(c.clone(), first_value, last_value);
+    }
+    let max_length = (last.get_max_length() as usize).min(size - 1);
+    for length in (last.get_length() as usize).max(2)..=max_length {
+        for head in 1..length {
+            let color = last.get_color();
+            let mut split = ConstraintList::new();
+            split.push(Constraint::new_colored(to_unit(head), color));
+            split.extend_from_slice(&c[..c.len() - 1]);
+            split.push(Constraint::new_colored(to_unit(length - head), color));
+            add_case(split, last_value, last_value);
+        }
+    }
+    cases
+}
+
+/// Find the cells of a line that wraps around, by solving each of its toroidal_cases.
+/// A cell is known if it has the same value in every case that can be solved.
+/// Returns None if no case can be solved.
+fn find_toroidal_cells<T: LineRef>(line: &T, scratch: &mut LineScratch) -> Option<Vec<Cell>> {
+    let mut found: Option<Vec<Cell>> = None;
+    for (constraints, cells) in toroidal_cases(line) {
+        let mut standalone = StandaloneLine::new(cells, &constraints);
+        if standalone.try_solve_line_complete(scratch).is_none() {
+            continue;
+        }
+        found = Some(match found.take() {
+            None => standalone.data,
+            Some(previous) => previous
+                .iter()
+                .zip(&standalone.data)
+                .map(|(&a, &b)| if a == b { a } else { Cell::Unknown })
+                .collect(),
+        });
+    }
+    found
+}
+
+/// Lines no longer than this may be solved by listing every arrangement of their constraints.
+const ENUMERATION_MAX_LENGTH: usize = 20;
+/// Lines with more free cells than this are never solved by listing every arrangement.
+const ENUMERATION_MAX_SLACK: usize = 5;
+
+/// Determine whether each cell in a line can be empty and/or filled,
+/// by listing every arrangement of the line's constraints that agrees with its known cells.
+/// Only used for short lines with exact constraints, where the number of
+/// arrangements is small enough that this beats building the node/edge graph.
+/// The result is written to scratch.node_values. Returns false if no arrangement fits.
+fn enumerate_possibilities<T: LineRef>(line: &T, scratch: &mut LineScratch) -> bool {
+    reset_node_values(&mut scratch.node_values, line.size() as usize, (false, false));
+    scratch.starts.clear();
+    scratch.masks.load(line);
+    scratch.bounds.load(line.get_constraints());
+    enumerate_placements(
+        line.get_constraints(),
+        &scratch.bounds,
+        &scratch.masks,
+        0,
+        &mut scratch.starts,
+        &mut scratch.node_values,
+    )
+}
+
+/// Place the constraints after those in `starts`, beginning at cell `pos`.
+/// Every complete arrangement is merged into `node_values`.
+/// Returns true if at least one complete arrangement was found.
+fn enumerate_placements(
+    c: &ConstraintList,
+    bounds: &ConstraintBounds,
+    masks: &LineMasks,
+    pos: usize,
+    starts: &mut Vec<usize>,
+    node_values: &mut [(bool, bool)],
+) -> bool {
+    let size = masks.size();
+    let i = starts.len();
+    if i == c.len() {
+        // Every remaining cell must be able to be empty
+        if masks.any_filled(pos, size) {
+            return false;
+        }
+        let mut next = 0;
+        for (start, constraint) in starts.iter().zip(c.iter()) {
+            let end = start + constraint.get_length() as usize;
+            for value in node_values[next..*start].iter_mut() {
+                value.0 = true;
+            }
+            for value in node_values[*start..end].iter_mut() {
+                value.1 = true;
+            }
+            next = end;
+        }
+        for value in node_values[next..].iter_mut() {
+            value.0 = true;
+        }
+        return true;
+    }
+    let length = bounds.length(i);
+    let (_left, right) = bounds.get(i);
+    let mut found = false;
+    for start in pos..=(size - right - length) {
+        if masks.can_fit_constraint(start, length) {
+            starts.push(start);
+            let next = start + length + 1;
+            found |= enumerate_placements(c, bounds, masks, next, starts, node_values);
+            starts.pop();
+        }
+        // A filled cell can not be skipped over
+        if masks.is_filled(start) {
+            break;
+        }
     }
+    found
+}
+
+fn get_node_range(i: usize, j: usize, bounds: &ConstraintBounds) -> (usize, usize) {
+    let value = bounds.length(i);
+    let (left, _right) = bounds.get(i);
+    (left + j, left + j + value)
 }
 
-fn determine_edge<T: LineRef>(i: usize, j: usize, k: usize, c: &ConstraintList, line: &T) -> bool {
+fn get_edge_range(
+    i: usize,
+    j: usize,
+    k: usize,
+    bounds: &ConstraintBounds,
+) -> Option<(usize, usize)> {
     if k <= j + 1 {
-        // if no separation, always true
-        // (verified by node truth value)
-        true
+        None
     } else {
-        let (left, _right) = get_constraint_bounds(&c, i);
-        let i0_value = c[i].get_length() as usize;
+        let (left, _right) = bounds.get(i);
+        let i0_value = bounds.length(i);
         // let i2 = i1 + 1;
         // from NODE[i,j] to NODE[i+1,k] where k >= j
         let pos = left + i0_value + j + 1;
         // check that gap between A[i,j] and A[i+1,k] is able to be all 0s
         let width = k - j - 1;
-        (pos..pos + width).all(|x| line.get_cell(x as Unit) != Cell::Filled)
+        Some((pos, pos + width))
     }
 }
 
+/// Find the last node NODE[i+1, k] that NODE[i, j] has a valid edge to.
+/// The edge to NODE[i+1, k] is valid if the gap between the two constraints can be all 0s.
+/// Each edge's gap contains the gap of the edge before it, so every edge from
+/// NODE[i+1, j] up to the first gap containing a filled cell is valid, and none after.
+fn last_edge(i: usize, j: usize, h: usize, bounds: &ConstraintBounds, masks: &LineMasks) -> usize {
+    let (left, _right) = bounds.get(i);
+    let i0_value = bounds.length(i);
+    // the gap to NODE[i+1, k] is [pos, pos + k - j - 1)
+    let pos = left + i0_value + j + 1;
+    let next_filled = masks.next_filled(pos.min(masks.size()));
+    (j + 1 + next_filled.saturating_sub(pos)).min(h - 1)
+}
+
+/// Why a line forces one of its cells to a value, as found by LineRef::explain_cell
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CellReason {
+    /// The constraint at the given index, of the given length, covers the cells in
+    /// [start, end) however it is placed, so they must be filled
+    Overlap {
+        constraint: usize,
+        length: Unit,
+        start: Unit,
+        end: Unit,
+    },
+    /// No constraint can be placed over the cell, so it must be empty
+    Unreachable,
+    /// Every valid placement of the constraints gives the cell this value, but no single
+    /// constraint does
+    Placements,
+}
+
 /// A reference on a board's row or column
 pub trait LineRef: fmt::Display + Sized {
     /// Get the length of this line
@@ -374,17 +1847,99 @@ pub trait LineRef: fmt::Display + Sized {
     fn get_cell(&self, index: Unit) -> Cell;
     /// Get this line's list of constraints
     fn get_constraints(&self) -> &ConstraintList;
+    /// Returns true if the given cell is out of play.
+    /// Masked cells are always empty, so they separate constraints like an empty cell.
+    fn is_masked(&self, _index: Unit) -> bool {
+        false
+    }
+    /// Returns true if this line has to be solved with colors in mind, because some of its
+    /// constraints or cells have a color other than the default
+    fn is_colored(&self) -> bool {
+        self.get_constraints().iter().any(|x| x.get_color() != 0)
+            || (0..self.size()).any(|i| matches!(self.get_cell(i), Cell::Colored(_)))
+    }
+    /// Returns true if this line wraps around, so a block may run off its end and continue at
+    /// its start. Blocks are listed by where they start, so a block that wraps around is last.
+    fn is_toroidal(&self) -> bool {
+        false
+    }
+    /// Returns true if the given cell may be filled with the given color
+    fn can_be_color(&self, index: Unit, color: Color) -> bool {
+        !self.is_masked(index)
+            && match self.get_cell(index) {
+                Cell::Unknown => true,
+                cell => cell.get_color() == Some(color),
+            }
+    }
     /// Returns true if all cells are filled
     fn is_completed(&self) -> bool {
         (0..self.size())
             .map(|i| self.get_cell(i))
             .all(|v| v != Cell::Unknown)
     }
+    /// Returns true if this line is completed and its blocks are exactly the ones its
+    /// constraints ask for
+    fn matches_constraints(&self) -> bool {
+        let constraints = self.get_constraints();
+        if get_line_total(constraints).is_some() {
+            return self.is_completed() && find_total_cells(self).is_some();
+        }
+        if self.is_toroidal() {
+            let blocks = match self.generate_new_constraints() {
+                Some(blocks) => blocks,
+                None => return false,
+            };
+            return blocks.len() == constraints.len()
+                && blocks.iter().zip(constraints).all(|(block, constraint)| {
+                    block.get_color() == constraint.get_color()
+                        && block.get_length() >= constraint.get_length().max(1)
+                        && block.get_length() <= constraint.get_max_length()
+                });
+        }
+        let mut matched = 0;
+        let mut i = 0;
+        while i < self.size() {
+            let color = match self.get_cell(i) {
+                Cell::Unknown => return false,
+                cell => cell.get_color(),
+            };
+            let color = match color {
+                Some(color) => color,
+                None => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let start = i;
+            while i < self.size() && self.get_cell(i).get_color() == Some(color) {
+                i += 1;
+            }
+            let length = i - start;
+            match constraints.get(matched) {
+                Some(constraint)
+                    if constraint.get_color() == color
+                        && length >= constraint.get_length().max(1)
+                        && length <= constraint.get_max_length() =>
+                {
+                    matched += 1
+                }
+                _ => return false,
+            }
+        }
+        matched == constraints.len()
+    }
+    /// Get the number of cells this line's constraints can shift by: its length minus the
+    /// fewest cells they fit in, or None if they do not fit at all
+    fn slack(&self) -> Option<usize> {
+        let constraints = self.get_constraints();
+        (self.size() as usize).checked_sub(get_min_line_length(constraints, self.is_toroidal()))
+    }
     /// Generate a StandaloneLine clone based on this Line
-    fn create_standalone_line(&self) -> StandaloneLine {
+    fn create_standalone_line(&self) -> StandaloneLine<'_> {
         StandaloneLine {
             constraints: self.get_constraints(),
             data: (0..self.size()).map(|i| self.get_cell(i)).collect(),
+            toroidal: self.is_toroidal(),
         }
     }
     /// Generate a list of constraints based on this Line
@@ -393,51 +1948,130 @@ pub trait LineRef: fmt::Display + Sized {
             None
         } else {
             let mut n = 0;
+            let mut color = 0;
             let mut ret = Vec::new();
             for i in 0..self.size() {
                 let cell = self.get_cell(i);
-                if cell == Cell::Filled {
-                    n += 1
-                } else if n > 0 {
-                    ret.push(Constraint::new(n));
+                if n > 0 && cell.get_color() != Some(color) {
+                    // the current block ends here
+                    ret.push(Constraint::new_colored(n, color));
                     n = 0
                 }
+                if let Some(c) = cell.get_color() {
+                    color = c;
+                    n += 1
+                }
             }
             if n > 0 {
-                ret.push(Constraint::new(n));
+                ret.push(Constraint::new_colored(n, color));
+            }
+            // a block running off the end continues at the start, as part of the last block
+            let first = self.get_cell(0).get_color();
+            let last = self.get_cell(self.size() - 1).get_color();
+            if self.is_toroidal() && ret.len() > 1 && first.is_some() && first == last {
+                let start = ret.remove(0);
+                let end = ret.pop().unwrap();
+                let length = start.get_length() + end.get_length();
+                ret.push(Constraint::new_colored(length, end.get_color()));
             }
             Some(ret)
         }
     }
     /// Determine if a string of 1's with 0's on either side can be fit in the given position
     fn can_fit_constraint(&self, pos: Unit, len: Unit) -> bool {
-        #[allow(unused_comparisons)]
-        if pos < 0 || pos + len > self.size() {
+        // pos + len may not fit in a Unit, so compute the end as a usize
+        let end = pos as usize + len as usize;
+        if end > self.size() as usize {
             panic!("OOB???? {}:{} [{}]", pos, len, self.size())
         }
+        let end = end as Unit;
         // Check left side
-        if pos > 0 {
-            if self.get_cell(pos - 1) == Cell::Filled {
-                return false;
-            }
+        if pos > 0 && self.get_cell(pos - 1).is_filled() {
+            return false;
         }
         // Check right side
-        if pos + len < self.size() {
-            if self.get_cell(pos + len) == Cell::Filled {
-                return false;
-            }
+        if end < self.size() && self.get_cell(end).is_filled() {
+            return false;
         }
         // check inner cells
-        for i in pos..(pos + len) {
-            if self.get_cell(i) == Cell::Empty {
+        for i in pos..end {
+            if self.get_cell(i) == Cell::Empty || self.is_masked(i) {
                 return false;
             }
         }
-        return true;
+        true
+    }
+    /// Build an automaton for this line's constraints, for try_solve_line_automaton
+    fn make_automaton(&self) -> LineAutomaton {
+        LineAutomaton::new(self.get_constraints(), self.size())
+    }
+    /// Explain why the given Unknown cell must have a value, given the rest of this line.
+    /// Returns the value and the reason, or None if the cell is already known, or if this line
+    /// does not force its value (or can not be solved at all).
+    fn explain_cell(&self, index: Unit) -> Option<(Cell, CellReason)> {
+        if self.get_cell(index) != Cell::Unknown {
+            return None;
+        }
+        if get_line_total(self.get_constraints()).is_some() {
+            let value = find_total_cells(self)?[index as usize];
+            return if value == Cell::Unknown {
+                None
+            } else {
+                Some((value, CellReason::Placements))
+            };
+        }
+        if self.is_toroidal() {
+            let value = find_toroidal_cells(self, &mut LineScratch::new())?[index as usize];
+            return if value == Cell::Unknown {
+                None
+            } else {
+                Some((value, CellReason::Placements))
+            };
+        }
+        if self.is_colored() {
+            let value = find_colored_possibilities(self)?.only_value(index as usize)?;
+            return Some((value, CellReason::Placements));
+        }
+        // solve a copy of this line exactly, to find out whether the cell is forced
+        let cells = (0..self.size())
+            .map(|i| if self.is_masked(i) { Cell::Empty } else { self.get_cell(i) })
+            .collect();
+        let mut line = StandaloneLine::new(cells, self.get_constraints());
+        line.try_solve_line_automaton(&self.make_automaton(), &mut LineScratch::new())?;
+        let value = line.get_cell(index);
+        if value == Cell::Unknown {
+            return None;
+        }
+        // overlaps are only found for exact, single-color constraints
+        let c = self.get_constraints();
+        if c.iter().any(|x| !x.is_exact() || x.get_color() != 0) {
+            return Some((value, CellReason::Placements));
+        }
+        let lengths: Vec<usize> = c.iter().map(|x| x.get_length() as usize).collect();
+        let (left, right) = constraint_extents(self, &lengths)?;
+        let i = index as usize;
+        let reason = if value == Cell::Empty {
+            let reachable = (0..lengths.len()).any(|k| left[k] <= i && i < right[k] + lengths[k]);
+            if reachable {
+                CellReason::Placements
+            } else {
+                CellReason::Unreachable
+            }
+        } else {
+            (0..lengths.len())
+                .find(|&k| right[k] <= i && i < left[k] + lengths[k])
+                .map_or(CellReason::Placements, |k| CellReason::Overlap {
+                    constraint: k,
+                    length: lengths[k] as Unit,
+                    start: right[k] as Unit,
+                    end: (left[k] + lengths[k]) as Unit,
+                })
+        };
+        Some((value, reason))
     }
     fn make_empty_node_list<T: Default + Clone>(&self) -> util::NodeList<T> {
         let c = self.get_constraints();
-        if c.len() == 0 {
+        if c.is_empty() {
             util::NodeList::<T>::new(0, 0)
         } else {
             let c_sum: usize = c.iter().map(|x| x.get_length() as usize).sum();
@@ -448,43 +2082,60 @@ pub trait LineRef: fmt::Display + Sized {
         }
     }
     /// Determine whether this line is solvable given its constraints
-    fn is_solvable(&self, nodelist: &mut util::NodeList<bool>) -> bool {
+    fn is_solvable(&self, scratch: &mut LineScratch) -> bool {
         let c = self.get_constraints();
         // special case: no constraints
-        if c.len() == 0 {
-            return (0..self.size()).all(|i| self.get_cell(i) != Cell::Filled);
+        if c.is_empty() {
+            return (0..self.size()).all(|i| !self.get_cell(i).is_filled());
+        }
+        // special case: a total number of filled cells, anywhere in the line
+        if get_line_total(c).is_some() {
+            return find_total_cells(self).is_some();
         }
-        let c_sum: usize = c.iter().map(|x| x.get_length() as usize).sum();
-        let extra_space = self.size() as usize + 1 - c_sum - c.len();
+        // special case: lines that wrap around
+        if self.is_toroidal() {
+            return find_toroidal_cells(self, scratch).is_some();
+        }
+        // special case: colored constraints
+        if self.is_colored() {
+            return find_colored_possibilities(self).is_some();
+        }
+        // special case: constraints without exact lengths
+        if c.iter().any(|x| !x.is_exact()) {
+            return find_ranged_possibilities(self).is_some();
+        }
+        let LineScratch {
+            nodes: nodelist,
+            masks,
+            bounds,
+            ..
+        } = scratch;
+        bounds.load(c);
+        let extra_space = self.size() as usize + 1 - bounds.total() - c.len();
         let num_nodes_width = c.len();
         let num_nodes_height = extra_space + 1;
+        masks.load(self);
+        nodelist.reset(num_nodes_width, num_nodes_height);
         // For each node NODE[i, j]:
         // [i] is the constraint index
         // [j] is the permutation
         // Determine viability of each node
         for i in 0..num_nodes_width {
-            let (left, _right) = get_constraint_bounds(&c, i);
-            let value = c[i].get_length();
+            let (left, _right) = bounds.get(i);
+            let value = bounds.length(i);
             for j in 0..num_nodes_height {
-                let mut nodevalue = self.can_fit_constraint((left + j) as Unit, value);
+                let mut nodevalue = masks.can_fit_constraint(left + j, value);
                 // If first node, check that everything to left can be 0
-                if nodevalue && i == 0 && j > 1 {
-                    for q in 0..(j - 1) {
-                        if self.get_cell(q as Unit) == Cell::Filled {
-                            nodevalue = false;
-                            break;
-                        }
-                    }
+                if nodevalue && i == 0 && j > 1 && masks.any_filled(0, j - 1) {
+                    nodevalue = false;
                 }
                 // If last node, check that everything to right can be 0
-                if nodevalue && i == num_nodes_width - 1 && j + 2 < num_nodes_height {
-                    for q in (self.size() as usize - num_nodes_height + j + 2)..self.size() as usize
-                    {
-                        if self.get_cell(q as Unit) == Cell::Filled {
-                            nodevalue = false;
-                            break;
-                        }
-                    }
+                if nodevalue
+                    && i == num_nodes_width - 1
+                    && j + 2 < num_nodes_height
+                    && masks.any_filled(masks.size() - num_nodes_height + j + 2, masks.size())
+                {
+                    nodevalue = false;
                 }
                 // set value
                 nodelist.set(i, j, nodevalue);
@@ -498,20 +2149,9 @@ pub trait LineRef: fmt::Display + Sized {
             for j in 0..num_nodes_height {
                 let pvalue = *nodelist.get(i, j);
                 if pvalue {
-                    let mut edgevalue = false;
-                    for k in j..num_nodes_height {
-                        if !*nodelist.get(i + 1, k) {
-                            continue;
-                        }
-                        // determine viability of edge
-                        // For each edge list EDGE[i][j, k]:
-                        // Represents edge from NODE[i, j] to NODE[i+1, k] where k >= j
-                        let edgev = determine_edge(i, j, k, &c, self);
-                        if edgev {
-                            edgevalue = true;
-                            break;
-                        }
-                    }
+                    // Only edges up to the last valid one from NODE[i, j] are viable
+                    let last = last_edge(i, j, num_nodes_height, bounds, masks);
+                    let edgevalue = (j..=last).any(|k| *nodelist.get(i + 1, k));
                     nodelist.set(i, j, edgevalue);
                 } else {
                     nodelist.set(i, j, false);
@@ -523,7 +2163,7 @@ pub trait LineRef: fmt::Display + Sized {
 
     fn do_fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for value in self.get_constraints() {
-            write!(f, "{} ", value.get_length())?;
+            write!(f, "{} ", value)?;
         }
         write!(f, "| ")?;
         for i in 0..self.size() {
@@ -534,56 +2174,127 @@ pub trait LineRef: fmt::Display + Sized {
 }
 
 /// A full nonogram board state.
-#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Board {
     width: Unit,
     height: Unit,
     cells: Vec<Cell>,
     row_constraints: Vec<ConstraintList>,
     col_constraints: Vec<ConstraintList>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    palette: Palette,
+    /// Cells which are permanently out of play.
+    /// Either empty if no cells are masked, or one entry per cell.
+    #[cfg_attr(feature = "serde", serde(default))]
+    mask: Vec<bool>,
+    /// Whether every line wraps around the edges of the board
+    #[cfg_attr(feature = "serde", serde(default))]
+    toroidal: bool,
+    /// Only ever accessed through &mut self; the Mutex just lets boards be shared
+    /// between threads
+    #[cfg_attr(feature = "serde", serde(skip))]
+    observers: Vec<(ObserverId, Mutex<Box<dyn CellObserver>>)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    next_observer_id: usize,
+    /// Previous values of cells modified since the oldest checkpoint, as (index, value)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    journal: Vec<(usize, Cell)>,
+    /// Length of the journal when each checkpoint was made
+    #[cfg_attr(feature = "serde", serde(skip))]
+    checkpoints: Vec<usize>,
+    /// Changes undone by rollback, as (index, value to restore)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    redo_stack: Vec<Vec<(usize, Cell)>>,
+}
+
+// Observers belong to the board they were added to, so clones start without any
+impl Clone for Board {
+    fn clone(&self) -> Board {
+        Board {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.clone(),
+            row_constraints: self.row_constraints.clone(),
+            col_constraints: self.col_constraints.clone(),
+            palette: self.palette.clone(),
+            mask: self.mask.clone(),
+            toroidal: self.toroidal,
+            observers: Vec::new(),
+            next_observer_id: 0,
+            journal: self.journal.clone(),
+            checkpoints: self.checkpoints.clone(),
+            redo_stack: self.redo_stack.clone(),
+        }
+    }
 }
 
 impl Board {
-    /// Construct an empty board
-    pub fn new_empty() -> Board {
+    /// Construct a board from its cells and constraints,
+    /// using the default palette and no masked cells
+    fn from_parts(
+        width: Unit,
+        height: Unit,
+        cells: Vec<Cell>,
+        row_constraints: Vec<ConstraintList>,
+        col_constraints: Vec<ConstraintList>,
+    ) -> Board {
         Board {
-            width: 0,
-            height: 0,
-            cells: Vec::new(),
-            row_constraints: Vec::new(),
-            col_constraints: Vec::new(),
+            width,
+            height,
+            cells,
+            row_constraints,
+            col_constraints,
+            palette: Palette::new(),
+            mask: Vec::new(),
+            toroidal: false,
+            observers: Vec::new(),
+            next_observer_id: 0,
+            journal: Vec::new(),
+            checkpoints: Vec::new(),
+            redo_stack: Vec::new(),
         }
     }
 
+    /// Construct an empty board
+    pub fn new_empty() -> Board {
+        Board::from_parts(0, 0, Vec::new(), Vec::new(), Vec::new())
+    }
+
     /// Construct a board with the given width and height,
     /// with all cells initialized to the given Cell value.
     pub fn new_filled(width: Unit, height: Unit, value: Cell) -> Board {
-        Board {
+        Board::from_parts(
             width,
             height,
-            cells: vec![value; width as usize * height as usize],
-            row_constraints: create_constraint_list(height as usize),
-            col_constraints: create_constraint_list(width as usize),
-        }
+            vec![value.normalized(); get_cell_count(width, height)],
+            create_constraint_list(height as usize),
+            create_constraint_list(width as usize),
+        )
     }
 
-    /// Read a puzzle file
-    pub fn read_csv_puzzle<R: io::BufRead>(handle: R) -> Board {
+    /// Read a puzzle file, as written by write_csv_puzzle: a line of clues for each column,
+    /// =COLUMNS, a line of clues for each row, then =ROWS.
+    pub fn read_csv_puzzle<R: io::BufRead>(handle: R) -> Result<Board, ParseError> {
         let mut cols = Vec::<ConstraintList>::new();
         let mut rows = Vec::<ConstraintList>::new();
         let mut is_cols = true;
-        let lines = handle.lines();
-        for line in lines {
-            let line = line.unwrap();
-            if line == "=COLUMNS" {
+        let mut finished = false;
+        for (i, line) in handle.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line == "=COLUMNS" && is_cols {
                 is_cols = false;
-            } else if line == "=ROWS" {
+            } else if line == "=ROWS" && !is_cols {
+                finished = true;
                 break;
             } else {
                 let mut clist = ConstraintList::new();
-                if line != "" {
-                    for field in line.split(",") {
-                        clist.push(Constraint::new(field.parse::<Unit>().unwrap()));
+                if !line.is_empty() {
+                    for (j, field) in line.split(',').enumerate() {
+                        let constraint = parse_constraint(field).ok_or_else(|| {
+                            ParseError::field(i + 1, j + 1, format!("invalid clue '{}'", field))
+                        })?;
+                        clist.push(constraint);
                     }
                 }
                 if is_cols {
@@ -593,211 +2304,1345 @@ impl Board {
                 }
             }
         }
-        Board {
-            width: cols.len() as Unit,
-            height: rows.len() as Unit,
-            cells: vec![Cell::Unknown; cols.len() * rows.len()],
-            col_constraints: cols,
-            row_constraints: rows,
+        if !finished {
+            return Err(ParseError::syntax(1, "missing =COLUMNS or =ROWS"));
         }
+        let too_big = |_| ParseError::Unsupported("puzzle is too large".to_string());
+        let width = Unit::try_from(cols.len()).map_err(too_big)?;
+        let height = Unit::try_from(rows.len()).map_err(too_big)?;
+        Ok(Board::from_parts(
+            width,
+            height,
+            vec![Cell::Unknown; get_cell_count(width, height)],
+            rows,
+            cols,
+        ))
     }
 
-    /// Read a solution file
-    pub fn read_csv_solution<R: io::Read>(handle: R) -> Board {
+    /// Write this board's constraints as a puzzle file.
+    /// Constraints are written in the same format that read_csv_puzzle reads.
+    pub fn write_csv_puzzle<W: io::Write>(&self, mut handle: W) -> io::Result<()> {
+        write_csv_constraints(&mut handle, &self.col_constraints)?;
+        writeln!(handle, "=COLUMNS")?;
+        write_csv_constraints(&mut handle, &self.row_constraints)?;
+        writeln!(handle, "=ROWS")
+    }
+
+    /// Read a solution file.
+    /// Records are read one at a time into a single reused buffer and parsed in place,
+    /// and each row's length is checked as soon as it is read, so large solutions load
+    /// without building any intermediate rows.
+    /// Blank lines and lines starting with '#' are ignored, and whitespace around values is
+    /// allowed.
+    pub fn read_csv_solution<R: io::Read>(handle: R) -> Result<Board, ParseError> {
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(false)
+            .flexible(true)
+            .comment(Some(b'#'))
+            .trim(csv::Trim::All)
             .from_reader(handle);
-        let mut records = reader.records();
-        if let Some(result) = records.next() {
-            let record = result.expect("CSV record with equal-length rows");
-            let width = record.len() as Unit;
-            let mut cells = Vec::new();
-            for field in record.iter() {
-                let ivalue = field.parse::<i64>();
-                cells.push(Cell::from_i64(ivalue.unwrap()).unwrap())
-            }
-            let mut height = 1;
-            for result in reader.records() {
-                let record = result.expect("CSV record with equal-length rows");
-                for field in record.iter() {
-                    let ivalue = field.parse::<i64>();
-                    cells.push(Cell::from_i64(ivalue.unwrap()).unwrap())
-                }
-                height += 1;
+        let mut record = csv::ByteRecord::new();
+        let mut cells = Vec::new();
+        let mut width = 0;
+        let mut height = 0usize;
+        while reader
+            .read_byte_record(&mut record)
+            .map_err(csv_parse_error)?
+        {
+            let line = record.position().map_or(height + 1, |pos| pos.line() as usize);
+            if record.len() == 1 && record[0].is_empty() {
+                continue;
             }
-            if width as usize * height as usize != cells.len() {
-                panic!("Size mis-match");
+            if height == 0 {
+                width = record.len();
+            } else if record.len() != width {
+                return Err(ParseError::field(
+                    line,
+                    width.min(record.len()) + 1,
+                    format!("expected {} cells, found {}", width, record.len()),
+                ));
             }
-            let mut board = Board {
-                width,
-                height,
-                cells,
-                row_constraints: create_constraint_list(height as usize),
-                col_constraints: create_constraint_list(width as usize),
-            };
-            board.generate_new_constraints();
-            board
-        } else {
-            println!("Loaded empty :(");
-            Board::new_empty()
+            for (i, field) in record.iter().enumerate() {
+                let cell = parse_cell_field(field).ok_or_else(|| {
+                    let value = String::from_utf8_lossy(field);
+                    ParseError::field(line, i + 1, format!("invalid cell value '{}'", value))
+                })?;
+                cells.push(cell);
+            }
+            height += 1;
+        }
+        if height == 0 {
+            return Err(ParseError::syntax(1, "the solution has no cells"));
+        }
+        let width = to_unit(width);
+        let height = to_unit(height);
+        let mut board = Board::from_parts(
+            width,
+            height,
+            cells,
+            create_constraint_list(height as usize),
+            create_constraint_list(width as usize),
+        );
+        board.generate_new_constraints();
+        Ok(board)
+    }
+
+    /// Write this board's cells as a solution file.
+    /// Cells are written in the same format that read_csv_solution reads.
+    pub fn write_csv_solution<W: io::Write>(&self, handle: W) -> io::Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(handle);
+        for row in 0..self.height {
+            let record = (0..self.width).map(|col| self.get_cell(col, row).to_i64().to_string());
+            writer.write_record(record)?;
+        }
+        writer.flush()
+    }
+
+    /// Get this board's width
+    pub fn get_width(&self) -> Unit {
+        self.width
+    }
+
+    /// Get this board's height
+    pub fn get_height(&self) -> Unit {
+        self.height
+    }
+
+    /// Get this board's size (width, height)
+    pub fn get_size(&self) -> (Unit, Unit) {
+        (self.width, self.height)
+    }
+
+    /// Get the number of cells
+    pub fn get_num_cells(&self) -> usize {
+        get_cell_count(self.width, self.height)
+    }
+
+    /// Get roughly how many bytes this board takes up, counting its cells, constraints and
+    /// mask but not its palette or undo history
+    pub fn heap_size(&self) -> usize {
+        use std::mem::size_of;
+        let constraints: usize = self
+            .row_constraints
+            .iter()
+            .chain(self.col_constraints.iter())
+            .map(|list| size_of::<ConstraintList>() + list.len() * size_of::<Constraint>())
+            .sum();
+        size_of::<Board>() + self.cells.len() * size_of::<Cell>() + constraints + self.mask.len()
+    }
+
+    /// Returns true if this board has exactly one cell (and at most one mask entry) per position.
+    /// This always holds unless the board was deserialized from malformed data.
+    #[cfg(feature = "serde")]
+    pub(crate) fn has_valid_storage(&self) -> bool {
+        let count = self.get_num_cells();
+        self.cells.len() == count && (self.mask.is_empty() || self.mask.len() == count)
+    }
+
+    /// Convert a column/row pair to an index
+    pub fn get_index(&self, col: Unit, row: Unit) -> usize {
+        (col as usize) + (row as usize) * (self.width as usize)
+    }
+
+    /// Convert index to column/row pair
+    pub fn get_coordinate(&self, index: usize) -> (Unit, Unit) {
+        (
+            (index % (self.width as usize)) as Unit,
+            (index / (self.width as usize)) as Unit,
+        )
+    }
+
+    /// Get the cell at the given column/row
+    pub fn get_cell(&self, col: Unit, row: Unit) -> Cell {
+        self.cells[self.get_index(col, row)]
+    }
+
+    /// Set the cell at the given column/row
+    pub fn set_cell(&mut self, col: Unit, row: Unit, value: Cell) {
+        let index = self.get_index(col, row);
+        self.write_cell(index, value);
+    }
+
+    /// Write a cell's value, recording it for rollback and notifying any observers
+    fn write_cell(&mut self, index: usize, value: Cell) {
+        if !self.checkpoints.is_empty() {
+            self.journal.push((index, self.cells[index]));
+            self.redo_stack.clear();
+        }
+        self.restore_cell(index, value);
+    }
+
+    /// Write a cell's value without recording it, notifying any observers
+    fn restore_cell(&mut self, index: usize, value: Cell) {
+        let before = self.cells[index];
+        let value = value.normalized();
+        self.cells[index] = value;
+        if !self.observers.is_empty() {
+            let (col, row) = self.get_coordinate(index);
+            let change = CellDiff {
+                col,
+                row,
+                before,
+                after: value,
+            };
+            for (_, observer) in self.observers.iter_mut() {
+                // a poisoned observer panicked on an earlier change, which it has
+                // already reported; keep notifying it about later ones
+                let observer = observer.get_mut().unwrap_or_else(PoisonError::into_inner);
+                observer.cell_changed(change);
+            }
+        }
+    }
+
+    /// Start recording cell changes so that they can be reverted with rollback.
+    /// Checkpoints nest; rollback and commit always apply to the most recent one.
+    /// This is much cheaper than cloning the board when only a few cells change.
+    pub fn checkpoint(&mut self) {
+        self.checkpoints.push(self.journal.len());
+        self.redo_stack.clear();
+    }
+
+    /// Revert every cell change made since the most recent checkpoint, and remove it.
+    /// The reverted changes can be reapplied with redo.
+    /// Returns false if there is no checkpoint.
+    pub fn rollback(&mut self) -> bool {
+        let start = match self.checkpoints.pop() {
+            Some(start) => start,
+            None => return false,
+        };
+        let mut undone = Vec::with_capacity(self.journal.len() - start);
+        while self.journal.len() > start {
+            let (index, value) = self.journal.pop().unwrap();
+            undone.push((index, self.cells[index]));
+            self.restore_cell(index, value);
+        }
+        if self.checkpoints.is_empty() {
+            self.journal.clear();
+        }
+        self.redo_stack.push(undone);
+        true
+    }
+
+    /// Reapply the changes reverted by the last rollback, restoring its checkpoint.
+    /// Returns false if there is nothing to redo.
+    /// Any cell change made after a rollback clears the redo history.
+    pub fn redo(&mut self) -> bool {
+        let undone = match self.redo_stack.pop() {
+            Some(undone) => undone,
+            None => return false,
+        };
+        self.checkpoints.push(self.journal.len());
+        for (index, value) in undone.into_iter().rev() {
+            self.journal.push((index, self.cells[index]));
+            self.restore_cell(index, value);
+        }
+        true
+    }
+
+    /// Remove the most recent checkpoint, keeping every change made since.
+    /// If there is an older checkpoint, those changes are now reverted by its rollback.
+    /// Returns false if there is no checkpoint.
+    pub fn commit(&mut self) -> bool {
+        if self.checkpoints.pop().is_none() {
+            return false;
+        }
+        if self.checkpoints.is_empty() {
+            self.journal.clear();
+        }
+        true
+    }
+
+    /// Get the number of checkpoints that have not been rolled back or committed
+    pub fn get_num_checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Register an observer that is notified every time a cell is set.
+    /// Observers are not copied to clones of this board, so they do not see
+    /// changes made to boards cloned during branching.
+    /// Returns an id that can be passed to remove_observer.
+    pub fn add_observer<O: CellObserver + 'static>(&mut self, observer: O) -> ObserverId {
+        let id = ObserverId(self.next_observer_id);
+        self.next_observer_id += 1;
+        self.observers.push((id, Mutex::new(Box::new(observer))));
+        id
+    }
+
+    /// Register an observer that sends every cell change through a channel
+    pub fn add_channel_observer(&mut self, sender: mpsc::Sender<CellDiff>) -> ObserverId {
+        self.add_observer(move |change: CellDiff| {
+            // the receiver hanging up just means nobody is listening anymore
+            let _ = sender.send(change);
+        })
+    }
+
+    /// Remove a previously registered observer
+    pub fn remove_observer(&mut self, id: ObserverId) {
+        self.observers.retain(|(x, _)| *x != id);
+    }
+
+    /// Returns true if the cell at the given column/row is out of play
+    pub fn is_masked(&self, col: Unit, row: Unit) -> bool {
+        self.is_masked_index(self.get_index(col, row))
+    }
+
+    /// Returns true if the cell at the given index is out of play
+    pub fn is_masked_index(&self, index: usize) -> bool {
+        self.mask.get(index).copied().unwrap_or(false)
+    }
+
+    /// Returns true if any of this board's cells are out of play
+    pub fn has_mask(&self) -> bool {
+        self.mask.iter().any(|x| *x)
+    }
+
+    /// Take a cell out of play, or put it back into play.
+    /// Masked cells are always empty, and act as separators for the lines that cross them.
+    /// Cells that are put back into play become undetermined.
+    pub fn set_masked(&mut self, col: Unit, row: Unit, masked: bool) {
+        let index = self.get_index(col, row);
+        if self.mask.is_empty() {
+            if !masked {
+                return;
+            }
+            self.mask = vec![false; self.get_num_cells()];
+        }
+        self.mask[index] = masked;
+        self.write_cell(index, if masked { Cell::Empty } else { Cell::Unknown });
+    }
+
+    /// Get the cell at the given index
+    pub fn get_cell_index(&self, index: usize) -> Cell {
+        self.cells[index]
+    }
+
+    /// Set the cell at the gien index
+    pub fn set_cell_index(&mut self, index: usize, value: Cell) {
+        self.write_cell(index, value);
+    }
+
+    /// Get this board's color palette
+    pub fn get_palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Replace this board's color palette
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Returns true if this board's lines wrap around its edges, so that a block may run off
+    /// one edge and continue at the opposite edge
+    pub fn is_toroidal(&self) -> bool {
+        self.toroidal
+    }
+
+    /// Make this board's lines wrap around its edges, or stop them wrapping
+    pub fn set_toroidal(&mut self, toroidal: bool) {
+        self.toroidal = toroidal;
+    }
+
+    /// Returns true if this board uses colors other than the default fill color
+    pub fn is_colored(&self) -> bool {
+        !self.palette.is_monochrome()
+    }
+
+    /// Returns true if the cell at the given column/row may be filled with the given color:
+    /// it is in play, Unknown or already that color, and both its row and its column have a
+    /// constraint of that color
+    pub fn can_be_color(&self, col: Unit, row: Unit, color: Color) -> bool {
+        let has_color = |ls: &ConstraintList| ls.iter().any(|x| x.get_color() == color);
+        !self.is_masked(col, row)
+            && match self.get_cell(col, row) {
+                Cell::Unknown => true,
+                cell => cell.get_color() == Some(color),
+            }
+            && has_color(self.get_row_constraints(row))
+            && has_color(self.get_col_constraints(col))
+    }
+
+    /// Get the filled values that the cell at the given index may be guessed to have: every
+    /// color that both its row and its column have a constraint of.
+    /// Boards that are not colored only ever give Cell::Filled.
+    pub fn fill_values(&self, index: usize) -> Vec<Cell> {
+        if !self.is_colored() {
+            return vec![Cell::Filled];
+        }
+        let (col, row) = self.get_coordinate(index);
+        let has_color = |ls: &ConstraintList, color| ls.iter().any(|x| x.get_color() == color);
+        (0..self.palette.num_colors())
+            .map(|color| color as Color)
+            .filter(|&color| {
+                has_color(self.get_row_constraints(row), color)
+                    && has_color(self.get_col_constraints(col), color)
+            })
+            .map(Cell::with_color)
+            .collect()
+    }
+
+    /// Get the constraints for the given row
+    pub fn get_row_constraints(&self, row: Unit) -> &ConstraintList {
+        &self.row_constraints[row as usize]
+    }
+
+    /// Get the constraints for the given column
+    pub fn get_col_constraints(&self, col: Unit) -> &ConstraintList {
+        &self.col_constraints[col as usize]
+    }
+
+    /// Get a mutable reference to a row from this board
+    pub fn get_row_mut(&mut self, row: Unit) -> BoardRowMut<'_> {
+        BoardRowMut {
+            board: self,
+            row,
+        }
+    }
+
+    /// Get a mutable reference to a column from this board
+    pub fn get_col_mut(&mut self, col: Unit) -> BoardColMut<'_> {
+        BoardColMut {
+            board: self,
+            col,
+        }
+    }
+
+    /// Get a reference to a row from this board
+    pub fn get_row_ref(&self, row: Unit) -> BoardRowRef<'_> {
+        BoardRowRef {
+            board: self,
+            row,
+        }
+    }
+
+    /// Get a reference to a column from this board
+    pub fn get_col_ref(&self, col: Unit) -> BoardColRef<'_> {
+        BoardColRef {
+            board: self,
+            col,
+        }
+    }
+
+    /// Iterate over references to each of this board's rows, from top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = BoardRowRef<'_>> + '_ {
+        (0..self.height).map(move |row| self.get_row_ref(row))
+    }
+
+    /// Iterate over references to each of this board's columns, from left to right
+    pub fn cols(&self) -> impl Iterator<Item = BoardColRef<'_>> + '_ {
+        (0..self.width).map(move |col| self.get_col_ref(col))
+    }
+
+    /// Iterate over mutable references to each of this board's rows
+    pub fn rows_mut(&mut self) -> RowsMut<'_> {
+        RowsMut {
+            board: self,
+            next: 0,
+        }
+    }
+
+    /// Iterate over mutable references to each of this board's columns
+    pub fn cols_mut(&mut self) -> ColsMut<'_> {
+        ColsMut {
+            board: self,
+            next: 0,
+        }
+    }
+
+    /// Get the widest printed row constraint in all of this board's row constraints
+    fn get_row_constraint_print_width(&self) -> usize {
+        self.row_constraints
+            .iter()
+            .flatten()
+            .map(|x| x.get_print_width())
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Get the widest printed column constraint in all of this board's column constraints
+    fn get_col_constraint_print_width(&self) -> usize {
+        self.col_constraints
+            .iter()
+            .flatten()
+            .map(|x| x.get_print_width())
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Get the maximum number of constraints on any row
+    pub(crate) fn get_max_row_constraints(&self) -> usize {
+        self.row_constraints
+            .iter()
+            .map(|x| x.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get the maximum number of constraints on any column
+    pub(crate) fn get_max_col_constraints(&self) -> usize {
+        self.col_constraints
+            .iter()
+            .map(|x| x.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Generate new constraints
+    pub(crate) fn generate_new_constraints(&mut self) {
+        let cols = self
+            .cols()
+            .map(|col| col.generate_new_constraints().unwrap())
+            .collect();
+        let rows = self
+            .rows()
+            .map(|row| row.generate_new_constraints().unwrap())
+            .collect();
+        self.col_constraints = cols;
+        self.row_constraints = rows;
+    }
+
+    /// Build a transformed copy of this board.
+    /// `map` converts a (column, row) in this board to a (column, row) in the new board.
+    fn transformed<F>(
+        &self,
+        width: Unit,
+        height: Unit,
+        row_constraints: Vec<ConstraintList>,
+        col_constraints: Vec<ConstraintList>,
+        map: F,
+    ) -> Board
+    where
+        F: Fn(Unit, Unit) -> (Unit, Unit),
+    {
+        let mut board = Board::from_parts(
+            width,
+            height,
+            vec![Cell::Unknown; self.cells.len()],
+            row_constraints,
+            col_constraints,
+        );
+        board.palette = self.palette.clone();
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let (new_col, new_row) = map(col, row);
+                board.set_cell(new_col, new_row, self.get_cell(col, row));
+                if self.is_masked(col, row) {
+                    board.set_masked(new_col, new_row, true);
+                }
+            }
+        }
+        board
+    }
+
+    /// Create a copy of this board rotated 90 degrees clockwise
+    pub fn rotate90(&self) -> Board {
+        let h = self.height;
+        // new rows are old columns read from bottom to top
+        let rows = self
+            .col_constraints
+            .iter()
+            .map(|ls| ls.iter().rev().copied().collect())
+            .collect();
+        // new columns are old rows, starting from the bottom row
+        let cols = self.row_constraints.iter().rev().cloned().collect();
+        self.transformed(h, self.width, rows, cols, |col, row| (h - 1 - row, col))
+    }
+
+    /// Create a copy of this board rotated 180 degrees
+    pub fn rotate180(&self) -> Board {
+        let (w, h) = self.get_size();
+        let reverse_all = |lists: &Vec<ConstraintList>| {
+            lists
+                .iter()
+                .rev()
+                .map(|ls| ls.iter().rev().copied().collect())
+                .collect()
+        };
+        let rows = reverse_all(&self.row_constraints);
+        let cols = reverse_all(&self.col_constraints);
+        self.transformed(w, h, rows, cols, |col, row| (w - 1 - col, h - 1 - row))
+    }
+
+    /// Create a copy of this board mirrored from left to right
+    pub fn flip_horizontal(&self) -> Board {
+        let (w, h) = self.get_size();
+        let rows = self
+            .row_constraints
+            .iter()
+            .map(|ls| ls.iter().rev().copied().collect())
+            .collect();
+        let cols = self.col_constraints.iter().rev().cloned().collect();
+        self.transformed(w, h, rows, cols, |col, row| (w - 1 - col, row))
+    }
+
+    /// Create a copy of this board mirrored from top to bottom
+    pub fn flip_vertical(&self) -> Board {
+        let (w, h) = self.get_size();
+        let rows = self.row_constraints.iter().rev().cloned().collect();
+        let cols = self
+            .col_constraints
+            .iter()
+            .map(|ls| ls.iter().rev().copied().collect())
+            .collect();
+        self.transformed(w, h, rows, cols, |col, row| (col, h - 1 - row))
+    }
+
+    /// List every cell that differs between this board and another board.
+    /// Returns None if the boards are not the same size.
+    pub fn diff(&self, other: &Board) -> Option<Vec<CellDiff>> {
+        if self.get_size() != other.get_size() {
+            return None;
+        }
+        let ret = self
+            .cells
+            .iter()
+            .zip(&other.cells)
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(i, (before, after))| {
+                let (col, row) = self.get_coordinate(i);
+                CellDiff {
+                    col,
+                    row,
+                    before: *before,
+                    after: *after,
+                }
+            })
+            .collect();
+        Some(ret)
+    }
+
+    /// Get a copy of this board where every cell that differs from the other board is Unknown,
+    /// such as to show where two solutions of an ambiguous puzzle disagree.
+    /// Returns None if the boards are not the same size.
+    pub fn agreement(&self, other: &Board) -> Option<Board> {
+        if self.get_size() != other.get_size() {
+            return None;
+        }
+        let mut ret = self.clone();
+        for (cell, other) in ret.cells.iter_mut().zip(&other.cells) {
+            if cell != other {
+                *cell = Cell::Unknown;
+            }
+        }
+        Some(ret)
+    }
+
+    /// Check that this puzzle could possibly be solved, without solving it.
+    /// Checks that there is a constraint list for every line, that every
+    /// line's constraints fit in the line, and that the row and column
+    /// constraints of each color add up to the same total.
+    /// Returns every problem found.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        use ValidationError::*;
+        if self.row_constraints.len() != self.height as usize {
+            let found = self.row_constraints.len();
+            return Err(vec![RowCountMismatch {
+                expected: self.height,
+                found,
+            }]);
+        }
+        if self.col_constraints.len() != self.width as usize {
+            let found = self.col_constraints.len();
+            return Err(vec![ColumnCountMismatch {
+                expected: self.width,
+                found,
+            }]);
+        }
+        let mut errors = Vec::new();
+        let all_constraints = self.row_constraints.iter().chain(&self.col_constraints);
+        if all_constraints.flatten().any(|x| x.get_length() == 0 && !x.is_total()) {
+            errors.push(ZeroLengthConstraint);
+        }
+        let mut bad_colors: Vec<Color> = self
+            .row_constraints
+            .iter()
+            .chain(&self.col_constraints)
+            .flatten()
+            .map(|x| x.get_color())
+            .chain(self.cells.iter().filter_map(|x| x.get_color()))
+            .filter(|x| self.palette.get(*x).is_none())
+            .collect();
+        bad_colors.sort_unstable();
+        bad_colors.dedup();
+        errors.extend(bad_colors.into_iter().map(UnknownColor));
+        for row in self.rows() {
+            let c = row.get_constraints();
+            if c.len() > 1 && c.iter().any(|x| x.is_total()) {
+                errors.push(RowMixedTotal(row.index()));
+            } else if get_min_line_length(c, self.toroidal) > self.width as usize {
+                errors.push(RowTooLong(row.index()));
+            } else if !is_line_satisfiable(&row) {
+                errors.push(RowUnsatisfiable(row.index()));
+            }
+        }
+        for col in self.cols() {
+            let c = col.get_constraints();
+            if c.len() > 1 && c.iter().any(|x| x.is_total()) {
+                errors.push(ColumnMixedTotal(col.index()));
+            } else if get_min_line_length(c, self.toroidal) > self.height as usize {
+                errors.push(ColumnTooLong(col.index()));
+            } else if !is_line_satisfiable(&col) {
+                errors.push(ColumnUnsatisfiable(col.index()));
+            }
+        }
+        for color in 0..self.palette.num_colors() {
+            let color = color as Color;
+            let rows = get_constraint_sum(&self.row_constraints, color, self.width);
+            let cols = get_constraint_sum(&self.col_constraints, color, self.height);
+            if rows.0 > cols.1 || cols.0 > rows.1 {
+                errors.push(SumMismatch { color, rows, cols });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check that the given solution's cells satisfy this board's constraints.
+    /// Returns every row and column that does not match.
+    pub fn check_solution(&self, solution: &Board) -> Result<(), Vec<Mismatch>> {
+        if self.get_size() != solution.get_size() {
+            return Err(vec![Mismatch::Size {
+                expected: self.get_size(),
+                found: solution.get_size(),
+            }]);
+        }
+        let unknown = solution.cells.iter().filter(|&&x| x == Cell::Unknown).count();
+        if unknown > 0 {
+            return Err(vec![Mismatch::Incomplete(unknown)]);
+        }
+        let mut errors = Vec::new();
+        for (row, line) in solution.rows().enumerate() {
+            let expected = self.get_row_constraints(row as Unit);
+            if !line_matches(expected, &line, self.toroidal) {
+                errors.push(Mismatch::Row {
+                    row: row as Unit,
+                    expected: expected.clone(),
+                    found: line.generate_new_constraints().unwrap(),
+                });
+            }
+        }
+        for (col, line) in solution.cols().enumerate() {
+            let expected = self.get_col_constraints(col as Unit);
+            if !line_matches(expected, &line, self.toroidal) {
+                errors.push(Mismatch::Column {
+                    col: col as Unit,
+                    expected: expected.clone(),
+                    found: line.generate_new_constraints().unwrap(),
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Display this board using the given characters for its cells.
+    /// Colored boards draw their cells and constraints in their palette's colors.
+    pub fn display_with<'a>(&'a self, chars: &'a CharMap) -> BoardDisplay<'a> {
+        BoardDisplay {
+            board: self,
+            chars,
+            monochrome: false,
+        }
+    }
+
+    /// Create a clone without constraints
+    pub fn clone_without_constraints(&self) -> Board {
+        Board {
+            cells: self.cells.clone(),
+            width: self.width,
+            height: self.height,
+            row_constraints: create_constraint_list(self.height as usize),
+            col_constraints: create_constraint_list(self.width as usize),
+            palette: self.palette.clone(),
+            mask: self.mask.clone(),
+            toroidal: self.toroidal,
+            observers: Vec::new(),
+            next_observer_id: 0,
+            journal: Vec::new(),
+            checkpoints: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+}
+
+/// A single cell that differs between two boards
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CellDiff {
+    pub col: Unit,
+    pub row: Unit,
+    /// The cell's value in the original board
+    pub before: Cell,
+    /// The cell's value in the compared board
+    pub after: Cell,
+}
+
+impl fmt::Display for CellDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "({}, {}): {} -> {}",
+            self.col, self.row, self.before, self.after
+        )
+    }
+}
+
+/// A change to a single cell of a board.
+/// Changes are compared and ordered by their cell's position only,
+/// so that a ChangeSet holds at most one change per cell.
+#[derive(Copy, Clone, Debug)]
+pub struct Change {
+    previous_value: Cell,
+    value: Cell,
+    col: Unit,
+    row: Unit,
+}
+
+impl Change {
+    pub fn new(col: Unit, row: Unit, previous_value: Cell, value: Cell) -> Change {
+        Change {
+            previous_value,
+            value,
+            col,
+            row,
+        }
+    }
+
+    pub fn get_col(&self) -> Unit {
+        self.col
+    }
+
+    pub fn get_row(&self) -> Unit {
+        self.row
+    }
+
+    /// Get the cell's value from before this change
+    pub fn get_previous_value(&self) -> Cell {
+        self.previous_value
+    }
+
+    /// Get the cell's value after this change
+    pub fn get_value(&self) -> Cell {
+        self.value
+    }
+}
+
+impl PartialEq for Change {
+    fn eq(&self, other: &Change) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Change {}
+
+impl PartialOrd for Change {
+    fn partial_cmp(&self, other: &Change) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Change {
+    fn cmp(&self, other: &Change) -> Ordering {
+        (self.row, self.col).cmp(&(other.row, other.col))
+    }
+}
+
+impl Hash for Change {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.row, self.col).hash(state);
+    }
+}
+
+/// A set of changes that have been made to a board, with at most one change per cell.
+/// Changes are kept in the same order as the board's cells.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeSet {
+    pub changes: BTreeSet<Change>,
+}
+
+impl ChangeSet {
+    pub fn new() -> ChangeSet {
+        ChangeSet::default()
+    }
+
+    /// Record a change.
+    /// If this set already changes the same cell, the two are combined into one change
+    /// from the older previous value to the newer value.
+    /// Changes that leave a cell at its previous value are removed.
+    pub fn record(&mut self, change: Change) {
+        let change = match self.changes.take(&change) {
+            Some(older) => Change {
+                previous_value: older.previous_value,
+                ..change
+            },
+            None => change,
+        };
+        if change.previous_value != change.value {
+            self.changes.insert(change);
+        }
+    }
+
+    /// Set a cell on a board, recording the change
+    pub fn set_cell(&mut self, b: &mut Board, col: Unit, row: Unit, value: Cell) {
+        self.record(Change::new(col, row, b.get_cell(col, row), value));
+        b.set_cell(col, row, value);
+    }
+
+    /// Set every changed cell on a board to its new value
+    pub fn apply(&self, b: &mut Board) {
+        for change in self.changes.iter() {
+            b.set_cell(change.col, change.row, change.value);
+        }
+    }
+
+    /// Set every changed cell on a board back to its previous value
+    pub fn revert(&self, b: &mut Board) {
+        for change in self.changes.iter() {
+            b.set_cell(change.col, change.row, change.previous_value);
+        }
+    }
+
+    /// Record every change from a set of changes made after this one
+    pub fn merge(&mut self, other: &ChangeSet) {
+        for change in other.changes.iter() {
+            self.record(*change);
         }
     }
 
-    /// Get this board's width
-    pub fn get_width(&self) -> Unit {
-        self.width
+    pub fn clear(&mut self) {
+        self.changes.clear();
     }
 
-    /// Get this board's height
-    pub fn get_height(&self) -> Unit {
-        self.height
+    pub fn len(&self) -> usize {
+        self.changes.len()
     }
 
-    /// Get this board's size (width, height)
-    pub fn get_size(&self) -> (Unit, Unit) {
-        (self.width, self.height)
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
     }
 
-    /// Get the number of cells
-    pub fn get_num_cells(&self) -> usize {
-        (self.width as usize) * (self.height as usize)
+    pub fn iter(&self) -> btree_set::Iter<'_, Change> {
+        self.changes.iter()
     }
+}
 
-    /// Convert a column/row pair to an index
-    pub fn get_index(&self, col: Unit, row: Unit) -> usize {
-        (col as usize) + (row as usize) * (self.width as usize)
+/// Receives a notification every time a board's cell is set.
+/// Implemented for any `FnMut(CellDiff)` closure.
+pub trait CellObserver: Send {
+    /// Called after a cell is set, with its previous and new values.
+    /// This is called even if the value did not change.
+    fn cell_changed(&mut self, change: CellDiff);
+}
+
+impl<F> CellObserver for F
+where
+    F: FnMut(CellDiff) + Send,
+{
+    fn cell_changed(&mut self, change: CellDiff) {
+        self(change)
     }
+}
 
-    /// Convert index to column/row pair
-    pub fn get_coordinate(&self, index: usize) -> (Unit, Unit) {
-        (
-            (index % (self.width as usize)) as Unit,
-            (index / (self.width as usize)) as Unit,
-        )
+/// Identifies an observer registered with Board::add_observer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(usize);
+
+/// A problem with a puzzle that makes it impossible to solve
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The number of row constraint lists does not match the board's height
+    RowCountMismatch { expected: Unit, found: usize },
+    /// The number of column constraint lists does not match the board's width
+    ColumnCountMismatch { expected: Unit, found: usize },
+    /// A constraint other than a total has a length of zero
+    ZeroLengthConstraint,
+    /// A constraint or cell uses a color that is not in the palette
+    UnknownColor(Color),
+    /// A row's constraints can not fit within the board's width
+    RowTooLong(Unit),
+    /// A column's constraints can not fit within the board's height
+    ColumnTooLong(Unit),
+    /// A row's constraints can not be satisfied given its masked or prefilled cells
+    RowUnsatisfiable(Unit),
+    /// A column's constraints can not be satisfied given its masked or prefilled cells
+    ColumnUnsatisfiable(Unit),
+    /// A row has a total alongside other constraints
+    RowMixedTotal(Unit),
+    /// A column has a total alongside other constraints
+    ColumnMixedTotal(Unit),
+    /// The total length of the row constraints of some color can never equal
+    /// the total length of the column constraints of that color.
+    /// Totals are given as (minimum, maximum).
+    SumMismatch {
+        color: Color,
+        rows: (usize, usize),
+        cols: (usize, usize),
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::RowCountMismatch { expected, found } => write!(
+                f,
+                "expected {} row constraint lists, found {}",
+                expected, found
+            ),
+            ValidationError::ColumnCountMismatch { expected, found } => write!(
+                f,
+                "expected {} column constraint lists, found {}",
+                expected, found
+            ),
+            ValidationError::ZeroLengthConstraint => write!(f, "constraints must not be zero"),
+            ValidationError::UnknownColor(color) => {
+                write!(f, "color {} is not in the palette", color)
+            }
+            ValidationError::RowTooLong(row) => {
+                write!(f, "constraints for row {} do not fit in the board", row)
+            }
+            ValidationError::ColumnTooLong(col) => {
+                write!(f, "constraints for column {} do not fit in the board", col)
+            }
+            ValidationError::RowUnsatisfiable(row) => {
+                write!(f, "constraints for row {} can not be satisfied", row)
+            }
+            ValidationError::ColumnUnsatisfiable(col) => {
+                write!(f, "constraints for column {} can not be satisfied", col)
+            }
+            ValidationError::RowMixedTotal(row) => {
+                write!(f, "row {} has a total along with other constraints", row)
+            }
+            ValidationError::ColumnMixedTotal(col) => {
+                write!(f, "column {} has a total along with other constraints", col)
+            }
+            ValidationError::SumMismatch { color, rows, cols } => {
+                let fmt_sum = |(min, max): (usize, usize)| {
+                    if min == max {
+                        min.to_string()
+                    } else {
+                        format!("{}-{}", min, max)
+                    }
+                };
+                write!(
+                    f,
+                    "row constraints of color {} add up to {}, but column constraints add up to {}",
+                    color,
+                    fmt_sum(rows),
+                    fmt_sum(cols)
+                )
+            }
+        }
     }
+}
 
-    /// Get the cell at the given column/row
-    pub fn get_cell(&self, col: Unit, row: Unit) -> Cell {
-        self.cells[self.get_index(col, row)]
+impl Error for ValidationError {}
+
+/// An error produced when a BoardBuilder is given an invalid puzzle
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// The number of row constraint lists does not match the board's height
+    RowCountMismatch { expected: Unit, found: usize },
+    /// The number of column constraint lists does not match the board's width
+    ColumnCountMismatch { expected: Unit, found: usize },
+    /// A row index is outside of the board
+    RowOutOfBounds(Unit),
+    /// A column index is outside of the board
+    ColumnOutOfBounds(Unit),
+    /// A cell coordinate (column, row) is outside of the board
+    CellOutOfBounds(Unit, Unit),
+    /// The finished board failed validation
+    Invalid(ValidationError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::RowCountMismatch { expected, found } => write!(
+                f,
+                "expected {} row constraint lists, found {}",
+                expected, found
+            ),
+            BuildError::ColumnCountMismatch { expected, found } => write!(
+                f,
+                "expected {} column constraint lists, found {}",
+                expected, found
+            ),
+            BuildError::RowOutOfBounds(row) => write!(f, "row {} is out of bounds", row),
+            BuildError::ColumnOutOfBounds(col) => write!(f, "column {} is out of bounds", col),
+            BuildError::CellOutOfBounds(col, row) => {
+                write!(f, "cell ({}, {}) is out of bounds", col, row)
+            }
+            BuildError::Invalid(ref e) => write!(f, "{}", e),
+        }
     }
+}
 
-    /// Set the cell at the given column/row
-    pub fn set_cell(&mut self, col: Unit, row: Unit, value: Cell) {
-        let index = self.get_index(col, row);
-        self.cells[index] = value;
+impl Error for BuildError {}
+
+/// A way in which a solution does not match a puzzle
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The solution is a different size than the puzzle, as (width, height)
+    Size {
+        expected: (Unit, Unit),
+        found: (Unit, Unit),
+    },
+    /// The solution has this many unknown cells
+    Incomplete(usize),
+    /// A row's cells do not match its constraints
+    Row {
+        row: Unit,
+        expected: ConstraintList,
+        found: ConstraintList,
+    },
+    /// A column's cells do not match its constraints
+    Column {
+        col: Unit,
+        expected: ConstraintList,
+        found: ConstraintList,
+    },
+}
+
+/// Format a list of constraints as a space separated list
+fn fmt_constraint_list(list: &[Constraint]) -> String {
+    if list.is_empty() {
+        return "0".to_string();
     }
+    let values: Vec<String> = list.iter().map(|x| x.to_string()).collect();
+    values.join(" ")
+}
 
-    /// Get the cell at the given index
-    pub fn get_cell_index(&self, index: usize) -> Cell {
-        self.cells[index]
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mismatch::Size { expected, found } => write!(
+                f,
+                "expected a {}x{} solution, found {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            Mismatch::Incomplete(count) => write!(f, "solution has {} unknown cells", count),
+            Mismatch::Row {
+                row,
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "row {}: expected {}, found {}",
+                row,
+                fmt_constraint_list(expected),
+                fmt_constraint_list(found)
+            ),
+            Mismatch::Column {
+                col,
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "column {}: expected {}, found {}",
+                col,
+                fmt_constraint_list(expected),
+                fmt_constraint_list(found)
+            ),
+        }
     }
+}
 
-    /// Set the cell at the gien index
-    pub fn set_cell_index(&mut self, index: usize, value: Cell) {
-        self.cells[index] = value;
+/// Determine whether a fully known line matches the given constraints,
+/// as a line that wraps around if toroidal is set
+fn line_matches<T: LineRef>(expected: &[Constraint], line: &T, toroidal: bool) -> bool {
+    let cells = (0..line.size()).map(|i| line.get_cell(i)).collect();
+    let constraints = expected.to_vec();
+    let line = StandaloneLine::new(cells, &constraints).with_toroidal(toroidal);
+    if expected.iter().all(|x| x.is_exact()) {
+        return line.generate_new_constraints().as_deref() == Some(expected);
     }
+    is_line_satisfiable(&line)
+}
 
-    /// Get the constraints for the given row
-    pub fn get_row_constraints(&self, row: Unit) -> &ConstraintList {
-        &self.row_constraints[row as usize]
+/// Determine whether a line's constraints can be satisfied given its current cells.
+/// Should only be called on lines whose constraints fit in the line.
+fn is_line_satisfiable<T: LineRef>(line: &T) -> bool {
+    // blank lines are always satisfiable if their constraints fit
+    if (0..line.size()).all(|i| line.get_cell(i) == Cell::Unknown) {
+        return true;
     }
+    line.is_solvable(&mut LineScratch::new())
+}
 
-    /// Get the constraints for the given column
-    pub fn get_col_constraints(&self, col: Unit) -> &ConstraintList {
-        &self.col_constraints[col as usize]
+/// Get the (minimum, maximum) total length of all constraints of the given color.
+/// The maximum length of a constraint is limited to the length of its line.
+fn get_constraint_sum(lists: &[ConstraintList], color: Color, size: Unit) -> (usize, usize) {
+    let mut min = 0;
+    let mut max = 0;
+    for constraint in lists.iter().flatten().filter(|x| x.get_color() == color) {
+        min += constraint.get_length() as usize;
+        let length = constraint.get_length();
+        max += constraint.get_max_length().min(size).max(length) as usize;
     }
+    (min, max)
+}
 
-    /// Get a mutable reference to a row from this board
-    pub fn get_row_mut(&mut self, row: Unit) -> BoardRowMut {
-        BoardRowMut {
-            board: self,
-            row: row,
+/// Get the minimum number of cells required to fit a list of constraints.
+/// Only constraints of the same color need a gap between them.
+/// If the line wraps around, its last and first constraints are next to each other too.
+fn get_min_line_length(ls: &ConstraintList, toroidal: bool) -> usize {
+    let sum: usize = ls.iter().map(|x| x.get_length() as usize).sum();
+    let wrap_gap = match (ls.first(), ls.last()) {
+        (Some(first), Some(last)) => {
+            toroidal && ls.len() > 1 && !first.is_total() && first.get_color() == last.get_color()
         }
+        _ => false,
+    };
+    sum + ls.windows(2).filter(|x| x[0].get_color() == x[1].get_color()).count()
+        + wrap_gap as usize
+}
+
+/// Get the total number of filled cells a line's constraints ask for, if its only
+/// constraint is a total
+pub fn get_line_total(ls: &[Constraint]) -> Option<Unit> {
+    match ls {
+        [x] if x.is_total() => Some(x.get_length()),
+        _ => None,
     }
+}
 
-    /// Get a mutable reference to a column from this board
-    pub fn get_col_mut(&mut self, col: Unit) -> BoardColMut {
-        BoardColMut {
-            board: self,
-            col: col,
+/// Constructs a Board in code.
+/// Lines that are not given any constraints are treated as empty lines.
+/// ```ignore
+/// let board = BoardBuilder::new(3, 2)
+///     .row(0, &[3])
+///     .row(1, &[1, 1])
+///     .col(0, &[2])
+///     .col(1, &[1])
+///     .col(2, &[2])
+///     .build()?;
+/// ```
+#[derive(Clone)]
+pub struct BoardBuilder {
+    width: Unit,
+    height: Unit,
+    row_constraints: Vec<ConstraintList>,
+    col_constraints: Vec<ConstraintList>,
+    cells: Vec<(Unit, Unit, Cell)>,
+    masked: Vec<(Unit, Unit)>,
+    palette: Palette,
+    error: Option<BuildError>,
+}
+
+impl BoardBuilder {
+    /// Start building a board with the given width and height
+    pub fn new(width: Unit, height: Unit) -> BoardBuilder {
+        BoardBuilder {
+            width,
+            height,
+            row_constraints: create_constraint_list(height as usize),
+            col_constraints: create_constraint_list(width as usize),
+            cells: Vec::new(),
+            masked: Vec::new(),
+            palette: Palette::new(),
+            error: None,
         }
     }
 
-    /// Get a reference to a row from this board
-    pub fn get_row_ref(&self, row: Unit) -> BoardRowRef {
-        BoardRowRef {
-            board: self,
-            row: row,
-        }
+    fn fail(mut self, error: BuildError) -> BoardBuilder {
+        self.error.get_or_insert(error);
+        self
     }
 
-    /// Get a reference to a column from this board
-    pub fn get_col_ref(&self, col: Unit) -> BoardColRef {
-        BoardColRef {
-            board: self,
-            col: col,
+    /// Set the constraints for a single row
+    pub fn row(mut self, row: Unit, lengths: &[Unit]) -> BoardBuilder {
+        if row >= self.height {
+            return self.fail(BuildError::RowOutOfBounds(row));
         }
+        self.row_constraints[row as usize] = lengths.iter().map(|x| Constraint::new(*x)).collect();
+        self
     }
 
-    /// Get the largest row constraint in all of this board's row constraints
-    fn get_largest_row_constraint(&self) -> Unit {
-        self.row_constraints
-            .iter()
-            .flat_map(|x| x)
-            .map(|x| x.get_length())
-            .max()
-            .unwrap_or(0)
+    /// Set the constraints for a single column
+    pub fn col(mut self, col: Unit, lengths: &[Unit]) -> BoardBuilder {
+        if col >= self.width {
+            return self.fail(BuildError::ColumnOutOfBounds(col));
+        }
+        self.col_constraints[col as usize] = lengths.iter().map(|x| Constraint::new(*x)).collect();
+        self
     }
 
-    /// Get the largest column constraint in all of this board's column constraints
-    fn get_largest_col_constraint(&self) -> Unit {
-        self.col_constraints
-            .iter()
-            .flat_map(|x| x)
-            .map(|x| x.get_length())
-            .max()
-            .unwrap_or(0)
+    /// Set the constraints for every row at once
+    pub fn rows(mut self, rows: Vec<ConstraintList>) -> BoardBuilder {
+        if rows.len() != self.height as usize {
+            let found = rows.len();
+            let expected = self.height;
+            return self.fail(BuildError::RowCountMismatch { expected, found });
+        }
+        self.row_constraints = rows;
+        self
     }
 
-    /// Get the maximum number of constraints on any row
-    fn get_max_row_constraints(&self) -> usize {
-        self.row_constraints
-            .iter()
-            .map(|x| x.len())
-            .max()
-            .unwrap_or(0)
+    /// Set the constraints for every column at once
+    pub fn cols(mut self, cols: Vec<ConstraintList>) -> BoardBuilder {
+        if cols.len() != self.width as usize {
+            let found = cols.len();
+            let expected = self.width;
+            return self.fail(BuildError::ColumnCountMismatch { expected, found });
+        }
+        self.col_constraints = cols;
+        self
     }
 
-    /// Get the maximum number of constraints on any column
-    fn get_max_col_constraints(&self) -> usize {
-        self.col_constraints
-            .iter()
-            .map(|x| x.len())
-            .max()
-            .unwrap_or(0)
+    /// Set the palette used by colored constraints and cells
+    pub fn palette(mut self, palette: Palette) -> BoardBuilder {
+        self.palette = palette;
+        self
     }
 
-    /// Generate new constraints
-    fn generate_new_constraints(&mut self) {
-        for col in 0..self.width {
-            self.col_constraints[col as usize] =
-                self.get_col_ref(col).generate_new_constraints().unwrap();
+    /// Prefill a cell with the given value
+    pub fn cell(mut self, col: Unit, row: Unit, value: Cell) -> BoardBuilder {
+        if col >= self.width || row >= self.height {
+            return self.fail(BuildError::CellOutOfBounds(col, row));
         }
-        for row in 0..self.height {
-            self.row_constraints[row as usize] =
-                self.get_row_ref(row).generate_new_constraints().unwrap();
+        self.cells.push((col, row, value));
+        self
+    }
+
+    /// Take a cell out of play, for puzzles that are not rectangular
+    pub fn mask(mut self, col: Unit, row: Unit) -> BoardBuilder {
+        if col >= self.width || row >= self.height {
+            return self.fail(BuildError::CellOutOfBounds(col, row));
         }
+        self.masked.push((col, row));
+        self
     }
 
-    /// Create a clone without constraints
-    pub fn clone_without_constraints(&self) -> Board {
-        Board {
-            cells: self.cells.clone(),
-            width: self.width,
-            height: self.height,
-            row_constraints: create_constraint_list(self.height as usize),
-            col_constraints: create_constraint_list(self.width as usize),
+    /// Validate the puzzle and construct the Board
+    pub fn build(self) -> Result<Board, BuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut board = Board::from_parts(
+            self.width,
+            self.height,
+            vec![Cell::Unknown; get_cell_count(self.width, self.height)],
+            self.row_constraints,
+            self.col_constraints,
+        );
+        board.palette = self.palette;
+        for (col, row, value) in self.cells {
+            board.set_cell(col, row, value);
+        }
+        for (col, row) in self.masked {
+            board.set_masked(col, row, true);
         }
+        if let Err(mut errors) = board.validate() {
+            return Err(BuildError::Invalid(errors.remove(0)));
+        }
+        Ok(board)
     }
 }
 
@@ -812,12 +3657,66 @@ fn get_print_width(value: Unit) -> usize {
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let big_row = self.get_largest_row_constraint();
-        let big_col = self.get_largest_col_constraint();
-        let row_item_width = get_print_width(big_row);
-        let col_item_width = get_print_width(big_col);
-        let num_row_items = self.get_max_row_constraints();
-        let num_col_items = self.get_max_col_constraints();
+        write!(f, "{}", self.display_with(&CharMap::default()))
+    }
+}
+
+/// Displays a board using the characters from a CharMap. Created by Board::display_with.
+pub struct BoardDisplay<'a> {
+    board: &'a Board,
+    chars: &'a CharMap,
+    /// Whether to leave out every ANSI escape code, for terminals without colors
+    monochrome: bool,
+}
+
+impl<'a> BoardDisplay<'a> {
+    /// Display the board without ANSI escape codes, telling cells apart by their glyphs alone
+    pub fn monochrome(self) -> BoardDisplay<'a> {
+        BoardDisplay {
+            monochrome: true,
+            ..self
+        }
+    }
+
+    /// Get the palette that cells and constraints are drawn in, if any
+    fn palette(&self) -> Option<&'a Palette> {
+        if self.board.is_colored() && !self.monochrome {
+            Some(self.board.get_palette())
+        } else {
+            None
+        }
+    }
+
+    /// Write a constraint right-aligned to the given width, on the background of its color
+    fn write_constraint(
+        &self,
+        f: &mut fmt::Formatter,
+        constraint: &Constraint,
+        width: usize,
+    ) -> fmt::Result {
+        let color = self
+            .palette()
+            .and_then(|palette| palette.get(constraint.get_color()));
+        match color {
+            Some(color) => {
+                let text = constraint.to_string();
+                let padding = width.saturating_sub(text.len());
+                write!(f, "{:padding$}{}{}\x1B[0m ", "", color.ansi_format(), text)
+            }
+            None => write!(f, "{:>width$} ", constraint, width = width),
+        }
+    }
+}
+
+impl<'a> fmt::Display for BoardDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let chars = self.chars;
+        let board = self.board;
+        let palette = self.palette();
+        let row_item_width = board.get_row_constraint_print_width();
+        let col_item_width = board.get_col_constraint_print_width();
+        let num_row_items = board.get_max_row_constraints();
+        let num_col_items = board.get_max_col_constraints();
         // print col constraints
         for i in 0..num_col_items {
             // print padding
@@ -827,61 +3726,59 @@ impl fmt::Display for Board {
                 "",
                 width = (row_item_width + 1) * num_row_items
             )?;
-            for col in 0..self.width {
-                let cols = self.get_col_constraints(col);
+            for col in 0..board.width {
+                let cols = board.get_col_constraints(col);
                 let colskip = num_col_items - cols.len();
                 if i + 1 > colskip {
-                    write!(
-                        f,
-                        "{:width$} ",
-                        cols[i - colskip].get_length(),
-                        width = col_item_width
-                    )?;
+                    self.write_constraint(f, &cols[i - colskip], col_item_width)?;
                 } else {
                     write!(f, "{:width$} ", "", width = col_item_width)?;
                 }
             }
             // print newline
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
 
-        write!(
+        writeln!(
             f,
-            "{0:-<width$}+{0:-<width2$}\n",
+            "{0:-<width$}+{0:-<width2$}",
             "",
             width = (row_item_width + 1) * num_row_items,
-            width2 = (col_item_width + 1) * self.col_constraints.len()
+            width2 = (col_item_width + 1) * board.col_constraints.len()
         )?;
         // print cells + row constraints
-        for row in 0..self.height {
+        for row in 0..board.height {
             // print row constraints before for each row
-            let rows = self.get_row_constraints(row);
+            let rows = board.get_row_constraints(row);
             let rowskip = num_row_items - rows.len();
             for i in 0..num_row_items {
                 if i + 1 > rowskip {
-                    write!(
-                        f,
-                        "{:width$} ",
-                        rows[i - rowskip].get_length(),
-                        width = row_item_width
-                    )?;
+                    self.write_constraint(f, &rows[i - rowskip], row_item_width)?;
                 } else {
                     write!(f, "{:width$} ", "", width = row_item_width)?;
                 }
             }
             write!(f, "| ")?;
-            for col in 0..self.width {
-                let cell = self.get_cell(col, row);
-                let (fmtstart, fmtend) = cell.get_format();
+            for col in 0..board.width {
+                if board.is_masked(col, row) {
+                    write!(f, "{:>width$} ", "", width = col_item_width)?;
+                    continue;
+                }
+                let cell = board.get_cell(col, row);
+                let (fmtstart, fmtend) = if self.monochrome {
+                    (String::new(), "")
+                } else {
+                    cell.get_format(palette)
+                };
                 write!(
                     f, 
                     "{}{:>width$}{} ", 
                     fmtstart,
-                    format!("{}", cell),
+                    chars.get_char(cell),
                     fmtend,
                     width = col_item_width)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -899,13 +3796,44 @@ pub struct BoardRowMut<'a> {
     row: Unit,
 }
 
+impl<'a> BoardRowRef<'a> {
+    /// Get the index of this row
+    pub fn index(&self) -> Unit {
+        self.row
+    }
+}
+
 impl<'a> BoardRowMut<'a> {
-    pub fn as_ref(&self) -> BoardRowRef {
+    pub fn as_ref(&self) -> BoardRowRef<'_> {
         BoardRowRef {
             board: self.board,
             row: self.row,
         }
     }
+
+    /// Get the index of this row
+    pub fn index(&self) -> Unit {
+        self.row
+    }
+}
+
+/// Mutable iteration over a board's rows.
+/// Only one row may be borrowed at a time, so this can not be a regular Iterator:
+/// `while let Some(mut row) = rows.next_line() { ... }`
+pub struct RowsMut<'a> {
+    board: &'a mut Board,
+    next: Unit,
+}
+
+impl<'a> RowsMut<'a> {
+    /// Get the next row, or None if every row has been visited
+    pub fn next_line(&mut self) -> Option<BoardRowMut<'_>> {
+        if self.next >= self.board.height {
+            return None;
+        }
+        self.next += 1;
+        Some(self.board.get_row_mut(self.next - 1))
+    }
 }
 
 impl<'a> LineRef for BoardRowMut<'a> {
@@ -920,6 +3848,22 @@ impl<'a> LineRef for BoardRowMut<'a> {
     fn get_constraints(&self) -> &ConstraintList {
         self.board.get_row_constraints(self.row)
     }
+
+    fn is_masked(&self, col: Unit) -> bool {
+        self.board.is_masked(col, self.row)
+    }
+
+    fn is_colored(&self) -> bool {
+        self.board.is_colored()
+    }
+
+    fn is_toroidal(&self) -> bool {
+        self.board.is_toroidal()
+    }
+
+    fn can_be_color(&self, col: Unit, color: Color) -> bool {
+        self.board.can_be_color(col, self.row, color)
+    }
 }
 
 impl<'a> LineRef for BoardRowRef<'a> {
@@ -934,12 +3878,32 @@ impl<'a> LineRef for BoardRowRef<'a> {
     fn get_constraints(&self) -> &ConstraintList {
         self.board.get_row_constraints(self.row)
     }
+
+    fn is_masked(&self, col: Unit) -> bool {
+        self.board.is_masked(col, self.row)
+    }
+
+    fn is_colored(&self) -> bool {
+        self.board.is_colored()
+    }
+
+    fn is_toroidal(&self) -> bool {
+        self.board.is_toroidal()
+    }
+
+    fn can_be_color(&self, col: Unit, color: Color) -> bool {
+        self.board.can_be_color(col, self.row, color)
+    }
 }
 
 impl<'a> LineMut for BoardRowMut<'a> {
     fn set_cell(&mut self, col: Unit, value: Cell) {
         self.board.set_cell(col, self.row, value)
     }
+
+    fn get_coordinate(&self, col: Unit) -> (Unit, Unit) {
+        (col, self.row)
+    }
 }
 
 /// A reference to a board's column
@@ -954,13 +3918,44 @@ pub struct BoardColMut<'a> {
     col: Unit,
 }
 
+impl<'a> BoardColRef<'a> {
+    /// Get the index of this column
+    pub fn index(&self) -> Unit {
+        self.col
+    }
+}
+
 impl<'a> BoardColMut<'a> {
-    pub fn as_ref(&self) -> BoardColRef {
+    pub fn as_ref(&self) -> BoardColRef<'_> {
         BoardColRef {
             board: self.board,
             col: self.col,
         }
     }
+
+    /// Get the index of this column
+    pub fn index(&self) -> Unit {
+        self.col
+    }
+}
+
+/// Mutable iteration over a board's columns.
+/// Only one column may be borrowed at a time, so this can not be a regular Iterator:
+/// `while let Some(mut col) = cols.next_line() { ... }`
+pub struct ColsMut<'a> {
+    board: &'a mut Board,
+    next: Unit,
+}
+
+impl<'a> ColsMut<'a> {
+    /// Get the next column, or None if every column has been visited
+    pub fn next_line(&mut self) -> Option<BoardColMut<'_>> {
+        if self.next >= self.board.width {
+            return None;
+        }
+        self.next += 1;
+        Some(self.board.get_col_mut(self.next - 1))
+    }
 }
 
 impl<'a> LineRef for BoardColMut<'a> {
@@ -975,6 +3970,22 @@ impl<'a> LineRef for BoardColMut<'a> {
     fn get_constraints(&self) -> &ConstraintList {
         self.board.get_col_constraints(self.col)
     }
+
+    fn is_masked(&self, row: Unit) -> bool {
+        self.board.is_masked(self.col, row)
+    }
+
+    fn is_colored(&self) -> bool {
+        self.board.is_colored()
+    }
+
+    fn is_toroidal(&self) -> bool {
+        self.board.is_toroidal()
+    }
+
+    fn can_be_color(&self, row: Unit, color: Color) -> bool {
+        self.board.can_be_color(self.col, row, color)
+    }
 }
 
 impl<'a> LineRef for BoardColRef<'a> {
@@ -989,29 +4000,58 @@ impl<'a> LineRef for BoardColRef<'a> {
     fn get_constraints(&self) -> &ConstraintList {
         self.board.get_col_constraints(self.col)
     }
+
+    fn is_masked(&self, row: Unit) -> bool {
+        self.board.is_masked(self.col, row)
+    }
+
+    fn is_colored(&self) -> bool {
+        self.board.is_colored()
+    }
+
+    fn is_toroidal(&self) -> bool {
+        self.board.is_toroidal()
+    }
+
+    fn can_be_color(&self, row: Unit, color: Color) -> bool {
+        self.board.can_be_color(self.col, row, color)
+    }
 }
 
 impl<'a> LineMut for BoardColMut<'a> {
     fn set_cell(&mut self, row: Unit, value: Cell) {
         self.board.set_cell(self.col, row, value)
     }
+
+    fn get_coordinate(&self, row: Unit) -> (Unit, Unit) {
+        (self.col, row)
+    }
 }
 
 /// A line that is not part of a board
 pub struct StandaloneLine<'a> {
     constraints: &'a ConstraintList,
     data: Vec<Cell>,
+    toroidal: bool,
 }
 
 impl<'a> StandaloneLine<'a> {
-    pub fn new(data: Vec<Cell>, constraints: &ConstraintList) -> StandaloneLine {
-        StandaloneLine { constraints, data }
+    pub fn new(data: Vec<Cell>, constraints: &ConstraintList) -> StandaloneLine<'_> {
+        StandaloneLine {
+            constraints,
+            data,
+            toroidal: false,
+        }
+    }
+    /// Get a copy of this line that wraps around, or that does not
+    pub fn with_toroidal(self, toroidal: bool) -> StandaloneLine<'a> {
+        StandaloneLine { toroidal, ..self }
     }
 }
 
 impl<'a> LineRef for StandaloneLine<'a> {
     fn size(&self) -> Unit {
-        self.data.len() as Unit
+        to_unit(self.data.len())
     }
 
     fn get_cell(&self, row: Unit) -> Cell {
@@ -1021,11 +4061,20 @@ impl<'a> LineRef for StandaloneLine<'a> {
     fn get_constraints(&self) -> &ConstraintList {
         self.constraints
     }
+
+    fn is_toroidal(&self) -> bool {
+        self.toroidal
+    }
 }
 
 impl<'a> LineMut for StandaloneLine<'a> {
     fn set_cell(&mut self, row: Unit, value: Cell) {
-        self.data[row as usize] = value;
+        self.data[row as usize] = value.normalized();
+    }
+
+    /// A standalone line is not part of a board, so it is treated as row 0
+    fn get_coordinate(&self, col: Unit) -> (Unit, Unit) {
+        (col, 0)
     }
 }
 
@@ -1039,6 +4088,10 @@ impl Hash for Board {
                     Cell::Empty => 0,
                     Cell::Filled => 1,
                     Cell::Unknown => 2,
+                    Cell::Colored(color) => {
+                        state.write_u8(*color);
+                        3
+                    }
                 };
             }
             state.write_u64(v);
@@ -1059,6 +4112,16 @@ impl PartialEq for Board {
 
 impl Eq for Board {}
 
+impl fmt::Debug for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Board")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("cells", &self.to_grid_string())
+            .finish()
+    }
+}
+
 impl<'a> fmt::Display for BoardColMut<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.do_fmt(f)
@@ -0,0 +1,1757 @@
+use crate::board::{Board, BoardDisplay, Cell, CharMap};
+use crate::format::pack::{self, PackReader};
+use crate::format::{self, report, ParseError};
+use crate::solver::{
+    self, ApproximateConfig, BranchHeuristic, ForcedCells, Limit, LineInfo, LineSolver, Progress,
+    ProgressCallback, SolutionCount, SolveOutcome, SolveResult, SolveStats, SolverConfig,
+    Uniqueness,
+};
+use crate::util;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage:
+    nonogram <puzzle>
+    nonogram solve [options] <puzzle>
+    nonogram solve [options] --webpbn <id>
+    nonogram solve --all [options] <puzzle or directory>...
+    nonogram solve-pack [--jobs <n>] [--timeout <secs>] [--max-branches <n>] [--toroidal]
+                        <pack> [<solution pack>]
+    nonogram count [--cap <n>] [--diff] [--toroidal] <puzzle>
+    nonogram hint [--toroidal] <puzzle>
+    nonogram forced [--timeout <secs>] [--max-branches <n>] [--toroidal] <puzzle>
+    nonogram probabilities [--heatmap] [--toroidal] <puzzle>
+    nonogram rate [--max-branches <n>] [--toroidal] <puzzle or directory>...
+    nonogram approximate [--genetic] [--steps <n>] [--seed <n>] [--timeout <secs>] [--toroidal]
+                         <puzzle>
+    nonogram bench [--runs <n>] [--line-alg <name>] [--max-branches <n>] [--csv] [--toroidal]
+                   <puzzle or directory>...
+    nonogram verify <solution> <puzzle>
+    nonogram export <puzzle> <output>
+    nonogram check-corpus <puzzle directory> <solution directory>
+
+solve options:
+    --save <file>      write the final state, which can be given as the puzzle to resume solving
+    --trace <file>     write a JSON replay of every deduction and branch
+    --chars <abc>      read and print grids using a for filled, b for empty and c for unknown cells
+    --require-unique   fail if the puzzle has more than one solution
+    --toroidal         lines wrap around the edges of the board, so a block may run off the end
+                       of its line and continue at the start; it is then the last clue of its line
+    --probe            try both values of each cell before guessing, keeping any that are forced
+    --crossings        before probing or guessing, try both values of each cell against only
+                       its row, its column and the lines near them, keeping any that are forced
+    --timeout <secs>   give up after this many seconds, keeping the cells deduced so far
+    --max-branches <n> give up rather than guess more than this many times, keeping the cells
+                       deduced so far; with 0, the puzzle is only line solved
+    --max-memory <mb>  give up once the boards kept by --transpositions, --best-first or
+                       --threads take up about this many megabytes
+    --heuristic <name> pick cells to guess by: first (unknown cell), unsolved (fewest unsolved
+                       cells in the row and column, the default), constrained
+                       (fewest ways to place the row and column's clues), adjacent
+                       (most filled neighbors and board edges), or likely (the cell most
+                       likely to be one value, by the ways its row and column can be placed)
+    --seed <n>         break ties between lines and cells to guess in an order drawn from n,
+                       instead of by position; each seed gives the same result on every run
+    --line-alg <name>  solve each row and column by: automaton (a state machine built from the
+                       clues, deducing every cell the line allows, the default), graph (the
+                       placements of the clues, which may miss cells on loose lines), or
+                       left-right (overlap of the leftmost and rightmost placements, faster but
+                       deduces fewer cells)
+    --line-cache <n>   remember the last n line solves, reusing them for lines that are unchanged
+    --transpositions   remember board states with no solution, so they are never searched twice
+    --learn            after each failed guess, skip the other value of guesses that played no
+                       part in it, and never again make every guess of a set that failed together
+    --restarts <n>     after n guesses without an answer, start guessing over with ties broken
+                       differently, allowing n, n, 2n, n, n, 2n, 4n, ... guesses per attempt
+    --best-first       guess next on whichever board found so far has the fewest unknown cells,
+                       instead of searching depth first; uses more memory, and can not be used
+                       with --trace
+    --time-phases      measure and print the time spent line solving, checking lines, probing,
+                       guessing and copying boards; reads the clock for every line solved
+    --auto             pick the line solver, probing and heuristic for each puzzle from its
+                       size, how much of it the clues fill and how loose its lines are,
+                       overriding --line-alg, --probe, --crossings and --heuristic
+    --verbose          print the line solver, heuristic and checks picked by --auto for each
+                       puzzle; can only be used with --auto
+    --threads <n>      search with n threads sharing the branches still to search; can not be
+                       used with --trace, --learn, --restarts or --best-first
+    --jobs <n>         with --all, solve n puzzles at once; --timeout and --max-branches apply
+                       to each puzzle
+    --check-against <strategy>
+                       solve again another way and fail if the two disagree: graph, left-right
+                       or automaton (line solver), depth-first or best-first (search), or
+                       brute-force (every combination of rows, skipped for large puzzles)
+    --output <fmt>     print results as text (the default), json, or a standalone html report
+
+A clue may be a length (3), a range of lengths (3-5), a hidden length (?) that can be any
+length, or, as the only clue of its line, the line's total number of filled cells (=7).
+
+Grids of color puzzles are printed in their colors; set NO_COLOR to a non-empty value to print
+every grid and heatmap without terminal colors.";
+
+/// Number of guesses allowed while looking for lines that conflict
+const CONFLICT_BRANCHES: usize = 10000;
+
+/// File extensions that are recognized as puzzles when searching directories
+const PUZZLE_EXTENSIONS: &[&str] = &["puzzle", "xml", "non", "g", "txt"];
+
+/// Find lines that show why a puzzle has no solution, if solving it found a contradiction
+fn find_conflict(initial: &Board, result: SolveResult) -> Option<Vec<LineInfo>> {
+    if result == SolveResult::Contradiction {
+        solver::find_conflicting_lines(initial, CONFLICT_BRANCHES)
+    } else {
+        None
+    }
+}
+
+/// List lines as text, such as "row 3, column 5"
+fn line_names(lines: &[LineInfo]) -> String {
+    let names: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    names.join(", ")
+}
+
+/// Whether terminal colors are turned off, by setting NO_COLOR to anything but an empty string
+fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// Display a board with the given characters, without terminal colors if NO_COLOR is set
+fn display_board<'a>(b: &'a Board, chars: &'a CharMap) -> BoardDisplay<'a> {
+    let display = b.display_with(chars);
+    if no_color() {
+        display.monochrome()
+    } else {
+        display
+    }
+}
+
+/// Print an error message and the usage, then exit
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+/// Expand directories into the puzzles they contain, sorted by name
+fn expand_paths(paths: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut ret = Vec::new();
+    for path in paths {
+        let path = PathBuf::from(path);
+        if !path.is_dir() {
+            ret.push(path);
+            continue;
+        }
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?.path();
+            let is_puzzle = entry
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| PUZZLE_EXTENSIONS.contains(&ext));
+            if entry.is_file() && is_puzzle {
+                entries.push(entry);
+            }
+        }
+        entries.sort();
+        ret.extend(entries);
+    }
+    Ok(ret)
+}
+
+/// Where a single puzzle to solve comes from
+enum PuzzleSource<'a> {
+    File(&'a Path),
+    Webpbn(u32),
+}
+
+impl<'a> PuzzleSource<'a> {
+    /// Get a name for this puzzle to show in messages
+    fn name(&self) -> String {
+        match *self {
+            PuzzleSource::File(path) => path.display().to_string(),
+            PuzzleSource::Webpbn(id) => format!("webpbn #{}", id),
+        }
+    }
+}
+
+/// Download a puzzle from webpbn.com
+#[cfg(feature = "http")]
+fn fetch_webpbn(id: u32) -> Result<Board, ParseError> {
+    Board::fetch_webpbn(id)
+}
+
+/// Download a puzzle from webpbn.com
+#[cfg(not(feature = "http"))]
+fn fetch_webpbn(_id: u32) -> Result<Board, ParseError> {
+    Err(ParseError::Unsupported(
+        "downloading puzzles requires the http feature".to_string(),
+    ))
+}
+
+/// How the results of solving are printed
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// A JSON object, for scripts
+    Json,
+    /// A standalone HTML page, for sharing
+    Html,
+}
+
+/// Options for the solve command
+struct SolveOptions {
+    /// Solve every puzzle given
+    all: bool,
+    /// How results are printed
+    output: OutputFormat,
+    /// Characters used to read and print grids
+    chars: CharMap,
+    /// Fail if a puzzle has more than one solution
+    require_unique: bool,
+    /// Solve puzzles as if their lines wrap around the edges of the board
+    toroidal: bool,
+    /// Configuration for the solver
+    config: SolverConfig,
+    /// File to write the board's final state to
+    save: Option<PathBuf>,
+    /// File to write a JSON replay of the solve to
+    trace: Option<PathBuf>,
+    /// Another way to solve each puzzle, whose result must agree with the first
+    check_against: Option<Strategy>,
+    /// Number of puzzles solved at once with --all
+    jobs: usize,
+    /// Pick the line solver, probing and heuristic for each puzzle by its profile
+    auto: bool,
+    /// Print the configuration picked by auto for each puzzle
+    verbose: bool,
+}
+
+impl Default for SolveOptions {
+    fn default() -> SolveOptions {
+        SolveOptions {
+            all: false,
+            output: OutputFormat::default(),
+            chars: CharMap::default(),
+            require_unique: false,
+            toroidal: false,
+            config: SolverConfig {
+                progress: Some(ProgressCallback::new(print_progress)),
+                ..SolverConfig::default()
+            },
+            save: None,
+            trace: None,
+            check_against: None,
+            jobs: 1,
+            auto: false,
+            verbose: false,
+        }
+    }
+}
+
+impl SolveOptions {
+    /// Get the configuration to solve a puzzle with
+    fn config_for(&self, b: &Board) -> SolverConfig {
+        if self.auto {
+            solver::auto_config(b, &self.config)
+        } else {
+            self.config.clone()
+        }
+    }
+}
+
+/// A way of solving a puzzle that another can be checked against
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Strategy {
+    /// Solve lines with the given line solver
+    Lines(LineSolver),
+    /// Search depth first
+    DepthFirst,
+    /// Search best first
+    BestFirst,
+    /// Try every combination of row fillings, for small boards only
+    BruteForce,
+}
+
+impl Strategy {
+    fn parse(value: &str) -> Option<Strategy> {
+        match value {
+            "graph" => Some(Strategy::Lines(LineSolver::Graph)),
+            "left-right" => Some(Strategy::Lines(LineSolver::LeftRight)),
+            "automaton" => Some(Strategy::Lines(LineSolver::Automaton)),
+            "depth-first" => Some(Strategy::DepthFirst),
+            "best-first" => Some(Strategy::BestFirst),
+            "brute-force" => Some(Strategy::BruteForce),
+            _ => None,
+        }
+    }
+
+    /// Get the name this strategy is given on the command line
+    fn name(self) -> &'static str {
+        match self {
+            Strategy::Lines(LineSolver::Graph) => "graph",
+            Strategy::Lines(LineSolver::LeftRight) => "left-right",
+            Strategy::Lines(LineSolver::Automaton) => "automaton",
+            Strategy::DepthFirst => "depth-first",
+            Strategy::BestFirst => "best-first",
+            Strategy::BruteForce => "brute-force",
+        }
+    }
+
+    /// Solve a board this way, with the rest of the configuration unchanged.
+    /// Boards too large to brute force give SolveResult::Exhausted(Limit::Branches).
+    fn solve(self, b: &mut Board, config: &SolverConfig) -> SolveResult {
+        let mut config = SolverConfig {
+            trace: false,
+            progress: None,
+            ..config.clone()
+        };
+        match self {
+            Strategy::Lines(line_solver) => config.line_solver = line_solver,
+            Strategy::DepthFirst => config.best_first = false,
+            Strategy::BestFirst => {
+                // best first search is only done with one thread
+                config.best_first = true;
+                config.threads = 1;
+            }
+            Strategy::BruteForce => return brute_force(b),
+        }
+        solver::stupid_branched_solver_set_with(b, &config).result
+    }
+}
+
+/// Solve a board with solver::brute_force_solutions, giving up if it is too large
+fn brute_force(b: &mut Board) -> SolveResult {
+    match solver::brute_force_solutions(b, 1).map(|mut solutions| solutions.pop()) {
+        Some(Some(solution)) => {
+            *b = solution;
+            SolveResult::Success
+        }
+        Some(None) => SolveResult::Contradiction,
+        None => SolveResult::Exhausted(Limit::Branches),
+    }
+}
+
+/// Solve a puzzle again with the given strategy, and describe how its result disagrees with
+/// the first one, if it does. Solutions may differ if both solve the puzzle, and results are
+/// not compared if either solve gave up.
+fn cross_check(
+    initial: &Board,
+    result: SolveResult,
+    solution: &Board,
+    strategy: Strategy,
+    config: &SolverConfig,
+) -> Option<String> {
+    let mut other = initial.clone();
+    let other_result = strategy.solve(&mut other, config);
+    let gave_up = |result| matches!(result, SolveResult::Timeout | SolveResult::Exhausted(_));
+    if gave_up(result) || gave_up(other_result) {
+        return None;
+    }
+    if result != other_result {
+        return Some(format!(
+            "{:?} with the first strategy but {:?} with {}",
+            result,
+            other_result,
+            strategy.name()
+        ));
+    }
+    if result != SolveResult::Success {
+        return None;
+    }
+    for (board, name) in [(solution, "the first strategy"), (&other, strategy.name())].iter() {
+        if let Err(mismatches) = initial.check_solution(board) {
+            return Some(format!(
+                "the solution from {} is wrong: {}",
+                name, mismatches[0]
+            ));
+        }
+    }
+    None
+}
+
+/// Print how far a solve has gotten, so that long solves show signs of life.
+/// This goes to stderr so that it never ends up inside a --output json report on stdout.
+fn print_progress(progress: Progress) {
+    eprintln!(
+        "{}/{} cells, {} branches",
+        progress.cells_solved, progress.cells_total, progress.branches
+    );
+}
+
+/// Create a file and write to it, printing any error
+fn write_file<F>(path: &Path, write: F) -> bool
+where
+    F: FnOnce(&mut io::BufWriter<fs::File>) -> io::Result<()>,
+{
+    let result = fs::File::create(path).and_then(|f| {
+        let mut handle = io::BufWriter::new(f);
+        write(&mut handle)?;
+        io::Write::flush(&mut handle)
+    });
+    if let Err(e) = result {
+        eprintln!("{}: {}", path.display(), e);
+        return false;
+    }
+    true
+}
+
+/// Solve a single puzzle, printing the board before and after
+fn solve_one(source: PuzzleSource, options: &SolveOptions) -> i32 {
+    let json = options.output == OutputFormat::Json;
+    let text = options.output == OutputFormat::Text;
+    let name = source.name();
+    let result = match source {
+        PuzzleSource::File(path) => format::read_file_with(path, &options.chars),
+        PuzzleSource::Webpbn(id) => fetch_webpbn(id),
+    };
+    let mut b = match result {
+        Ok(b) => b,
+        Err(e) => {
+            if json {
+                println!(
+                    "{}",
+                    report::error_report_json(&name, "error", &[e.to_string()])
+                );
+            } else {
+                eprintln!("{}: {}", name, e);
+            }
+            return 1;
+        }
+    };
+    if options.toroidal {
+        b.set_toroidal(true);
+    }
+    if text {
+        println!("{}", display_board(&b, &options.chars));
+    }
+    if let Err(errors) = b.validate() {
+        let errors: Vec<String> = errors.iter().map(|x| x.to_string()).collect();
+        if json {
+            println!("{}", report::error_report_json(&name, "invalid", &errors));
+        } else {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+        }
+        return 1;
+    }
+    let initial = b.clone();
+    let config = SolverConfig {
+        trace: options.trace.is_some(),
+        ..options.config_for(&b)
+    };
+    if text && options.auto && options.verbose {
+        println!(
+            "Solving with the {:?} line solver, {:?} heuristic{}{}",
+            config.line_solver,
+            config.heuristic,
+            if config.probing { ", probing" } else { "" },
+            if config.crossings { ", crossings" } else { "" },
+        );
+    }
+    let SolveOutcome {
+        result,
+        stats,
+        guesses,
+        ..
+    } = solver::stupid_branched_solver_set_with(&mut b, &config);
+    // searched for separately, rather than trusting SolveOutcome::unexplored, so that no
+    // deduction the solver makes without guessing can hide another solution
+    let other = if options.require_unique && result == SolveResult::Success {
+        solver::find_other_solution_with(&initial, &b, &config)
+    } else {
+        Ok(None)
+    };
+    let unique = if options.require_unique && result == SolveResult::Success {
+        Some(match other {
+            Ok(None) => Uniqueness::Unique,
+            Ok(Some(_)) => Uniqueness::Ambiguous,
+            Err(_) => Uniqueness::Unknown,
+        })
+    } else {
+        None
+    };
+    let gave_up = other.as_ref().err().copied();
+    let other = other.unwrap_or(None);
+    let conflict = find_conflict(&initial, result);
+    let disagreement = options
+        .check_against
+        .and_then(|strategy| cross_check(&initial, result, &b, strategy, &config));
+    if let Some(disagreement) = disagreement.as_ref() {
+        eprintln!("{}: solvers disagree: {}", name, disagreement);
+    }
+    match options.output {
+        OutputFormat::Text => {
+            println!("{:?}", result);
+            if let Some(lines) = conflict.as_ref() {
+                println!(
+                    "These lines can not all be satisfied: {}",
+                    line_names(lines)
+                );
+            }
+            println!("{}", stats);
+            if let Some(phases) = stats.phases.as_ref() {
+                println!("Time by phase: {}", phases);
+            }
+            if !guesses.is_empty() {
+                let guesses: Vec<String> = guesses.iter().map(|x| x.to_string()).collect();
+                println!("Guesses taken: {}", guesses.join(", "));
+            }
+            println!("{}x{}", b.get_width(), b.get_height());
+            let grid = b.clone_without_constraints();
+            println!("{}", display_board(&grid, &options.chars));
+            if let Some(partial) = stats.best_partial.as_ref() {
+                let unknown = (0..partial.get_num_cells())
+                    .filter(|&i| partial.get_cell_index(i) == Cell::Unknown)
+                    .count();
+                println!(
+                    "Closest guess before giving up, with {} cells unknown:",
+                    unknown
+                );
+                let grid = partial.clone_without_constraints();
+                println!("{}", display_board(&grid, &options.chars));
+            }
+            if let Some(agreed) = stats.explored_agreement.as_ref() {
+                let known = (0..agreed.get_num_cells())
+                    .filter(|&i| agreed.get_cell_index(i) != Cell::Unknown)
+                    .count();
+                println!("Cells every branch searched agreed on, {} in all:", known);
+                let grid = agreed.clone_without_constraints();
+                println!("{}", display_board(&grid, &options.chars));
+            }
+            if let Some(other) = other.as_ref() {
+                let differences = b.diff(other).map_or(0, |diff| diff.len());
+                println!(
+                    "Ambiguous: another solution differs in {} cells",
+                    differences
+                );
+                let grid = other.clone_without_constraints();
+                println!("{}", display_board(&grid, &options.chars));
+                if let Some(agreement) = b.agreement(other) {
+                    println!("Cells that differ between the solutions:");
+                    let grid = agreement.clone_without_constraints();
+                    println!("{}", display_board(&grid, &options.chars));
+                }
+            } else if let Some(result) = gave_up {
+                println!(
+                    "Uniqueness unknown: the search for another solution gave up ({:?})",
+                    result
+                );
+            } else if unique.is_some() {
+                println!("Unique");
+            }
+        }
+        OutputFormat::Json => {
+            let json =
+                report::solve_report_json(&name, &b, result, &stats, unique, conflict.as_deref());
+            println!("{}", json);
+        }
+        OutputFormat::Html => {
+            let stdout = io::stdout();
+            let handle = io::BufWriter::new(stdout.lock());
+            if let Err(e) = report::write_html_report(handle, &name, &b, result, &stats) {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    }
+    if let Some(path) = options.save.as_deref() {
+        if !write_file(path, |f| b.write_state(f)) {
+            return 1;
+        }
+    }
+    if let Some(path) = options.trace.as_deref() {
+        let trace = stats.trace.as_deref().unwrap_or(&[]);
+        if !write_file(path, |f| initial.write_trace_json(trace, f)) {
+            return 1;
+        }
+    }
+    if result == SolveResult::Success
+        && unique != Some(Uniqueness::Ambiguous)
+        && unique != Some(Uniqueness::Unknown)
+        && disagreement.is_none()
+    {
+        0
+    } else {
+        1
+    }
+}
+
+/// The outcome of solving one of the puzzles given to solve_all
+struct ListedSolve {
+    name: String,
+    /// The solve, or the status and problems of a puzzle that could not be solved
+    outcome: Result<ListedResult, (&'static str, Vec<String>)>,
+}
+
+/// A puzzle solved by solve_all
+struct ListedResult {
+    board: Board,
+    result: SolveResult,
+    stats: SolveStats,
+    unique: Option<Uniqueness>,
+    conflict: Option<Vec<LineInfo>>,
+    /// How the solve disagreed with SolveOptions::check_against, if it did
+    disagreement: Option<String>,
+}
+
+/// Solve one of the puzzles given to solve_all
+fn solve_listed(path: &Path, options: &SolveOptions) -> ListedSolve {
+    let name = path.display().to_string();
+    let mut b = match format::read_file_with(path, &options.chars) {
+        Ok(b) => b,
+        Err(e) => {
+            return ListedSolve {
+                name,
+                outcome: Err(("error", vec![e.to_string()])),
+            }
+        }
+    };
+    if options.toroidal {
+        b.set_toroidal(true);
+    }
+    if let Err(problems) = b.validate() {
+        let problems = problems.iter().map(|x| x.to_string()).collect();
+        return ListedSolve {
+            name,
+            outcome: Err(("invalid", problems)),
+        };
+    }
+    let initial = b.clone();
+    let config = options.config_for(&b);
+    let SolveOutcome { result, stats, .. } =
+        solver::stupid_branched_solver_set_with(&mut b, &config);
+    // as in solve_one, other solutions are always searched for
+    let unique = if options.require_unique && result == SolveResult::Success {
+        Some(
+            match solver::find_other_solution_with(&initial, &b, &config) {
+                Ok(None) => Uniqueness::Unique,
+                Ok(Some(_)) => Uniqueness::Ambiguous,
+                Err(_) => Uniqueness::Unknown,
+            },
+        )
+    } else {
+        None
+    };
+    let conflict = find_conflict(&initial, result);
+    let disagreement = options
+        .check_against
+        .and_then(|strategy| cross_check(&initial, result, &b, strategy, &config));
+    ListedSolve {
+        name,
+        outcome: Ok(ListedResult {
+            board: b,
+            result,
+            stats,
+            unique,
+            conflict,
+            disagreement,
+        }),
+    }
+}
+
+/// Solve every given puzzle, printing a line for each and a summary at the end.
+/// Puzzles are solved on SolveOptions::jobs threads at once, but printed in order.
+fn solve_all(paths: &[String], options: &SolveOptions) -> i32 {
+    let json = options.output == OutputFormat::Json;
+    let paths = match expand_paths(paths) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let start = Instant::now();
+    let mut solved = 0;
+    let mut unsolved = 0;
+    let mut ambiguous = 0;
+    let mut unchecked = 0;
+    let mut timeouts = 0;
+    let mut exhausted = 0;
+    let mut errors = 0;
+    let mut total_time = Duration::default();
+    let mut total_branches = 0;
+    let mut reports = Vec::new();
+    let job = |path: &PathBuf| solve_listed(path, options);
+    util::for_each_parallel(&paths, options.jobs, job, |listed| {
+        let name = listed.name;
+        let solve = match listed.outcome {
+            Ok(solve) => solve,
+            Err((status, problems)) => {
+                if json {
+                    reports.push(report::error_report_json(&name, status, &problems));
+                } else {
+                    println!("{}: {}: {}", name, status, problems[0]);
+                }
+                errors += 1;
+                return;
+            }
+        };
+        let ListedResult {
+            board: b,
+            result,
+            stats,
+            unique,
+            conflict,
+            disagreement,
+        } = solve;
+        if let Some(disagreement) = disagreement.as_ref() {
+            eprintln!("{}: solvers disagree: {}", name, disagreement);
+            errors += 1;
+        }
+        match (result, unique) {
+            (SolveResult::Success, Some(Uniqueness::Ambiguous)) => ambiguous += 1,
+            (SolveResult::Success, Some(Uniqueness::Unknown)) => unchecked += 1,
+            (SolveResult::Success, _) => solved += 1,
+            (SolveResult::Contradiction, _) => unsolved += 1,
+            (SolveResult::Timeout, _) => timeouts += 1,
+            (SolveResult::Exhausted(_), _) => exhausted += 1,
+        }
+        if json {
+            reports.push(report::solve_report_json(
+                &name,
+                &b,
+                result,
+                &stats,
+                unique,
+                conflict.as_deref(),
+            ));
+        } else {
+            let note = match conflict.as_ref() {
+                Some(lines) => format!(" ({})", line_names(lines)),
+                None if unique == Some(Uniqueness::Ambiguous) => " (ambiguous)".to_string(),
+                None if unique == Some(Uniqueness::Unknown) => " (uniqueness unknown)".to_string(),
+                None => String::new(),
+            };
+            println!(
+                "{}: {}{} in {:.3}s, {} branches",
+                name,
+                report::result_name(result),
+                note,
+                stats.elapsed.as_secs_f64(),
+                stats.branches
+            );
+        }
+        total_time += stats.elapsed;
+        total_branches += stats.branches;
+    });
+    let wall_clock = start.elapsed();
+    if json {
+        println!("{{\"puzzles\":[");
+        println!("{}", reports.join(",\n"));
+        println!(
+            "],\"summary\":{{\"puzzles\":{},\"solved\":{},\"ambiguous\":{},\"uniqueness_unknown\":{},\"contradictions\":{},\"timeouts\":{},\"exhausted\":{},\"errors\":{},\"elapsed\":{:.6},\"wall_clock\":{:.6},\"branches\":{}}}}}",
+            paths.len(),
+            solved,
+            ambiguous,
+            unchecked,
+            unsolved,
+            timeouts,
+            exhausted,
+            errors,
+            total_time.as_secs_f64(),
+            wall_clock.as_secs_f64(),
+            total_branches
+        );
+    } else {
+        // with several jobs, the solve times add up to more than the time taken
+        let wall_clock = if options.jobs > 1 {
+            format!(" ({:.3}s wall clock)", wall_clock.as_secs_f64())
+        } else {
+            String::new()
+        };
+        // only checked with --require-unique and limits
+        let unchecked = if unchecked > 0 {
+            format!(", {} of unknown uniqueness", unchecked)
+        } else {
+            String::new()
+        };
+        println!(
+            "{} puzzles: {} solved, {} ambiguous{}, {} contradictions, {} timeouts, {} exhausted, {} errors in {:.3}s{}, {} branches",
+            paths.len(),
+            solved,
+            ambiguous,
+            unchecked,
+            unsolved,
+            timeouts,
+            exhausted,
+            errors,
+            total_time.as_secs_f64(),
+            wall_clock,
+            total_branches
+        );
+    }
+    if unsolved + ambiguous + unchecked + timeouts + exhausted + errors > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Solve every puzzle in a pack, printing a line for each and a summary at the end.
+/// If an output path is given, the solutions are written to it as a pack.
+/// Puzzles are read and their solutions written as they are solved, so the whole pack is
+/// never kept in memory.
+fn run_solve_pack(args: &[String]) -> i32 {
+    let mut config = SolverConfig::default();
+    let mut jobs = 1;
+    let mut toroidal = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--jobs" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => jobs = n,
+                _ => usage_error("--jobs requires a positive number"),
+            },
+            "--timeout" => config.max_duration = Some(parse_timeout(args.next())),
+            "--max-branches" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) => config.max_branches = Some(n),
+                _ => usage_error("--max-branches requires a number"),
+            },
+            "--toroidal" => toroidal = true,
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(Path::new(path)),
+        }
+    }
+    let (pack_path, output_path) = match paths.as_slice() {
+        [pack] => (*pack, None),
+        [pack, output] => (*pack, Some(*output)),
+        _ => usage_error("expected a pack and an optional solution pack"),
+    };
+    let handle = match fs::File::open(pack_path) {
+        Ok(f) => io::BufReader::new(f),
+        Err(e) => {
+            eprintln!("{}: {}", pack_path.display(), e);
+            return 1;
+        }
+    };
+    let mut output = match output_path {
+        Some(path) => match fs::File::create(path) {
+            Ok(f) => Some(io::BufWriter::new(f)),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                return 1;
+            }
+        },
+        None => None,
+    };
+    let mut write_error = None;
+    let start = Instant::now();
+    let mut solved = 0;
+    let mut unsolved = 0;
+    let mut timeouts = 0;
+    let mut exhausted = 0;
+    let mut errors = 0;
+    let mut total_time = Duration::default();
+    let job = |entry: Result<pack::PackEntry, ParseError>| {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("{}: {}", pack_path.display(), e)),
+        };
+        let mut b = entry.board;
+        if toroidal {
+            b.set_toroidal(true);
+        }
+        if let Err(problems) = b.validate() {
+            return Err(format!("{}: invalid: {}", entry.name, problems[0]));
+        }
+        let SolveOutcome { result, stats, .. } =
+            solver::stupid_branched_solver_set_with(&mut b, &config);
+        Ok((entry.name, b, result, stats))
+    };
+    util::for_each_parallel(PackReader::new(handle), jobs, job, |solve| match solve {
+        Err(problem) => {
+            println!("{}", problem);
+            errors += 1;
+        }
+        Ok((name, b, result, stats)) => {
+            println!(
+                "{}: {} in {:.3}s, {} branches",
+                name,
+                report::result_name(result),
+                stats.elapsed.as_secs_f64(),
+                stats.branches
+            );
+            total_time += stats.elapsed;
+            match result {
+                SolveResult::Success => solved += 1,
+                SolveResult::Contradiction => unsolved += 1,
+                SolveResult::Timeout => timeouts += 1,
+                SolveResult::Exhausted(_) => exhausted += 1,
+            }
+            if result != SolveResult::Success || write_error.is_some() {
+                return;
+            }
+            if let Some(f) = output.as_mut() {
+                if let Err(e) = pack::write_solution_pack(f, std::iter::once((name.as_str(), &b))) {
+                    write_error = Some(e);
+                }
+            }
+        }
+    });
+    let wall_clock = if jobs > 1 {
+        format!(" ({:.3}s wall clock)", start.elapsed().as_secs_f64())
+    } else {
+        String::new()
+    };
+    println!(
+        "{} puzzles: {} solved, {} contradictions, {} timeouts, {} exhausted, {} errors in {:.3}s{}",
+        solved + unsolved + timeouts + exhausted + errors,
+        solved,
+        unsolved,
+        timeouts,
+        exhausted,
+        errors,
+        total_time.as_secs_f64(),
+        wall_clock
+    );
+    if let (Some(path), Some(f)) = (output_path, output.as_mut()) {
+        let result = match write_error {
+            Some(e) => Err(e),
+            None => io::Write::flush(f),
+        };
+        if let Err(e) = result {
+            eprintln!("{}: {}", path.display(), e);
+            return 1;
+        }
+    }
+    if unsolved + timeouts + exhausted + errors > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Read a puzzle for a command that works on a single one, making its lines wrap around if
+/// toroidal is set. Prints any error, or every problem that makes the puzzle invalid, and
+/// returns None.
+fn load_puzzle(path: &Path, toroidal: bool) -> Option<Board> {
+    let mut b = match format::read_file(path) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if toroidal {
+        b.set_toroidal(true);
+    }
+    if let Err(errors) = b.validate() {
+        for error in errors {
+            eprintln!("{}: {}", path.display(), error);
+        }
+        return None;
+    }
+    Some(b)
+}
+
+/// Count a puzzle's solutions, up to a cap, and optionally show where they differ
+fn run_count(args: &[String]) -> i32 {
+    let mut cap = 2;
+    let mut diff = false;
+    let mut toroidal = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cap" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => cap = n,
+                _ => usage_error("--cap requires a positive number"),
+            },
+            "--diff" => diff = true,
+            "--toroidal" => toroidal = true,
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(Path::new(path)),
+        }
+    }
+    let path = match paths.as_slice() {
+        [path] => path,
+        _ => usage_error("expected a single puzzle"),
+    };
+    let b = match load_puzzle(path, toroidal) {
+        Some(b) => b,
+        None => return 1,
+    };
+    if diff {
+        return print_solution_diff(path, &b, cap);
+    }
+    let count = solver::count_solutions(&b, cap);
+    println!("{}: {}", path.display(), count);
+    match count {
+        SolutionCount::Exact(1) => 0,
+        _ => 1,
+    }
+}
+
+/// Look for a solution to a puzzle too large to solve exactly, by local search or a genetic
+/// algorithm, printing the best board found and the lines it violates
+fn run_approximate(args: &[String]) -> i32 {
+    let mut config = ApproximateConfig::default();
+    let mut genetic = false;
+    let mut toroidal = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) => config.max_steps = n,
+                _ => usage_error("--steps requires a number"),
+            },
+            "--seed" => match args.next().map(|x| x.parse::<u64>()) {
+                Some(Ok(seed)) => config.seed = seed,
+                _ => usage_error("--seed requires a number"),
+            },
+            "--timeout" => config.max_duration = Some(parse_timeout(args.next())),
+            "--genetic" => genetic = true,
+            "--toroidal" => toroidal = true,
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(Path::new(path)),
+        }
+    }
+    let path = match paths.as_slice() {
+        [path] => path,
+        _ => usage_error("expected a single puzzle"),
+    };
+    let b = match load_puzzle(path, toroidal) {
+        Some(b) => b,
+        None => return 1,
+    };
+    let approximation = if genetic {
+        solver::genetic_search(&b, &config)
+    } else {
+        solver::local_search(&b, &config)
+    };
+    let approximation = match approximation {
+        Some(approximation) => approximation,
+        None => {
+            println!("{:?}", SolveResult::Contradiction);
+            return 1;
+        }
+    };
+    let grid = approximation.board.clone_without_constraints();
+    println!("{}", display_board(&grid, &CharMap::default()));
+    println!("{} steps", approximation.steps);
+    if approximation.is_solution() {
+        println!("Solved");
+        0
+    } else {
+        println!(
+            "{} lines violated: {}",
+            approximation.violated.len(),
+            line_names(&approximation.violated)
+        );
+        1
+    }
+}
+
+/// Find up to cap solutions of a puzzle, and print them combined, with every cell that is not
+/// the same in all of them shown as unknown
+fn print_solution_diff(path: &Path, b: &Board, cap: usize) -> i32 {
+    let solutions: Vec<Board> = solver::solve_all(b).take(cap).collect();
+    let count = if solutions.len() < cap {
+        SolutionCount::Exact(solutions.len())
+    } else {
+        SolutionCount::AtLeast(solutions.len())
+    };
+    println!("{}: {}", path.display(), count);
+    let mut solutions = solutions.into_iter();
+    let first = match solutions.next() {
+        Some(first) => first,
+        None => return 1,
+    };
+    let combined = solutions.fold(first, |combined, solution| {
+        combined.agreement(&solution).unwrap_or(combined)
+    });
+    let differing = (0..combined.get_num_cells())
+        .filter(|&i| combined.get_cell_index(i) == Cell::Unknown)
+        .count();
+    if differing > 0 {
+        println!("{} cells differ between the solutions found:", differing);
+    }
+    let grid = combined.clone_without_constraints();
+    println!("{}", display_board(&grid, &CharMap::default()));
+    match count {
+        SolutionCount::Exact(1) => 0,
+        _ => 1,
+    }
+}
+
+/// Print the cells that are the same in every solution of a puzzle
+fn run_forced(args: &[String]) -> i32 {
+    let mut config = SolverConfig::default();
+    let mut toroidal = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => config.max_duration = Some(parse_timeout(args.next())),
+            "--max-branches" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) => config.max_branches = Some(n),
+                _ => usage_error("--max-branches requires a number"),
+            },
+            "--toroidal" => toroidal = true,
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(Path::new(path)),
+        }
+    }
+    let path = match paths.as_slice() {
+        [path] => path,
+        _ => usage_error("expected a single puzzle"),
+    };
+    let b = match load_puzzle(path, toroidal) {
+        Some(b) => b,
+        None => return 1,
+    };
+    let ForcedCells {
+        board: forced,
+        undecided,
+    } = match solver::forced_cells_with(&b, &config) {
+        Some(forced) => forced,
+        None => {
+            println!("{}: no solutions", path.display());
+            return 1;
+        }
+    };
+    let unknown = (0..forced.get_num_cells())
+        .filter(|&i| forced.get_cell_index(i) == Cell::Unknown)
+        .count();
+    println!(
+        "{}: {} of {} cells are the same in every solution",
+        path.display(),
+        forced.get_num_cells() - unknown,
+        forced.get_num_cells()
+    );
+    if undecided > 0 {
+        println!(
+            "{}: {} cells could not be decided within the limits",
+            path.display(),
+            undecided
+        );
+    }
+    let grid = forced.clone_without_constraints();
+    println!("{}", display_board(&grid, &CharMap::default()));
+    0
+}
+
+/// Print the next cells of a puzzle that can be deduced from a single line, and why
+fn run_hint(args: &[String]) -> i32 {
+    let mut toroidal = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--toroidal" => toroidal = true,
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(Path::new(path)),
+        }
+    }
+    let path = match paths.as_slice() {
+        [path] => path,
+        _ => usage_error("expected a single puzzle"),
+    };
+    let b = match load_puzzle(path, toroidal) {
+        Some(b) => b,
+        None => return 1,
+    };
+    match solver::next_hint(&b) {
+        Some(hint) => {
+            println!("{}", hint);
+            for explanation in hint.explain(&b) {
+                println!("  {}", explanation);
+            }
+            0
+        }
+        None => {
+            println!(
+                "{}: no cells can be deduced from a single line",
+                path.display()
+            );
+            1
+        }
+    }
+}
+
+/// Print the estimated probability, as a percentage, that each cell of a puzzle is filled, or
+/// a heatmap of the probabilities
+fn run_probabilities(args: &[String]) -> i32 {
+    let mut heatmap = false;
+    let mut toroidal = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--heatmap" => heatmap = true,
+            "--toroidal" => toroidal = true,
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(Path::new(path)),
+        }
+    }
+    let path = match paths.as_slice() {
+        [path] => path,
+        _ => usage_error("expected a single puzzle"),
+    };
+    let b = match load_puzzle(path, toroidal) {
+        Some(b) => b,
+        None => return 1,
+    };
+    match solver::fill_probabilities(&b) {
+        Some(probabilities) if heatmap && no_color() => {
+            print!("{}", probabilities.heatmap().monochrome());
+            0
+        }
+        Some(probabilities) if heatmap => {
+            print!("{}", probabilities.heatmap());
+            0
+        }
+        Some(probabilities) => {
+            print!("{}", probabilities);
+            0
+        }
+        None => {
+            println!("{:?}", SolveResult::Contradiction);
+            1
+        }
+    }
+}
+
+/// Rate how hard each given puzzle is, printing a line for each
+fn run_rate(args: &[String]) -> i32 {
+    let mut config = SolverConfig::default();
+    let mut toroidal = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-branches" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) => config.max_branches = Some(n),
+                _ => usage_error("--max-branches requires a number"),
+            },
+            "--toroidal" => toroidal = true,
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(path.to_string()),
+        }
+    }
+    if paths.is_empty() {
+        usage_error("expected at least one puzzle");
+    }
+    let paths = match expand_paths(&paths) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let mut failed = 0;
+    for path in paths.iter() {
+        let mut b = match format::read_file(path) {
+            Ok(b) => b,
+            Err(e) => {
+                println!("{}: error: {}", path.display(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        if toroidal {
+            b.set_toroidal(true);
+        }
+        if let Err(problems) = b.validate() {
+            println!("{}: invalid: {}", path.display(), problems[0]);
+            failed += 1;
+            continue;
+        }
+        match solver::rate(&b, &config) {
+            Ok(rating) => println!("{}: {}", path.display(), rating),
+            Err(result) => {
+                println!("{}: {}", path.display(), report::result_name(result));
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Parse the value of --timeout, a number of seconds
+fn parse_timeout(value: Option<&String>) -> Duration {
+    match value.and_then(|x| x.parse::<f64>().ok()) {
+        Some(secs) => match Duration::try_from_secs_f64(secs) {
+            Ok(duration) => duration,
+            Err(_) => usage_error("--timeout must not be negative"),
+        },
+        None => usage_error("--timeout requires a number of seconds"),
+    }
+}
+
+/// Parse the value of --line-alg
+fn parse_line_solver(value: Option<&String>) -> LineSolver {
+    match value.map(|x| x.as_str()) {
+        Some("graph") => LineSolver::Graph,
+        Some("left-right") => LineSolver::LeftRight,
+        Some("automaton") => LineSolver::Automaton,
+        _ => usage_error("--line-alg must be graph, left-right or automaton"),
+    }
+}
+
+/// Timings of solving one puzzle several times, as measured by the bench command
+struct BenchResult {
+    result: SolveResult,
+    min: Duration,
+    mean: Duration,
+    max: Duration,
+    branches: usize,
+    passes: usize,
+}
+
+/// Solve a puzzle the given number of times, timing each solve
+fn bench_one(b: &Board, config: &SolverConfig, runs: usize) -> BenchResult {
+    let mut times = Vec::with_capacity(runs);
+    let mut last = None;
+    for _ in 0..runs {
+        let mut board = b.clone();
+        let SolveOutcome { result, stats, .. } =
+            solver::stupid_branched_solver_set_with(&mut board, config);
+        times.push(stats.elapsed);
+        last = Some((result, stats));
+    }
+    let (result, stats) = last.unwrap();
+    BenchResult {
+        result,
+        min: times.iter().copied().min().unwrap(),
+        mean: times.iter().sum::<Duration>() / runs as u32,
+        max: times.iter().copied().max().unwrap(),
+        branches: stats.branches,
+        passes: stats.passes,
+    }
+}
+
+/// Solve each puzzle several times, printing how long it took and how much searching it needed,
+/// as text or as CSV
+fn run_bench(args: &[String]) -> i32 {
+    let mut config = SolverConfig::default();
+    let mut runs = 5;
+    let mut csv = false;
+    let mut toroidal = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => runs = n,
+                _ => usage_error("--runs requires a positive number"),
+            },
+            "--line-alg" => config.line_solver = parse_line_solver(args.next()),
+            "--max-branches" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) => config.max_branches = Some(n),
+                _ => usage_error("--max-branches requires a number"),
+            },
+            "--csv" => csv = true,
+            "--toroidal" => toroidal = true,
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(path.to_string()),
+        }
+    }
+    if paths.is_empty() {
+        usage_error("expected at least one puzzle");
+    }
+    let paths = match expand_paths(&paths) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let mut writer = csv::Writer::from_writer(io::stdout());
+    if csv {
+        let header = [
+            "file", "result", "runs", "min", "mean", "max", "branches", "passes",
+        ];
+        if let Err(e) = writer.write_record(header) {
+            eprintln!("{}", e);
+            return 1;
+        }
+    }
+    let mut failed = 0;
+    for path in paths.iter() {
+        let name = path.display().to_string();
+        let mut b = match format::read_file(path) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("{}: error: {}", name, e);
+                failed += 1;
+                continue;
+            }
+        };
+        if toroidal {
+            b.set_toroidal(true);
+        }
+        if let Err(problems) = b.validate() {
+            eprintln!("{}: invalid: {}", name, problems[0]);
+            failed += 1;
+            continue;
+        }
+        let bench = bench_one(&b, &config, runs);
+        if csv {
+            let record = [
+                name,
+                report::result_name(bench.result).to_string(),
+                runs.to_string(),
+                format!("{:.6}", bench.min.as_secs_f64()),
+                format!("{:.6}", bench.mean.as_secs_f64()),
+                format!("{:.6}", bench.max.as_secs_f64()),
+                bench.branches.to_string(),
+                bench.passes.to_string(),
+            ];
+            if let Err(e) = writer.write_record(&record) {
+                eprintln!("{}", e);
+                return 1;
+            }
+        } else {
+            println!(
+                "{}: {}, min {:.3}s, mean {:.3}s, max {:.3}s, {} branches, {} passes",
+                name,
+                report::result_name(bench.result),
+                bench.min.as_secs_f64(),
+                bench.mean.as_secs_f64(),
+                bench.max.as_secs_f64(),
+                bench.branches,
+                bench.passes
+            );
+        }
+    }
+    if let Err(e) = writer.flush() {
+        eprintln!("{}", e);
+        return 1;
+    }
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Check that a solution matches a puzzle, printing every mismatched row and column
+fn run_verify(args: &[String]) -> i32 {
+    let (solution_path, puzzle_path) = match args {
+        [solution, puzzle] => (Path::new(solution), Path::new(puzzle)),
+        _ => usage_error("expected a solution and a puzzle"),
+    };
+    let mut boards = Vec::new();
+    for path in [solution_path, puzzle_path].iter() {
+        match format::read_file(path) {
+            Ok(b) => boards.push(b),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                return 1;
+            }
+        }
+    }
+    let (solution, puzzle) = (&boards[0], &boards[1]);
+    if let Err(errors) = puzzle.validate() {
+        for error in errors {
+            eprintln!("{}: {}", puzzle_path.display(), error);
+        }
+        return 1;
+    }
+    match puzzle.check_solution(solution) {
+        Ok(()) => {
+            println!(
+                "{} solves {}",
+                solution_path.display(),
+                puzzle_path.display()
+            );
+            0
+        }
+        Err(mismatches) => {
+            for mismatch in mismatches {
+                println!("{}", mismatch);
+            }
+            1
+        }
+    }
+}
+
+/// Convert a puzzle to the format given by the output file's extension,
+/// such as .g for the Olsak solver or .xml and .non for pbnsolve
+fn run_export(args: &[String]) -> i32 {
+    let (input, output) = match args {
+        [input, output] => (Path::new(input), Path::new(output)),
+        _ => usage_error("expected a puzzle and an output file"),
+    };
+    let format = match output
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(format::Format::from_extension)
+    {
+        Some(format) => format,
+        None => usage_error("could not tell the output format from its extension"),
+    };
+    let b = match format::read_file(input) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{}: {}", input.display(), e);
+            return 1;
+        }
+    };
+    if write_file(output, |f| format::write_format(&b, f, format)) {
+        0
+    } else {
+        1
+    }
+}
+
+/// Find every file in a directory with the given extension, keyed by file stem
+fn find_by_stem(dir: &Path, extension: &str) -> io::Result<BTreeMap<String, PathBuf>> {
+    let mut ret = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(extension) {
+            continue;
+        }
+        if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+            ret.insert(stem.to_string(), path.clone());
+        }
+    }
+    Ok(ret)
+}
+
+/// Check that every puzzle in a directory has a matching solution in another directory,
+/// and that every pair matches
+fn run_check_corpus(args: &[String]) -> i32 {
+    let (puzzle_dir, solution_dir) = match args {
+        [puzzles, solutions] => (Path::new(puzzles), Path::new(solutions)),
+        _ => usage_error("expected a puzzle directory and a solution directory"),
+    };
+    let puzzles = find_by_stem(puzzle_dir, "puzzle");
+    let solutions = find_by_stem(solution_dir, "solution");
+    let (puzzles, solutions) = match (puzzles, solutions) {
+        (Ok(puzzles), Ok(solutions)) => (puzzles, solutions),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let mut problems = 0;
+    for stem in solutions.keys().filter(|stem| !puzzles.contains_key(*stem)) {
+        println!("{}: missing puzzle", solutions[stem].display());
+        problems += 1;
+    }
+    for (stem, puzzle_path) in puzzles.iter() {
+        let solution_path = match solutions.get(stem) {
+            Some(path) => path,
+            None => {
+                println!("{}: missing solution", puzzle_path.display());
+                problems += 1;
+                continue;
+            }
+        };
+        let puzzle = format::read_file(puzzle_path);
+        let solution = format::read_file(solution_path);
+        let (puzzle, solution) = match (puzzle, solution) {
+            (Ok(puzzle), Ok(solution)) => (puzzle, solution),
+            (Err(e), _) => {
+                println!("{}: {}", puzzle_path.display(), e);
+                problems += 1;
+                continue;
+            }
+            (_, Err(e)) => {
+                println!("{}: {}", solution_path.display(), e);
+                problems += 1;
+                continue;
+            }
+        };
+        if let Err(errors) = puzzle.validate() {
+            for error in errors {
+                println!("{}: {}", puzzle_path.display(), error);
+            }
+            problems += 1;
+            continue;
+        }
+        if let Err(mismatches) = puzzle.check_solution(&solution) {
+            for mismatch in mismatches {
+                println!("{}: {}", puzzle_path.display(), mismatch);
+            }
+            problems += 1;
+        }
+    }
+    println!(
+        "{} puzzles, {} solutions, {} problems",
+        puzzles.len(),
+        solutions.len(),
+        problems
+    );
+    if problems > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Run the solve command
+fn run_solve(args: &[String]) -> i32 {
+    let mut options = SolveOptions::default();
+    let mut jobs = None;
+    let mut webpbn = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--all" => options.all = true,
+            "--jobs" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => jobs = Some(n),
+                _ => usage_error("--jobs requires a positive number"),
+            },
+            "--save" => match args.next() {
+                Some(path) => options.save = Some(PathBuf::from(path)),
+                None => usage_error("--save requires a file"),
+            },
+            "--output" => match args.next().map(|x| x.as_str()) {
+                Some("text") => options.output = OutputFormat::Text,
+                Some("json") => options.output = OutputFormat::Json,
+                Some("html") => options.output = OutputFormat::Html,
+                _ => usage_error("--output must be text, json or html"),
+            },
+            "--require-unique" => options.require_unique = true,
+            "--toroidal" => options.toroidal = true,
+            "--probe" => options.config.probing = true,
+            "--crossings" => options.config.crossings = true,
+            "--auto" => options.auto = true,
+            "--verbose" => options.verbose = true,
+            "--timeout" => options.config.max_duration = Some(parse_timeout(args.next())),
+            "--max-branches" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(max)) => options.config.max_branches = Some(max),
+                _ => usage_error("--max-branches requires a number"),
+            },
+            "--max-memory" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(mib)) => options.config.max_memory = Some(mib.saturating_mul(1 << 20)),
+                _ => usage_error("--max-memory requires a number of megabytes"),
+            },
+            "--seed" => match args.next().map(|x| x.parse::<u64>()) {
+                Some(Ok(seed)) => options.config.seed = Some(seed),
+                _ => usage_error("--seed requires a number"),
+            },
+            "--heuristic" => match args.next().map(|x| x.as_str()) {
+                Some("first") => options.config.heuristic = BranchHeuristic::FirstUnknown,
+                Some("unsolved") => options.config.heuristic = BranchHeuristic::FewestUnsolved,
+                Some("constrained") => options.config.heuristic = BranchHeuristic::MostConstrained,
+                Some("adjacent") => options.config.heuristic = BranchHeuristic::Adjacency,
+                Some("likely") => options.config.heuristic = BranchHeuristic::Probability,
+                _ => usage_error(
+                    "--heuristic must be first, unsolved, constrained, adjacent or likely",
+                ),
+            },
+            "--line-alg" => options.config.line_solver = parse_line_solver(args.next()),
+            "--transpositions" => options.config.transposition_table = true,
+            "--learn" => options.config.learning = true,
+            "--best-first" => options.config.best_first = true,
+            "--time-phases" => options.config.time_phases = true,
+            "--threads" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => options.config.threads = n,
+                _ => usage_error("--threads requires a positive number"),
+            },
+            "--check-against" => match args.next().and_then(|x| Strategy::parse(x)) {
+                Some(strategy) => options.check_against = Some(strategy),
+                None => usage_error(
+                    "--check-against must be graph, left-right, automaton, depth-first, best-first or brute-force",
+                ),
+            },
+            "--restarts" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => options.config.restart_branches = Some(n),
+                _ => usage_error("--restarts requires a positive number"),
+            },
+            "--line-cache" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(size)) => options.config.line_cache_size = size,
+                _ => usage_error("--line-cache requires a number"),
+            },
+            "--chars" => match args.next().and_then(|x| CharMap::parse(x)) {
+                Some(chars) => options.chars = chars,
+                None => usage_error("--chars requires three different characters"),
+            },
+            "--trace" => match args.next() {
+                Some(path) => options.trace = Some(PathBuf::from(path)),
+                None => usage_error("--trace requires a file"),
+            },
+            "--webpbn" => match args.next().map(|id| id.parse::<u32>()) {
+                Some(Ok(id)) => webpbn = Some(id),
+                _ => usage_error("--webpbn requires a puzzle ID"),
+            },
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {}", flag)),
+            path => paths.push(path.to_string()),
+        }
+    }
+    if options.config.threads > 1 {
+        let config = &options.config;
+        if options.trace.is_some() {
+            usage_error("--trace can not be used with --threads");
+        }
+        if config.learning || config.restart_branches.is_some() || config.best_first {
+            usage_error("--learn, --restarts and --best-first can not be used with --threads");
+        }
+    }
+    if let Some(jobs) = jobs {
+        if !options.all {
+            usage_error("--jobs can only be used with --all");
+        }
+        options.jobs = jobs;
+    }
+    if options.verbose && !options.auto {
+        usage_error("--verbose can only be used with --auto");
+    }
+    if options.config.best_first && options.trace.is_some() {
+        usage_error("--trace can not be used with --best-first");
+    }
+    if options.all {
+        if options.output == OutputFormat::Html {
+            usage_error("--output html can not be used with --all");
+        }
+        if options.save.is_some() || options.trace.is_some() {
+            usage_error("--save and --trace can not be used with --all");
+        }
+        if webpbn.is_some() {
+            usage_error("--webpbn can not be used with --all");
+        }
+        if paths.is_empty() {
+            usage_error("expected puzzles to solve");
+        }
+        return solve_all(&paths, &options);
+    }
+    if let Some(id) = webpbn {
+        if !paths.is_empty() {
+            usage_error("--webpbn can not be used with a puzzle file");
+        }
+        return solve_one(PuzzleSource::Webpbn(id), &options);
+    }
+    match paths.as_slice() {
+        [path] => solve_one(PuzzleSource::File(Path::new(path)), &options),
+        _ => usage_error("expected a single puzzle to solve"),
+    }
+}
+
+/// Run the command line interface, returning the exit code
+pub fn run(args: &[String]) -> i32 {
+    match args.first().map(|s| s.as_str()) {
+        Some("solve") => run_solve(&args[1..]),
+        Some("solve-pack") => run_solve_pack(&args[1..]),
+        Some("count") => run_count(&args[1..]),
+        Some("hint") => run_hint(&args[1..]),
+        Some("forced") => run_forced(&args[1..]),
+        Some("probabilities") => run_probabilities(&args[1..]),
+        Some("rate") => run_rate(&args[1..]),
+        Some("approximate") => run_approximate(&args[1..]),
+        Some("bench") => run_bench(&args[1..]),
+        Some("verify") => run_verify(&args[1..]),
+        Some("export") => run_export(&args[1..]),
+        Some("check-corpus") => run_check_corpus(&args[1..]),
+        Some(path) if args.len() == 1 => solve_one(
+            PuzzleSource::File(Path::new(path)),
+            &SolveOptions::default(),
+        ),
+        _ => usage_error("expected a command"),
+    }
+}
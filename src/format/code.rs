@@ -0,0 +1,192 @@
+use super::ParseError;
+use crate::board::{Board, BoardBuilder, Cell, Constraint, ConstraintList, Unit};
+use std::convert::TryFrom;
+
+/// The version written at the start of every code
+const CODE_VERSION: u8 = 1;
+
+/// Set in a code's flags if it contains a grid
+const FLAG_GRID: u8 = 1;
+
+/// The URL-safe base64 alphabet
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encode bytes as unpadded URL-safe base64
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut ret = String::with_capacity((bytes.len() * 4).div_ceil(3));
+    for chunk in bytes.chunks(3) {
+        let mut value = 0u32;
+        for (i, &byte) in chunk.iter().enumerate() {
+            value |= (byte as u32) << (16 - 8 * i);
+        }
+        for i in 0..=chunk.len() {
+            let index = (value >> (18 - 6 * i)) & 0x3F;
+            ret.push(BASE64_ALPHABET[index as usize] as char);
+        }
+    }
+    ret
+}
+
+/// Decode unpadded URL-safe base64. Padding characters are ignored.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u32> = text
+        .trim()
+        .trim_end_matches('=')
+        .bytes()
+        .map(|c| {
+            BASE64_ALPHABET
+                .iter()
+                .position(|&x| x == c)
+                .map(|x| x as u32)
+        })
+        .collect::<Option<_>>()?;
+    if digits.len() % 4 == 1 {
+        return None;
+    }
+    let mut ret = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let mut value = 0u32;
+        for (i, &digit) in chunk.iter().enumerate() {
+            value |= digit << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            ret.push((value >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(ret)
+}
+
+/// Append a variable length integer, 7 bits at a time
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Read a variable length integer written by write_varint
+fn read_varint<I: Iterator<Item = u8>>(bytes: &mut I) -> Option<usize> {
+    let mut ret = 0usize;
+    for shift in (0..64).step_by(7) {
+        let byte = bytes.next()?;
+        ret |= ((byte & 0x7F) as usize).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(ret);
+        }
+    }
+    None
+}
+
+/// Read a Unit written by write_varint
+fn read_unit<I: Iterator<Item = u8>>(bytes: &mut I) -> Option<Unit> {
+    read_varint(bytes).and_then(|x| Unit::try_from(x).ok())
+}
+
+/// Get the value used to store a cell in a grid run
+fn cell_kind(cell: Cell) -> usize {
+    match cell {
+        Cell::Empty => 0,
+        Cell::Unknown => 2,
+        Cell::Filled | Cell::Colored(_) => 1,
+    }
+}
+
+/// Decode the bytes of a code into a board
+fn decode(bytes: &[u8]) -> Option<Result<Board, ParseError>> {
+    let mut bytes = bytes.iter().copied();
+    if bytes.next()? != CODE_VERSION {
+        return None;
+    }
+    let flags = bytes.next()?;
+    let width = read_unit(&mut bytes)?;
+    let height = read_unit(&mut bytes)?;
+    let mut read_lists = |count: Unit| -> Option<Vec<ConstraintList>> {
+        (0..count)
+            .map(|_| {
+                let length = read_varint(&mut bytes)?;
+                (0..length)
+                    .map(|_| read_unit(&mut bytes).map(Constraint::new))
+                    .collect()
+            })
+            .collect()
+    };
+    let cols = read_lists(width)?;
+    let rows = read_lists(height)?;
+    let mut builder = BoardBuilder::new(width, height).rows(rows).cols(cols);
+    if flags & FLAG_GRID != 0 {
+        let mut index = 0;
+        let count = width as usize * height as usize;
+        while index < count {
+            let run = read_varint(&mut bytes)?;
+            let cell = match run & 3 {
+                0 => Cell::Empty,
+                1 => Cell::Filled,
+                2 => Cell::Unknown,
+                _ => return None,
+            };
+            let length = run >> 2;
+            if length == 0 || index + length > count {
+                return None;
+            }
+            for i in index..index + length {
+                let col = (i % width as usize) as Unit;
+                let row = (i / width as usize) as Unit;
+                builder = builder.cell(col, row, cell);
+            }
+            index += length;
+        }
+    }
+    if bytes.next().is_some() {
+        return None;
+    }
+    Some(builder.build().map_err(ParseError::from))
+}
+
+impl Board {
+    /// Encode this board's constraints, and optionally its cells,
+    /// as a short string that can be shared in chat or URLs.
+    /// Returns None if the board has colored constraints or constraints of unknown length,
+    /// which codes can not represent.
+    pub fn to_code(&self, include_grid: bool) -> Option<String> {
+        let mut bytes = vec![CODE_VERSION, if include_grid { FLAG_GRID } else { 0 }];
+        write_varint(&mut bytes, self.get_width() as usize);
+        write_varint(&mut bytes, self.get_height() as usize);
+        let cols = (0..self.get_width()).map(|col| self.get_col_constraints(col));
+        let rows = (0..self.get_height()).map(|row| self.get_row_constraints(row));
+        for list in cols.chain(rows) {
+            write_varint(&mut bytes, list.len());
+            for constraint in list {
+                if !constraint.is_exact() || constraint.get_color() != 0 {
+                    return None;
+                }
+                write_varint(&mut bytes, constraint.get_length() as usize);
+            }
+        }
+        if include_grid {
+            let mut run: Option<(usize, usize)> = None;
+            for i in 0..self.get_num_cells() {
+                let kind = cell_kind(self.get_cell_index(i));
+                run = match run {
+                    Some((run_kind, length)) if run_kind == kind => Some((kind, length + 1)),
+                    Some((run_kind, length)) => {
+                        write_varint(&mut bytes, length << 2 | run_kind);
+                        Some((kind, 1))
+                    }
+                    None => Some((kind, 1)),
+                };
+            }
+            if let Some((kind, length)) = run {
+                write_varint(&mut bytes, length << 2 | kind);
+            }
+        }
+        Some(base64_encode(&bytes))
+    }
+
+    /// Decode a board from a code created by to_code
+    pub fn from_code(code: &str) -> Result<Board, ParseError> {
+        base64_decode(code)
+            .and_then(|bytes| decode(&bytes))
+            .unwrap_or_else(|| Err(ParseError::syntax(1, "invalid puzzle code")))
+    }
+}
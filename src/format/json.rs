@@ -0,0 +1,29 @@
+use super::ParseError;
+use crate::board::Board;
+use std::io;
+
+impl Board {
+    /// Read a board, including its cells, constraints and palette, from JSON.
+    /// The JSON is in the same form as this board's serde serialization.
+    pub fn read_json<R: io::Read>(handle: R) -> Result<Board, ParseError> {
+        let board: Board = serde_json::from_reader(handle).map_err(|e| {
+            if e.is_io() {
+                ParseError::Io(e.into())
+            } else {
+                ParseError::syntax(e.line(), e.to_string())
+            }
+        })?;
+        if !board.has_valid_storage() {
+            return Err(ParseError::syntax(
+                1,
+                "number of cells does not match the board's size",
+            ));
+        }
+        Ok(board)
+    }
+
+    /// Write this board, including its cells, constraints and palette, as JSON.
+    pub fn write_json<W: io::Write>(&self, handle: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(handle, self).map_err(io::Error::from)
+    }
+}
@@ -0,0 +1,300 @@
+//! Readers and writers for puzzle formats used by other solvers and websites.
+//! The crate's own CSV formats live in board.rs.
+use crate::board::{Board, BuildError, CharMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub mod code;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod non;
+pub mod olsak;
+pub mod pack;
+pub mod raster;
+pub mod report;
+pub mod state;
+pub mod svg;
+pub mod text;
+pub mod trace;
+pub mod webpbn;
+
+/// An error produced while reading a puzzle file
+#[derive(Debug)]
+pub enum ParseError {
+    /// The underlying reader failed
+    Io(io::Error),
+    /// The file is malformed. Lines are numbered starting at 1.
+    Syntax { line: usize, message: String },
+    /// A single value in the file is malformed. Lines and columns are numbered starting at 1.
+    Field {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The file is well formed, but describes a puzzle this crate can not represent
+    Unsupported(String),
+    /// The puzzle described by the file is not valid
+    Build(BuildError),
+}
+
+impl ParseError {
+    pub(crate) fn syntax<S: Into<String>>(line: usize, message: S) -> ParseError {
+        ParseError::Syntax {
+            line,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn field<S: Into<String>>(line: usize, column: usize, message: S) -> ParseError {
+        ParseError::Field {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "{}", e),
+            ParseError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            ParseError::Field {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            ParseError::Unsupported(message) => write!(f, "unsupported puzzle: {}", message),
+            ParseError::Build(e) => write!(f, "invalid puzzle: {}", e),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            ParseError::Build(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+impl From<BuildError> for ParseError {
+    fn from(e: BuildError) -> ParseError {
+        ParseError::Build(e)
+    }
+}
+
+/// A puzzle file format that can be read by read_file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Constraints as comma separated lines, ending with =COLUMNS and =ROWS
+    CsvPuzzle,
+    /// A solved grid of comma separated 0/1 values
+    CsvSolution,
+    /// Steven Simpson's .non format
+    Non,
+    /// webpbn's XML format
+    WebpbnXml,
+    /// The Olsak solver's .g format
+    Olsak,
+    /// Plain text clues, columns then rows
+    ClueText,
+    /// A solved grid of cell glyphs
+    AsciiGrid,
+    /// A serialized Board
+    Json,
+    /// A partially solved board written by Board::write_state
+    State,
+}
+
+impl Format {
+    /// Get the format usually associated with the given file extension.
+    /// Returns None for extensions that could be more than one format, such as "txt".
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        match extension.to_ascii_lowercase().as_str() {
+            "puzzle" => Some(Format::CsvPuzzle),
+            "solution" => Some(Format::CsvSolution),
+            "non" => Some(Format::Non),
+            "xml" | "pbn" => Some(Format::WebpbnXml),
+            "g" => Some(Format::Olsak),
+            "json" => Some(Format::Json),
+            "state" => Some(Format::State),
+            _ => None,
+        }
+    }
+
+    /// Guess a file's format from its contents
+    pub fn from_contents(contents: &str) -> Option<Format> {
+        Format::from_contents_with(contents, &CharMap::default())
+    }
+
+    /// Guess a file's format from its contents, recognizing grids written with the given
+    /// characters
+    pub fn from_contents_with(contents: &str, chars: &CharMap) -> Option<Format> {
+        let trimmed = contents.trim_start();
+        if trimmed.starts_with('<') {
+            return Some(Format::WebpbnXml);
+        }
+        if trimmed.starts_with('{') {
+            return Some(Format::Json);
+        }
+        let lines: Vec<&str> = contents
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.contains(&"=CELLS") {
+            return Some(Format::State);
+        }
+        if lines
+            .iter()
+            .any(|&line| line == "=COLUMNS" || line == "=ROWS")
+        {
+            return Some(Format::CsvPuzzle);
+        }
+        // Checked before Olsak, since grids may use '#' for filled cells
+        let is_grid_row = |line: &&str| {
+            line.chars().all(|c| {
+                c == ' ' || c == '|' || c == chars.filled || c == chars.empty || c == chars.unknown
+            })
+        };
+        if !lines.is_empty() && lines.iter().all(is_grid_row) {
+            return Some(Format::AsciiGrid);
+        }
+        let first = *lines.first()?;
+        if first.starts_with(':') || first.starts_with('#') {
+            return Some(Format::Olsak);
+        }
+        let is_keyword = |line: &&str| {
+            let keyword = line.split_whitespace().next().unwrap_or("");
+            [
+                "width",
+                "height",
+                "rows",
+                "columns",
+                "title",
+                "catalogue",
+                "by",
+            ]
+            .contains(&keyword)
+        };
+        if lines.iter().any(is_keyword) {
+            return Some(Format::Non);
+        }
+        let is_solution_row = |line: &&str| {
+            line.contains(',')
+                && line
+                    .split(',')
+                    .all(|v| ["0", "1", "-1"].contains(&v.trim()))
+        };
+        if lines.iter().all(is_solution_row) {
+            return Some(Format::CsvSolution);
+        }
+        Some(Format::ClueText)
+    }
+
+    /// Guess a file's format from its extension, or from its contents if the extension is
+    /// not recognized
+    pub fn detect(path: &Path, contents: &str) -> Option<Format> {
+        Format::detect_with(path, contents, &CharMap::default())
+    }
+
+    /// Guess a file's format from its extension, or from its contents if the extension is
+    /// not recognized, recognizing grids written with the given characters.
+    /// A state file is also a valid CSV puzzle, so one with a .puzzle extension is still
+    /// read as a state rather than losing its cells.
+    pub fn detect_with(path: &Path, contents: &str, chars: &CharMap) -> Option<Format> {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Format::from_extension)
+        {
+            Some(Format::CsvPuzzle) if contents.lines().any(|line| line.trim() == "=CELLS") => {
+                Some(Format::State)
+            }
+            Some(format) => Some(format),
+            None => Format::from_contents_with(contents, chars),
+        }
+    }
+}
+
+/// Read a puzzle file in the given format
+pub fn read_format<R: io::BufRead>(handle: R, format: Format) -> Result<Board, ParseError> {
+    read_format_with(handle, format, &CharMap::default())
+}
+
+/// Read a puzzle file in the given format, reading grids with the given characters
+pub fn read_format_with<R: io::BufRead>(
+    handle: R,
+    format: Format,
+    chars: &CharMap,
+) -> Result<Board, ParseError> {
+    match format {
+        Format::CsvPuzzle => Board::read_csv_puzzle(handle),
+        Format::CsvSolution => Board::read_csv_solution(handle),
+        Format::Non => Board::read_non(handle),
+        Format::WebpbnXml => Board::read_webpbn_xml(handle),
+        Format::Olsak => Board::read_olsak(handle),
+        Format::ClueText => Board::read_clue_text(handle),
+        Format::AsciiGrid => Board::read_ascii_solution_with(handle, chars),
+        Format::State => Board::read_state(handle),
+        #[cfg(feature = "serde")]
+        Format::Json => Board::read_json(handle),
+        #[cfg(not(feature = "serde"))]
+        Format::Json => Err(ParseError::Unsupported(
+            "reading JSON requires the serde feature".to_string(),
+        )),
+    }
+}
+
+/// Write a board in the given format.
+/// Puzzle formats only hold the board's constraints, and solution formats only hold its cells.
+pub fn write_format<W: io::Write>(board: &Board, mut handle: W, format: Format) -> io::Result<()> {
+    match format {
+        Format::CsvPuzzle => board.write_csv_puzzle(handle),
+        Format::CsvSolution => board.write_csv_solution(handle),
+        Format::Non => board.write_non(handle, None),
+        Format::WebpbnXml => board.write_webpbn_xml(handle, None),
+        Format::Olsak => board.write_olsak(handle),
+        Format::AsciiGrid => write!(handle, "{}", board.to_grid_string()),
+        Format::State => board.write_state(handle),
+        #[cfg(feature = "serde")]
+        Format::Json => board.write_json(handle),
+        #[cfg(not(feature = "serde"))]
+        Format::Json => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "writing JSON requires the serde feature",
+        )),
+        Format::ClueText => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "plain text clues can not be written",
+        )),
+    }
+}
+
+/// Read a puzzle file, detecting its format from its extension and contents
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Board, ParseError> {
+    read_file_with(path, &CharMap::default())
+}
+
+/// Read a puzzle file, detecting its format from its extension and contents,
+/// and reading grids with the given characters
+pub fn read_file_with<P: AsRef<Path>>(path: P, chars: &CharMap) -> Result<Board, ParseError> {
+    let path = path.as_ref();
+    let contents = fs::read(path)?;
+    let text = String::from_utf8_lossy(&contents);
+    let format = Format::detect_with(path, &text, chars)
+        .ok_or_else(|| ParseError::Unsupported("could not detect the file's format".to_string()))?;
+    read_format_with(&contents[..], format, chars)
+}
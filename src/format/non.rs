@@ -0,0 +1,167 @@
+use super::ParseError;
+use crate::board::{parse_constraint, Board, BoardBuilder, Cell, ConstraintList, Unit};
+use std::io;
+
+/// Parse a single line of clues, such as "3,1,2" or "0" for an empty line
+fn parse_clue_line(line_number: usize, line: &str) -> Result<ConstraintList, ParseError> {
+    let mut ret = ConstraintList::new();
+    for value in line.split(|c: char| c == ',' || c.is_whitespace()) {
+        if value.is_empty() || value == "0" {
+            continue;
+        }
+        let constraint = parse_constraint(value)
+            .ok_or_else(|| ParseError::syntax(line_number, format!("invalid clue '{}'", value)))?;
+        ret.push(constraint);
+    }
+    Ok(ret)
+}
+
+/// Parse the value of a width or height keyword
+fn parse_size(line_number: usize, value: Option<&str>) -> Result<Unit, ParseError> {
+    value
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| ParseError::syntax(line_number, "expected a size"))
+}
+
+/// Write a list of clue lines in .non format
+fn write_clue_lines<W: io::Write>(handle: &mut W, lists: &[&ConstraintList]) -> io::Result<()> {
+    for list in lists {
+        if list.is_empty() {
+            writeln!(handle, "0")?;
+            continue;
+        }
+        let mut first = true;
+        for constraint in list.iter() {
+            if !constraint.is_exact() || constraint.get_color() != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the .non format only supports black and white clues of known length",
+                ));
+            }
+            if !first {
+                write!(handle, ",")?;
+            }
+            first = false;
+            write!(handle, "{}", constraint.get_length())?;
+        }
+        writeln!(handle)?;
+    }
+    Ok(())
+}
+
+impl Board {
+    /// Read a puzzle in Steven Simpson's .non format.
+    /// Metadata such as the title and author, as well as any goal, are ignored.
+    pub fn read_non<R: io::BufRead>(handle: R) -> Result<Board, ParseError> {
+        let mut width = None;
+        let mut height = None;
+        let mut rows = None;
+        let mut cols = None;
+        let mut lines = handle.lines().enumerate().map(|(i, line)| (i + 1, line));
+        while let Some((line_number, line)) = lines.next() {
+            let line = line?;
+            let line = line.trim();
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let keyword = parts.next().unwrap_or("");
+            let value = parts.next();
+            match keyword {
+                "width" => width = Some(parse_size(line_number, value)?),
+                "height" => height = Some(parse_size(line_number, value)?),
+                "rows" | "columns" => {
+                    let count = if keyword == "rows" { height } else { width };
+                    let count = count.ok_or_else(|| {
+                        ParseError::syntax(
+                            line_number,
+                            format!("'{}' must come after the puzzle's size", keyword),
+                        )
+                    })?;
+                    // empty lines are written as "0", so blank ones are only spacing
+                    let mut lists = Vec::new();
+                    while lists.len() < count as usize {
+                        let (line_number, line) = lines.next().ok_or_else(|| {
+                            ParseError::syntax(line_number, format!("not enough {}", keyword))
+                        })?;
+                        let line = line?;
+                        if !line.trim().is_empty() {
+                            lists.push(parse_clue_line(line_number, &line)?);
+                        }
+                    }
+                    if keyword == "rows" {
+                        rows = Some(lists);
+                    } else {
+                        cols = Some(lists);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(ParseError::syntax(1, "missing width or height")),
+        };
+        let rows = rows.ok_or_else(|| ParseError::syntax(1, "missing rows"))?;
+        let cols = cols.ok_or_else(|| ParseError::syntax(1, "missing columns"))?;
+        let board = BoardBuilder::new(width, height)
+            .rows(rows)
+            .cols(cols)
+            .build()?;
+        Ok(board)
+    }
+
+    /// Write this board's constraints in Steven Simpson's .non format.
+    /// If a solution is given, it is written as the puzzle's goal.
+    pub fn write_non<W: io::Write>(
+        &self,
+        mut handle: W,
+        solution: Option<&Board>,
+    ) -> io::Result<()> {
+        writeln!(handle, "width {}", self.get_width())?;
+        writeln!(handle, "height {}", self.get_height())?;
+        writeln!(handle)?;
+        writeln!(handle, "rows")?;
+        let rows: Vec<_> = (0..self.get_height())
+            .map(|row| self.get_row_constraints(row))
+            .collect();
+        write_clue_lines(&mut handle, &rows)?;
+        writeln!(handle)?;
+        writeln!(handle, "columns")?;
+        let cols: Vec<_> = (0..self.get_width())
+            .map(|col| self.get_col_constraints(col))
+            .collect();
+        write_clue_lines(&mut handle, &cols)?;
+        if let Some(solution) = solution {
+            writeln!(handle)?;
+            write!(handle, "goal \"")?;
+            for row in 0..solution.get_height() {
+                for col in 0..solution.get_width() {
+                    let value = match solution.get_cell(col, row) {
+                        Cell::Empty => '0',
+                        Cell::Unknown => '?',
+                        Cell::Filled | Cell::Colored(_) => '1',
+                    };
+                    write!(handle, "{}", value)?;
+                }
+            }
+            writeln!(handle, "\"")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_lines_between_clues_are_skipped() {
+        let non = "width 2\nheight 2\n\nrows\n\n1\n\n0\ncolumns\n1\n\n0\n";
+        let board = Board::read_non(non.as_bytes()).unwrap();
+        assert_eq!(board.get_row_constraints(0)[0].get_length(), 1);
+        assert!(board.get_row_constraints(1).is_empty());
+        assert_eq!(board.get_col_constraints(0)[0].get_length(), 1);
+        assert!(board.get_col_constraints(1).is_empty());
+
+        let short = "width 1\nheight 2\nrows\n1\n\n";
+        assert!(Board::read_non(short.as_bytes()).is_err());
+    }
+}
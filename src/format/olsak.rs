@@ -0,0 +1,116 @@
+use super::ParseError;
+use crate::board::{parse_constraint, Board, BoardBuilder, ConstraintList, Unit};
+use std::convert::TryFrom;
+use std::io;
+
+/// Parse a single line of clues, such as "3 1 2" or "0" for an empty line
+fn parse_clue_line(line_number: usize, line: &str) -> Result<ConstraintList, ParseError> {
+    let mut ret = ConstraintList::new();
+    for value in line.split_whitespace() {
+        if value == "0" {
+            continue;
+        }
+        if value.ends_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(ParseError::Unsupported(
+                "color puzzles can only be read from webpbn XML".to_string(),
+            ));
+        }
+        let constraint = parse_constraint(value)
+            .ok_or_else(|| ParseError::syntax(line_number, format!("invalid clue '{}'", value)))?;
+        ret.push(constraint);
+    }
+    Ok(ret)
+}
+
+/// Write a list of clue lines in .g format
+fn write_clue_lines<W: io::Write>(handle: &mut W, lists: &[&ConstraintList]) -> io::Result<()> {
+    for list in lists {
+        if list.is_empty() {
+            writeln!(handle, "0")?;
+            continue;
+        }
+        let mut first = true;
+        for constraint in list.iter() {
+            if !constraint.is_exact() || constraint.get_color() != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "only black and white clues of known length can be written in .g format",
+                ));
+            }
+            if !first {
+                write!(handle, " ")?;
+            }
+            first = false;
+            write!(handle, "{}", constraint.get_length())?;
+        }
+        writeln!(handle)?;
+    }
+    Ok(())
+}
+
+impl Board {
+    /// Read a puzzle in the Olsak solver's .g format.
+    /// Rows are listed after a ': rows' line, and columns after a ': columns' line.
+    /// Lines starting with '#' are comments.
+    pub fn read_olsak<R: io::BufRead>(handle: R) -> Result<Board, ParseError> {
+        let mut rows = Vec::new();
+        let mut cols = Vec::new();
+        let mut section: Option<&mut Vec<ConstraintList>> = None;
+        for (i, line) in handle.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix(':') {
+                section = match header.trim() {
+                    "rows" => Some(&mut rows),
+                    "columns" => Some(&mut cols),
+                    _ => {
+                        return Err(ParseError::syntax(
+                            line_number,
+                            format!("unknown section '{}'", header.trim()),
+                        ))
+                    }
+                };
+                continue;
+            }
+            match section {
+                Some(ref mut lists) => lists.push(parse_clue_line(line_number, line)?),
+                None => {
+                    return Err(ParseError::syntax(
+                        line_number,
+                        "clues must come after ': rows' or ': columns'",
+                    ))
+                }
+            }
+        }
+        if rows.is_empty() || cols.is_empty() {
+            return Err(ParseError::syntax(1, "missing rows or columns"));
+        }
+        let too_big = |_| ParseError::Unsupported("puzzle is too large".to_string());
+        let width = Unit::try_from(cols.len()).map_err(too_big)?;
+        let height = Unit::try_from(rows.len()).map_err(too_big)?;
+        let board = BoardBuilder::new(width, height)
+            .rows(rows)
+            .cols(cols)
+            .build()?;
+        Ok(board)
+    }
+
+    /// Write this board's constraints in the Olsak solver's .g format,
+    /// which can also be read by pbnsolve.
+    pub fn write_olsak<W: io::Write>(&self, mut handle: W) -> io::Result<()> {
+        writeln!(handle, ": rows")?;
+        let rows: Vec<_> = (0..self.get_height())
+            .map(|row| self.get_row_constraints(row))
+            .collect();
+        write_clue_lines(&mut handle, &rows)?;
+        writeln!(handle, ": columns")?;
+        let cols: Vec<_> = (0..self.get_width())
+            .map(|col| self.get_col_constraints(col))
+            .collect();
+        write_clue_lines(&mut handle, &cols)
+    }
+}
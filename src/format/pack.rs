@@ -0,0 +1,193 @@
+//! Packs are single files holding many named puzzles.
+//! Each puzzle starts with a line of the form `=PUZZLE <name>`, followed by the puzzle in
+//! any text format that read_format can detect from its contents.
+use super::{read_format, Format, ParseError};
+use crate::board::Board;
+use std::io;
+
+/// The line that starts each puzzle in a pack, followed by the puzzle's name
+const PUZZLE_HEADER: &str = "=PUZZLE";
+
+/// A single named puzzle in a pack
+pub struct PackEntry {
+    pub name: String,
+    pub board: Board,
+}
+
+/// Reads the puzzles in a pack one at a time. A malformed puzzle gives an error, and reading
+/// carries on with the next one.
+pub struct PackReader<R: io::BufRead> {
+    lines: io::Lines<R>,
+    /// Number of lines read so far
+    line_number: usize,
+    /// The name of the next puzzle, and the line its header is on
+    next: Option<(String, usize)>,
+    /// Set once the end of the pack or an error reading it has been reached
+    done: bool,
+}
+
+impl<R: io::BufRead> PackReader<R> {
+    /// Create a reader for the pack in the given handle
+    pub fn new(handle: R) -> PackReader<R> {
+        PackReader {
+            lines: handle.lines(),
+            line_number: 0,
+            next: None,
+            done: false,
+        }
+    }
+
+    /// Read the next entry's header, ignoring blank lines before it.
+    /// If anything else comes first, it is skipped up to the next header, which is kept for
+    /// the next entry.
+    fn read_header(&mut self) -> Result<Option<(String, usize)>, ParseError> {
+        let mut error = None;
+        for line in &mut self.lines {
+            self.line_number += 1;
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match parse_header(line) {
+                Some(name) if error.is_none() => return Ok(Some((name, self.line_number))),
+                Some(name) => {
+                    self.next = Some((name, self.line_number));
+                    break;
+                }
+                None if error.is_none() => {
+                    error = Some(ParseError::syntax(
+                        self.line_number,
+                        format!("expected '{} <name>'", PUZZLE_HEADER),
+                    ))
+                }
+                None => {}
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the entry whose header has already been read
+    fn read_entry(&mut self, name: String, header_line: usize) -> Result<PackEntry, ParseError> {
+        let mut contents = String::new();
+        for line in &mut self.lines {
+            self.line_number += 1;
+            let line = line?;
+            if let Some(next) = parse_header(line.trim()) {
+                self.next = Some((next, self.line_number));
+                break;
+            }
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+        let format = Format::from_contents(&contents).ok_or_else(|| {
+            ParseError::syntax(header_line, format!("puzzle '{}' is empty", name))
+        })?;
+        let board = read_format(contents.as_bytes(), format).map_err(|e| match e {
+            ParseError::Syntax { line, message } => ParseError::Syntax {
+                line: line + header_line,
+                message,
+            },
+            ParseError::Field {
+                line,
+                column,
+                message,
+            } => ParseError::Field {
+                line: line + header_line,
+                column,
+                message,
+            },
+            e => e,
+        })?;
+        Ok(PackEntry { name, board })
+    }
+}
+
+impl<R: io::BufRead> Iterator for PackReader<R> {
+    type Item = Result<PackEntry, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let header = match self.next.take() {
+            Some(header) => Ok(Some(header)),
+            None => self.read_header(),
+        };
+        let result = match header {
+            Ok(Some((name, line))) => self.read_entry(name, line),
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => Err(e),
+        };
+        // a malformed entry is skipped, but nothing more can be read after a failed read
+        if let Err(ParseError::Io(_)) = result {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+/// Get the puzzle name from a header line, or None if the line is not a header
+fn parse_header(line: &str) -> Option<String> {
+    let name = line.strip_prefix(PUZZLE_HEADER)?;
+    if !name.is_empty() && !name.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(name.trim().to_string())
+}
+
+/// Write a pack of puzzles, writing each board's constraints in the CSV puzzle format
+pub fn write_pack<'a, W, I>(mut handle: W, entries: I) -> io::Result<()>
+where
+    W: io::Write,
+    I: IntoIterator<Item = (&'a str, &'a Board)>,
+{
+    for (name, board) in entries {
+        writeln!(handle, "{} {}", PUZZLE_HEADER, name)?;
+        board.write_csv_puzzle(&mut handle)?;
+    }
+    Ok(())
+}
+
+/// Write a pack of solutions, writing each board's cells in the CSV solution format
+pub fn write_solution_pack<'a, W, I>(mut handle: W, entries: I) -> io::Result<()>
+where
+    W: io::Write,
+    I: IntoIterator<Item = (&'a str, &'a Board)>,
+{
+    for (name, board) in entries {
+        writeln!(handle, "{} {}", PUZZLE_HEADER, name)?;
+        board.write_csv_solution(&mut handle)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_skips_malformed_entries() {
+        let puzzle = "width 1\nheight 1\nrows\n1\ncolumns\n1\n";
+        let pack = format!(
+            "junk\n=PUZZLE a\n{}=PUZZLE b\nwidth x\n=PUZZLE c\n{}",
+            puzzle, puzzle
+        );
+        let entries: Vec<_> = PackReader::new(pack.as_bytes()).collect();
+        let names: Vec<Option<&str>> = entries
+            .iter()
+            .map(|entry| entry.as_ref().ok().map(|x| x.name.as_str()))
+            .collect();
+        assert_eq!(names, vec![None, Some("a"), None, Some("c")]);
+        assert!(matches!(
+            entries[0],
+            Err(ParseError::Syntax { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+#[cfg(feature = "image")]
+use super::ParseError;
+use crate::board::Board;
+#[cfg(feature = "image")]
+use crate::board::{to_unit, Cell, Unit};
+use std::io;
+
+/// Convert an image error into a ParseError
+#[cfg(feature = "image")]
+fn image_error(e: image::ImageError) -> ParseError {
+    match e {
+        image::ImageError::IoError(e) => ParseError::Io(e),
+        e => ParseError::Unsupported(e.to_string()),
+    }
+}
+
+impl Board {
+    /// Write this board's cells as a plain PBM image.
+    /// Each cell is drawn as a scale by scale block of pixels.
+    /// Filled cells of any color are black; empty and unknown cells are white.
+    pub fn write_pbm<W: io::Write>(&self, mut handle: W, scale: usize) -> io::Result<()> {
+        let width = self.get_width() as usize * scale;
+        let height = self.get_height() as usize * scale;
+        writeln!(handle, "P1")?;
+        writeln!(handle, "{} {}", width, height)?;
+        for y in 0..height {
+            let row = (y / scale) as _;
+            let line: Vec<&str> = (0..width)
+                .map(|x| {
+                    if self.get_cell((x / scale) as _, row).is_filled() {
+                        "1"
+                    } else {
+                        "0"
+                    }
+                })
+                .collect();
+            writeln!(handle, "{}", line.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// Render this board's cells as an image.
+    /// Each cell is drawn as a scale by scale block of pixels.
+    /// Filled cells use their palette color, empty cells are white,
+    /// and unknown cells are gray.
+    #[cfg(feature = "image")]
+    // Unit is already u32 with the wide-units feature
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_image(&self, scale: u32) -> image::RgbImage {
+        let width = self.get_width() as u32 * scale;
+        let height = self.get_height() as u32 * scale;
+        image::RgbImage::from_fn(width, height, |x, y| {
+            let cell = self.get_cell((x / scale) as _, (y / scale) as _);
+            let (r, g, b) = match cell {
+                Cell::Unknown => (128, 128, 128),
+                Cell::Empty => (255, 255, 255),
+                Cell::Filled | Cell::Colored(_) => self
+                    .get_palette()
+                    .get(cell.get_color().unwrap())
+                    .map(|color| color.rgb)
+                    .unwrap_or((0, 0, 0)),
+            };
+            image::Rgb([r, g, b])
+        })
+    }
+
+    /// Save this board's cells as a PNG image.
+    /// See to_image.
+    #[cfg(feature = "image")]
+    pub fn save_png<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        scale: u32,
+    ) -> image::ImageResult<()> {
+        self.to_image(scale)
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+
+    /// Create a solved board from an image, with one cell per pixel.
+    /// Pixels darker than the threshold are filled, and all other pixels are empty.
+    /// Transparent pixels are always empty.
+    /// Constraints are generated from the resulting cells.
+    #[cfg(feature = "image")]
+    pub fn from_image<P: AsRef<std::path::Path>>(
+        path: P,
+        threshold: u8,
+    ) -> Result<Board, ParseError> {
+        let image = image::open(path).map_err(image_error)?;
+        Ok(Board::from_rgba_image(&image.to_rgba8(), threshold))
+    }
+
+    /// Create a solved board from an image, first resizing it to the given number of cells.
+    /// See from_image.
+    #[cfg(feature = "image")]
+    pub fn from_image_resized<P: AsRef<std::path::Path>>(
+        path: P,
+        width: u32,
+        height: u32,
+        threshold: u8,
+    ) -> Result<Board, ParseError> {
+        let image = image::open(path).map_err(image_error)?;
+        let image = image.resize_exact(width, height, image::imageops::FilterType::Triangle);
+        Ok(Board::from_rgba_image(&image.to_rgba8(), threshold))
+    }
+
+    /// Create a solved board from an image, with one cell per pixel.
+    /// See from_image.
+    #[cfg(feature = "image")]
+    pub fn from_rgba_image(image: &image::RgbaImage, threshold: u8) -> Board {
+        let width = to_unit(image.width() as usize);
+        let height = to_unit(image.height() as usize);
+        let mut board = Board::new_filled(width, height, Cell::Empty);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+            if a >= 128 && luma < threshold as u32 {
+                board.set_cell(x as Unit, y as Unit, Cell::Filled);
+            }
+        }
+        board.generate_new_constraints();
+        board
+    }
+}
@@ -0,0 +1,245 @@
+use super::trace;
+use crate::board::Board;
+use crate::solver::{Limit, LineInfo, SolveResult, SolveStats, Uniqueness};
+use std::fmt::Write;
+use std::io;
+
+/// Styles used by HTML reports
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+figure { display: inline-block; margin: 0 2em 2em 0; vertical-align: top; }
+figcaption { text-align: center; }
+table { border-collapse: collapse; }
+th, td { text-align: left; padding: 0.2em 1em 0.2em 0; }";
+
+/// Quote and escape a string for use in JSON
+pub fn json_string(value: &str) -> String {
+    let mut ret = String::with_capacity(value.len() + 2);
+    ret.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(ret, "\\u{:04x}", c as u32).unwrap(),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// Escape a string for use in HTML text and attributes
+pub fn html_string(value: &str) -> String {
+    let mut ret = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Get the name used for a solve result in reports
+pub fn result_name(result: SolveResult) -> &'static str {
+    match result {
+        SolveResult::Success => "solved",
+        SolveResult::Contradiction => "contradiction",
+        SolveResult::Timeout => "timeout",
+        SolveResult::Exhausted(_) => "exhausted",
+    }
+}
+
+/// Create a JSON object describing the result of solving a board.
+/// The grid is written one string per row, using the glyphs from Board::to_grid_string.
+/// If phases were timed, the time spent in each is included as "phases", in seconds by the
+/// phase's name from PhaseTimes::iter, with spaces replaced by underscores.
+/// If the solution was checked for uniqueness, the result is included as "unique": true or
+/// false, or null if the check gave up before finding out.
+/// If lines were found that can not all be satisfied, they are included as "conflict",
+/// a list of {"type", "index"} objects.
+/// If the solver gave up, the best partial board it reached is included as "best_partial",
+/// and the cells every branch it searched agreed on as "explored_agreement", in the same form
+/// as the grid.
+pub fn solve_report_json(
+    name: &str,
+    board: &Board,
+    result: SolveResult,
+    stats: &SolveStats,
+    unique: Option<Uniqueness>,
+    conflict: Option<&[LineInfo]>,
+) -> String {
+    let grid: Vec<String> = board.to_grid_string().lines().map(json_string).collect();
+    let cells_per_pass: Vec<String> = stats.cells_per_pass.iter().map(|x| x.to_string()).collect();
+    let phases = stats.phases.map_or(String::new(), |phases| {
+        let phases: Vec<String> = phases
+            .iter()
+            .map(|(name, time)| {
+                format!(
+                    "{}:{:.6}",
+                    json_string(&name.replace(' ', "_")),
+                    time.as_secs_f64()
+                )
+            })
+            .collect();
+        format!(",\"phases\":{{{}}}", phases.join(","))
+    });
+    format!(
+        "{{\"file\":{},\"status\":\"{}\",\"width\":{},\"height\":{},\"grid\":[{}],\
+\"passes\":{},\"lines_solved\":{},\"cells_deduced\":{},\"cells_per_pass\":[{}],\
+\"branches\":{},\"max_depth\":{},\"contradictions\":{},\"probes\":{},\"line_cache_hits\":{},\"transposition_hits\":{},\"elapsed\":{:.6}{}{}{}{}{}}}",
+        json_string(name),
+        result_name(result),
+        board.get_width(),
+        board.get_height(),
+        grid.join(","),
+        stats.passes,
+        stats.lines_solved,
+        stats.cells_deduced(),
+        cells_per_pass.join(","),
+        stats.branches,
+        stats.max_depth,
+        stats.contradictions,
+        stats.probes,
+        stats.line_cache_hits,
+        stats.transposition_hits,
+        stats.elapsed.as_secs_f64(),
+        phases,
+        stats.best_partial.as_ref().map_or(String::new(), |partial| {
+            let grid: Vec<String> = partial.to_grid_string().lines().map(json_string).collect();
+            format!(",\"best_partial\":[{}]", grid.join(","))
+        }),
+        stats.explored_agreement.as_ref().map_or(String::new(), |agreed| {
+            let grid: Vec<String> = agreed.to_grid_string().lines().map(json_string).collect();
+            format!(",\"explored_agreement\":[{}]", grid.join(","))
+        }),
+        unique.map_or(String::new(), |x| {
+            let unique = match x {
+                Uniqueness::Unique => "true",
+                Uniqueness::Ambiguous => "false",
+                Uniqueness::Unknown => "null",
+            };
+            format!(",\"unique\":{}", unique)
+        }),
+        conflict.map_or(String::new(), |lines| {
+            let lines: Vec<String> = lines.iter().map(|&line| trace::line_json(line)).collect();
+            format!(",\"conflict\":[{}]", lines.join(","))
+        })
+    )
+}
+
+/// Create a JSON object describing a puzzle that could not be solved
+/// because it could not be read or is invalid
+pub fn error_report_json(name: &str, status: &str, errors: &[String]) -> String {
+    let errors: Vec<String> = errors.iter().map(|x| json_string(x)).collect();
+    format!(
+        "{{\"file\":{},\"status\":\"{}\",\"errors\":[{}]}}",
+        json_string(name),
+        status,
+        errors.join(",")
+    )
+}
+
+/// Write a standalone HTML page describing the result of solving a board, with the puzzle and
+/// its solution drawn as inline SVG images, followed by difficulty metrics and solver statistics
+pub fn write_html_report<W: io::Write>(
+    mut handle: W,
+    name: &str,
+    board: &Board,
+    result: SolveResult,
+    stats: &SolveStats,
+) -> io::Result<()> {
+    let num_cells = board.get_num_cells();
+    let filled = (0..num_cells)
+        .filter(|&i| board.get_cell_index(i).get_color().is_some())
+        .count();
+    let clues: usize = (0..board.get_width())
+        .map(|col| board.get_col_constraints(col).len())
+        .chain((0..board.get_height()).map(|row| board.get_row_constraints(row).len()))
+        .sum();
+    let first_pass = stats.cells_per_pass.first().copied().unwrap_or(0);
+    let percent = |value: usize| 100.0 * value as f64 / num_cells.max(1) as f64;
+    let rating = match (result, stats.branches) {
+        (SolveResult::Contradiction, _) => "unsolvable",
+        (SolveResult::Timeout, _) => "unknown, the solver ran out of time",
+        (SolveResult::Exhausted(Limit::Branches), _) => {
+            "unknown, the solver reached its branch limit"
+        }
+        (SolveResult::Exhausted(Limit::Memory), _) => {
+            "unknown, the solver reached its memory limit"
+        }
+        (_, 0) => "solvable by line logic alone",
+        _ => "requires guessing",
+    };
+    let name = html_string(name);
+    writeln!(handle, "<!DOCTYPE html>")?;
+    writeln!(handle, "<html>")?;
+    writeln!(handle, "<head>")?;
+    writeln!(handle, "<meta charset=\"utf-8\">")?;
+    writeln!(handle, "<title>{}</title>", name)?;
+    writeln!(handle, "<style>\n{}\n</style>", HTML_STYLE)?;
+    writeln!(handle, "</head>")?;
+    writeln!(handle, "<body>")?;
+    writeln!(handle, "<h1>{}</h1>", name)?;
+    writeln!(handle, "<figure>")?;
+    board.write_svg(&mut handle, false)?;
+    writeln!(handle, "<figcaption>Puzzle</figcaption>")?;
+    writeln!(handle, "</figure>")?;
+    writeln!(handle, "<figure>")?;
+    board.write_svg(&mut handle, true)?;
+    let caption = if result == SolveResult::Success {
+        "Solution"
+    } else {
+        "Final state"
+    };
+    writeln!(handle, "<figcaption>{}</figcaption>", caption)?;
+    writeln!(handle, "</figure>")?;
+    let difficulty = [
+        (
+            "Size",
+            format!("{}x{}", board.get_width(), board.get_height()),
+        ),
+        ("Clues", clues.to_string()),
+        (
+            "Filled cells",
+            format!("{} ({:.1}%)", filled, percent(filled)),
+        ),
+        (
+            "Deduced in the first pass",
+            format!("{} ({:.1}%)", first_pass, percent(first_pass)),
+        ),
+        ("Rating", rating.to_string()),
+    ];
+    let mut statistics = vec![
+        ("Status", result_name(result).to_string()),
+        ("Passes", stats.passes.to_string()),
+        ("Lines solved", stats.lines_solved.to_string()),
+        ("Cells deduced", stats.cells_deduced().to_string()),
+        ("Branches", stats.branches.to_string()),
+        ("Deepest branch", stats.max_depth.to_string()),
+        ("Contradictions", stats.contradictions.to_string()),
+        ("Probes", stats.probes.to_string()),
+        ("Cached lines", stats.line_cache_hits.to_string()),
+        ("Repeated states", stats.transposition_hits.to_string()),
+        ("Time", format!("{:.3}s", stats.elapsed.as_secs_f64())),
+    ];
+    if let Some(phases) = stats.phases.as_ref() {
+        statistics.push(("Time by phase", phases.to_string()));
+    }
+    for (title, rows) in [("Difficulty", &difficulty[..]), ("Solver", &statistics[..])].iter() {
+        writeln!(handle, "<h2>{}</h2>", title)?;
+        writeln!(handle, "<table>")?;
+        for (key, value) in rows.iter() {
+            writeln!(handle, "<tr><th>{}</th><td>{}</td></tr>", key, value)?;
+        }
+        writeln!(handle, "</table>")?;
+    }
+    writeln!(handle, "</body>")?;
+    writeln!(handle, "</html>")
+}
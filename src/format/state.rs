@@ -0,0 +1,122 @@
+use super::ParseError;
+use crate::board::{parse_constraint, Board, BoardBuilder, Cell, ConstraintList, Unit};
+use std::convert::TryFrom;
+use std::io;
+
+/// Parse a line of comma separated constraints
+fn parse_constraint_line(line_number: usize, line: &str) -> Result<ConstraintList, ParseError> {
+    if line.is_empty() {
+        return Ok(ConstraintList::new());
+    }
+    line.split(',')
+        .map(|field| {
+            parse_constraint(field)
+                .ok_or_else(|| ParseError::syntax(line_number, format!("invalid clue '{}'", field)))
+        })
+        .collect()
+}
+
+/// Parse a line of comma separated cell values
+fn parse_cell_line(line_number: usize, line: &str) -> Result<Vec<Cell>, ParseError> {
+    line.split(',')
+        .map(|field| {
+            field
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .and_then(Cell::from_i64)
+                .ok_or_else(|| ParseError::syntax(line_number, format!("invalid cell '{}'", field)))
+        })
+        .collect()
+}
+
+impl Board {
+    /// Write this board's constraints and current cells, including unknown cells,
+    /// so that solving can later be resumed with read_state.
+    /// The constraints are written exactly as write_csv_puzzle writes them, followed by
+    /// the cells as in write_csv_solution and a final =CELLS line,
+    /// so a state file can also be read as a plain puzzle.
+    pub fn write_state<W: io::Write>(&self, mut handle: W) -> io::Result<()> {
+        self.write_csv_puzzle(&mut handle)?;
+        self.write_csv_solution(&mut handle)?;
+        writeln!(handle, "=CELLS")
+    }
+
+    /// Read a board written by write_state
+    pub fn read_state<R: io::BufRead>(handle: R) -> Result<Board, ParseError> {
+        let mut cols = Vec::new();
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        let mut section = 0;
+        for (i, line) in handle.lines().enumerate() {
+            let line = line?;
+            match line.trim() {
+                "=COLUMNS" if section == 0 => section = 1,
+                "=ROWS" if section == 1 => section = 2,
+                "=CELLS" if section == 2 => {
+                    section = 3;
+                    break;
+                }
+                line if section == 0 => cols.push(parse_constraint_line(i + 1, line)?),
+                line if section == 1 => rows.push(parse_constraint_line(i + 1, line)?),
+                line if section == 2 => cells.push(parse_cell_line(i + 1, line)?),
+                line => {
+                    return Err(ParseError::syntax(
+                        i + 1,
+                        format!("unexpected line '{}'", line),
+                    ))
+                }
+            }
+        }
+        if section != 3 {
+            return Err(ParseError::syntax(1, "missing =COLUMNS, =ROWS or =CELLS"));
+        }
+        let too_big = |_| ParseError::Unsupported("puzzle is too large".to_string());
+        let width = Unit::try_from(cols.len()).map_err(too_big)?;
+        let height = Unit::try_from(rows.len()).map_err(too_big)?;
+        if cells.len() != rows.len() || cells.iter().any(|row| row.len() != cols.len()) {
+            return Err(ParseError::syntax(
+                1,
+                format!(
+                    "cells do not match the puzzle's size of {}x{}",
+                    width, height
+                ),
+            ));
+        }
+        let mut board = BoardBuilder::new(width, height)
+            .rows(rows)
+            .cols(cols)
+            .build()?;
+        for (i, cell) in cells.into_iter().flatten().enumerate() {
+            board.set_cell_index(i, cell);
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use std::path::Path;
+
+    #[test]
+    fn state_keeps_cells_under_any_extension() {
+        let mut board = BoardBuilder::new(2, 1)
+            .row(0, &[1])
+            .col(0, &[1])
+            .build()
+            .unwrap();
+        board.set_cell(0, 0, Cell::Filled);
+        let mut contents = Vec::new();
+        board.write_state(&mut contents).unwrap();
+        let contents = String::from_utf8(contents).unwrap();
+        for name in &["saved.state", "saved.puzzle", "saved"] {
+            let format = Format::detect(Path::new(name), &contents);
+            assert_eq!(format, Some(Format::State), "{}", name);
+        }
+        let read = Board::read_state(contents.as_bytes()).unwrap();
+        assert_eq!(read.get_cell(0, 0), Cell::Filled);
+        assert_eq!(read.get_cell(1, 0), Cell::Unknown);
+    }
+}
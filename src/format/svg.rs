@@ -0,0 +1,145 @@
+use crate::board::{Board, Cell, Color};
+use std::io;
+
+/// The size of a single cell, in pixels
+const CELL_SIZE: usize = 20;
+
+/// Every this many cells, a thicker line is drawn
+const MAJOR_LINE_INTERVAL: usize = 5;
+
+/// Get the color used to draw something of the given palette color
+fn get_fill(board: &Board, color: Color) -> String {
+    let (r, g, b) = board
+        .get_palette()
+        .get(color)
+        .map(|color| color.rgb)
+        .unwrap_or((0, 0, 0));
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Write a single clue number centered at the given position
+fn write_clue<W: io::Write>(
+    handle: &mut W,
+    board: &Board,
+    x: usize,
+    y: usize,
+    text: String,
+    color: Color,
+) -> io::Result<()> {
+    writeln!(
+        handle,
+        "<text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>",
+        x,
+        y,
+        get_fill(board, color),
+        text
+    )
+}
+
+impl Board {
+    /// Write this board as an SVG image, with the row and column clues drawn in the margins.
+    /// If include_cells is false, only the empty grid is drawn, which is suitable for printing
+    /// the puzzle. Otherwise, filled cells are drawn as well, which is suitable for printing
+    /// the solution.
+    pub fn write_svg<W: io::Write>(&self, mut handle: W, include_cells: bool) -> io::Result<()> {
+        let width = self.get_width() as usize;
+        let height = self.get_height() as usize;
+        let left = self.get_max_row_constraints().max(1) * CELL_SIZE;
+        let top = self.get_max_col_constraints().max(1) * CELL_SIZE;
+        let total_width = left + width * CELL_SIZE + 1;
+        let total_height = top + height * CELL_SIZE + 1;
+        writeln!(
+            handle,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">",
+            total_width, total_height
+        )?;
+        writeln!(
+            handle,
+            "<rect width=\"{}\" height=\"{}\" fill=\"#ffffff\"/>",
+            total_width, total_height
+        )?;
+        // Cells
+        for row in 0..height {
+            for col in 0..width {
+                let x = left + col * CELL_SIZE;
+                let y = top + row * CELL_SIZE;
+                let fill = if self.is_masked(col as _, row as _) {
+                    "#808080".to_string()
+                } else if !include_cells {
+                    continue;
+                } else {
+                    match self.get_cell(col as _, row as _) {
+                        Cell::Unknown | Cell::Empty => continue,
+                        cell => get_fill(self, cell.get_color().unwrap()),
+                    }
+                };
+                writeln!(
+                    handle,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                    x, y, CELL_SIZE, CELL_SIZE, fill
+                )?;
+            }
+        }
+        // Clues
+        writeln!(
+            handle,
+            "<g font-family=\"sans-serif\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\">",
+            CELL_SIZE * 3 / 5
+        )?;
+        for row in 0..height {
+            let constraints = self.get_row_constraints(row as _);
+            let y = top + row * CELL_SIZE + CELL_SIZE / 2;
+            for (i, constraint) in constraints.iter().enumerate() {
+                let x = left - (constraints.len() - i) * CELL_SIZE + CELL_SIZE / 2;
+                let text = constraint.to_string();
+                write_clue(&mut handle, self, x, y, text, constraint.get_color())?;
+            }
+        }
+        for col in 0..width {
+            let constraints = self.get_col_constraints(col as _);
+            let x = left + col * CELL_SIZE + CELL_SIZE / 2;
+            for (i, constraint) in constraints.iter().enumerate() {
+                let y = top - (constraints.len() - i) * CELL_SIZE + CELL_SIZE / 2;
+                let text = constraint.to_string();
+                write_clue(&mut handle, self, x, y, text, constraint.get_color())?;
+            }
+        }
+        writeln!(handle, "</g>")?;
+        // Grid lines
+        writeln!(handle, "<g stroke=\"#000000\" stroke-linecap=\"square\">")?;
+        for col in 0..=width {
+            let x = left + col * CELL_SIZE;
+            let stroke = if col % MAJOR_LINE_INTERVAL == 0 || col == width {
+                2
+            } else {
+                1
+            };
+            writeln!(
+                handle,
+                "<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\" stroke-width=\"{3}\"/>",
+                x,
+                top,
+                top + height * CELL_SIZE,
+                stroke
+            )?;
+        }
+        for row in 0..=height {
+            let y = top + row * CELL_SIZE;
+            let stroke = if row % MAJOR_LINE_INTERVAL == 0 || row == height {
+                2
+            } else {
+                1
+            };
+            writeln!(
+                handle,
+                "<line x1=\"{0}\" y1=\"{2}\" x2=\"{1}\" y2=\"{2}\" stroke-width=\"{3}\"/>",
+                left,
+                left + width * CELL_SIZE,
+                y,
+                stroke
+            )?;
+        }
+        writeln!(handle, "</g>")?;
+        writeln!(handle, "</svg>")
+    }
+}
@@ -0,0 +1,213 @@
+use super::ParseError;
+use crate::board::{parse_constraint, Board, BoardBuilder, Cell, CharMap, ConstraintList, Unit};
+use std::convert::TryFrom;
+use std::io;
+
+/// Parse a single line of clues, such as "3 1 2" or "3, 1, 2".
+/// An empty line may be written as "0" or "-".
+fn parse_clue_line(line_number: usize, line: &str) -> Result<ConstraintList, ParseError> {
+    let mut ret = ConstraintList::new();
+    let separators = |c: char| c.is_whitespace() || c == ',' || c == ';' || c == '.';
+    for value in line.split(separators) {
+        if value.is_empty() || value == "0" || value == "-" {
+            continue;
+        }
+        let constraint = parse_constraint(value)
+            .ok_or_else(|| ParseError::syntax(line_number, format!("invalid clue '{}'", value)))?;
+        ret.push(constraint);
+    }
+    Ok(ret)
+}
+
+impl Board {
+    /// Read a puzzle from plain text clues, as commonly copied out of puzzle apps.
+    /// Each line holds the clues of one column, then after a blank line,
+    /// each line holds the clues of one row.
+    /// Lines that contain only words, such as "Columns:" or "Rows:", are treated like blank lines.
+    pub fn read_clue_text<R: io::BufRead>(handle: R) -> Result<Board, ParseError> {
+        let mut blocks: Vec<Vec<ConstraintList>> = Vec::new();
+        let mut in_block = false;
+        for (i, line) in handle.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            let is_heading = line
+                .chars()
+                .all(|c| !c.is_ascii_digit() && c != '?' && c != '-');
+            if line.is_empty() || is_heading {
+                in_block = false;
+                continue;
+            }
+            if !in_block {
+                blocks.push(Vec::new());
+                in_block = true;
+            }
+            let list = parse_clue_line(i + 1, line)?;
+            blocks.last_mut().unwrap().push(list);
+        }
+        if blocks.len() != 2 {
+            return Err(ParseError::syntax(
+                1,
+                format!(
+                    "expected column clues and row clues separated by a blank line, found {} groups of clues",
+                    blocks.len()
+                ),
+            ));
+        }
+        let rows = blocks.pop().unwrap();
+        let cols = blocks.pop().unwrap();
+        let too_big = |_| ParseError::Unsupported("puzzle is too large".to_string());
+        let width = Unit::try_from(cols.len()).map_err(too_big)?;
+        let height = Unit::try_from(rows.len()).map_err(too_big)?;
+        let board = BoardBuilder::new(width, height)
+            .rows(rows)
+            .cols(cols)
+            .build()?;
+        Ok(board)
+    }
+}
+
+/// Parse a grid of cell glyphs, such as "X.X" or "?X." with the default CharMap.
+/// Spaces and '|' borders are ignored unless the CharMap uses them, as are lines starting
+/// with '+', so that a board printed by this crate can be read back in if it was printed
+/// without clues (see Board::clone_without_constraints) and without terminal colors (see
+/// BoardDisplay::monochrome), and has no masked cells, which are printed as spaces.
+fn parse_grid<R: io::BufRead>(
+    handle: R,
+    chars: &CharMap,
+) -> Result<(Unit, Unit, Vec<Cell>), ParseError> {
+    let is_border = |c: char| (c == ' ' || c == '|') && chars.get_cell(c).is_none();
+    let mut width = None;
+    let mut height = 0;
+    let mut cells = Vec::new();
+    for (i, line) in handle.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_matches(|c: char| c.is_whitespace() && chars.get_cell(c).is_none());
+        let is_separator = line.starts_with('+') && chars.get_cell('+').is_none();
+        if line.is_empty() || is_separator {
+            continue;
+        }
+        let mut row_width = 0;
+        for glyph in line.chars().filter(|&c| !is_border(c)) {
+            let cell = chars
+                .get_cell(glyph)
+                .ok_or_else(|| ParseError::syntax(i + 1, format!("invalid cell '{}'", glyph)))?;
+            cells.push(cell);
+            row_width += 1;
+        }
+        if *width.get_or_insert(row_width) != row_width {
+            return Err(ParseError::syntax(
+                i + 1,
+                "every row must have the same number of cells",
+            ));
+        }
+        height += 1;
+    }
+    let too_big = |_| ParseError::Unsupported("puzzle is too large".to_string());
+    let width = Unit::try_from(width.unwrap_or(0)).map_err(too_big)?;
+    let height = Unit::try_from(height).map_err(too_big)?;
+    Ok((width, height, cells))
+}
+
+impl Board {
+    /// Read a solved board from a grid of cell glyphs, one row per line.
+    /// Filled cells are 'X' (or a color glyph), and empty cells are '.'.
+    /// Constraints are generated from the cells.
+    pub fn read_ascii_solution<R: io::BufRead>(handle: R) -> Result<Board, ParseError> {
+        Board::read_ascii_solution_with(handle, &CharMap::default())
+    }
+
+    /// Read a solved board from a grid of cell glyphs, using the given characters.
+    /// See read_ascii_solution.
+    pub fn read_ascii_solution_with<R: io::BufRead>(
+        handle: R,
+        chars: &CharMap,
+    ) -> Result<Board, ParseError> {
+        let (width, height, cells) = parse_grid(handle, chars)?;
+        if cells.contains(&Cell::Unknown) {
+            return Err(ParseError::Unsupported(
+                "solutions can not contain unknown cells".to_string(),
+            ));
+        }
+        let mut board = Board::new_filled(width, height, Cell::Empty);
+        for (i, cell) in cells.into_iter().enumerate() {
+            board.set_cell_index(i, cell);
+        }
+        board.generate_new_constraints();
+        Ok(board)
+    }
+
+    /// Read a partially solved grid of cell glyphs and apply it to this board.
+    /// Unknown cells ('?') are left as they are.
+    /// The grid must be the same size as this board.
+    pub fn read_ascii_cells<R: io::BufRead>(&mut self, handle: R) -> Result<(), ParseError> {
+        self.read_ascii_cells_with(handle, &CharMap::default())
+    }
+
+    /// Read a partially solved grid of cell glyphs using the given characters,
+    /// and apply it to this board. See read_ascii_cells.
+    pub fn read_ascii_cells_with<R: io::BufRead>(
+        &mut self,
+        handle: R,
+        chars: &CharMap,
+    ) -> Result<(), ParseError> {
+        let (width, height, cells) = parse_grid(handle, chars)?;
+        if (width, height) != self.get_size() {
+            return Err(ParseError::Unsupported(format!(
+                "grid is {}x{}, but the board is {}x{}",
+                width,
+                height,
+                self.get_width(),
+                self.get_height()
+            )));
+        }
+        for (i, cell) in cells.into_iter().enumerate() {
+            if cell != Cell::Unknown {
+                self.set_cell_index(i, cell);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Board {
+    /// Get this board's cells as a grid of glyphs, one row per line,
+    /// without any constraints or borders.
+    /// The result can be read back with read_ascii_solution or read_ascii_cells.
+    pub fn to_grid_string(&self) -> String {
+        self.to_grid_string_with(&CharMap::default())
+    }
+
+    /// Get this board's cells as a grid of glyphs, using the given characters.
+    /// See to_grid_string.
+    pub fn to_grid_string_with(&self, chars: &CharMap) -> String {
+        let mut ret = String::with_capacity(self.get_num_cells() + self.get_height() as usize);
+        for row in 0..self.get_height() {
+            for col in 0..self.get_width() {
+                ret.push(chars.get_char(self.get_cell(col, row)));
+            }
+            ret.push('\n');
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printed_grid_reads_back() {
+        let mut board = Board::new_filled(3, 2, Cell::Unknown);
+        board.set_cell(0, 0, Cell::Filled);
+        board.set_cell(2, 1, Cell::Empty);
+        let grid = board.clone_without_constraints();
+        let printed = grid
+            .display_with(&CharMap::default())
+            .monochrome()
+            .to_string();
+        let (width, height, cells) = parse_grid(printed.as_bytes(), &CharMap::default()).unwrap();
+        assert_eq!((width, height), (3, 2));
+        let expected: Vec<Cell> = (0..6).map(|i| board.get_cell_index(i)).collect();
+        assert_eq!(cells, expected);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::board::{Board, ConstraintList};
+use crate::solver::{LineInfo, LineSolver, LineType, TraceEvent};
+use std::io;
+
+/// Format a list of constraint lists as a JSON array of arrays of lengths.
+/// Lengths that are not exact are written as strings, such as "3-5", "?" or "=7".
+fn constraints_json<'a, I: Iterator<Item = &'a ConstraintList>>(lists: I) -> String {
+    let lists: Vec<String> = lists
+        .map(|list| {
+            let values: Vec<String> = list
+                .iter()
+                .map(|x| {
+                    if x.is_exact() {
+                        x.to_string()
+                    } else {
+                        format!("\"{}\"", x)
+                    }
+                })
+                .collect();
+            format!("[{}]", values.join(","))
+        })
+        .collect();
+    format!("[{}]", lists.join(","))
+}
+
+/// Format a line as a JSON object
+pub fn line_json(line: LineInfo) -> String {
+    let linetype = match line.linetype {
+        LineType::Row => "row",
+        LineType::Column => "column",
+    };
+    format!("{{\"type\":\"{}\",\"index\":{}}}", linetype, line.index)
+}
+
+/// Get the name of a line solver, as used by the --line-alg option
+fn technique_name(technique: LineSolver) -> &'static str {
+    match technique {
+        LineSolver::Graph => "graph",
+        LineSolver::LeftRight => "left-right",
+        LineSolver::Automaton => "automaton",
+    }
+}
+
+impl Board {
+    /// Write a JSON replay of solving this board, given the trace recorded while solving it.
+    /// This board should be in the state it was in before solving began.
+    ///
+    /// Cell values are written as in write_csv_solution: -1 is unknown, 0 is empty,
+    /// 1 is filled, and higher values are colors.
+    /// Each event is one of:
+    ///  - {"event": "deduce", "col", "row", "value", "line": {"type", "index"}, "technique",
+    ///    "pass", "depth"}, where technique is the line solver used ("graph", "left-right"
+    ///    or "automaton"), pass counts from 1, and depth is that of the current branch
+    ///  - {"event": "branch", "col", "row", "value", "depth"}
+    ///  - {"event": "contradiction", "line": {"type", "index"}}
+    ///  - {"event": "backtrack", "depth"}, after which every cell should be restored to
+    ///    its value from before the branch that entered the given depth
+    ///  - {"event": "probe", "col", "row", "value", "pass", "depth"}, for cells set while probing
+    ///  - {"event": "crossing", "col", "row", "value", "pass", "depth"}, for cells set while
+    ///    checking crossing lines
+    pub fn write_trace_json<W: io::Write>(
+        &self,
+        trace: &[TraceEvent],
+        mut handle: W,
+    ) -> io::Result<()> {
+        writeln!(handle, "{{")?;
+        writeln!(handle, "  \"width\": {},", self.get_width())?;
+        writeln!(handle, "  \"height\": {},", self.get_height())?;
+        let cols = (0..self.get_width()).map(|col| self.get_col_constraints(col));
+        writeln!(handle, "  \"columns\": {},", constraints_json(cols))?;
+        let rows = (0..self.get_height()).map(|row| self.get_row_constraints(row));
+        writeln!(handle, "  \"rows\": {},", constraints_json(rows))?;
+        let cells: Vec<String> = (0..self.get_num_cells())
+            .map(|i| self.get_cell_index(i).to_i64().to_string())
+            .collect();
+        writeln!(handle, "  \"cells\": [{}],", cells.join(","))?;
+        writeln!(handle, "  \"events\": [")?;
+        for (i, event) in trace.iter().enumerate() {
+            let json = match *event {
+                TraceEvent::Deduce {
+                    col,
+                    row,
+                    value,
+                    line,
+                    technique,
+                    pass,
+                    depth,
+                } => format!(
+                    "{{\"event\":\"deduce\",\"col\":{},\"row\":{},\"value\":{},\"line\":{},\
+                     \"technique\":\"{}\",\"pass\":{},\"depth\":{}}}",
+                    col,
+                    row,
+                    value.to_i64(),
+                    line_json(line),
+                    technique_name(technique),
+                    pass,
+                    depth
+                ),
+                TraceEvent::Branch {
+                    col,
+                    row,
+                    value,
+                    depth,
+                } => format!(
+                    "{{\"event\":\"branch\",\"col\":{},\"row\":{},\"value\":{},\"depth\":{}}}",
+                    col,
+                    row,
+                    value.to_i64(),
+                    depth
+                ),
+                TraceEvent::Contradiction { line } => format!(
+                    "{{\"event\":\"contradiction\",\"line\":{}}}",
+                    line_json(line)
+                ),
+                TraceEvent::Backtrack { depth } => {
+                    format!("{{\"event\":\"backtrack\",\"depth\":{}}}", depth)
+                }
+                TraceEvent::Probe {
+                    col,
+                    row,
+                    value,
+                    pass,
+                    depth,
+                } => format!(
+                    "{{\"event\":\"probe\",\"col\":{},\"row\":{},\"value\":{},\
+                     \"pass\":{},\"depth\":{}}}",
+                    col,
+                    row,
+                    value.to_i64(),
+                    pass,
+                    depth
+                ),
+                TraceEvent::Crossing {
+                    col,
+                    row,
+                    value,
+                    pass,
+                    depth,
+                } => format!(
+                    "{{\"event\":\"crossing\",\"col\":{},\"row\":{},\"value\":{},\
+                     \"pass\":{},\"depth\":{}}}",
+                    col,
+                    row,
+                    value.to_i64(),
+                    pass,
+                    depth
+                ),
+            };
+            let separator = if i + 1 < trace.len() { "," } else { "" };
+            writeln!(handle, "    {}{}", json, separator)?;
+        }
+        writeln!(handle, "  ]")?;
+        writeln!(handle, "}}")
+    }
+}
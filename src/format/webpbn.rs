@@ -0,0 +1,346 @@
+use super::ParseError;
+use crate::board::{
+    parse_constraint, Board, BoardBuilder, Cell, Color, ConstraintList, Palette, Unit, COLOR_GLYPHS,
+};
+use roxmltree::{Document, Node, ParsingOptions};
+use std::convert::TryFrom;
+use std::io;
+
+/// Parse a hex color such as "f00" or "ff0000"
+fn parse_rgb(value: &str) -> Option<(u8, u8, u8)> {
+    let value = value.trim().trim_start_matches('#');
+    let digits: Vec<u8> = value
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    match digits.len() {
+        3 => Some((digits[0] * 17, digits[1] * 17, digits[2] * 17)),
+        6 => Some((
+            digits[0] * 16 + digits[1],
+            digits[2] * 16 + digits[3],
+            digits[4] * 16 + digits[5],
+        )),
+        _ => None,
+    }
+}
+
+/// Get the line number of a node
+fn line_of(doc: &Document, node: Node) -> usize {
+    doc.text_pos_at(node.range().start).row as usize
+}
+
+/// Read the colors of a <puzzle> into a palette, with the default color as color 0 and
+/// every other color, except the background, in the order they are listed
+fn read_palette(doc: &Document, puzzle: Node) -> Result<Palette, ParseError> {
+    let default_color = puzzle.attribute("defaultcolor").unwrap_or("black");
+    let background = puzzle.attribute("backgroundcolor").unwrap_or("white");
+    let mut colors = Vec::new();
+    let mut default_rgb = (0, 0, 0);
+    for color in puzzle.children().filter(|n| n.has_tag_name("color")) {
+        let line = line_of(doc, color);
+        let name = color
+            .attribute("name")
+            .ok_or_else(|| ParseError::syntax(line, "color has no name"))?;
+        if name == background {
+            continue;
+        }
+        let rgb = parse_rgb(color.text().unwrap_or(""))
+            .ok_or_else(|| ParseError::syntax(line, "invalid color value"))?;
+        if name == default_color {
+            default_rgb = rgb;
+        } else {
+            colors.push((name, rgb));
+        }
+    }
+    let mut palette = Palette::with_default(default_color, default_rgb);
+    for (name, rgb) in colors {
+        if palette.find(name).is_none() && palette.add(name, rgb).is_none() {
+            return Err(ParseError::Unsupported("too many colors".to_string()));
+        }
+    }
+    Ok(palette)
+}
+
+/// Read every <line> in a <clues> block, looking up the color of each count in the palette
+fn read_clues(
+    doc: &Document,
+    clues: Node,
+    palette: &Palette,
+) -> Result<Vec<ConstraintList>, ParseError> {
+    let mut ret = Vec::new();
+    for line in clues.children().filter(|n| n.has_tag_name("line")) {
+        let mut list = ConstraintList::new();
+        for count in line.children().filter(|n| n.has_tag_name("count")) {
+            let color = match count.attribute("color") {
+                Some(name) => palette.find(name).ok_or_else(|| {
+                    ParseError::syntax(line_of(doc, count), format!("unknown color '{}'", name))
+                })?,
+                None => 0,
+            };
+            let text = count.text().unwrap_or("");
+            let constraint = parse_constraint(text).ok_or_else(|| {
+                ParseError::syntax(line_of(doc, count), format!("invalid count '{}'", text))
+            })?;
+            list.push(constraint.with_color(color));
+        }
+        ret.push(list);
+    }
+    Ok(ret)
+}
+
+impl Board {
+    /// Read a puzzle in webpbn's XML format (as exported by webpbn.com).
+    /// If the file contains several puzzles, only the first is read.
+    /// The puzzle's colors, other than the background, become the board's palette.
+    /// Any solution image in the file is ignored.
+    pub fn read_webpbn_xml<R: io::Read>(mut handle: R) -> Result<Board, ParseError> {
+        let mut text = String::new();
+        handle.read_to_string(&mut text)?;
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..ParsingOptions::default()
+        };
+        let doc = Document::parse_with_options(&text, options)
+            .map_err(|e| ParseError::syntax(e.pos().row as usize, e.to_string()))?;
+        let puzzle = doc
+            .descendants()
+            .find(|n| n.has_tag_name("puzzle"))
+            .ok_or_else(|| ParseError::syntax(1, "no <puzzle> element"))?;
+        if let Some(kind) = puzzle.attribute("type") {
+            if kind != "grid" {
+                return Err(ParseError::Unsupported(format!("{} puzzles", kind)));
+            }
+        }
+        let palette = read_palette(&doc, puzzle)?;
+        let mut rows = None;
+        let mut cols = None;
+        for clues in puzzle.children().filter(|n| n.has_tag_name("clues")) {
+            let lists = read_clues(&doc, clues, &palette)?;
+            match clues.attribute("type") {
+                Some("rows") => rows = Some(lists),
+                Some("columns") => cols = Some(lists),
+                _ => {
+                    return Err(ParseError::syntax(
+                        line_of(&doc, clues),
+                        "clues must have a type of 'rows' or 'columns'",
+                    ))
+                }
+            }
+        }
+        let line = line_of(&doc, puzzle);
+        let rows = rows.ok_or_else(|| ParseError::syntax(line, "missing row clues"))?;
+        let cols = cols.ok_or_else(|| ParseError::syntax(line, "missing column clues"))?;
+        let too_big = |_| ParseError::Unsupported("puzzle is too large".to_string());
+        let width = Unit::try_from(cols.len()).map_err(too_big)?;
+        let height = Unit::try_from(rows.len()).map_err(too_big)?;
+        let board = BoardBuilder::new(width, height)
+            .palette(palette)
+            .rows(rows)
+            .cols(cols)
+            .build()?;
+        Ok(board)
+    }
+}
+
+/// Escape text for use inside an XML attribute or element
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Write a <clues> block for the given lines
+fn write_clues<'a, W, I>(handle: &mut W, kind: &str, board: &Board, lines: I) -> io::Result<()>
+where
+    W: io::Write,
+    I: Iterator<Item = &'a ConstraintList>,
+{
+    writeln!(handle, "<clues type=\"{}\">", kind)?;
+    for list in lines {
+        write!(handle, "<line>")?;
+        for constraint in list {
+            if !constraint.is_exact() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "webpbn XML can only represent blocks of known length",
+                ));
+            }
+            if constraint.get_color() == 0 {
+                write!(handle, "<count>{}</count>", constraint.get_length())?;
+            } else {
+                let name = color_name(board.get_palette(), constraint.get_color())?;
+                write!(
+                    handle,
+                    "<count color=\"{}\">{}</count>",
+                    escape(name),
+                    constraint.get_length()
+                )?;
+            }
+        }
+        writeln!(handle, "</line>")?;
+    }
+    writeln!(handle, "</clues>")
+}
+
+/// Get the name of a color in the palette, or an error if the palette does not have it
+fn color_name(palette: &Palette, color: Color) -> io::Result<&str> {
+    match palette.get(color) {
+        Some(entry) => Ok(&entry.name),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("color {} is not in the palette", color),
+        )),
+    }
+}
+
+impl Board {
+    /// Write this board's constraints in webpbn's XML format.
+    /// If a solution is given, its cells are included as the puzzle's goal image.
+    /// Fails with io::ErrorKind::InvalidInput if the board uses a color its palette does not
+    /// have, or has more colors than there are glyphs to tell them apart in the goal image,
+    /// or if the solution is a different size from the board or has Unknown cells.
+    pub fn write_webpbn_xml<W: io::Write>(
+        &self,
+        mut handle: W,
+        solution: Option<&Board>,
+    ) -> io::Result<()> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let palette = self.get_palette();
+        let default_color = color_name(palette, 0)?;
+        if palette.num_colors() > COLOR_GLYPHS.len() {
+            return Err(invalid("too many colors to write a goal image"));
+        }
+        if let Some(solution) = solution {
+            if solution.get_size() != self.get_size() {
+                return Err(invalid("the solution is not the same size as the puzzle"));
+            }
+            for i in 0..solution.get_num_cells() {
+                match solution.get_cell_index(i) {
+                    Cell::Unknown => return Err(invalid("the solution has unknown cells")),
+                    cell => {
+                        if let Some(color) = cell.get_color() {
+                            color_name(palette, color)?;
+                        }
+                    }
+                }
+            }
+        }
+        // the background is not in the palette, so its name must not be either
+        let mut background = "white".to_string();
+        while palette.find(&background).is_some() {
+            background.push('_');
+        }
+        writeln!(handle, "<?xml version=\"1.0\"?>")?;
+        writeln!(
+            handle,
+            "<!DOCTYPE pbn SYSTEM \"http://webpbn.com/pbn-0.3.dtd\">"
+        )?;
+        writeln!(handle, "<puzzleset>")?;
+        writeln!(
+            handle,
+            "<puzzle type=\"grid\" defaultcolor=\"{}\" backgroundcolor=\"{}\">",
+            escape(default_color),
+            escape(&background)
+        )?;
+        writeln!(
+            handle,
+            "<color name=\"{}\" char=\".\">fff</color>",
+            escape(&background)
+        )?;
+        for color in 0..palette.num_colors() {
+            let entry = palette.get(color as Color).unwrap();
+            let (r, g, b) = entry.rgb;
+            writeln!(
+                handle,
+                "<color name=\"{}\" char=\"{}\">{:02x}{:02x}{:02x}</color>",
+                escape(&entry.name),
+                Cell::with_color(color as Color),
+                r,
+                g,
+                b
+            )?;
+        }
+        let cols = (0..self.get_width()).map(|col| self.get_col_constraints(col));
+        write_clues(&mut handle, "columns", self, cols)?;
+        let rows = (0..self.get_height()).map(|row| self.get_row_constraints(row));
+        write_clues(&mut handle, "rows", self, rows)?;
+        if let Some(solution) = solution {
+            writeln!(handle, "<solution type=\"goal\">")?;
+            writeln!(handle, "<image>")?;
+            for row in 0..solution.get_height() {
+                write!(handle, "|")?;
+                for col in 0..solution.get_width() {
+                    write!(handle, "{}", solution.get_cell(col, row))?;
+                }
+                writeln!(handle, "|")?;
+            }
+            writeln!(handle, "</image>")?;
+            writeln!(handle, "</solution>")?;
+        }
+        writeln!(handle, "</puzzle>")?;
+        writeln!(handle, "</puzzleset>")
+    }
+}
+
+/// The URL that webpbn puzzles are exported from, given a puzzle ID
+#[cfg(feature = "http")]
+const WEBPBN_EXPORT_URL: &str = "https://webpbn.com/XMLpuz.cgi?id=";
+
+impl Board {
+    /// Download and read a puzzle from webpbn.com, given its ID
+    #[cfg(feature = "http")]
+    pub fn fetch_webpbn(id: u32) -> Result<Board, ParseError> {
+        let url = format!("{}{}", WEBPBN_EXPORT_URL, id);
+        let response = ureq::get(&url).call().map_err(|e| match e {
+            ureq::Error::Status(code, _) => ParseError::Unsupported(format!(
+                "webpbn returned status {} for puzzle {}",
+                code, id
+            )),
+            e => ParseError::Io(io::Error::other(e)),
+        })?;
+        Board::read_webpbn_xml(response.into_reader())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_reads_back() {
+        let mut palette = Palette::with_default("black", (0, 0, 0));
+        palette.add("white", (255, 255, 255));
+        let mut solution = Board::new_filled(3, 1, Cell::Empty);
+        solution.set_palette(palette);
+        solution.set_cell(0, 0, Cell::Filled);
+        solution.set_cell(2, 0, Cell::Colored(1));
+        solution.generate_new_constraints();
+        let mut xml = Vec::new();
+        solution
+            .write_webpbn_xml(&mut xml, Some(&solution))
+            .unwrap();
+        let puzzle = Board::read_webpbn_xml(&xml[..]).unwrap();
+        assert_eq!(puzzle.get_palette().num_colors(), 2);
+        assert_eq!(
+            puzzle.get_row_constraints(0),
+            solution.get_row_constraints(0)
+        );
+    }
+
+    #[test]
+    fn export_rejects_bad_solutions() {
+        let mut solution = Board::new_filled(2, 2, Cell::Filled);
+        solution.generate_new_constraints();
+        let mut partial = solution.clone();
+        partial.set_cell(1, 1, Cell::Unknown);
+        let small = Board::new_filled(1, 2, Cell::Filled);
+        for other in [partial, small].iter() {
+            let error = solution
+                .write_webpbn_xml(io::sink(), Some(other))
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
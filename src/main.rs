@@ -1,9 +1,9 @@
 #![allow(unused_macros)]
 pub mod board;
+pub mod cli;
+pub mod format;
 pub mod solver;
 pub mod util;
-use std::fs;
-use std::io;
 
 macro_rules! make_constraints {
     ($( $value:expr ),*) => {
@@ -43,17 +43,8 @@ macro_rules! make_line {
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        panic!()
-    }
-    let puzzlef = fs::File::open(&args[1]).unwrap();
-    let puzzlef = io::BufReader::new(puzzlef);
-    let mut b = board::Board::read_csv_puzzle(puzzlef);
-    println!("{}", b);
-    println!("{:?}", solver::stupid_branched_solver_set(&mut b));
-    println!("{}x{}", b.get_width(), b.get_height());
-    println!("{}", b.clone_without_constraints());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(cli::run(&args));
 }
 
 // currently unsolvable within a reasonable time afaik (takes longer than a few minutes):
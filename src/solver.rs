@@ -1,388 +1,5707 @@
-use crate::board::{self, Unit};
+use crate::board::{self, ChangeSet, Unit};
 use crate::util::{self, PrioritySet};
-use std::collections::BTreeSet;
+use std::cell::{self, RefCell};
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::mem;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum LineType {
     Row,
     Column,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct LineInfo {
     pub index: Unit,
     pub linetype: LineType,
 }
 
+impl fmt::Display for LineInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.linetype {
+            LineType::Row => write!(f, "row {}", self.index),
+            LineType::Column => write!(f, "column {}", self.index),
+        }
+    }
+}
+
 /// Completely solving only has two possibilities:
-/// A successful solve, or a contradiction discovery
+/// A successful solve, or a contradiction discovery.
+/// A solver with a time, branch or memory limit may also give up before finding either.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SolveResult {
     Success,
     Contradiction,
+    /// SolverConfig::max_duration passed before the board was solved
+    Timeout,
+    /// The given limit was reached before the board was solved
+    Exhausted(Limit),
+}
+
+/// A limit, other than time, that a branching solver can give up at
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// SolverConfig::max_branches guesses were made
+    Branches,
+    /// The boards kept by the search took up more than SolverConfig::max_memory bytes
+    Memory,
+}
+
+/// A cell's value, guessed by a branching solver
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Guess {
+    pub col: Unit,
+    pub row: Unit,
+    pub value: board::Cell,
+}
+
+impl Guess {
+    fn new(b: &board::Board, index: usize, value: board::Cell) -> Guess {
+        let (col, row) = b.get_coordinate(index);
+        Guess { col, row, value }
+    }
+}
+
+impl fmt::Display for Guess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {}) {:?}", self.col, self.row, self.value)
+    }
+}
+
+/// Everything a branching solver found, as returned by stupid_branched_solver_with and
+/// stupid_branched_solver_set_with
+#[derive(Clone, Debug)]
+pub struct SolveOutcome {
+    pub result: SolveResult,
+    /// The solution, if the board was solved. If the search gave up, the board with the fewest
+    /// Unknown cells it reached (see SolveStats::best_partial); otherwise, the cells deduced
+    /// before guessing.
+    pub board: board::Board,
+    /// The guesses that led to the solution, outermost first, or if the search gave up, the
+    /// guesses it was searching below. Empty if the board has no solution.
+    pub guesses: Vec<Guess>,
+    pub stats: SolveStats,
+    /// Whether the search ended without searching every branch, so that the board may have
+    /// solutions other than the one found. Always true if the search gave up.
+    pub unexplored: bool,
+}
+
+impl SolveOutcome {
+    /// Gather the outcome of a search of b that ended with the given result
+    fn new(
+        b: &board::Board,
+        result: SolveResult,
+        stats: SolveStats,
+        context: &SearchContext,
+    ) -> SolveOutcome {
+        let board = match stats.best_partial.as_ref() {
+            Some(partial) if result != SolveResult::Success => partial.clone(),
+            _ => b.clone(),
+        };
+        let guesses = if result == SolveResult::Contradiction {
+            Vec::new()
+        } else {
+            context.path.take()
+        };
+        SolveOutcome {
+            result,
+            board,
+            guesses,
+            stats,
+            unexplored: match result {
+                SolveResult::Success => context.unexplored.get(),
+                SolveResult::Contradiction => false,
+                SolveResult::Timeout | SolveResult::Exhausted(_) => true,
+            },
+        }
+    }
+}
+
+/// How a branching solver picks the Unknown cell to guess when line solving stalls
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BranchHeuristic {
+    /// The first Unknown cell, by index
+    FirstUnknown,
+    /// The cell whose row and column have the fewest unsolved cells
+    FewestUnsolved,
+    /// The most constrained cell: the one whose row and column have the fewest valid
+    /// placements of their clues, given the cells solved so far.
+    /// Ties are broken by FewestUnsolved.
+    MostConstrained,
+    /// The cell with the most filled neighbors, counting the edges of the board, where
+    /// the clues are, as filled. Such guesses tend to propagate further.
+    /// Ties are broken by FewestUnsolved.
+    Adjacency,
+    /// The cell whose value is most certain, by fill_probabilities.
+    /// Ties are broken by FewestUnsolved.
+    Probability,
+}
+
+/// The algorithm used to solve a single line
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LineSolver {
+    /// Build a graph of the valid placements of the line's clues (try_solve_line_complete).
+    /// May miss deductions on lines with a lot of slack, leaving more work to branching.
+    Graph,
+    /// Intersect the leftmost and rightmost placements of the clues
+    /// (try_solve_line_left_right). Faster, but may deduce fewer cells, leaving more work
+    /// to branching.
+    LeftRight,
+    /// Run a finite automaton built from the line's clues (try_solve_line_automaton).
+    /// Deduces every cell that can be deduced from the line alone, and the automaton is
+    /// built once per line rather than every time the line is solved.
+    #[default]
+    Automaton,
+}
+
+/// Solve a line with the given line solver, returning the modified cells,
+/// or None if a contradiction was found.
+/// If the line's cells are the same as the last time solving it deduced nothing, which
+/// happens when backtracking undoes every change made to it since, it is not solved again.
+fn solve_line<L: board::LineMut>(
+    line: &mut L,
+    lineid: LineInfo,
+    nodecache: &mut NodeListCache,
+    line_solver: LineSolver,
+    stats: &mut SolveStats,
+) -> Option<ChangeSet> {
+    let unchanged = nodecache.quiet(lineid).as_ref().is_some_and(|quiet| {
+        quiet.line_solver == line_solver
+            && quiet
+                .cells
+                .iter()
+                .enumerate()
+                .all(|(i, &cell)| line.get_cell(i as Unit) == cell)
+    });
+    if unchanged {
+        stats.unchanged_lines += 1;
+        return Some(ChangeSet::new());
+    }
+    let result = solve_line_memoized(line, lineid, nodecache, line_solver, stats);
+    if result.as_ref().is_some_and(|changes| changes.is_empty()) {
+        // nothing changed, so these are also the cells the line was solved with
+        let quiet = nodecache.quiet(lineid).get_or_insert_with(|| QuietLine {
+            line_solver,
+            cells: Vec::with_capacity(line.size() as usize),
+        });
+        quiet.line_solver = line_solver;
+        quiet.cells.clear();
+        quiet
+            .cells
+            .extend((0..line.size()).map(|i| line.get_cell(i)));
+    }
+    result
+}
+
+/// Same as solve_line_uncached, except that if the same line was solved with the same cells
+/// before, the memoized result is reused
+fn solve_line_memoized<L: board::LineMut>(
+    line: &mut L,
+    lineid: LineInfo,
+    nodecache: &mut NodeListCache,
+    line_solver: LineSolver,
+    stats: &mut SolveStats,
+) -> Option<ChangeSet> {
+    if nodecache.memo.capacity() == 0 {
+        return solve_line_uncached(line, lineid, nodecache, line_solver);
+    }
+    let cells: Vec<board::Cell> = (0..line.size()).map(|i| line.get_cell(i)).collect();
+    let mut hasher = DefaultHasher::new();
+    lineid.hash(&mut hasher);
+    cells.hash(&mut hasher);
+    let key = hasher.finish();
+    // different lines may share a hash, so the line itself is checked too
+    if let Some(memo) = nodecache
+        .memo
+        .get(&key)
+        .filter(|memo| memo.line == lineid && memo.cells == cells)
+    {
+        stats.line_cache_hits += 1;
+        let solved = memo.solved.clone()?;
+        let mut modified = Vec::new();
+        for (i, (old, new)) in cells.iter().zip(solved.iter()).enumerate() {
+            if old != new {
+                line.set_cell(i as Unit, *new);
+                modified.push(i as Unit);
+            }
+        }
+        return Some(line.record_changes(&modified));
+    }
+    let result = solve_line_uncached(line, lineid, nodecache, line_solver);
+    let solved = result
+        .as_ref()
+        .map(|_| (0..line.size()).map(|i| line.get_cell(i)).collect());
+    nodecache.memo.insert(
+        key,
+        LineMemo {
+            line: lineid,
+            cells,
+            solved,
+        },
+    );
+    result
+}
+
+fn solve_line_uncached<L: board::LineMut>(
+    line: &mut L,
+    lineid: LineInfo,
+    nodecache: &mut NodeListCache,
+    line_solver: LineSolver,
+) -> Option<ChangeSet> {
+    let index = lineid.index as usize;
+    let scratch = &mut nodecache.scratch;
+    let automaton = match lineid.linetype {
+        LineType::Row => &nodecache.row_automata[index],
+        LineType::Column => &nodecache.col_automata[index],
+    };
+    match line_solver {
+        LineSolver::Graph => line.try_solve_line_changes(scratch),
+        LineSolver::LeftRight => {
+            let modified = line.try_solve_line_left_right(scratch)?;
+            Some(line.record_changes(&modified))
+        }
+        LineSolver::Automaton => {
+            let modified = line.try_solve_line_automaton(automaton, scratch)?;
+            Some(line.record_changes(&modified))
+        }
+    }
+}
+
+/// Options for the branching solvers
+#[derive(Clone, Debug)]
+pub struct SolverConfig {
+    /// How to pick the cell to branch on
+    pub heuristic: BranchHeuristic,
+    /// If true, every deduction and branch is recorded in SolveStats::trace
+    pub trace: bool,
+    /// If true, stupid_branched_solver_set probes cells before branching: when line solving
+    /// stalls, each Unknown cell is tentatively set to Filled and to Empty, and if either
+    /// value leads to a contradiction, the cell is set to the other value.
+    pub probing: bool,
+    /// If true, stupid_branched_solver_set checks crossing lines before probing or branching:
+    /// when line solving stalls, each Unknown cell is tentatively set to Filled and to Empty,
+    /// and only its row and column, and then the lines crossing cells they change, are solved.
+    /// If either value leaves one of these lines, or a line crossing a cell they change, with
+    /// no solution, the cell is set to the other value. This finds cells that no single line
+    /// can, while solving only a bounded number of lines for each value tried.
+    pub crossings: bool,
+    /// How each row and column is solved
+    pub line_solver: LineSolver,
+    /// Number of line solves to remember, so a line whose cells are unchanged since it was
+    /// last solved (often the case after backtracking) is not solved again.
+    /// The least recently used results are forgotten first. If 0, nothing is remembered.
+    pub line_cache_size: usize,
+    /// If true, stupid_branched_solver_set works out which guesses led to each contradiction.
+    /// A guess that played no part in a contradiction is not tried with its other value, and
+    /// each small set of guesses found to fail together is remembered, so that no later branch
+    /// makes all of them again.
+    pub learning: bool,
+    /// If true, stupid_branched_solver_set remembers every board state, after line solving,
+    /// that it searched without finding a solution, and does not search it again if a
+    /// different order of guesses reaches it.
+    pub transposition_table: bool,
+    /// If Some, the branching solvers give up once this much time has passed, returning
+    /// SolveResult::Timeout. Any unfinished guesses are undone, so the board is left with
+    /// only the cells that were deduced before guessing.
+    pub max_duration: Option<Duration>,
+    /// If Some, the branching solvers give up rather than guess more than this many times,
+    /// returning SolveResult::Exhausted(Limit::Branches). Limits are checked just before each
+    /// guess, so with 0, the board is only line solved. As with max_duration, unfinished
+    /// guesses are undone.
+    pub max_branches: Option<usize>,
+    /// If Some, stupid_branched_solver_set gives up once the boards it keeps take up more than
+    /// about this many bytes, returning SolveResult::Exhausted(Limit::Memory). These are the
+    /// boards remembered by transposition_table, those waiting to be searched by best_first
+    /// or by several threads, and the copies stupid_branched_solver keeps of each board it
+    /// guesses on. As with max_duration, unfinished guesses are undone.
+    pub max_memory: Option<usize>,
+    /// If true, the time spent in each part of solving is measured, in SolveStats::phases.
+    /// This reads the clock several times for every line solved.
+    pub time_phases: bool,
+    /// If Some, called with the search's progress every progress_interval branches
+    pub progress: Option<ProgressCallback>,
+    /// Number of branches searched between calls to progress
+    pub progress_interval: usize,
+    /// If Some, ties between lines waiting to be solved, and between cells the heuristic
+    /// rates the same, are broken in a pseudo-random order drawn from this seed, instead of
+    /// by their position. With one thread, a seed always gives the same search, on any
+    /// machine, so trying several seeds shows how much a heuristic's results owe to luck.
+    /// With more than one, the order threads take branches in still varies from run to run.
+    pub seed: Option<u64>,
+    /// If Some, stupid_branched_solver_set restarts its search from the cells deduced before
+    /// guessing whenever a run of guesses searches too many branches without an answer.
+    /// Runs are given this many branches times the next number in the Luby sequence
+    /// (1, 1, 2, 1, 1, 2, 4, ...), and each run after the first breaks ties as if with a
+    /// different seed, so that a bad early guess is not searched below forever.
+    pub restart_branches: Option<usize>,
+    /// If true, stupid_branched_solver_set keeps every board it has guessed its way to but
+    /// not yet searched below, and always guesses next on the one with the fewest Unknown
+    /// cells, instead of searching depth first. Boards already reached by a different order
+    /// of guesses are skipped. This can use much more memory than a depth first search, and
+    /// tracing, learning and restarts are not used: the boards searched do not follow one
+    /// another, so a trace could not be followed to rebuild them.
+    pub best_first: bool,
+    /// Number of threads stupid_branched_solver_set searches with. With more than one, the
+    /// branches waiting to be searched are kept in a queue shared by every thread, which any
+    /// idle thread takes from, and boards already reached by a different order of guesses
+    /// are skipped if transposition_table is set. Tracing, learning, restarts and best_first
+    /// are not used, and which solution is found may change from run to run.
+    pub threads: usize,
+}
+
+impl Default for SolverConfig {
+    fn default() -> SolverConfig {
+        SolverConfig {
+            heuristic: BranchHeuristic::FewestUnsolved,
+            trace: false,
+            probing: false,
+            crossings: false,
+            line_solver: LineSolver::default(),
+            line_cache_size: 0,
+            learning: false,
+            transposition_table: false,
+            max_duration: None,
+            max_branches: None,
+            max_memory: None,
+            time_phases: false,
+            progress: None,
+            progress_interval: 100,
+            seed: None,
+            restart_branches: None,
+            best_first: false,
+            threads: 1,
+        }
+    }
+}
+
+/// Measurements of a board that hint at how it is best solved, as used by auto_config
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoardProfile {
+    /// Number of cells on the board
+    pub cells: usize,
+    /// Fraction of the cells that the row clues fill
+    pub density: f64,
+    /// Mean over every row and column of its slack as a fraction of its length.
+    /// The more slack lines have, the less line solving alone deduces.
+    pub slack: f64,
+    /// Whether any clue has a color other than the default, or a length that is not exact
+    pub complex_clues: bool,
+}
+
+impl BoardProfile {
+    pub fn new(b: &board::Board) -> BoardProfile {
+        use board::LineRef;
+        let cells = b.get_num_cells();
+        let filled: usize = (0..b.get_height())
+            .flat_map(|row| b.get_row_constraints(row).iter())
+            .map(|constraint| constraint.get_length() as usize)
+            .sum();
+        let slacks: Vec<f64> = b
+            .rows()
+            .map(|row| (row.slack(), row.size()))
+            .chain(b.cols().map(|col| (col.slack(), col.size())))
+            .map(|(slack, size)| slack.unwrap_or(0) as f64 / (size as f64).max(1.0))
+            .collect();
+        let complex_clues = (0..b.get_height())
+            .flat_map(|row| b.get_row_constraints(row).iter())
+            .chain((0..b.get_width()).flat_map(|col| b.get_col_constraints(col).iter()))
+            .any(|constraint| constraint.get_color() != 0 || !constraint.is_exact());
+        BoardProfile {
+            cells,
+            density: filled as f64 / (cells as f64).max(1.0),
+            slack: slacks.iter().sum::<f64>() / (slacks.len() as f64).max(1.0),
+            complex_clues,
+        }
+    }
+}
+
+// The limits auto_config picks by are rough cut-offs rather than tuned values. The board sizes
+// are those of common puzzles: 20x20, 30x30 and 50x50.
+
+/// Average share of a line left free by its clues (BoardProfile::slack) above which a
+/// board's clues are loose enough to leave plain line solving stuck early
+const AUTO_LOOSE_SLACK: f64 = 0.4;
+/// Most cells a board with loose clues is probed on. Probing tries both values of every
+/// unknown cell, and may do so again after each one it deduces.
+const AUTO_MAX_PROBING_CELLS: usize = 900;
+/// Most cells a board can have for guesses to be picked by counting placements, which gets
+/// slow on longer lines
+const AUTO_MAX_COUNTING_CELLS: usize = 400;
+/// Share of cells filled (BoardProfile::density) below which a board is sparse
+const AUTO_SPARSE_DENSITY: f64 = 0.3;
+/// Fewest cells a board must have for line solves to be cached
+const AUTO_MIN_CACHED_CELLS: usize = 2500;
+/// Number of line solves cached on large boards, when none was asked for
+const AUTO_LINE_CACHE_SIZE: usize = 4096;
+
+/// Pick the line solver, probing and branching heuristic suited to a board, by its
+/// BoardProfile, instead of solving every board the same way. Everything else, such as the
+/// limits, seed and progress callback, is kept from the given configuration.
+pub fn auto_config(b: &board::Board, base: &SolverConfig) -> SolverConfig {
+    let profile = BoardProfile::new(b);
+    let mut config = base.clone();
+    // Loose clues leave line solving stuck early, so it pays to deduce every cell each line
+    // allows and to look harder before guessing. Probing tries every cell, so it is only
+    // worth it on boards small enough to try them all many times.
+    if profile.slack > AUTO_LOOSE_SLACK || profile.complex_clues {
+        config.line_solver = LineSolver::Automaton;
+        config.crossings = true;
+        config.probing = profile.cells <= AUTO_MAX_PROBING_CELLS;
+    } else {
+        config.line_solver = LineSolver::Graph;
+        config.crossings = false;
+        config.probing = false;
+    }
+    // Counting placements is slow on large boards, but picks better guesses on small ones.
+    // Sparse boards are mostly empty, so guesses next to filled cells pin things down faster.
+    config.heuristic = if profile.cells <= AUTO_MAX_COUNTING_CELLS {
+        BranchHeuristic::MostConstrained
+    } else if profile.density < AUTO_SPARSE_DENSITY {
+        BranchHeuristic::Adjacency
+    } else {
+        BranchHeuristic::FewestUnsolved
+    };
+    // Large boards backtrack over long lines, many of which are the same as before
+    if profile.cells > AUTO_MIN_CACHED_CELLS && config.line_cache_size == 0 {
+        config.line_cache_size = AUTO_LINE_CACHE_SIZE;
+    }
+    config
+}
+
+/// How far a branching search has gotten, as given to a ProgressCallback
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of cells that are not Unknown on the board being searched
+    pub cells_solved: usize,
+    /// Number of cells on the board
+    pub cells_total: usize,
+    /// Number of branches searched so far
+    pub branches: usize,
+}
+
+/// A function that is called with a search's progress.
+/// Clones of a ProgressCallback share the same function.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<Mutex<dyn FnMut(Progress) + Send>>);
+
+impl ProgressCallback {
+    pub fn new<F>(callback: F) -> ProgressCallback
+    where
+        F: FnMut(Progress) + Send + 'static,
+    {
+        ProgressCallback(Arc::new(Mutex::new(callback)))
+    }
+
+    fn call(&self, progress: Progress) {
+        // a callback that panicked on another thread stops that thread's search, not this one
+        let mut callback = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        (*callback)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ProgressCallback")
+    }
+}
+
+/// Longest set of guesses kept by Nogoods; longer ones are rarely made again
+const MAX_NOGOOD_LENGTH: usize = 12;
+/// Most sets of guesses kept by Nogoods
+const MAX_NOGOODS: usize = 100_000;
+
+/// Sets of guesses, as (index, value), that were found to lead to a contradiction
+#[derive(Default)]
+struct Nogoods {
+    nogoods: Vec<Vec<(usize, board::Cell)>>,
+    /// The nogoods each guess is in
+    by_guess: HashMap<(usize, board::Cell), Vec<usize>>,
+}
+
+impl Nogoods {
+    /// Remember a set of guesses, unless it is too long or too many are already remembered
+    fn learn(&mut self, nogood: Vec<(usize, board::Cell)>) {
+        if nogood.is_empty()
+            || nogood.len() > MAX_NOGOOD_LENGTH
+            || self.nogoods.len() >= MAX_NOGOODS
+        {
+            return;
+        }
+        for &guess in nogood.iter() {
+            self.by_guess
+                .entry(guess)
+                .or_default()
+                .push(self.nogoods.len());
+        }
+        self.nogoods.push(nogood);
+    }
+
+    /// Find a nogood whose guesses, other than setting the given cell to the given value, all
+    /// match the board, and get the cells of those other guesses
+    fn find(&self, b: &board::Board, index: usize, value: board::Cell) -> Option<Vec<usize>> {
+        let ids = self.by_guess.get(&(index, value))?;
+        let nogood = ids.iter().map(|&id| &self.nogoods[id]).find(|nogood| {
+            nogood
+                .iter()
+                .all(|&(i, v)| i == index || b.get_cell_index(i) == v)
+        })?;
+        Some(
+            nogood
+                .iter()
+                .map(|&(i, _)| i)
+                .filter(|&i| i != index)
+                .collect(),
+        )
+    }
+}
+
+/// Settings shared by every level of a branching search
+struct SearchContext<'a> {
+    config: &'a SolverConfig,
+    /// When the search gives up, if it has a time limit
+    deadline: Option<Instant>,
+    /// Board states that were searched without finding a solution,
+    /// if SolverConfig::transposition_table is set
+    dead_ends: Option<RefCell<HashSet<board::Board>>>,
+    /// Sets of guesses that lead to a contradiction, if SolverConfig::learning is set
+    nogoods: Option<RefCell<Nogoods>>,
+    /// The seed ties are broken with in the current run of guesses
+    seed: cell::Cell<Option<u64>>,
+    /// Number of branches after which the current run of guesses is given up, if
+    /// SolverConfig::restart_branches is set
+    restart_at: cell::Cell<Option<usize>>,
+    /// Approximate number of bytes taken up by the boards the search keeps
+    memory: cell::Cell<usize>,
+    /// The guesses leading to where the search ended, outermost first
+    path: RefCell<Vec<Guess>>,
+    /// Whether the search ended with branches it never searched
+    unexplored: cell::Cell<bool>,
+}
+
+impl<'a> SearchContext<'a> {
+    fn new(config: &SolverConfig, start: Instant) -> SearchContext<'_> {
+        SearchContext {
+            config,
+            deadline: config.max_duration.map(|duration| start + duration),
+            dead_ends: if config.transposition_table {
+                Some(RefCell::new(HashSet::new()))
+            } else {
+                None
+            },
+            nogoods: if config.learning {
+                Some(RefCell::new(Nogoods::default()))
+            } else {
+                None
+            },
+            seed: cell::Cell::new(config.seed),
+            restart_at: cell::Cell::new(None),
+            memory: cell::Cell::new(0),
+            path: RefCell::new(Vec::new()),
+            unexplored: cell::Cell::new(false),
+        }
+    }
+
+    /// Remember where the search ended, as given in SolveOutcome
+    fn end_at(&self, path: Vec<Guess>, unexplored: bool) {
+        *self.path.borrow_mut() = path;
+        self.unexplored.set(unexplored);
+    }
+
+    /// Count memory taken up by a board, or other state, that the search keeps
+    fn hold(&self, bytes: usize) {
+        self.memory.set(self.memory.get() + bytes);
+    }
+
+    /// Stop counting memory counted by hold, once the search no longer keeps it
+    fn release(&self, bytes: usize) {
+        self.memory.set(self.memory.get().saturating_sub(bytes));
+    }
+
+    /// Whether the search keeps more than SolverConfig::max_memory bytes
+    fn out_of_memory(&self) -> bool {
+        self.config
+            .max_memory
+            .is_some_and(|max| self.memory.get() > max)
+    }
+
+    /// Remember a set of guesses, as (index, value), that can not all be right
+    fn learn_nogood(&self, nogood: Vec<(usize, board::Cell)>) {
+        if let Some(nogoods) = self.nogoods.as_ref() {
+            nogoods.borrow_mut().learn(nogood);
+        }
+    }
+
+    /// Find a remembered set of guesses that would all be made by setting a cell to the given
+    /// value, and get the cells other than that one in it
+    fn find_nogood(
+        &self,
+        b: &board::Board,
+        index: usize,
+        value: board::Cell,
+    ) -> Option<Vec<usize>> {
+        self.nogoods.as_ref()?.borrow().find(b, index, value)
+    }
+
+    /// Whether this board state was already searched without finding a solution
+    fn is_dead_end(&self, b: &board::Board) -> bool {
+        self.dead_ends
+            .as_ref()
+            .is_some_and(|dead_ends| dead_ends.borrow().contains(b))
+    }
+
+    /// Remember that this board state has no solution
+    fn add_dead_end(&self, b: &board::Board, stats: &mut SolveStats) {
+        if let Some(dead_ends) = self.dead_ends.as_ref() {
+            let timer = stats.start_phase(Phase::Cloning);
+            if dead_ends.borrow_mut().insert(b.clone()) {
+                self.hold(b.heap_size());
+            }
+            stats.stop_phase(timer);
+        }
+    }
+
+    /// Whether the search has run out of time
+    fn timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Call the progress callback, if there is one and it is due
+    fn report_progress(&self, b: &board::Board, stats: &SolveStats) {
+        let callback = match self.config.progress.as_ref() {
+            Some(callback) => callback,
+            None => return,
+        };
+        if !stats
+            .branches
+            .is_multiple_of(self.config.progress_interval.max(1))
+        {
+            return;
+        }
+        let cells_total = b.get_num_cells();
+        let cells_solved = (0..cells_total)
+            .filter(|&i| b.get_cell_index(i) != board::Cell::Unknown)
+            .count();
+        callback.call(Progress {
+            cells_solved,
+            cells_total,
+            branches: stats.branches,
+        });
+    }
+
+    /// Keep a board that line solving stalled on as the best partial board so far, if it is,
+    /// and narrow the cells explored branches agree on to those it has, if the search may
+    /// give up before finishing
+    fn note_partial(&self, b: &board::Board, meta: &BoardMeta, stats: &mut SolveStats) {
+        let config = self.config;
+        if config.max_duration.is_some()
+            || config.max_branches.is_some()
+            || config.max_memory.is_some()
+        {
+            stats.note_partial(b, meta.num_unsolved);
+            if stats.depth > 0 {
+                stats.note_explored(b);
+            }
+        }
+    }
+
+    /// Whether the search has made SolverConfig::max_branches guesses. The first branch
+    /// searched, before any guess, is not one.
+    fn out_of_branches(&self, stats: &SolveStats) -> bool {
+        self.config
+            .max_branches
+            .is_some_and(|max| stats.branches > max)
+    }
+
+    /// Get the result to give up with, if the search has reached one of its limits.
+    /// Called before making a guess, so that the cells a branch deduces are kept.
+    /// Reaching the end of a run of guesses, before restarting, gives
+    /// SolveResult::Exhausted(Limit::Branches).
+    fn check_limits(&self, stats: &SolveStats) -> Option<SolveResult> {
+        if self.timed_out() {
+            Some(SolveResult::Timeout)
+        } else if self.out_of_memory() {
+            Some(SolveResult::Exhausted(Limit::Memory))
+        } else if self.out_of_branches(stats)
+            || self.restart_at.get().is_some_and(|at| stats.branches >= at)
+        {
+            Some(SolveResult::Exhausted(Limit::Branches))
+        } else {
+            None
+        }
+    }
+}
+
+/// A part of solving that SolveStats::phases measures the time of
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Phase {
+    LineSolving,
+    SolvabilityChecks,
+    Probing,
+    Branching,
+    Cloning,
+}
+
+/// Time spent in each part of solving, as recorded in SolveStats::phases.
+/// Each moment is counted in only one phase: a phase started during another, such as line
+/// solving after probing finds a cell, pauses it. Time not spent in any of these, such as
+/// checking limits and reporting progress, is only counted in SolveStats::elapsed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PhaseTimes {
+    /// Solving rows and columns, including looking them up in the line cache
+    pub line_solving: Duration,
+    /// Checking that the lines crossing newly deduced cells can still be solved
+    pub solvability_checks: Duration,
+    /// Probing and checking crossings (see SolverConfig::probing and SolverConfig::crossings),
+    /// including solving lines to try each value, which is thrown away
+    pub probing: Duration,
+    /// Picking cells to guess, setting them, and reverting failed guesses
+    pub branching: Duration,
+    /// Copying boards, to search them later or to remember them
+    pub cloning: Duration,
+}
+
+impl PhaseTimes {
+    fn add(&mut self, phase: Phase, time: Duration) {
+        *match phase {
+            Phase::LineSolving => &mut self.line_solving,
+            Phase::SolvabilityChecks => &mut self.solvability_checks,
+            Phase::Probing => &mut self.probing,
+            Phase::Branching => &mut self.branching,
+            Phase::Cloning => &mut self.cloning,
+        } += time;
+    }
+
+    fn merge(&mut self, other: &PhaseTimes) {
+        self.line_solving += other.line_solving;
+        self.solvability_checks += other.solvability_checks;
+        self.probing += other.probing;
+        self.branching += other.branching;
+        self.cloning += other.cloning;
+    }
+
+    /// Get every phase with its name, in the order they are displayed
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Duration)> {
+        vec![
+            ("line solving", self.line_solving),
+            ("solvability checks", self.solvability_checks),
+            ("probing", self.probing),
+            ("branching", self.branching),
+            ("cloning", self.cloning),
+        ]
+        .into_iter()
+    }
+}
+
+impl fmt::Display for PhaseTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, time)) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {:.3}s", name, time.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+/// A phase being timed, as started by SolveStats::start_phase, and the phase it paused
+struct PhaseTimer {
+    outer: Option<Phase>,
+}
+
+/// Statistics collected while solving a board.
+/// A pass is a single run of line propagation until it can make no further
+/// progress (or until a contradiction or solution is found).
+#[derive(Clone, Debug, Default)]
+pub struct SolveStats {
+    /// Number of propagation passes performed
+    pub passes: usize,
+    /// Number of individual row/column solves performed
+    pub lines_solved: usize,
+    /// Number of cells deduced during each pass
+    pub cells_per_pass: Vec<usize>,
+    /// Number of branches explored
+    pub branches: usize,
+    /// Deepest branch reached, where 0 is the root of the search
+    pub max_depth: usize,
+    /// Number of contradictions hit
+    pub contradictions: usize,
+    /// Number of values tried while probing
+    pub probes: usize,
+    /// Number of line solves answered from the cache (see SolverConfig::line_cache_size)
+    pub line_cache_hits: usize,
+    /// Number of line solves skipped because the line was the same as the last time solving
+    /// it deduced nothing
+    pub unchanged_lines: usize,
+    /// Number of board states not searched again (see SolverConfig::transposition_table)
+    pub transposition_hits: usize,
+    /// Number of guesses whose other value was not tried, because the contradiction their
+    /// branch led to did not depend on them (see SolverConfig::learning)
+    pub backjumps: usize,
+    /// Number of branches not searched because they would make a set of guesses already
+    /// found to fail (see SolverConfig::learning)
+    pub nogood_hits: usize,
+    /// Number of times the search was started over (see SolverConfig::restart_branches)
+    pub restarts: usize,
+    /// Total wall-clock time spent solving
+    pub elapsed: Duration,
+    /// If SolverConfig::time_phases is set, how much of the time spent solving went to each
+    /// part of it. When searching with several threads, this is the total over every thread,
+    /// so it may add up to more than elapsed.
+    pub phases: Option<PhaseTimes>,
+    /// The phase being timed, and when it started or last took over from a phase started
+    /// during it
+    current_phase: Option<(Phase, Instant)>,
+    /// If Some, every deduction and branch made while solving is recorded here, in order
+    pub trace: Option<Vec<TraceEvent>>,
+    /// If the search gave up at SolverConfig::max_duration, max_branches or max_memory, the
+    /// board with the fewest Unknown cells it had reached by guessing without finding a
+    /// contradiction.
+    /// Only the cells that are set on the board the solver leaves behind are certain; the
+    /// others on this board follow from guesses, and may be wrong.
+    pub best_partial: Option<board::Board>,
+    /// Number of Unknown cells on best_partial
+    best_unsolved: usize,
+    /// If the search gave up, the cells that had the same value on every board line solving
+    /// stalled on after the first guess, with every other cell Unknown. These include the
+    /// cells deduced before guessing, and any cell that every branch searched agreed on;
+    /// a branch the search did not reach may still disagree.
+    pub explored_agreement: Option<board::Board>,
+    /// Depth of the branch currently being solved
+    depth: usize,
+    /// The line that could not be solved, if line solving found a contradiction in the branch
+    /// currently being solved
+    contradicted_line: Option<LineInfo>,
+}
+
+/// A single step taken while solving, as recorded in SolveStats::trace
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// A cell was deduced by solving the given line with the given line solver,
+    /// during the given pass (counting from 1, as in SolveStats::passes), in the
+    /// branch at the given depth
+    Deduce {
+        col: Unit,
+        row: Unit,
+        value: board::Cell,
+        line: LineInfo,
+        technique: LineSolver,
+        pass: usize,
+        depth: usize,
+    },
+    /// A cell's value was guessed, entering a branch at the given depth
+    Branch {
+        col: Unit,
+        row: Unit,
+        value: board::Cell,
+        depth: usize,
+    },
+    /// The given line could not be solved, so the current branch is impossible
+    Contradiction { line: LineInfo },
+    /// The branch at the given depth failed, and every cell was restored to
+    /// its value from before that branch was entered
+    Backtrack { depth: usize },
+    /// A cell was set while probing, because its other values led to a contradiction.
+    /// The pass is the one that stalled before probing.
+    Probe {
+        col: Unit,
+        row: Unit,
+        value: board::Cell,
+        pass: usize,
+        depth: usize,
+    },
+    /// A cell was set while checking crossing lines, because its other value left its row
+    /// or column, or a line crossing them, with no solution.
+    /// The pass is the one that stalled before checking.
+    Crossing {
+        col: Unit,
+        row: Unit,
+        value: board::Cell,
+        pass: usize,
+        depth: usize,
+    },
+}
+
+impl TraceEvent {
+    /// Get the cell this event assigned, as (col, row, value), if any.
+    /// Every cell that was Unknown before solving is assigned by exactly one event that is not
+    /// undone by a later Backtrack, so following these from the starting board reconstructs
+    /// the solution.
+    pub fn assignment(&self) -> Option<(Unit, Unit, board::Cell)> {
+        match *self {
+            TraceEvent::Deduce {
+                col, row, value, ..
+            }
+            | TraceEvent::Branch {
+                col, row, value, ..
+            }
+            | TraceEvent::Probe {
+                col, row, value, ..
+            }
+            | TraceEvent::Crossing {
+                col, row, value, ..
+            } => Some((col, row, value)),
+            TraceEvent::Contradiction { .. } | TraceEvent::Backtrack { .. } => None,
+        }
+    }
+}
+
+impl SolveStats {
+    /// Total number of cells deduced across every pass
+    pub fn cells_deduced(&self) -> usize {
+        self.cells_per_pass.iter().sum()
+    }
+
+    fn start_pass(&mut self) {
+        self.passes += 1;
+        self.cells_per_pass.push(0);
+    }
+
+    fn add_deduced(&mut self, amt: usize) {
+        if let Some(v) = self.cells_per_pass.last_mut() {
+            *v += amt;
+        }
+    }
+
+    fn enter_branch(&mut self, depth: usize) {
+        self.branches += 1;
+        self.max_depth = self.max_depth.max(depth);
+        self.depth = depth;
+        self.contradicted_line = None;
+    }
+
+    /// Record the end of a search that gave the given result
+    fn finish(&mut self, result: SolveResult, start: Instant) {
+        self.elapsed = start.elapsed();
+        if let SolveResult::Success | SolveResult::Contradiction = result {
+            self.best_partial = None;
+            self.explored_agreement = None;
+        }
+    }
+
+    /// Add the statistics of a search done alongside this one, by another thread
+    fn merge(&mut self, other: SolveStats) {
+        self.passes += other.passes;
+        self.lines_solved += other.lines_solved;
+        self.cells_per_pass.extend(other.cells_per_pass);
+        self.branches += other.branches;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.contradictions += other.contradictions;
+        self.probes += other.probes;
+        self.line_cache_hits += other.line_cache_hits;
+        self.unchanged_lines += other.unchanged_lines;
+        self.transposition_hits += other.transposition_hits;
+        if let (Some(phases), Some(other)) = (self.phases.as_mut(), other.phases.as_ref()) {
+            phases.merge(other);
+        }
+        if let Some(partial) = other.best_partial {
+            self.note_partial(&partial, other.best_unsolved);
+        }
+        if let Some(agreed) = other.explored_agreement {
+            self.note_explored(&agreed);
+        }
+    }
+
+    /// Start timing a phase, if phases are timed, pausing the phase being timed until the
+    /// new one is stopped
+    fn start_phase(&mut self, phase: Phase) -> PhaseTimer {
+        let phases = match self.phases.as_mut() {
+            Some(phases) => phases,
+            None => return PhaseTimer { outer: None },
+        };
+        let now = Instant::now();
+        let outer = self.current_phase.map(|(outer, since)| {
+            phases.add(outer, now - since);
+            outer
+        });
+        self.current_phase = Some((phase, now));
+        PhaseTimer { outer }
+    }
+
+    /// Stop timing the phase started last, resuming the one it paused
+    fn stop_phase(&mut self, timer: PhaseTimer) {
+        let phases = match self.phases.as_mut() {
+            Some(phases) => phases,
+            None => return,
+        };
+        let now = Instant::now();
+        if let Some((phase, since)) = self.current_phase {
+            phases.add(phase, now - since);
+        }
+        self.current_phase = timer.outer.map(|outer| (outer, now));
+    }
+
+    /// Keep a board as best_partial, if it has fewer Unknown cells than the one kept so far
+    fn note_partial(&mut self, b: &board::Board, unsolved: usize) {
+        if self.best_partial.is_none() || unsolved < self.best_unsolved {
+            let timer = self.start_phase(Phase::Cloning);
+            self.best_partial = Some(b.clone());
+            self.best_unsolved = unsolved;
+            self.stop_phase(timer);
+        }
+    }
+
+    /// Set every cell of explored_agreement that differs from the given board to Unknown
+    fn note_explored(&mut self, b: &board::Board) {
+        match self.explored_agreement.as_mut() {
+            Some(agreed) => {
+                for index in 0..agreed.get_num_cells() {
+                    if agreed.get_cell_index(index) != b.get_cell_index(index) {
+                        agreed.set_cell_index(index, board::Cell::Unknown);
+                    }
+                }
+            }
+            None => {
+                let timer = self.start_phase(Phase::Cloning);
+                self.explored_agreement = Some(b.clone());
+                self.stop_phase(timer);
+            }
+        }
+    }
+
+    fn record(&mut self, event: TraceEvent) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(event);
+        }
+    }
+
+    /// Record the cells deduced by solving a line with the given line solver
+    fn record_line(&mut self, line: LineInfo, technique: LineSolver, changes: &ChangeSet) {
+        if self.trace.is_none() {
+            return;
+        }
+        for change in changes.iter() {
+            self.record(TraceEvent::Deduce {
+                col: change.get_col(),
+                row: change.get_row(),
+                value: change.get_value(),
+                line,
+                technique,
+                pass: self.passes,
+                depth: self.depth,
+            });
+        }
+    }
+
+    /// Record a guess of the given cell's value
+    fn record_branch(&mut self, b: &board::Board, index: usize, value: board::Cell, depth: usize) {
+        let (col, row) = b.get_coordinate(index);
+        self.record(TraceEvent::Branch {
+            col,
+            row,
+            value,
+            depth,
+        });
+    }
+
+    /// Record a contradiction found while solving a line
+    fn record_contradiction(&mut self, linetype: LineType, index: Unit) {
+        self.contradictions += 1;
+        self.contradicted_line = Some(LineInfo { index, linetype });
+        self.record(TraceEvent::Contradiction {
+            line: LineInfo { index, linetype },
+        });
+    }
+}
+
+impl fmt::Display for SolveStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} passes, {} lines solved, {} cells deduced, {} branches (depth {}), {} contradictions in {:.3}s",
+            self.passes,
+            self.lines_solved,
+            self.cells_deduced(),
+            self.branches,
+            self.max_depth,
+            self.contradictions,
+            self.elapsed.as_secs_f64()
+        )?;
+        if self.probes > 0 {
+            write!(f, ", {} probes", self.probes)?;
+        }
+        if self.line_cache_hits > 0 {
+            write!(f, ", {} cached lines", self.line_cache_hits)?;
+        }
+        if self.unchanged_lines > 0 {
+            write!(f, ", {} unchanged lines", self.unchanged_lines)?;
+        }
+        if self.transposition_hits > 0 {
+            write!(f, ", {} repeated states", self.transposition_hits)?;
+        }
+        if self.backjumps > 0 {
+            write!(f, ", {} backjumps", self.backjumps)?;
+        }
+        if self.nogood_hits > 0 {
+            write!(f, ", {} learned prunes", self.nogood_hits)?;
+        }
+        if self.restarts > 0 {
+            write!(f, ", {} restarts", self.restarts)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct BoardMeta {
+    pub num_unsolved: usize,
+    pub unsolved_per_row: Vec<usize>,
+    pub unsolved_per_column: Vec<usize>,
+    /// Cells solved since this metadata was created,
+    /// so that they can be reverted when backtracking
+    pub changes: ChangeSet,
+    /// Why each cell was set, if SolverConfig::learning is set
+    implications: Option<Implications>,
+    /// Whether each row has had cells set or unset since stupid_solver_set last solved it.
+    /// Solving a line that is not dirty again would deduce nothing, so it is skipped.
+    dirty_rows: Vec<bool>,
+    dirty_columns: Vec<bool>,
+}
+
+impl BoardMeta {
+    pub fn solve(&mut self, col: Unit, row: Unit) {
+        self.num_unsolved -= 1;
+        self.unsolved_per_row[row as usize] -= 1;
+        self.unsolved_per_column[col as usize] -= 1;
+        self.dirty_rows[row as usize] = true;
+        self.dirty_columns[col as usize] = true;
+    }
+
+    pub fn unsolve(&mut self, col: Unit, row: Unit) {
+        self.num_unsolved += 1;
+        self.unsolved_per_row[row as usize] += 1;
+        self.unsolved_per_column[col as usize] += 1;
+        self.dirty_rows[row as usize] = true;
+        self.dirty_columns[col as usize] = true;
+    }
+
+    /// Check if a line has changed since it was last solved
+    pub fn is_dirty(&self, line: LineInfo) -> bool {
+        match line.linetype {
+            LineType::Row => self.dirty_rows[line.index as usize],
+            LineType::Column => self.dirty_columns[line.index as usize],
+        }
+    }
+
+    fn set_dirty(&mut self, line: LineInfo, dirty: bool) {
+        match line.linetype {
+            LineType::Row => self.dirty_rows[line.index as usize] = dirty,
+            LineType::Column => self.dirty_columns[line.index as usize] = dirty,
+        }
+    }
+
+    pub fn new(width: usize, height: usize) -> BoardMeta {
+        BoardMeta {
+            num_unsolved: width * height,
+            unsolved_per_row: vec![width; height],
+            unsolved_per_column: vec![height; width],
+            changes: ChangeSet::new(),
+            implications: None,
+            dirty_rows: vec![true; height],
+            dirty_columns: vec![true; width],
+        }
+    }
+
+    /// Create metadata for a board that may already have some cells determined,
+    /// such as prefilled or masked cells.
+    pub fn from_board(b: &board::Board) -> BoardMeta {
+        let mut meta = BoardMeta::new(b.get_width() as usize, b.get_height() as usize);
+        for i in 0..b.get_num_cells() {
+            if b.get_cell_index(i) != board::Cell::Unknown {
+                let (col, row) = b.get_coordinate(i);
+                meta.solve(col, row);
+            }
+        }
+        meta
+    }
+
+    /// Mark the cells in a set of changes to Unknown cells as solved, recording the changes
+    pub fn record(&mut self, changes: &ChangeSet) {
+        for change in changes.iter() {
+            self.solve(change.get_col(), change.get_row());
+            self.set_antecedent(change.get_col(), change.get_row(), Antecedent::Search);
+        }
+        self.changes.merge(changes);
+    }
+
+    /// Same as record, for changes deduced by solving the given line
+    fn record_deduced(&mut self, line: LineInfo, changes: &ChangeSet) {
+        self.record(changes);
+        for change in changes.iter() {
+            self.set_antecedent(change.get_col(), change.get_row(), Antecedent::Line(line));
+        }
+    }
+
+    /// Set an Unknown cell on the board and mark it as solved, recording the change
+    pub fn set_cell(&mut self, b: &mut board::Board, col: Unit, row: Unit, value: board::Cell) {
+        self.solve(col, row);
+        self.changes.set_cell(b, col, row, value);
+        self.set_antecedent(col, row, Antecedent::Search);
+    }
+
+    /// Revert a set of changes made by record or set_cell,
+    /// marking their cells as unsolved again
+    pub fn revert(&mut self, b: &mut board::Board, changes: &ChangeSet) {
+        changes.revert(b);
+        for change in changes.iter() {
+            self.unsolve(change.get_col(), change.get_row());
+            self.set_antecedent(change.get_col(), change.get_row(), Antecedent::Search);
+        }
+    }
+
+    /// Record why a cell was set, if implications are being kept
+    fn set_antecedent(&mut self, col: Unit, row: Unit, antecedent: Antecedent) {
+        let width = self.unsolved_per_column.len();
+        if let Some(implications) = self.implications.as_mut() {
+            implications.set(col as usize + row as usize * width, antecedent);
+        }
+    }
+
+    /// Mark a cell that was just set with set_cell as a guess, entering a new branch
+    fn push_guess(&mut self, index: usize) {
+        if let Some(implications) = self.implications.as_mut() {
+            implications.set(index, Antecedent::Guess);
+            implications.guesses.push(index);
+        }
+    }
+
+    /// Leave the branch entered by the last guess
+    fn pop_guess(&mut self) {
+        if let Some(implications) = self.implications.as_mut() {
+            implications.guesses.pop();
+        }
+    }
+
+    /// Work out which guesses led to a contradiction on the given line, or, if it was not found
+    /// by a single line, treat every guess as having led to it
+    fn note_conflict(&mut self, b: &board::Board, line: Option<LineInfo>) {
+        if let Some(implications) = self.implications.as_mut() {
+            implications.conflict = match line {
+                Some(line) => implications.guesses_behind(b, line_cells(b, line)),
+                None => implications.guesses.clone(),
+            };
+        }
+    }
+
+    pub fn is_column_solved(&self, index: usize) -> bool {
+        self.unsolved_per_column[index] == 0
+    }
+
+    pub fn is_row_solved(&self, index: usize) -> bool {
+        self.unsolved_per_row[index] == 0
+    }
+}
+
+/// Why a cell was set, as kept by Implications
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Antecedent {
+    /// The cell was known before the search began
+    Given,
+    /// The cell was guessed
+    Guess,
+    /// The cell was deduced by solving this line, from the cells set in it before
+    Line(LineInfo),
+    /// The cell was set some other way, such as by probing, so it may depend on every guess
+    /// made before it
+    Search,
+}
+
+/// Why each cell of a board was set and in what order, for finding the guesses behind a
+/// contradiction
+#[derive(Clone)]
+struct Implications {
+    /// When each cell was last set, counting up from 1; cells known before the search are 0
+    stamps: Vec<u64>,
+    antecedents: Vec<Antecedent>,
+    clock: u64,
+    /// The cells guessed to reach the current branch, outermost first
+    guesses: Vec<usize>,
+    /// The guessed cells behind the last contradiction found
+    conflict: Vec<usize>,
+}
+
+impl Implications {
+    fn new(b: &board::Board) -> Implications {
+        Implications {
+            stamps: vec![0; b.get_num_cells()],
+            antecedents: vec![Antecedent::Given; b.get_num_cells()],
+            clock: 0,
+            guesses: Vec::new(),
+            conflict: Vec::new(),
+        }
+    }
+
+    fn set(&mut self, index: usize, antecedent: Antecedent) {
+        self.clock += 1;
+        self.stamps[index] = self.clock;
+        self.antecedents[index] = antecedent;
+    }
+
+    /// Get the current guesses that the values of the given cells follow from
+    fn guesses_behind(&self, b: &board::Board, cells: Vec<usize>) -> Vec<usize> {
+        let first = match self.guesses.first() {
+            Some(&guess) => self.stamps[guess],
+            None => return Vec::new(),
+        };
+        let mut seen = vec![false; b.get_num_cells()];
+        let mut found = Vec::new();
+        // every guess made before this depends on a cell set by the search
+        let mut search_stamp = 0;
+        let mut stack = cells;
+        while let Some(i) = stack.pop() {
+            if seen[i] || b.get_cell_index(i) == board::Cell::Unknown || self.stamps[i] < first {
+                continue;
+            }
+            seen[i] = true;
+            match self.antecedents[i] {
+                Antecedent::Given => {}
+                Antecedent::Guess => found.push(i),
+                Antecedent::Search => search_stamp = search_stamp.max(self.stamps[i]),
+                Antecedent::Line(line) => stack.extend(
+                    line_cells(b, line)
+                        .into_iter()
+                        .filter(|&j| self.stamps[j] < self.stamps[i]),
+                ),
+            }
+        }
+        self.guesses
+            .iter()
+            .copied()
+            .filter(|&guess| found.contains(&guess) || self.stamps[guess] < search_stamp)
+            .collect()
+    }
+}
+
+/// Get the index of each cell in a line
+fn line_cells(b: &board::Board, line: LineInfo) -> Vec<usize> {
+    match line.linetype {
+        LineType::Row => (0..b.get_width())
+            .map(|col| b.get_index(col, line.index))
+            .collect(),
+        LineType::Column => (0..b.get_height())
+            .map(|row| b.get_index(line.index, row))
+            .collect(),
+    }
+}
+
+/// The result of solving a line, for memoizing line solves
+struct LineMemo {
+    line: LineInfo,
+    /// The line's cells before solving
+    cells: Vec<board::Cell>,
+    /// The line's cells after solving, or None if it was a contradiction
+    solved: Option<Vec<board::Cell>>,
+}
+
+pub struct NodeListCache {
+    /// Working memory shared by every line solve
+    pub scratch: board::LineScratch,
+    /// Automata for LineSolver::Automaton, built once per line
+    pub row_automata: Vec<board::LineAutomaton>,
+    pub col_automata: Vec<board::LineAutomaton>,
+    /// Number of cells that solving each line is expected to determine for each of its cells
+    /// that changed, as given by line_gain
+    pub row_gains: Vec<u32>,
+    pub col_gains: Vec<u32>,
+    /// Recently solved lines, keyed by a hash of the line and its cells
+    memo: util::LruCache<u64, LineMemo>,
+    /// Each line's state the last time solving it deduced nothing
+    row_quiet: Vec<Option<QuietLine>>,
+    col_quiet: Vec<Option<QuietLine>>,
+}
+
+/// A line's state when solving it deduced nothing, so that it is not solved again until it
+/// changes
+#[derive(Clone)]
+struct QuietLine {
+    line_solver: LineSolver,
+    cells: Vec<board::Cell>,
+}
+
+impl NodeListCache {
+    /// Get a line's state the last time solving it deduced nothing, if kept
+    fn quiet(&mut self, line: LineInfo) -> &mut Option<QuietLine> {
+        match line.linetype {
+            LineType::Row => &mut self.row_quiet[line.index as usize],
+            LineType::Column => &mut self.col_quiet[line.index as usize],
+        }
+    }
+}
+
+fn make_node_list_cache(board: &board::Board, line_cache_size: usize) -> NodeListCache {
+    use board::LineRef;
+    NodeListCache {
+        memo: util::LruCache::new(line_cache_size),
+        scratch: board::LineScratch::new(),
+        row_automata: board.rows().map(|row| row.make_automaton()).collect(),
+        col_automata: board.cols().map(|col| col.make_automaton()).collect(),
+        row_gains: board.rows().map(|row| line_gain(&row)).collect(),
+        col_gains: board.cols().map(|col| line_gain(&col)).collect(),
+        row_quiet: vec![None; board.get_height() as usize],
+        col_quiet: vec![None; board.get_width() as usize],
+    }
+}
+
+/// Estimate how many cells solving a line will determine for each of its cells that changed.
+/// The less slack its constraints have, the more each known cell pins them down: a line with
+/// no slack is determined entirely, while one with a lot may gain only the cell itself.
+fn line_gain<T: board::LineRef>(line: &T) -> u32 {
+    match line.slack() {
+        Some(slack) => cmp::max(line.size() as usize / (slack + 1), 1) as u32,
+        None => 1,
+    }
+}
+
+/// Estimate how many cells solving a line will determine before any of its cells are known:
+/// the cells every constraint covers wherever it is placed
+fn line_overlap<T: board::LineRef>(line: &T) -> u32 {
+    let slack = match line.slack() {
+        Some(slack) => slack,
+        None => return 0,
+    };
+    line.get_constraints()
+        .iter()
+        .map(|constraint| (constraint.get_length() as usize).saturating_sub(slack) as u32)
+        .sum()
+}
+
+/// Queue a line after one of its cells changed, raising its priority by how many cells solving
+/// it is expected to determine. A line whose cells change several times before it is solved
+/// is raised each time.
+fn queue_changed_line(
+    to_solve: &mut PrioritySet<LineInfo>,
+    nodecache: &NodeListCache,
+    line: LineInfo,
+) {
+    let gain = match line.linetype {
+        LineType::Row => nodecache.row_gains[line.index as usize],
+        LineType::Column => nodecache.col_gains[line.index as usize],
+    };
+    to_solve.insert_with_priority(line, gain);
+}
+
+/// Slightly smarter version of stupid_solver.
+/// Counts as a single pass in the given stats.
+pub fn stupid_solver_set(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    nodecache: &mut NodeListCache,
+    stats: &mut SolveStats,
+    line_solver: LineSolver,
+) -> Option<SolveResult> {
+    use board::LineRef;
+    stats.start_pass();
+    while !to_solve.is_empty() {
+        let lineid = to_solve.pop().unwrap();
+        // a line queued again before it changed would deduce nothing new
+        if !meta.is_dirty(lineid) {
+            continue;
+        }
+        match lineid.linetype {
+            LineType::Row => {
+                if meta.is_row_solved(lineid.index as usize) {
+                    continue;
+                }
+                stats.lines_solved += 1;
+                let mut row = b.get_row_mut(lineid.index);
+                // solve this row
+                let timer = stats.start_phase(Phase::LineSolving);
+                let solved = solve_line(&mut row, lineid, nodecache, line_solver, stats);
+                stats.stop_phase(timer);
+                if let Some(changes) = solved {
+                    stats.add_deduced(changes.len());
+                    stats.record_line(lineid, line_solver, &changes);
+                    // mark these cells as solved
+                    meta.record_deduced(lineid, &changes);
+                    meta.set_dirty(lineid, false);
+                    // check that no columns are contradicted
+                    let timer = stats.start_phase(Phase::SolvabilityChecks);
+                    for change in changes.iter() {
+                        let col_i = change.get_col();
+                        let col = b.get_col_ref(col_i);
+                        if !col.is_solvable(&mut nodecache.scratch) {
+                            stats.stop_phase(timer);
+                            stats.record_contradiction(LineType::Column, col_i);
+                            return Some(SolveResult::Contradiction);
+                        }
+                        // add column to columns that may now be solvable
+                        if !meta.is_column_solved(col_i as usize) {
+                            let line = LineInfo {
+                                index: col_i,
+                                linetype: LineType::Column,
+                            };
+                            queue_changed_line(to_solve, nodecache, line);
+                        }
+                    }
+                    stats.stop_phase(timer);
+                } else {
+                    stats.record_contradiction(LineType::Row, lineid.index);
+                    return Some(SolveResult::Contradiction);
+                }
+            }
+            LineType::Column => {
+                if meta.is_column_solved(lineid.index as usize) {
+                    continue;
+                }
+                stats.lines_solved += 1;
+                let mut col = b.get_col_mut(lineid.index);
+                // solve this column
+                let timer = stats.start_phase(Phase::LineSolving);
+                let solved = solve_line(&mut col, lineid, nodecache, line_solver, stats);
+                stats.stop_phase(timer);
+                if let Some(changes) = solved {
+                    stats.add_deduced(changes.len());
+                    stats.record_line(lineid, line_solver, &changes);
+                    meta.record_deduced(lineid, &changes);
+                    meta.set_dirty(lineid, false);
+                    // check that no rows are contradicted
+                    let timer = stats.start_phase(Phase::SolvabilityChecks);
+                    for change in changes.iter() {
+                        let row_i = change.get_row();
+                        let row = b.get_row_ref(row_i);
+                        if !row.is_solvable(&mut nodecache.scratch) {
+                            stats.stop_phase(timer);
+                            stats.record_contradiction(LineType::Row, row_i);
+                            return Some(SolveResult::Contradiction);
+                        }
+                        if !meta.is_row_solved(row_i as usize) {
+                            let line = LineInfo {
+                                index: row_i,
+                                linetype: LineType::Row,
+                            };
+                            queue_changed_line(to_solve, nodecache, line);
+                        }
+                    }
+                    stats.stop_phase(timer);
+                } else {
+                    stats.record_contradiction(LineType::Column, lineid.index);
+                    return Some(SolveResult::Contradiction);
+                }
+            }
+        }
+        if meta.num_unsolved == 0 {
+            return Some(SolveResult::Success);
+        }
+    }
+    if meta.num_unsolved == 0 {
+        Some(SolveResult::Success)
+    } else {
+        None
+    }
+}
+
+/// A very basic test solving implementation.
+/// Does not always find a solution as it does not branch;
+/// only performs line solving algorithm.
+/// Returns Some(SolveResult) if a success or contradiction was found;
+/// Returns None if the board is in an incomplete solving state.
+/// Counts as a single pass in the given stats, like stupid_solver_set.
+pub fn stupid_solver(
+    b: &mut board::Board,
+    nodecache: &mut NodeListCache,
+    stats: &mut SolveStats,
+    line_solver: LineSolver,
+) -> Option<SolveResult> {
+    use board::LineRef;
+    let (width, height) = b.get_size();
+    let mut tiles_to_solve = 0;
+    for i in 0..b.get_num_cells() {
+        if b.get_cell_index(i) == board::Cell::Unknown {
+            tiles_to_solve += 1;
+        }
+    }
+    let mut solved_this_round = 1i64;
+    stats.start_pass();
+    while solved_this_round > 0 && tiles_to_solve > 0 {
+        solved_this_round = 0;
+        for i in 0..width {
+            stats.lines_solved += 1;
+            let mut col = b.get_col_mut(i);
+            let line = LineInfo {
+                index: i,
+                linetype: LineType::Column,
+            };
+            if let Some(changes) = solve_line(&mut col, line, nodecache, line_solver, stats) {
+                stats.add_deduced(changes.len());
+                // check all rows for contradiction
+                stats.record_line(line, line_solver, &changes);
+                for change in changes.iter() {
+                    let j = change.get_row();
+                    let row = b.get_row_ref(j);
+                    if !row.is_solvable(&mut nodecache.scratch) {
+                        // contradiction found :(
+                        stats.record_contradiction(LineType::Row, j);
+                        return Some(SolveResult::Contradiction);
+                    }
+                }
+                // everything is okily dokily :)
+                solved_this_round += changes.len() as i64;
+                tiles_to_solve -= changes.len() as i64;
+            } else {
+                // contradiction found :(
+                stats.record_contradiction(LineType::Column, i);
+                return Some(SolveResult::Contradiction);
+            }
+        }
+        for i in 0..height {
+            stats.lines_solved += 1;
+            let mut row = b.get_row_mut(i);
+            let line = LineInfo {
+                index: i,
+                linetype: LineType::Row,
+            };
+            if let Some(changes) = solve_line(&mut row, line, nodecache, line_solver, stats) {
+                stats.add_deduced(changes.len());
+                // check all rows for contradiction
+                stats.record_line(line, line_solver, &changes);
+                for change in changes.iter() {
+                    let j = change.get_col();
+                    let col = b.get_col_ref(j);
+                    if !col.is_solvable(&mut nodecache.scratch) {
+                        // contradiction found :(
+                        stats.record_contradiction(LineType::Column, j);
+                        return Some(SolveResult::Contradiction);
+                    }
+                }
+                // everything is okily dokily :)
+                solved_this_round += changes.len() as i64;
+                tiles_to_solve -= changes.len() as i64;
+            } else {
+                // contradiction found :(
+                stats.record_contradiction(LineType::Row, i);
+                return Some(SolveResult::Contradiction);
+            }
+        }
+    }
+    if tiles_to_solve == 0 {
+        Some(SolveResult::Success)
+    } else {
+        None
+    }
+}
+
+/// A very basic solver that utilizes branching when no solution can be found.
+/// Branches are just clones of the Board, which is inefficient.
+/// Will eventually arrive to a solution
+pub fn stupid_branched_solver(b: &mut board::Board, nodecache: &mut NodeListCache) -> SolveOutcome {
+    let config = SolverConfig {
+        heuristic: BranchHeuristic::FirstUnknown,
+        ..SolverConfig::default()
+    };
+    stupid_branched_solver_with(b, nodecache, &config)
+}
+
+/// Same as stupid_branched_solver, but with the given configuration
+pub fn stupid_branched_solver_with(
+    b: &mut board::Board,
+    nodecache: &mut NodeListCache,
+    config: &SolverConfig,
+) -> SolveOutcome {
+    let start = Instant::now();
+    let mut stats = SolveStats {
+        trace: if config.trace { Some(Vec::new()) } else { None },
+        phases: config.time_phases.then(PhaseTimes::default),
+        ..SolveStats::default()
+    };
+    let context = SearchContext::new(config, start);
+    let value = _stupid_branched_solver(b, nodecache, 0, &mut stats, &context);
+    stats.elapsed = start.elapsed();
+    SolveOutcome::new(b, value, stats, &context)
+}
+
+/// A guess being searched by _stupid_branched_solver, in place of a level of recursion
+struct BoardGuess {
+    /// The board before the guess, so that the other value can be tried on a fresh copy
+    board: board::Board,
+    /// The cell guessed
+    index: usize,
+    /// The values to try, from guess_values
+    values: Vec<board::Cell>,
+    /// Number of values tried so far, including the one being tried
+    tried: usize,
+}
+
+/// Search the branches below a board depth first, keeping the guesses being searched on a
+/// stack rather than recursing, so that guess-heavy puzzles can not overflow the call stack.
+/// b is always the board of the branch being searched, and guesses keep a copy of the board
+/// they were made on, which b is set back to if every value fails.
+fn _stupid_branched_solver(
+    b: &mut board::Board,
+    nodecache: &mut NodeListCache,
+    depth: usize,
+    stats: &mut SolveStats,
+    context: &SearchContext,
+) -> SolveResult {
+    // guesses made below the given depth, outermost first
+    let mut stack: Vec<BoardGuess> = Vec::new();
+    loop {
+        let branch_depth = depth + stack.len();
+        stats.enter_branch(branch_depth);
+        context.report_progress(b, stats);
+        let mut result = match stupid_solver(b, nodecache, stats, context.config.line_solver) {
+            Some(result) => result,
+            None => match context.check_limits(stats) {
+                Some(result) => result,
+                None => {
+                    // stupid_solver does not keep a BoardMeta up to date, so build one here
+                    let meta = BoardMeta::from_board(b);
+                    let index = match context
+                        .config
+                        .heuristic
+                        .choose(b, &meta, context.seed.get())
+                    {
+                        Some(index) => index,
+                        None => panic!("HUH?"),
+                    };
+                    // First, try 0
+                    context.hold(b.heap_size());
+                    stack.push(BoardGuess {
+                        board: b.clone(),
+                        index,
+                        values: guess_values(b, index),
+                        tried: 1,
+                    });
+                    b.set_cell_index(index, board::Cell::Empty);
+                    stats.record_branch(b, index, board::Cell::Empty, branch_depth + 1);
+                    continue;
+                }
+            },
+        };
+        if result != SolveResult::Contradiction {
+            let path = stack
+                .iter()
+                .map(|guess| Guess::new(b, guess.index, guess.values[guess.tried - 1]))
+                .collect();
+            let unexplored = stack.iter().any(|guess| guess.tried < guess.values.len());
+            context.end_at(path, unexplored);
+        }
+        // hand the result back to the guesses it came from, until one has a value left to try
+        loop {
+            let branch_depth = depth + stack.len();
+            let guess = match stack.last_mut() {
+                Some(guess) => guess,
+                None => return result,
+            };
+            if result == SolveResult::Success {
+                // b is the solution
+                return result;
+            }
+            stats.record(TraceEvent::Backtrack {
+                depth: branch_depth,
+            });
+            if result == SolveResult::Contradiction && guess.tried < guess.values.len() {
+                if let Some(limit) = context.check_limits(stats) {
+                    let path = stack[..stack.len() - 1]
+                        .iter()
+                        .map(|guess| Guess::new(b, guess.index, guess.values[guess.tried - 1]))
+                        .collect();
+                    context.end_at(path, true);
+                    result = limit;
+                    *b = pop_guess(&mut stack, context);
+                    continue;
+                }
+                // Now, try the next value
+                let value = guess.values[guess.tried];
+                guess.tried += 1;
+                *b = guess.board.clone();
+                b.set_cell_index(guess.index, value);
+                stats.record_branch(b, guess.index, value, branch_depth);
+                break;
+            }
+            // every value failed, or a limit was reached, so the guess fails the same way
+            *b = pop_guess(&mut stack, context);
+        }
+    }
+}
+
+/// Remove the innermost guess of _stupid_branched_solver, returning the board it was made on
+fn pop_guess(stack: &mut Vec<BoardGuess>, context: &SearchContext) -> board::Board {
+    let guess = stack.pop().unwrap();
+    context.release(guess.board.heap_size());
+    guess.board
+}
+
+pub fn stupid_branched_solver_set(b: &mut board::Board) -> SolveOutcome {
+    stupid_branched_solver_set_with(b, &SolverConfig::default())
+}
+
+/// Same as stupid_branched_solver_set, but every deduction and branch is recorded
+/// in the returned stats' trace
+pub fn stupid_branched_solver_set_traced(b: &mut board::Board) -> SolveOutcome {
+    let config = SolverConfig {
+        trace: true,
+        ..SolverConfig::default()
+    };
+    stupid_branched_solver_set_with(b, &config)
+}
+
+/// Get every row and column of a board, to be solved.
+/// Lines whose constraints overlap the most are solved first, with ties broken by the given
+/// seed, if any.
+fn all_lines(b: &board::Board, seed: Option<u64>) -> PrioritySet<LineInfo> {
+    let mut to_solve = PrioritySet::with_seed(seed);
+    for col in b.cols() {
+        let line = LineInfo {
+            index: col.index(),
+            linetype: LineType::Column,
+        };
+        to_solve.insert_with_priority(line, line_overlap(&col) + 1);
+    }
+    for row in b.rows() {
+        let line = LineInfo {
+            index: row.index(),
+            linetype: LineType::Row,
+        };
+        to_solve.insert_with_priority(line, line_overlap(&row) + 1);
+    }
+    to_solve
+}
+
+/// Call step with each way of going on placing a line's clues from clue k at cell i, given the
+/// cells solved so far: leaving cell i empty, or placing clue k there with each length it could
+/// have. Each way is given as the length placed (0 for leaving cell i empty), and the clue and
+/// cell placing goes on from; any cells skipped after the placed block are empty.
+/// For a line whose clue is a total, k is instead the number of cells filled so far.
+/// The line is taken to have two ends; a line that wraps around is split by placement_cases
+/// into lines that do.
+fn placement_steps<L, F>(line: &L, k: usize, i: usize, mut step: F)
+where
+    L: board::LineRef,
+    F: FnMut(usize, usize, usize),
+{
+    let size = line.size() as usize;
+    let constraints = line.get_constraints();
+    let cell = |i: usize| line.get_cell(i as Unit);
+    let can_be_empty = |i: usize| matches!(cell(i), board::Cell::Unknown | board::Cell::Empty);
+    let can_be_color = |i: usize, color: board::Color| {
+        let value = cell(i);
+        value == board::Cell::Unknown || value.get_color() == Some(color)
+    };
+    if i == size {
+        return;
+    }
+    if can_be_empty(i) {
+        step(0, k, i + 1);
+    }
+    if let Some(total) = board::get_line_total(constraints) {
+        if k < total as usize && can_be_color(i, constraints[0].get_color()) {
+            step(1, k + 1, i + 1);
+        }
+        return;
+    }
+    let constraint = match constraints.get(k) {
+        Some(constraint) => constraint,
+        None => return,
+    };
+    let color = constraint.get_color();
+    let needs_gap = constraints
+        .get(k + 1)
+        .is_some_and(|next| next.get_color() == color);
+    let min_length = constraint.get_length().max(1) as usize;
+    let max_length = constraint.get_max_length() as usize;
+    for length in 1..=max_length.min(size - i) {
+        if !can_be_color(i + length - 1, color) {
+            break;
+        }
+        if length < min_length {
+            continue;
+        }
+        let end = i + length;
+        if end == size {
+            step(length, k + 1, end);
+        } else if cell(end).get_color() == Some(color) {
+            // The block would be longer than this length
+        } else if needs_gap {
+            if can_be_empty(end) {
+                step(length, k + 1, end + 1);
+            }
+        } else {
+            step(length, k + 1, end);
+        }
+    }
+}
+
+/// The number of clues placement_steps places in a line, counting each cell of a total
+fn placement_stages<L: board::LineRef>(line: &L) -> usize {
+    let constraints = line.get_constraints();
+    board::get_line_total(constraints).map_or(constraints.len(), |total| total as usize)
+}
+
+/// Count the ways to place a line's clues k.. in cells i.., given the cells solved so far,
+/// as table[k][i]. Counts saturate at u64::MAX.
+fn placement_table<L: board::LineRef>(line: &L) -> Vec<Vec<u64>> {
+    let size = line.size() as usize;
+    let num = placement_stages(line);
+    let mut ways = vec![vec![0u64; size + 1]; num + 1];
+    ways[num][size] = 1;
+    for k in (0..=num).rev() {
+        for i in (0..size).rev() {
+            let mut total = 0u64;
+            placement_steps(line, k, i, |_, next_k, next_i| {
+                total = total.saturating_add(ways[next_k][next_i]);
+            });
+            ways[k][i] = total;
+        }
+    }
+    ways
+}
+
+/// Get the lines with two ends whose placements together are the placements of a line: the
+/// line itself, or the board::toroidal_cases of a line that wraps around
+fn placement_cases<L: board::LineRef>(line: &L) -> Vec<(board::ConstraintList, Vec<board::Cell>)> {
+    if line.is_toroidal() {
+        board::toroidal_cases(line)
+    } else {
+        let cells = (0..line.size()).map(|i| line.get_cell(i)).collect();
+        vec![(line.get_constraints().clone(), cells)]
+    }
+}
+
+/// Count the ways a line's clues can be placed, given the cells solved so far.
+/// The count saturates at u64::MAX.
+fn count_line_placements<L: board::LineRef>(line: &L) -> u64 {
+    if !line.is_toroidal() {
+        return placement_table(line)[0][0];
+    }
+    placement_cases(line)
+        .into_iter()
+        .fold(0u64, |total, (constraints, cells)| {
+            let case = board::StandaloneLine::new(cells, &constraints);
+            total.saturating_add(placement_table(&case)[0][0])
+        })
+}
+
+/// Pick one of the ways a line's clues can be placed at random, given its placement_table.
+/// Every way is equally likely, unless there are so many that the table saturated.
+/// None if the clues can not be placed at all.
+fn random_placement<L: board::LineRef>(
+    line: &L,
+    table: &[Vec<u64>],
+    rng: &mut util::Rng,
+) -> Option<Vec<board::Cell>> {
+    let size = line.size() as usize;
+    if table[0][0] == 0 {
+        return None;
+    }
+    let constraints = line.get_constraints();
+    let is_total = board::get_line_total(constraints).is_some();
+    let mut cells = vec![board::Cell::Empty; size];
+    let (mut k, mut i) = (0, 0);
+    while i < size {
+        let mut steps = Vec::new();
+        placement_steps(line, k, i, |length, next_k, next_i| {
+            if table[next_k][next_i] > 0 {
+                steps.push((length, next_k, next_i));
+            }
+        });
+        let total = steps
+            .iter()
+            .fold(0u64, |total, &(_, k, i)| total.saturating_add(table[k][i]));
+        let mut pick = rng.below(total);
+        let &(length, next_k, next_i) = steps
+            .iter()
+            .find(|&&(_, k, i)| {
+                if pick < table[k][i] {
+                    true
+                } else {
+                    pick -= table[k][i];
+                    false
+                }
+            })
+            .unwrap_or(&steps[steps.len() - 1]);
+        if length > 0 {
+            // every stage of a total places its one clue
+            let clue = if is_total { 0 } else { k };
+            let value = board::Cell::with_color(constraints[clue].get_color());
+            for cell in cells[i..i + length].iter_mut() {
+                *cell = value;
+            }
+        }
+        k = next_k;
+        i = next_i;
+    }
+    Some(cells)
+}
+
+/// For each cell of a line, the fraction of the ways its clues can be placed, given the cells
+/// solved so far, that fill it. None if the clues can not be placed at all.
+fn line_fill_fractions<L: board::LineRef>(line: &L) -> Option<Vec<f64>> {
+    let (total, filled) = if line.is_toroidal() {
+        let mut total = 0.0;
+        let mut filled = vec![0.0; line.size() as usize];
+        for (constraints, cells) in placement_cases(line) {
+            let case = board::StandaloneLine::new(cells, &constraints);
+            let (ways, case_filled) = line_fill_ways(&case);
+            total += ways;
+            for (cell, ways) in filled.iter_mut().zip(case_filled) {
+                *cell += ways;
+            }
+        }
+        (total, filled)
+    } else {
+        line_fill_ways(line)
+    };
+    if total == 0.0 {
+        return None;
+    }
+    Some(filled.iter().map(|&ways| ways / total).collect())
+}
+
+/// Count the ways a line's clues can be placed, given the cells solved so far, and for each
+/// cell, how many of them fill it. The line is taken to have two ends.
+fn line_fill_ways<L: board::LineRef>(line: &L) -> (f64, Vec<f64>) {
+    let size = line.size() as usize;
+    let num = placement_stages(line);
+    // after[k][i] is the number of ways to place clues k.. in cells i.., and before[k][i]
+    // the number of ways to place clues ..k in cells ..i
+    let mut after = vec![vec![0.0; size + 1]; num + 1];
+    after[num][size] = 1.0;
+    for k in (0..=num).rev() {
+        for i in (0..size).rev() {
+            let mut total = 0.0;
+            placement_steps(line, k, i, |_, next_k, next_i| {
+                total += after[next_k][next_i]
+            });
+            after[k][i] = total;
+        }
+    }
+    let mut filled = vec![0.0; size];
+    if after[0][0] == 0.0 {
+        return (0.0, filled);
+    }
+    let mut before = vec![vec![0.0; size + 1]; num + 1];
+    before[0][0] = 1.0;
+    for i in 0..size {
+        for k in 0..=num {
+            let ways = before[k][i];
+            if ways == 0.0 {
+                continue;
+            }
+            placement_steps(line, k, i, |length, next_k, next_i| {
+                before[next_k][next_i] += ways;
+                let through = ways * after[next_k][next_i];
+                for cell in filled[i..i + length].iter_mut() {
+                    *cell += through;
+                }
+            });
+        }
+    }
+    (after[0][0], filled)
+}
+
+/// The estimated probability that each cell of a board is filled, as found by
+/// fill_probabilities
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbabilityGrid {
+    width: Unit,
+    height: Unit,
+    cells: Vec<f64>,
+}
+
+impl ProbabilityGrid {
+    pub fn get_size(&self) -> (Unit, Unit) {
+        (self.width, self.height)
+    }
+
+    /// Get the probability, in [0, 1], that a cell is filled
+    pub fn get(&self, col: Unit, row: Unit) -> f64 {
+        self.cells[col as usize + row as usize * self.width as usize]
+    }
+
+    pub fn get_index(&self, index: usize) -> f64 {
+        self.cells[index]
+    }
+
+    /// Display this grid as a heatmap on an ANSI terminal, with each cell brighter the more
+    /// likely it is to be filled
+    pub fn heatmap(&self) -> Heatmap<'_> {
+        Heatmap {
+            grid: self,
+            monochrome: false,
+        }
+    }
+}
+
+/// Shading characters used by a monochrome Heatmap, from least to most likely to be filled
+const HEATMAP_SHADES: [char; 5] = [' ', '\u{2591}', '\u{2592}', '\u{2593}', '\u{2588}'];
+
+/// Displays a ProbabilityGrid as a heatmap. Created by ProbabilityGrid::heatmap.
+pub struct Heatmap<'a> {
+    grid: &'a ProbabilityGrid,
+    monochrome: bool,
+}
+
+impl<'a> Heatmap<'a> {
+    /// Display the heatmap without ANSI escape codes, shading cells with block characters
+    pub fn monochrome(self) -> Heatmap<'a> {
+        Heatmap {
+            monochrome: true,
+            ..self
+        }
+    }
+}
+
+impl<'a> fmt::Display for Heatmap<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let grid = self.grid;
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                let probability = grid.get(col, row);
+                if self.monochrome {
+                    let last = HEATMAP_SHADES.len() - 1;
+                    let shade = HEATMAP_SHADES[(probability * last as f64).round() as usize];
+                    write!(f, "{}{}", shade, shade)?;
+                } else {
+                    // the 24 shades of gray from 232 (black) to 255 (white) of the 256 color
+                    // palette
+                    let shade = 232 + (probability * 23.0).round() as u8;
+                    write!(f, "\x1b[48;5;{}m  ", shade)?;
+                }
+            }
+            if self.monochrome {
+                writeln!(f)?;
+            } else {
+                writeln!(f, "\x1b[0m")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProbabilityGrid {
+    /// Write each cell's probability as a percentage
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let separator = if col == 0 { "" } else { " " };
+                write!(f, "{}{:>3.0}", separator, self.get(col, row) * 100.0)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimate the probability that each cell of a board is filled. A cell's row and column each
+/// give the fraction of the ways their clues can be placed, given the cells solved so far,
+/// that fill it; these are combined as if they were independent evidence.
+/// Solved cells have a probability of 0 or 1.
+/// Returns None if some line's clues can not be placed at all.
+pub fn fill_probabilities(b: &board::Board) -> Option<ProbabilityGrid> {
+    let rows = b
+        .rows()
+        .map(|row| line_fill_fractions(&row))
+        .collect::<Option<Vec<Vec<f64>>>>()?;
+    let cols = b
+        .cols()
+        .map(|col| line_fill_fractions(&col))
+        .collect::<Option<Vec<Vec<f64>>>>()?;
+    let (width, height) = b.get_size();
+    let cells = (0..b.get_num_cells())
+        .map(|i| match b.get_cell_index(i) {
+            board::Cell::Unknown => {
+                let (col, row) = b.get_coordinate(i);
+                let by_row = rows[row as usize][col as usize];
+                let by_col = cols[col as usize][row as usize];
+                let filled = by_row * by_col;
+                let empty = (1.0 - by_row) * (1.0 - by_col);
+                if filled + empty > 0.0 {
+                    filled / (filled + empty)
+                } else {
+                    // The row and column disagree completely
+                    (by_row + by_col) / 2.0
+                }
+            }
+            value if value.is_filled() => 1.0,
+            _ => 0.0,
+        })
+        .collect();
+    Some(ProbabilityGrid {
+        width,
+        height,
+        cells,
+    })
+}
+
+impl BranchHeuristic {
+    /// Pick the cell to branch on. Returns None if no cell is Unknown.
+    /// Cells that rate the same are picked between with the given seed, or by taking the first
+    /// one without a seed.
+    fn choose(self, b: &board::Board, meta: &BoardMeta, seed: Option<u64>) -> Option<usize> {
+        let mut unknown =
+            (0..b.get_num_cells()).filter(|i| b.get_cell_index(*i) == board::Cell::Unknown);
+        let tiebreak = |i: usize| seed.map_or(0, |seed| util::mix_seed(seed, i as u64));
+        // sum number of unsolved cells in same row and column
+        let unsolved = |i: usize| {
+            let (col, row) = b.get_coordinate(i);
+            meta.unsolved_per_row[row as usize] + meta.unsolved_per_column[col as usize]
+        };
+        match self {
+            BranchHeuristic::FirstUnknown => unknown.next(),
+            BranchHeuristic::FewestUnsolved => unknown.min_by_key(|&i| (unsolved(i), tiebreak(i))),
+            BranchHeuristic::MostConstrained => {
+                let rows: Vec<u64> = b.rows().map(|row| count_line_placements(&row)).collect();
+                let cols: Vec<u64> = b.cols().map(|col| count_line_placements(&col)).collect();
+                unknown.min_by_key(|&i| {
+                    let (col, row) = b.get_coordinate(i);
+                    let placements = rows[row as usize].saturating_add(cols[col as usize]);
+                    (placements, unsolved(i), tiebreak(i))
+                })
+            }
+            BranchHeuristic::Adjacency => unknown.min_by_key(|&i| {
+                let (col, row) = b.get_coordinate(i);
+                let (width, height) = b.get_size();
+                let is_filled = |col: Option<Unit>, row: Option<Unit>| match (col, row) {
+                    (Some(col), Some(row)) if col < width && row < height => {
+                        b.get_cell(col, row).is_filled()
+                    }
+                    _ => true,
+                };
+                let neighbors = [
+                    is_filled(col.checked_sub(1), Some(row)),
+                    is_filled(col.checked_add(1), Some(row)),
+                    is_filled(Some(col), row.checked_sub(1)),
+                    is_filled(Some(col), row.checked_add(1)),
+                ];
+                let score = neighbors.iter().filter(|&&x| x).count();
+                (cmp::Reverse(score), unsolved(i), tiebreak(i))
+            }),
+            BranchHeuristic::Probability => {
+                let probabilities = fill_probabilities(b);
+                unknown.min_by_key(|&i| {
+                    // distance from an even chance, in millionths
+                    let certainty = probabilities.as_ref().map_or(0, |probabilities| {
+                        ((probabilities.get_index(i) - 0.5).abs() * 1e6) as u64
+                    });
+                    (cmp::Reverse(certainty), unsolved(i), tiebreak(i))
+                })
+            }
+        }
+    }
+}
+
+/// Set the cell being branched on, and queue its row and column
+fn prepare_branch(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    index: usize,
+    value: board::Cell,
+) {
+    let (col_i, row_i) = b.get_coordinate(index);
+    to_solve.insert(LineInfo {
+        linetype: LineType::Row,
+        index: row_i,
+    });
+    to_solve.insert(LineInfo {
+        linetype: LineType::Column,
+        index: col_i,
+    });
+    meta.set_cell(b, col_i, row_i, value);
+}
+
+/// The outcome of probing a board, or of checking its crossing lines
+enum ProbeResult {
+    /// A cell was set, so line solving may make progress again
+    Progress,
+    /// Both values of some cell lead to a contradiction
+    Contradiction,
+    /// No cell could be set
+    Stalled,
+    /// The search ran out of time while probing
+    Timeout,
+}
+
+/// Try every value of each Unknown cell in turn, propagating each with line solving.
+/// Sets the first cell found where every value but one leads to a contradiction, and queues its
+/// row and column in to_solve. A value that solves the board is not set, since the other values
+/// may solve it too; that is left to guessing, which keeps track of the values not tried.
+/// Every other change made while probing is reverted.
+fn probe(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    nodecache: &mut NodeListCache,
+    stats: &mut SolveStats,
+    context: &SearchContext,
+) -> ProbeResult {
+    use board::Cell;
+    for index in 0..b.get_num_cells() {
+        if b.get_cell_index(index) != Cell::Unknown {
+            continue;
+        }
+        if context.timed_out() {
+            return ProbeResult::Timeout;
+        }
+        // the values that did not lead to a contradiction
+        let mut remaining = b.fill_values(index);
+        remaining.push(Cell::Empty);
+        let num_values = remaining.len();
+        for value in remaining.clone() {
+            let changes = mem::take(&mut meta.changes);
+            prepare_branch(b, meta, to_solve, index, value);
+            stats.probes += 1;
+            // Deductions made while probing are thrown away, so they are not counted
+            let mut probe_stats = SolveStats::default();
+            let result = stupid_solver_set(
+                b,
+                meta,
+                to_solve,
+                nodecache,
+                &mut probe_stats,
+                context.config.line_solver,
+            );
+            let probed = mem::replace(&mut meta.changes, changes);
+            meta.revert(b, &probed);
+            to_solve.clear();
+            match result {
+                Some(SolveResult::Contradiction) => remaining.retain(|&x| x != value),
+                Some(SolveResult::Success)
+                | Some(SolveResult::Timeout)
+                | Some(SolveResult::Exhausted(_))
+                | None => {}
+            }
+        }
+        match remaining[..] {
+            [] => return ProbeResult::Contradiction,
+            [value] if num_values > 1 => {
+                commit_probe(b, meta, to_solve, stats, index, value);
+                return ProbeResult::Progress;
+            }
+            _ => {}
+        }
+    }
+    ProbeResult::Stalled
+}
+
+/// Set a cell found by probing
+fn commit_probe(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    stats: &mut SolveStats,
+    index: usize,
+    value: board::Cell,
+) {
+    prepare_branch(b, meta, to_solve, index, value);
+    stats.add_deduced(1);
+    let (col, row) = b.get_coordinate(index);
+    stats.record(TraceEvent::Probe {
+        col,
+        row,
+        value,
+        pass: stats.passes,
+        depth: stats.depth,
+    });
+}
+
+/// Try every value of each Unknown cell in turn, solving only its row and column and checking
+/// that every line crossing a cell they change can still be solved.
+/// Sets the first cell found where every value but one fails, and queues its row and column
+/// in to_solve.
+/// Every other change made while checking is reverted.
+fn check_crossings(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    nodecache: &mut NodeListCache,
+    stats: &mut SolveStats,
+    context: &SearchContext,
+) -> ProbeResult {
+    use board::Cell;
+    for index in 0..b.get_num_cells() {
+        if b.get_cell_index(index) != Cell::Unknown {
+            continue;
+        }
+        if context.timed_out() {
+            return ProbeResult::Timeout;
+        }
+        let (col, row) = b.get_coordinate(index);
+        // the values that left every checked line solvable
+        let mut remaining = b.fill_values(index);
+        remaining.push(Cell::Empty);
+        let num_values = remaining.len();
+        for value in remaining.clone() {
+            let changes = mem::take(&mut meta.changes);
+            meta.set_cell(b, col, row, value);
+            let solvable = crossings_solvable(b, meta, nodecache, context, col, row);
+            let checked = mem::replace(&mut meta.changes, changes);
+            meta.revert(b, &checked);
+            if !solvable {
+                remaining.retain(|&x| x != value);
+            }
+        }
+        match remaining[..] {
+            [] => return ProbeResult::Contradiction,
+            [value] if num_values > 1 => {
+                prepare_branch(b, meta, to_solve, index, value);
+                stats.add_deduced(1);
+                stats.record(TraceEvent::Crossing {
+                    col,
+                    row,
+                    value,
+                    pass: stats.passes,
+                    depth: stats.depth,
+                });
+                return ProbeResult::Progress;
+            }
+            _ => {}
+        }
+    }
+    ProbeResult::Stalled
+}
+
+/// Number of rounds of line solving done by crossings_solvable
+const CROSSING_ROUNDS: usize = 2;
+
+/// Solve the row and column of the given cell, then the lines crossing cells they changed, and
+/// so on for CROSSING_ROUNDS rounds, and check that the lines crossing cells changed in the last
+/// round can still be solved.
+/// Returns false if any of these lines has no solution.
+fn crossings_solvable(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    nodecache: &mut NodeListCache,
+    context: &SearchContext,
+    col: Unit,
+    row: Unit,
+) -> bool {
+    use board::LineRef;
+    // Deductions made while checking are thrown away, so they are not counted
+    let mut stats = SolveStats::default();
+    let mut lines = vec![
+        LineInfo {
+            index: row,
+            linetype: LineType::Row,
+        },
+        LineInfo {
+            index: col,
+            linetype: LineType::Column,
+        },
+    ];
+    for _ in 0..CROSSING_ROUNDS {
+        // each round solves the lines crossing the cells changed in the round before
+        let mut crossing = BTreeSet::new();
+        for &lineid in lines.iter() {
+            let changes = match lineid.linetype {
+                LineType::Row => solve_line(
+                    &mut b.get_row_mut(lineid.index),
+                    lineid,
+                    nodecache,
+                    context.config.line_solver,
+                    &mut stats,
+                ),
+                LineType::Column => solve_line(
+                    &mut b.get_col_mut(lineid.index),
+                    lineid,
+                    nodecache,
+                    context.config.line_solver,
+                    &mut stats,
+                ),
+            };
+            let changes = match changes {
+                Some(changes) => changes,
+                None => return false,
+            };
+            meta.record(&changes);
+            for change in changes.iter() {
+                crossing.insert(match lineid.linetype {
+                    LineType::Row => LineInfo {
+                        index: change.get_col(),
+                        linetype: LineType::Column,
+                    },
+                    LineType::Column => LineInfo {
+                        index: change.get_row(),
+                        linetype: LineType::Row,
+                    },
+                });
+            }
+        }
+        lines = crossing.into_iter().collect();
+    }
+    lines.iter().all(|line| match line.linetype {
+        LineType::Row => b
+            .get_row_ref(line.index)
+            .is_solvable(&mut nodecache.scratch),
+        LineType::Column => b
+            .get_col_ref(line.index)
+            .is_solvable(&mut nodecache.scratch),
+    })
+}
+
+/// Same as stupid_branched_solver_set, but with the given configuration
+pub fn stupid_branched_solver_set_with(
+    b: &mut board::Board,
+    config: &SolverConfig,
+) -> SolveOutcome {
+    let start = Instant::now();
+    let mut stats = SolveStats {
+        trace: if config.trace && !config.best_first {
+            Some(Vec::new())
+        } else {
+            None
+        },
+        phases: config.time_phases.then(PhaseTimes::default),
+        ..SolveStats::default()
+    };
+    let mut meta = BoardMeta::from_board(b);
+    if config.learning && !config.best_first {
+        meta.implications = Some(Implications::new(b));
+    }
+    let mut to_solve = all_lines(b, config.seed);
+    let mut nodecache = make_node_list_cache(b, config.line_cache_size);
+    let context = SearchContext::new(config, start);
+    if config.best_first {
+        let value = best_first_search(
+            b,
+            &mut meta,
+            &mut to_solve,
+            &mut stats,
+            &mut nodecache,
+            &context,
+        );
+        stats.finish(value, start);
+        return SolveOutcome::new(b, value, stats, &context);
+    }
+    if config.threads > 1 {
+        let value = parallel_search(
+            b,
+            &mut meta,
+            &mut to_solve,
+            &mut stats,
+            &mut nodecache,
+            &context,
+            start,
+        );
+        stats.finish(value, start);
+        return SolveOutcome::new(b, value, stats, &context);
+    }
+    let mut run = 0;
+    let value = loop {
+        if let Some(unit) = config.restart_branches {
+            run += 1;
+            context
+                .restart_at
+                .set(Some(stats.branches + unit.max(1) * util::luby(run)));
+        }
+        let value = _stupid_branched_solver_set(
+            b,
+            &mut meta,
+            &mut to_solve,
+            0,
+            &mut stats,
+            &mut nodecache,
+            &context,
+        );
+        if value != SolveResult::Exhausted(Limit::Branches)
+            || config.restart_branches.is_none()
+            || context.out_of_branches(&stats)
+        {
+            break value;
+        }
+        // the unfinished guesses were undone, so search again from the cells deduced before them
+        stats.restarts += 1;
+        let seed = util::mix_seed(config.seed.unwrap_or(0), run as u64);
+        context.seed.set(Some(seed));
+        to_solve = all_lines(b, Some(seed));
+    };
+    stats.finish(value, start);
+    SolveOutcome::new(b, value, stats, &context)
+}
+
+/// Solve lines, and check crossings and probe if the configuration asks for it, until the
+/// board is solved, a contradiction is found, or nothing more can be deduced.
+/// Also gets the line a contradiction was found in, if line solving found it.
+fn deduce(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    nodecache: &mut NodeListCache,
+    stats: &mut SolveStats,
+    context: &SearchContext,
+) -> (Option<SolveResult>, Option<LineInfo>) {
+    let mut result = stupid_solver_set(
+        b,
+        meta,
+        to_solve,
+        nodecache,
+        stats,
+        context.config.line_solver,
+    );
+    // the line that line solving found a contradiction in, if any; contradictions found by
+    // checking crossings or probing depend on more than one line
+    let mut contradicted_line = stats.contradicted_line.take();
+    while result.is_none() && (context.config.crossings || context.config.probing) {
+        let timer = stats.start_phase(Phase::Probing);
+        let mut step = ProbeResult::Stalled;
+        if context.config.crossings {
+            step = check_crossings(b, meta, to_solve, nodecache, stats, context);
+        }
+        if let (ProbeResult::Stalled, true) = (&step, context.config.probing) {
+            step = probe(b, meta, to_solve, nodecache, stats, context);
+        }
+        stats.stop_phase(timer);
+        result = match step {
+            ProbeResult::Progress => {
+                stats.contradicted_line = None;
+                let result = stupid_solver_set(
+                    b,
+                    meta,
+                    to_solve,
+                    nodecache,
+                    stats,
+                    context.config.line_solver,
+                );
+                contradicted_line = stats.contradicted_line.take();
+                result
+            }
+            ProbeResult::Contradiction => Some(SolveResult::Contradiction),
+            ProbeResult::Stalled => break,
+            ProbeResult::Timeout => Some(SolveResult::Timeout),
+        };
+    }
+    (result, contradicted_line)
+}
+
+/// Get the values a guessed cell is tried with, in order: Empty, then each value it may be
+/// filled with
+fn guess_values(b: &board::Board, index: usize) -> Vec<board::Cell> {
+    let mut values = vec![board::Cell::Empty];
+    values.extend(b.fill_values(index));
+    values
+}
+
+/// A guess being searched by _stupid_branched_solver_set, in place of a level of recursion
+struct GuessFrame {
+    /// The cell guessed
+    index: usize,
+    /// The values to try, from guess_values
+    values: Vec<board::Cell>,
+    /// Number of values tried so far, or skipped as learned to fail
+    tried: usize,
+    /// Cells solved in the branch that made this guess, set aside while the guess's own
+    /// branch records its changes separately, so that they can be reverted
+    changes: ChangeSet,
+    /// The guesses behind every value's contradiction, if SolverConfig::learning is set
+    conflict: Vec<usize>,
+}
+
+impl GuessFrame {
+    /// Get the next value of the guessed cell to try, skipping values that would make a set of
+    /// guesses learned to fail, or None if every value has been tried
+    fn next_value(
+        &mut self,
+        b: &board::Board,
+        meta: &BoardMeta,
+        stats: &mut SolveStats,
+        context: &SearchContext,
+    ) -> Option<board::Cell> {
+        while let Some(&value) = self.values.get(self.tried) {
+            self.tried += 1;
+            if let Some(cells) = context.find_nogood(b, self.index, value) {
+                stats.nogood_hits += 1;
+                if let Some(implications) = meta.implications.as_ref() {
+                    self.conflict.extend(implications.guesses_behind(b, cells));
+                }
+                continue;
+            }
+            return Some(value);
+        }
+        None
+    }
+}
+
+/// How a branch of _stupid_branched_solver_set went, once line solving finished with it
+enum BranchStart {
+    /// Nothing more could be deduced, so the given cell must be guessed
+    Guess(usize),
+    /// The branch ended with the given result without guessing
+    Done(SolveResult),
+}
+
+/// Enter a branch of _stupid_branched_solver_set at the given depth, deducing all that can be
+/// deduced and picking a cell to guess if that is not enough
+fn enter_branch(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    depth: usize,
+    stats: &mut SolveStats,
+    nodecache: &mut NodeListCache,
+    context: &SearchContext,
+) -> BranchStart {
+    stats.enter_branch(depth);
+    context.report_progress(b, stats);
+    let (result, contradicted_line) = deduce(b, meta, to_solve, nodecache, stats, context);
+    if let Some(result) = result {
+        if result == SolveResult::Contradiction {
+            meta.note_conflict(b, contradicted_line);
+        }
+        return BranchStart::Done(result);
+    }
+    context.note_partial(b, meta, stats);
+    if context.is_dead_end(b) {
+        stats.transposition_hits += 1;
+        meta.note_conflict(b, None);
+        return BranchStart::Done(SolveResult::Contradiction);
+    }
+    if let Some(result) = context.check_limits(stats) {
+        return BranchStart::Done(result);
+    }
+    let timer = stats.start_phase(Phase::Branching);
+    let index = context.config.heuristic.choose(b, meta, context.seed.get());
+    stats.stop_phase(timer);
+    match index {
+        Some(index) => BranchStart::Guess(index),
+        None => panic!("HUH?"),
+    }
+}
+
+/// Search the branches below a board depth first, keeping the guesses being searched on a
+/// stack rather than recursing, so that guess-heavy puzzles can not overflow the call stack.
+/// Guesses are made on b itself, and reverted with the changes recorded in meta.
+fn _stupid_branched_solver_set(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    depth: usize,
+    stats: &mut SolveStats,
+    nodecache: &mut NodeListCache,
+    context: &SearchContext,
+) -> SolveResult {
+    // guesses made below the given depth, outermost first
+    let mut stack: Vec<GuessFrame> = Vec::new();
+    let mut branch = enter_branch(b, meta, to_solve, depth, stats, nodecache, context);
+    loop {
+        // the result of the branch just searched, if it ended
+        let mut result = match branch {
+            BranchStart::Guess(index) => {
+                stack.push(GuessFrame {
+                    index,
+                    values: guess_values(b, index),
+                    tried: 0,
+                    changes: mem::take(&mut meta.changes),
+                    conflict: Vec::new(),
+                });
+                None
+            }
+            BranchStart::Done(result) => {
+                if result != SolveResult::Contradiction {
+                    let path = stack
+                        .iter()
+                        .map(|frame| Guess::new(b, frame.index, frame.values[frame.tried - 1]))
+                        .collect();
+                    let unexplored = stack.iter().any(|frame| frame.tried < frame.values.len());
+                    context.end_at(path, unexplored);
+                }
+                Some(result)
+            }
+        };
+        // hand the result back to the guesses it came from, until one has a value left to try
+        branch = loop {
+            let mut frame = match stack.pop() {
+                Some(frame) => frame,
+                None => return result.expect("a branch that guesses has a value to try"),
+            };
+            // the depth of the branch that made this guess
+            let guess_depth = depth + stack.len();
+            if let Some(branch_result) = result {
+                let branch_changes = mem::take(&mut meta.changes);
+                meta.pop_guess();
+                if branch_result == SolveResult::Success {
+                    meta.changes = frame.changes;
+                    meta.changes.merge(&branch_changes);
+                    continue;
+                }
+                stats.record(TraceEvent::Backtrack {
+                    depth: guess_depth + 1,
+                });
+                let timer = stats.start_phase(Phase::Branching);
+                meta.revert(b, &branch_changes);
+                stats.stop_phase(timer);
+                if branch_result != SolveResult::Contradiction {
+                    // a limit was reached
+                    meta.changes = frame.changes;
+                    continue;
+                }
+                if let Some(implications) = meta.implications.as_ref() {
+                    if !implications.conflict.contains(&frame.index) {
+                        // the other values would lead to the same contradiction, which is
+                        // left as this branch's conflict
+                        stats.backjumps += 1;
+                        meta.changes = frame.changes;
+                        context.add_dead_end(b, stats);
+                        continue;
+                    }
+                    let index = frame.index;
+                    frame
+                        .conflict
+                        .extend(implications.conflict.iter().filter(|&&c| c != index));
+                }
+            }
+            if let Some(value) = frame.next_value(b, meta, stats, context) {
+                if let Some(limit) = context.check_limits(stats) {
+                    let path = stack
+                        .iter()
+                        .map(|frame| Guess::new(b, frame.index, frame.values[frame.tried - 1]))
+                        .collect();
+                    context.end_at(path, true);
+                    meta.changes = frame.changes;
+                    result = Some(limit);
+                    continue;
+                }
+                // Line solving stops early on a contradiction, so start from a clean queue
+                let timer = stats.start_phase(Phase::Branching);
+                to_solve.clear();
+                prepare_branch(b, meta, to_solve, frame.index, value);
+                meta.push_guess(frame.index);
+                stats.stop_phase(timer);
+                stats.record_branch(b, frame.index, value, guess_depth + 1);
+                stack.push(frame);
+                break enter_branch(
+                    b,
+                    meta,
+                    to_solve,
+                    guess_depth + 1,
+                    stats,
+                    nodecache,
+                    context,
+                );
+            }
+            meta.changes = frame.changes;
+            // None worked; it's a contradiction
+            context.add_dead_end(b, stats);
+            if let Some(implications) = meta.implications.as_mut() {
+                let mut conflict = frame.conflict;
+                conflict.sort_unstable();
+                conflict.dedup();
+                let nogood = conflict.iter().map(|&c| (c, b.get_cell_index(c))).collect();
+                context.learn_nogood(nogood);
+                implications.conflict = conflict;
+            }
+            result = Some(SolveResult::Contradiction);
+        };
+    }
+}
+
+/// A partially solved board waiting to be searched by AllSolutions or best_first_search
+struct SearchState {
+    board: board::Board,
+    meta: BoardMeta,
+    to_solve: PrioritySet<LineInfo>,
+    depth: usize,
+    /// The guesses that led to this board, outermost first
+    guesses: Vec<Guess>,
+}
+
+impl SearchState {
+    /// Get roughly how many bytes this state takes up
+    fn heap_size(&self) -> usize {
+        let lines = self.board.get_width() as usize + self.board.get_height() as usize;
+        self.board.heap_size()
+            + lines * (mem::size_of::<usize>() + mem::size_of::<bool>())
+            + self.meta.changes.len() * mem::size_of::<board::Change>()
+            + self.to_solve.len() * mem::size_of::<(LineInfo, u32, u64)>()
+            + self.guesses.len() * mem::size_of::<Guess>()
+    }
+
+    /// Copy this state, to guess on
+    fn branch(&self) -> SearchState {
+        SearchState {
+            board: self.board.clone(),
+            meta: self.meta.clone(),
+            to_solve: self.to_solve.clone(),
+            depth: self.depth + 1,
+            guesses: self.guesses.clone(),
+        }
+    }
+
+    /// Guess the value of a cell, queueing its row and column to be solved
+    fn guess(&mut self, index: usize, value: board::Cell) {
+        self.to_solve.clear();
+        prepare_branch(
+            &mut self.board,
+            &mut self.meta,
+            &mut self.to_solve,
+            index,
+            value,
+        );
+        self.guesses.push(Guess::new(&self.board, index, value));
+    }
+}
+
+/// A board waiting in best_first_search's frontier. The board with the fewest unsolved
+/// cells is searched first, and of those, the one that was found last.
+struct FrontierState {
+    order: usize,
+    state: SearchState,
+}
+
+impl PartialEq for FrontierState {
+    fn eq(&self, other: &FrontierState) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for FrontierState {}
+
+impl PartialOrd for FrontierState {
+    fn partial_cmp(&self, other: &FrontierState) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FrontierState {
+    fn cmp(&self, other: &FrontierState) -> cmp::Ordering {
+        other
+            .state
+            .meta
+            .num_unsolved
+            .cmp(&self.state.meta.num_unsolved)
+            .then(self.order.cmp(&other.order))
+    }
+}
+
+/// Search for a solution by always guessing on the most solved board found so far that
+/// nothing is known to be wrong with, as set by SolverConfig::best_first.
+/// On success the solution is written to b; otherwise b is left with the cells deduced
+/// before guessing.
+// Boards are hashed by their cells alone, which observers can not change
+#[allow(clippy::mutable_key_type)]
+fn best_first_search(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    stats: &mut SolveStats,
+    nodecache: &mut NodeListCache,
+    context: &SearchContext,
+) -> SolveResult {
+    stats.enter_branch(0);
+    context.report_progress(b, stats);
+    if let (Some(result), _) = deduce(b, meta, to_solve, nodecache, stats, context) {
+        return result;
+    }
+    context.note_partial(b, meta, stats);
+    // boards already in the frontier or searched, after line solving
+    let mut seen = HashSet::new();
+    seen.insert(b.clone());
+    meta.changes.clear();
+    let root = SearchState {
+        board: b.clone(),
+        meta: meta.clone(),
+        to_solve: to_solve.clone(),
+        depth: 0,
+        guesses: Vec::new(),
+    };
+    context.hold(b.heap_size() + root.heap_size());
+    let mut frontier = BinaryHeap::new();
+    frontier.push(FrontierState {
+        order: 0,
+        state: root,
+    });
+    let mut order = 0;
+    while let Some(FrontierState { state, .. }) = frontier.pop() {
+        context.release(state.heap_size());
+        let timer = stats.start_phase(Phase::Branching);
+        let index = context
+            .config
+            .heuristic
+            .choose(&state.board, &state.meta, context.seed.get());
+        stats.stop_phase(timer);
+        let index = match index {
+            Some(index) => index,
+            None => continue,
+        };
+        // Empty is tried last, so that if its board is left with as many unsolved cells as
+        // another value's, it was found last and is searched first
+        for value in guess_values(&state.board, index).into_iter().rev() {
+            if let Some(result) = context.check_limits(stats) {
+                context.end_at(state.guesses.clone(), true);
+                return result;
+            }
+            let timer = stats.start_phase(Phase::Cloning);
+            let mut child = state.branch();
+            stats.stop_phase(timer);
+            let timer = stats.start_phase(Phase::Branching);
+            child.guess(index, value);
+            stats.stop_phase(timer);
+            stats.record_branch(&state.board, index, value, child.depth);
+            stats.enter_branch(child.depth);
+            context.report_progress(&child.board, stats);
+            match deduce(
+                &mut child.board,
+                &mut child.meta,
+                &mut child.to_solve,
+                nodecache,
+                stats,
+                context,
+            ) {
+                (Some(SolveResult::Success), _) => {
+                    // Empty is tried last
+                    let unexplored = value != board::Cell::Empty || !frontier.is_empty();
+                    context.end_at(child.guesses, unexplored);
+                    *b = child.board;
+                    return SolveResult::Success;
+                }
+                (Some(SolveResult::Contradiction), _) => {}
+                (Some(result), _) => {
+                    context.end_at(child.guesses, true);
+                    return result;
+                }
+                (None, _) => {
+                    context.note_partial(&child.board, &child.meta, stats);
+                    let timer = stats.start_phase(Phase::Cloning);
+                    let unseen = seen.insert(child.board.clone());
+                    stats.stop_phase(timer);
+                    if unseen {
+                        // Each board keeps its own copy, so changes never need reverting
+                        child.meta.changes.clear();
+                        context.hold(child.board.heap_size() + child.heap_size());
+                        order += 1;
+                        frontier.push(FrontierState {
+                            order,
+                            state: child,
+                        });
+                    } else {
+                        stats.transposition_hits += 1;
+                    }
+                }
+            }
+        }
+    }
+    SolveResult::Contradiction
+}
+
+/// Branches waiting to be searched by the threads of parallel_search
+struct WorkQueue {
+    pending: Vec<SearchState>,
+    /// Number of threads searching a branch, which may add more to pending
+    busy: usize,
+    /// How the search ended, once it has
+    finished: Option<SearchEnd>,
+}
+
+/// How parallel_search ended
+struct SearchEnd {
+    result: SolveResult,
+    /// The branch that was solved, or that was being searched when the search gave up
+    state: Option<SearchState>,
+    /// Whether some branch was never searched
+    unexplored: bool,
+}
+
+/// State shared by the threads of parallel_search
+struct SharedSearch {
+    queue: Mutex<WorkQueue>,
+    /// Signalled when a branch is added to the queue or the search ends
+    ready: Condvar,
+    /// Set once the search ends, so that busy threads stop
+    stop: AtomicBool,
+    /// Number of guesses searched by every thread
+    guesses: AtomicUsize,
+    /// Boards, after line solving, that a thread has already reached,
+    /// if SolverConfig::transposition_table is set
+    seen: Option<Mutex<HashSet<board::Board>>>,
+    /// Approximate number of bytes taken up by the branches waiting to be searched and the
+    /// boards in seen
+    memory: AtomicUsize,
+    threads: usize,
+}
+
+impl SharedSearch {
+    /// Lock the queue. A thread that panicked while holding it only stops the search, which
+    /// the other threads still need the queue to find out about.
+    fn lock_queue(&self) -> MutexGuard<'_, WorkQueue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// End the search with the given result, unless it already ended. The branch is the one
+    /// that was solved or being searched, and the calling thread may have other branches of
+    /// its own left unsearched.
+    fn finish(&self, result: SolveResult, state: Option<SearchState>, unexplored: bool) {
+        let mut queue = self.lock_queue();
+        if queue.finished.is_none() {
+            // every branch left is either waiting or being searched by a busy thread
+            let unexplored = unexplored || !queue.pending.is_empty() || queue.busy > 1;
+            queue.finished = Some(SearchEnd {
+                result,
+                state,
+                unexplored,
+            });
+        }
+        self.stop.store(true, Ordering::Relaxed);
+        self.ready.notify_all();
+    }
+
+    /// Wait for a branch to search, after the calling thread runs out of its own.
+    /// Returns None once the search has ended, ending it with a contradiction if no thread
+    /// has any branches left.
+    fn take(&self) -> Option<SearchState> {
+        let mut queue = self.lock_queue();
+        queue.busy -= 1;
+        loop {
+            // a thread that panicked stops the search without finishing it
+            if queue.finished.is_some() || self.stop.load(Ordering::Relaxed) {
+                return None;
+            }
+            if let Some(state) = queue.pending.pop() {
+                queue.busy += 1;
+                return Some(state);
+            }
+            if queue.busy == 0 {
+                queue.finished = Some(SearchEnd {
+                    result: SolveResult::Contradiction,
+                    state: None,
+                    unexplored: false,
+                });
+                self.stop.store(true, Ordering::Relaxed);
+                self.ready.notify_all();
+                return None;
+            }
+            queue = self
+                .ready
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Give a branch to the queue if some thread may be waiting for one, or give it back
+    fn give(&self, state: SearchState) -> Option<SearchState> {
+        let mut queue = self.lock_queue();
+        if queue.pending.len() + queue.busy > self.threads {
+            return Some(state);
+        }
+        queue.pending.push(state);
+        self.ready.notify_one();
+        None
+    }
+
+    /// Whether a board was already reached by some thread, remembering it if not
+    // Boards are hashed by their cells alone, which observers can not change
+    #[allow(clippy::mutable_key_type)]
+    fn already_seen(&self, b: &board::Board, stats: &mut SolveStats) -> bool {
+        let seen = match self.seen.as_ref() {
+            Some(seen) => seen,
+            None => return false,
+        };
+        // copied before locking, so that other threads only wait for the insert
+        let timer = stats.start_phase(Phase::Cloning);
+        let copy = b.clone();
+        stats.stop_phase(timer);
+        let inserted = seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(copy);
+        if inserted {
+            self.hold(b.heap_size());
+        }
+        !inserted
+    }
+
+    /// Count memory taken up by a branch or board that the search keeps
+    fn hold(&self, bytes: usize) {
+        self.memory.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Stop counting memory counted by hold, once the search no longer keeps it
+    fn release(&self, bytes: usize) {
+        let _ = self
+            .memory
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |memory| {
+                Some(memory.saturating_sub(bytes))
+            });
+    }
+}
+
+/// Stops the search if the thread holding it panics, so that the other threads do not wait
+/// forever for the branches it had
+struct StopOnPanic<'a>(&'a SharedSearch);
+
+impl Drop for StopOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // stopped while holding the lock, so that no thread misses the notification
+            let _queue = self.0.lock_queue();
+            self.0.stop.store(true, Ordering::Relaxed);
+            self.0.ready.notify_all();
+        }
+    }
+}
+
+/// Search depth first from the branches in the shared queue, on one of parallel_search's
+/// threads, until the search ends
+fn parallel_worker(
+    shared: &SharedSearch,
+    root: &board::Board,
+    config: &SolverConfig,
+    start: Instant,
+) -> SolveStats {
+    let _stop = StopOnPanic(shared);
+    let context = SearchContext::new(config, start);
+    let mut stats = SolveStats {
+        phases: config.time_phases.then(PhaseTimes::default),
+        ..SolveStats::default()
+    };
+    let mut nodecache = make_node_list_cache(root, config.line_cache_size);
+    // this thread's branches, the next one to search last
+    let mut stack = Vec::new();
+    while !shared.stop.load(Ordering::Relaxed) {
+        let mut state = match stack.pop().or_else(|| shared.take()) {
+            Some(state) => state,
+            None => break,
+        };
+        shared.release(state.heap_size());
+        // the root branch is the only one that is not a guess
+        let guesses = if state.depth > 0 {
+            shared.guesses.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            0
+        };
+        if config.max_branches.is_some_and(|max| guesses > max) {
+            shared.finish(SolveResult::Exhausted(Limit::Branches), Some(state), true);
+            break;
+        }
+        let memory = shared.memory.load(Ordering::Relaxed);
+        if config.max_memory.is_some_and(|max| memory > max) {
+            shared.finish(SolveResult::Exhausted(Limit::Memory), Some(state), true);
+            break;
+        }
+        if context.timed_out() {
+            shared.finish(SolveResult::Timeout, Some(state), true);
+            break;
+        }
+        stats.enter_branch(state.depth);
+        context.report_progress(&state.board, &stats);
+        let (result, _) = deduce(
+            &mut state.board,
+            &mut state.meta,
+            &mut state.to_solve,
+            &mut nodecache,
+            &mut stats,
+            &context,
+        );
+        match result {
+            Some(SolveResult::Success) => {
+                shared.finish(SolveResult::Success, Some(state), !stack.is_empty());
+                break;
+            }
+            Some(SolveResult::Contradiction) => continue,
+            Some(result) => {
+                shared.finish(result, Some(state), true);
+                break;
+            }
+            None => {}
+        }
+        context.note_partial(&state.board, &state.meta, &mut stats);
+        let seen = state.depth > 0 && shared.already_seen(&state.board, &mut stats);
+        if seen {
+            stats.transposition_hits += 1;
+            continue;
+        }
+        let timer = stats.start_phase(Phase::Branching);
+        let index = config
+            .heuristic
+            .choose(&state.board, &state.meta, context.seed.get());
+        stats.stop_phase(timer);
+        let index = match index {
+            Some(index) => index,
+            None => continue,
+        };
+        // Every branch keeps its own copy of the board, so changes never need reverting
+        state.meta.changes.clear();
+        let timer = stats.start_phase(Phase::Cloning);
+        let mut filled: Vec<SearchState> = state
+            .board
+            .fill_values(index)
+            .into_iter()
+            .map(|value| {
+                let mut branch = state.branch();
+                branch.guess(index, value);
+                branch
+            })
+            .collect();
+        stats.stop_phase(timer);
+        let timer = stats.start_phase(Phase::Branching);
+        state.guess(index, board::Cell::Empty);
+        stats.stop_phase(timer);
+        state.depth += 1;
+        shared.hold(filled.iter().map(SearchState::heap_size).sum::<usize>() + state.heap_size());
+        // Empty is searched first by this thread, and each filled value shared with an idle one
+        while let Some(branch) = filled.pop() {
+            stack.extend(shared.give(branch));
+        }
+        stack.push(state);
+    }
+    stats
+}
+
+/// Search for a solution with several threads sharing a queue of branches, as set by
+/// SolverConfig::threads. On success the solution is written to b; otherwise b is left
+/// with the cells deduced before guessing.
+fn parallel_search(
+    b: &mut board::Board,
+    meta: &mut BoardMeta,
+    to_solve: &mut PrioritySet<LineInfo>,
+    stats: &mut SolveStats,
+    nodecache: &mut NodeListCache,
+    context: &SearchContext,
+    start: Instant,
+) -> SolveResult {
+    stats.enter_branch(0);
+    context.report_progress(b, stats);
+    if let (Some(result), _) = deduce(b, meta, to_solve, nodecache, stats, context) {
+        return result;
+    }
+    context.note_partial(b, meta, stats);
+    let config = context.config;
+    meta.changes.clear();
+    let root = SearchState {
+        board: b.clone(),
+        meta: meta.clone(),
+        to_solve: to_solve.clone(),
+        depth: 0,
+        guesses: Vec::new(),
+    };
+    let root_size = root.heap_size();
+    let shared = SharedSearch {
+        queue: Mutex::new(WorkQueue {
+            pending: vec![root],
+            busy: config.threads,
+            finished: None,
+        }),
+        ready: Condvar::new(),
+        stop: AtomicBool::new(false),
+        guesses: AtomicUsize::new(0),
+        seen: if config.transposition_table {
+            Some(Mutex::new(HashSet::new()))
+        } else {
+            None
+        },
+        memory: AtomicUsize::new(root_size),
+        threads: config.threads,
+    };
+    let worker_config = SolverConfig {
+        trace: false,
+        learning: false,
+        transposition_table: false,
+        restart_branches: None,
+        best_first: false,
+        ..config.clone()
+    };
+    let root_board: &board::Board = b;
+    let worker_stats: Vec<SolveStats> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..config.threads)
+            .map(|_| scope.spawn(|| parallel_worker(&shared, root_board, &worker_config, start)))
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    });
+    for other in worker_stats {
+        stats.merge(other);
+    }
+    let queue = shared.queue.into_inner().unwrap();
+    let end = queue.finished.unwrap_or(SearchEnd {
+        result: SolveResult::Contradiction,
+        state: None,
+        unexplored: false,
+    });
+    if let Some(state) = end.state {
+        context.end_at(state.guesses, end.unexplored);
+        if end.result == SolveResult::Success {
+            *b = state.board;
+        }
+    }
+    end.result
+}
+
+/// Iterates over every solution of a board. Created by solve_all.
+pub struct AllSolutions {
+    /// Branches that have not been searched yet, the next one to search last
+    stack: Vec<SearchState>,
+    nodecache: NodeListCache,
+    heuristic: BranchHeuristic,
+    stats: SolveStats,
+    /// When to give up, from SolverConfig::max_duration
+    deadline: Option<Instant>,
+    /// Most guesses to make, from SolverConfig::max_branches
+    max_branches: Option<usize>,
+    /// If the search gave up at one of its limits, the result it gave up with
+    gave_up: Option<SolveResult>,
+}
+
+impl AllSolutions {
+    /// Get statistics for the search so far
+    pub fn stats(&self) -> &SolveStats {
+        &self.stats
+    }
+
+    /// If the search gave up at one of its limits, so that there may be solutions it has not
+    /// found, returns SolveResult::Timeout or SolveResult::Exhausted(Limit::Branches).
+    pub fn gave_up(&self) -> Option<SolveResult> {
+        self.gave_up
+    }
+}
+
+impl Iterator for AllSolutions {
+    type Item = board::Board;
+
+    fn next(&mut self) -> Option<board::Board> {
+        if self.gave_up.is_some() {
+            return None;
+        }
+        let start = Instant::now();
+        let mut ret = None;
+        while let Some(mut state) = self.stack.pop() {
+            self.stats.enter_branch(state.depth);
+            let result = stupid_solver_set(
+                &mut state.board,
+                &mut state.meta,
+                &mut state.to_solve,
+                &mut self.nodecache,
+                &mut self.stats,
+                LineSolver::Graph,
+            );
+            match result {
+                Some(SolveResult::Success) => {
+                    ret = Some(state.board);
+                    break;
+                }
+                Some(SolveResult::Contradiction) => continue,
+                Some(SolveResult::Timeout) | Some(SolveResult::Exhausted(_)) => {
+                    unreachable!("line solving has no limits")
+                }
+                None => {}
+            }
+            let index = match self.heuristic.choose(&state.board, &state.meta, None) {
+                Some(index) => index,
+                None => continue,
+            };
+            // as with SearchContext::check_limits, the first branch is not a guess
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                self.gave_up = Some(SolveResult::Timeout);
+            } else if self
+                .max_branches
+                .is_some_and(|max| self.stats.branches > max)
+            {
+                self.gave_up = Some(SolveResult::Exhausted(Limit::Branches));
+            }
+            if self.gave_up.is_some() {
+                break;
+            }
+            // Every branch keeps its own copy of the board, so changes never need reverting
+            state.meta.changes.clear();
+            // Push Filled first, so that Empty is searched first like the other solvers
+            for value in state.board.fill_values(index).into_iter().rev() {
+                let mut filled = state.branch();
+                filled.guess(index, value);
+                self.stack.push(filled);
+            }
+            state.guess(index, board::Cell::Empty);
+            state.depth += 1;
+            self.stack.push(state);
+        }
+        self.stats.elapsed += start.elapsed();
+        ret
+    }
+}
+
+/// Find every solution of a board by searching every branch, instead of stopping at
+/// the first success. Solutions are found lazily, in the same order as
+/// stupid_branched_solver_set would try them.
+pub fn solve_all(b: &board::Board) -> AllSolutions {
+    solve_all_with(b, &SolverConfig::default())
+}
+
+/// Same as solve_all, but giving up at the configuration's max_duration, counted from now,
+/// or its max_branches, counted over every solution found. Once the search gives up, the
+/// iterator ends, and AllSolutions::gave_up says why. The other options are not used.
+pub fn solve_all_with(b: &board::Board, config: &SolverConfig) -> AllSolutions {
+    let state = SearchState {
+        board: b.clone(),
+        meta: BoardMeta::from_board(b),
+        to_solve: all_lines(b, None),
+        depth: 0,
+        guesses: Vec::new(),
+    };
+    AllSolutions {
+        stack: vec![state],
+        nodecache: make_node_list_cache(b, 0),
+        heuristic: BranchHeuristic::FewestUnsolved,
+        stats: SolveStats::default(),
+        deadline: config
+            .max_duration
+            .map(|duration| Instant::now() + duration),
+        max_branches: config.max_branches,
+        gave_up: None,
+    }
+}
+
+/// The number of solutions a board has, as found by count_solutions
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SolutionCount {
+    /// The board has exactly this many solutions
+    Exact(usize),
+    /// The search stopped after finding this many solutions, so there may be more
+    AtLeast(usize),
+}
+
+impl fmt::Display for SolutionCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SolutionCount::Exact(0) => write!(f, "no solutions"),
+            SolutionCount::Exact(1) => write!(f, "a unique solution"),
+            SolutionCount::Exact(n) => write!(f, "{} solutions", n),
+            SolutionCount::AtLeast(n) => write!(f, "at least {} solutions", n),
+        }
+    }
+}
+
+/// Count a board's solutions, stopping once cap solutions have been found.
+/// Solutions are dropped as soon as they are found, so this only uses as much memory as
+/// the search itself. A cap of 2 is enough to tell whether a board is unsolvable, has a
+/// unique solution, or is ambiguous.
+pub fn count_solutions(b: &board::Board, cap: usize) -> SolutionCount {
+    let count = solve_all(b).take(cap).count();
+    if count < cap {
+        SolutionCount::Exact(count)
+    } else {
+        SolutionCount::AtLeast(count)
+    }
+}
+
+/// Search a puzzle for a solution other than the given one, to check that the given
+/// solution is unique. Returns the first different solution found, or None if there is none.
+pub fn find_other_solution(puzzle: &board::Board, solution: &board::Board) -> Option<board::Board> {
+    solve_all(puzzle).find(|other| other != solution)
+}
+
+/// Whether a puzzle's solution is the only one, as checked by find_other_solution_with
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Uniqueness {
+    Unique,
+    /// Another solution was found
+    Ambiguous,
+    /// The search for another solution gave up before finding one or ruling them out
+    Unknown,
+}
+
+/// Same as find_other_solution, but giving up at the configuration's limits, as solve_all_with
+/// does. If the search gives up first, returns the result it gave up with, since there may be
+/// another solution it did not reach.
+pub fn find_other_solution_with(
+    puzzle: &board::Board,
+    solution: &board::Board,
+    config: &SolverConfig,
+) -> Result<Option<board::Board>, SolveResult> {
+    let mut solutions = solve_all_with(puzzle, config);
+    match solutions.find(|other| other != solution) {
+        Some(other) => Ok(Some(other)),
+        None => match solutions.gave_up() {
+            Some(result) => Err(result),
+            None => Ok(None),
+        },
+    }
+}
+
+/// The cells found by forced_cells_with
+#[derive(Clone, Debug)]
+pub struct ForcedCells {
+    /// The board with every cell known to have the same value in every solution set, and
+    /// every other cell Unknown
+    pub board: board::Board,
+    /// Number of the board's Unknown cells that were left Unknown because a search reached
+    /// the configuration's limits, so they may still be the same in every solution
+    pub undecided: usize,
+}
+
+/// Find the cells that have the same value in every solution of a board, which are all that
+/// can be solved for certain if the board is ambiguous. Returns a board with those cells set
+/// and every other cell Unknown, or None if the board has no solution.
+/// Cells not deduced by line solving are checked by searching for a solution where the cell
+/// has another value; each solution found rules out every cell it differs in at once.
+pub fn forced_cells(b: &board::Board) -> Option<board::Board> {
+    forced_cells_with(b, &SolverConfig::default()).map(|forced| forced.board)
+}
+
+/// Same as forced_cells, but searching with the given configuration.
+/// The configuration's max_duration applies to the whole call, and its other limits to each
+/// search. A cell whose search gives up is left Unknown and counted in
+/// ForcedCells::undecided, and if the search for a first solution gives up, only the cells
+/// deduced by line solving are returned.
+pub fn forced_cells_with(b: &board::Board, config: &SolverConfig) -> Option<ForcedCells> {
+    let deadline = config
+        .max_duration
+        .map(|duration| Instant::now() + duration);
+    let search_config = || SolverConfig {
+        max_duration: deadline.map(|deadline| deadline.saturating_duration_since(Instant::now())),
+        ..config.clone()
+    };
+    // line solving alone, which is certain, and leaves fewer cells to check
+    let line_config = SolverConfig {
+        max_branches: Some(0),
+        ..search_config()
+    };
+    let (result, deduced, _stats) = solve_with_assumptions_with(b, &[], &line_config);
+    if result == SolveResult::Contradiction {
+        return None;
+    }
+    let (result, mut forced, _stats) = solve_with_assumptions_with(&deduced, &[], &search_config());
+    match result {
+        SolveResult::Success => {}
+        SolveResult::Contradiction => return None,
+        SolveResult::Timeout | SolveResult::Exhausted(_) => {
+            let undecided = (0..deduced.get_num_cells())
+                .filter(|&index| deduced.get_cell_index(index) == board::Cell::Unknown)
+                .count();
+            return Some(ForcedCells {
+                board: deduced,
+                undecided,
+            });
+        }
+    }
+    let width = b.get_width() as usize;
+    let mut undecided = 0;
+    for index in 0..b.get_num_cells() {
+        if deduced.get_cell_index(index) != board::Cell::Unknown {
+            continue;
+        }
+        let col = (index % width) as Unit;
+        let row = (index / width) as Unit;
+        // every other value the cell could have, given its row's clues
+        let mut values = vec![board::Cell::Empty];
+        for constraint in deduced.get_row_constraints(row).iter() {
+            let value = board::Cell::with_color(constraint.get_color());
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        for value in values {
+            let current = forced.get_cell_index(index);
+            if current == board::Cell::Unknown {
+                break;
+            }
+            if value == current {
+                continue;
+            }
+            let (result, other, _stats) =
+                solve_with_assumptions_with(&deduced, &[(col, row, value)], &search_config());
+            match result {
+                SolveResult::Success => forced = forced.agreement(&other).unwrap_or(forced),
+                SolveResult::Contradiction => {}
+                SolveResult::Timeout | SolveResult::Exhausted(_) => {
+                    forced.set_cell_index(index, board::Cell::Unknown);
+                    undecided += 1;
+                }
+            }
+        }
+    }
+    Some(ForcedCells {
+        board: forced,
+        undecided,
+    })
+}
+
+/// Most fillings of a single row, or combinations of row fillings, that
+/// brute_force_solutions will try
+pub const MAX_BRUTE_FORCE_COMBINATIONS: u64 = 1 << 22;
+
+/// Whether a line with no Unknown cells has exactly the blocks its clues ask for.
+/// If the line is toroidal, a block running off its end continues at its start.
+fn blocks_match(cells: &[board::Cell], constraints: &[board::Constraint], toroidal: bool) -> bool {
+    if let Some(total) = board::get_line_total(constraints) {
+        let color = Some(constraints[0].get_color());
+        let filled = cells.iter().filter(|cell| cell.is_filled());
+        return filled.clone().all(|cell| cell.get_color() == color)
+            && filled.count() == total as usize;
+    }
+    let mut blocks: Vec<(board::Color, usize)> = Vec::new();
+    let mut previous = None;
+    for cell in cells.iter() {
+        let color = cell.get_color();
+        match (color, blocks.last_mut()) {
+            (Some(color), Some(block)) if previous == Some(color) => block.1 += 1,
+            (Some(color), _) => blocks.push((color, 1)),
+            (None, _) => {}
+        }
+        previous = color;
+    }
+    let first = cells.first().and_then(|cell| cell.get_color());
+    if toroidal && blocks.len() > 1 && first.is_some() && first == previous {
+        let (_, length) = blocks.remove(0);
+        blocks.last_mut().unwrap().1 += length;
+    }
+    blocks.len() == constraints.len()
+        && blocks
+            .iter()
+            .zip(constraints)
+            .all(|(&(color, length), constraint)| {
+                color == constraint.get_color()
+                    && length >= constraint.get_length().max(1) as usize
+                    && length <= constraint.get_max_length() as usize
+            })
+}
+
+/// Get every way of filling a row's Unknown cells that matches the row's clues, found by
+/// trying every value in each cell. None if there are too many ways to try.
+fn brute_force_row(b: &board::Board, row: Unit) -> Option<Vec<Vec<board::Cell>>> {
+    let constraints = b.get_row_constraints(row);
+    let mut values = vec![board::Cell::Empty];
+    for constraint in constraints.iter() {
+        let value = board::Cell::with_color(constraint.get_color());
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+    let cells: Vec<board::Cell> = (0..b.get_width()).map(|col| b.get_cell(col, row)).collect();
+    let unknown: Vec<usize> = (0..cells.len())
+        .filter(|&i| cells[i] == board::Cell::Unknown)
+        .collect();
+    let tries = (values.len() as u64).checked_pow(unknown.len() as u32)?;
+    if tries > MAX_BRUTE_FORCE_COMBINATIONS {
+        return None;
+    }
+    let mut fillings = Vec::new();
+    let mut filling = cells;
+    for n in 0..tries {
+        let mut rest = n;
+        for &i in unknown.iter() {
+            filling[i] = values[(rest % values.len() as u64) as usize];
+            rest /= values.len() as u64;
+        }
+        if blocks_match(&filling, constraints, b.is_toroidal()) {
+            fillings.push(filling.clone());
+        }
+    }
+    Some(fillings)
+}
+
+/// Find up to cap solutions of a small board by trying every combination of row fillings that
+/// match their rows' clues, and keeping those whose columns match too. This shares nothing
+/// with the line solvers, so that they can be checked against it. Returns None if the board
+/// has more than MAX_BRUTE_FORCE_COMBINATIONS combinations to try.
+pub fn brute_force_solutions(b: &board::Board, cap: usize) -> Option<Vec<board::Board>> {
+    let mut rows = Vec::new();
+    let mut combinations: u64 = 1;
+    for row in 0..b.get_height() {
+        let fillings = brute_force_row(b, row)?;
+        combinations = combinations.saturating_mul(fillings.len() as u64);
+        rows.push(fillings);
+    }
+    if combinations > MAX_BRUTE_FORCE_COMBINATIONS {
+        return None;
+    }
+    let mut solutions = Vec::new();
+    if combinations == 0 {
+        return Some(solutions);
+    }
+    let width = b.get_width() as usize;
+    let height = b.get_height() as usize;
+    let mut grid = vec![board::Cell::Empty; width * height];
+    let mut column = vec![board::Cell::Empty; height];
+    for n in 0..combinations {
+        // the nth combination, counting the last row's fillings fastest
+        let mut rest = n;
+        for (row, fillings) in rows.iter().enumerate().rev() {
+            let filling = &fillings[(rest % fillings.len() as u64) as usize];
+            grid[row * width..(row + 1) * width].copy_from_slice(filling);
+            rest /= fillings.len() as u64;
+        }
+        let columns_match = (0..width).all(|col| {
+            for row in 0..height {
+                column[row] = grid[col + row * width];
+            }
+            blocks_match(&column, b.get_col_constraints(col as Unit), b.is_toroidal())
+        });
+        if columns_match {
+            let mut solution = b.clone();
+            for (i, &value) in grid.iter().enumerate() {
+                solution.set_cell_index(i, value);
+            }
+            solutions.push(solution);
+            if solutions.len() >= cap {
+                break;
+            }
+        }
+    }
+    Some(solutions)
+}
+
+/// Chance that a step of local_search replaces a random row, even if that makes things worse,
+/// so that it does not get stuck
+const LOCAL_SEARCH_NOISE: f64 = 0.1;
+
+/// Number of random placements local_search tries at each step, keeping the best
+const LOCAL_SEARCH_TRIES: usize = 8;
+
+/// Configuration for the approximate solvers
+#[derive(Clone, Debug)]
+pub struct ApproximateConfig {
+    /// Seed the random choices are drawn from; each seed gives the same result on every run
+    pub seed: u64,
+    /// Most steps to take before giving up with the best board found
+    pub max_steps: usize,
+    /// Longest time to search before giving up with the best board found
+    pub max_duration: Option<Duration>,
+}
+
+impl Default for ApproximateConfig {
+    fn default() -> ApproximateConfig {
+        ApproximateConfig {
+            seed: 0,
+            max_steps: 100_000,
+            max_duration: None,
+        }
+    }
+}
+
+/// The best board an approximate solver found, which is a solution only if no lines are
+/// violated. Approximate solvers never show that a solution is unique.
+#[derive(Clone, Debug)]
+pub struct Approximation {
+    /// The board, with every cell solved
+    pub board: board::Board,
+    /// Rows and columns whose clues the board does not match
+    pub violated: Vec<LineInfo>,
+    /// Number of steps taken
+    pub steps: usize,
+}
+
+impl Approximation {
+    pub fn is_solution(&self) -> bool {
+        self.violated.is_empty()
+    }
+}
+
+/// Get every row and column of a solved board whose clues it does not match
+fn violated_lines(b: &board::Board) -> Vec<LineInfo> {
+    let (width, height) = b.get_size();
+    let mut violated = Vec::new();
+    for row in 0..height {
+        let cells: Vec<board::Cell> = (0..width).map(|col| b.get_cell(col, row)).collect();
+        if !blocks_match(&cells, b.get_row_constraints(row), b.is_toroidal()) {
+            violated.push(LineInfo {
+                index: row,
+                linetype: LineType::Row,
+            });
+        }
+    }
+    for col in 0..width {
+        let cells: Vec<board::Cell> = (0..height).map(|row| b.get_cell(col, row)).collect();
+        if !blocks_match(&cells, b.get_col_constraints(col), b.is_toroidal()) {
+            violated.push(LineInfo {
+                index: col,
+                linetype: LineType::Column,
+            });
+        }
+    }
+    violated
+}
+
+/// How far a line is from matching its clues: 0 if it does, otherwise 1 more than the
+/// difference between its number of filled cells and its clues' total length
+fn line_cost(cells: &[board::Cell], constraints: &[board::Constraint], toroidal: bool) -> usize {
+    if blocks_match(cells, constraints, toroidal) {
+        return 0;
+    }
+    let filled = cells.iter().filter(|cell| cell.is_filled()).count();
+    let wanted: usize = constraints
+        .iter()
+        .map(|constraint| constraint.get_length().max(1) as usize)
+        .sum();
+    1 + (filled as isize - wanted as isize).unsigned_abs()
+}
+
+/// One of the placement_cases of a line, with its placement_table
+struct PlacementCase {
+    constraints: board::ConstraintList,
+    cells: Vec<board::Cell>,
+    table: Vec<Vec<u64>>,
+}
+
+/// Random placements of each row's clues, for the approximate solvers
+struct RowPlacements {
+    /// The board, with the cells line solving deduced
+    board: board::Board,
+    /// The placement cases of each row
+    rows: Vec<Vec<PlacementCase>>,
+}
+
+impl RowPlacements {
+    /// Line solve a board, and count the ways to place each row's clues in what is left.
+    /// None if the board has no solution.
+    fn new(b: &board::Board) -> Option<RowPlacements> {
+        let mut board = b.clone();
+        let mut nodecache = make_node_list_cache(&board, 0);
+        let mut stats = SolveStats::default();
+        let line_solver = LineSolver::default();
+        if stupid_solver(&mut board, &mut nodecache, &mut stats, line_solver)
+            == Some(SolveResult::Contradiction)
+        {
+            return None;
+        }
+        let rows: Vec<Vec<PlacementCase>> = board
+            .rows()
+            .map(|row| {
+                placement_cases(&row)
+                    .into_iter()
+                    .map(|(constraints, cells)| {
+                        let case = board::StandaloneLine::new(cells.clone(), &constraints);
+                        let table = placement_table(&case);
+                        PlacementCase {
+                            constraints,
+                            cells,
+                            table,
+                        }
+                    })
+                    .filter(|case| case.table[0][0] > 0)
+                    .collect()
+            })
+            .collect();
+        if rows.iter().any(|cases| cases.is_empty()) {
+            return None;
+        }
+        Some(RowPlacements { board, rows })
+    }
+
+    /// Get a random placement of a row's clues. Each case is picked in proportion to its
+    /// number of placements, so that every placement is equally likely.
+    fn random_row(&self, row: usize, rng: &mut util::Rng) -> Vec<board::Cell> {
+        let cases = &self.rows[row];
+        let mut case = &cases[0];
+        if cases.len() > 1 {
+            let total = cases
+                .iter()
+                .fold(0u64, |total, case| total.saturating_add(case.table[0][0]));
+            let mut pick = rng.below(total);
+            case = cases
+                .iter()
+                .find(|case| {
+                    if pick < case.table[0][0] {
+                        true
+                    } else {
+                        pick -= case.table[0][0];
+                        false
+                    }
+                })
+                .unwrap_or(&cases[cases.len() - 1]);
+        }
+        let line = board::StandaloneLine::new(case.cells.clone(), &case.constraints);
+        random_placement(&line, &case.table, rng)
+            .expect("cases with no placements are left out by RowPlacements::new")
+    }
+
+    /// Check if a cell was deduced by line solving, so no placement changes it
+    fn is_known(&self, col: usize, row: usize) -> bool {
+        self.board.get_cell(col as Unit, row as Unit) != board::Cell::Unknown
+    }
+
+    /// Turn rows of cells into a board, and find the lines it violates
+    fn approximation(&self, rows: &[Vec<board::Cell>], steps: usize) -> Approximation {
+        let mut board = self.board.clone();
+        let width = board.get_width() as usize;
+        for (row, cells) in rows.iter().enumerate() {
+            for (col, &value) in cells.iter().enumerate() {
+                let index = col + row * width;
+                if board.get_cell_index(index) == board::Cell::Unknown {
+                    board.set_cell_index(index, value);
+                }
+            }
+        }
+        let violated = violated_lines(&board);
+        Approximation {
+            board,
+            violated,
+            steps,
+        }
+    }
+}
+
+/// A row that a step of local_search could replace, and the new cost of each column that
+/// would change
+struct RowChange {
+    /// Change in the total cost of the columns
+    change: isize,
+    row: usize,
+    cells: Vec<board::Cell>,
+    costs: Vec<(usize, usize)>,
+}
+
+/// Look for a solution by stochastic local search. Every row starts as a random placement of
+/// its clues, then each step picks a violated column and replaces whichever of a few random
+/// rows crossing it most reduces how far the columns are from their clues.
+/// Unlike exact search this can not show that there is no other solution, or that there is
+/// none at all, but it can get close to a solution of a puzzle too large for exact search.
+/// Returns None if line solving finds the board has no solution.
+pub fn local_search(b: &board::Board, config: &ApproximateConfig) -> Option<Approximation> {
+    let start = Instant::now();
+    let placements = RowPlacements::new(b)?;
+    let mut rng = util::Rng::new(config.seed);
+    let width = b.get_width() as usize;
+    let height = b.get_height() as usize;
+    let column = |rows: &[Vec<board::Cell>], col: usize| -> Vec<board::Cell> {
+        rows.iter().map(|cells| cells[col]).collect()
+    };
+    let col_cost = |cells: &[board::Cell], col: usize| {
+        line_cost(cells, b.get_col_constraints(col as Unit), b.is_toroidal())
+    };
+    let mut rows: Vec<Vec<board::Cell>> = (0..height)
+        .map(|row| placements.random_row(row, &mut rng))
+        .collect();
+    let mut costs: Vec<usize> = (0..width)
+        .map(|col| col_cost(&column(&rows, col), col))
+        .collect();
+    let mut best_cost: usize = costs.iter().sum();
+    let mut best_rows = rows.clone();
+    let mut steps = 0;
+    while best_cost > 0
+        && steps < config.max_steps
+        && config
+            .max_duration
+            .is_none_or(|duration| start.elapsed() < duration)
+    {
+        steps += 1;
+        let violated: Vec<usize> = (0..width).filter(|&col| costs[col] > 0).collect();
+        let col = violated[rng.below(violated.len() as u64) as usize];
+        // Only rows whose cell in this column is unknown can change it
+        let mut candidates: Vec<usize> = (0..height)
+            .filter(|&row| !placements.is_known(col, row))
+            .collect();
+        if candidates.is_empty() {
+            candidates = (0..height).collect();
+        }
+        let tries = if rng.chance(LOCAL_SEARCH_NOISE) {
+            1
+        } else {
+            LOCAL_SEARCH_TRIES
+        };
+        let mut chosen: Option<RowChange> = None;
+        for _ in 0..tries {
+            let row = candidates[rng.below(candidates.len() as u64) as usize];
+            let cells = placements.random_row(row, &mut rng);
+            let mut change = 0;
+            let mut new_costs = Vec::new();
+            for col in (0..width).filter(|&col| cells[col] != rows[row][col]) {
+                let mut column = column(&rows, col);
+                column[row] = cells[col];
+                let cost = col_cost(&column, col);
+                change += cost as isize - costs[col] as isize;
+                new_costs.push((col, cost));
+            }
+            if chosen.as_ref().is_none_or(|chosen| change < chosen.change) {
+                chosen = Some(RowChange {
+                    change,
+                    row,
+                    cells,
+                    costs: new_costs,
+                });
+            }
+        }
+        if let Some(chosen) = chosen {
+            rows[chosen.row] = chosen.cells;
+            for (col, cost) in chosen.costs {
+                costs[col] = cost;
+            }
+        }
+        let cost = costs.iter().sum();
+        if cost < best_cost {
+            best_cost = cost;
+            best_rows.clone_from(&rows);
+        }
+    }
+    Some(placements.approximation(&best_rows, steps))
+}
+
+/// Number of candidates in each generation of genetic_search
+const GENETIC_POPULATION: usize = 64;
+
+/// Number of random candidates genetic_search compares to pick each parent, taking the fittest
+const GENETIC_TOURNAMENT: usize = 3;
+
+/// Chance that a child made by genetic_search has a random row replaced with a random placement
+const GENETIC_MUTATION: f64 = 0.5;
+
+/// A candidate of genetic_search: a placement of every row's clues, and how many lines match
+/// their clues
+#[derive(Clone)]
+struct Candidate {
+    rows: Vec<Vec<board::Cell>>,
+    fitness: usize,
+}
+
+impl Candidate {
+    fn new(b: &board::Board, rows: Vec<Vec<board::Cell>>) -> Candidate {
+        let satisfied_columns = (0..b.get_width())
+            .filter(|&col| {
+                let cells: Vec<board::Cell> =
+                    rows.iter().map(|cells| cells[col as usize]).collect();
+                blocks_match(&cells, b.get_col_constraints(col), b.is_toroidal())
+            })
+            .count();
+        Candidate {
+            fitness: rows.len() + satisfied_columns,
+            rows,
+        }
+    }
+
+    /// Pick the fittest of a few random candidates
+    fn tournament<'a>(population: &'a [Candidate], rng: &mut util::Rng) -> &'a Candidate {
+        (0..GENETIC_TOURNAMENT)
+            .map(|_| &population[rng.below(population.len() as u64) as usize])
+            .max_by_key(|candidate| candidate.fitness)
+            .unwrap()
+    }
+}
+
+/// Look for a solution with a genetic algorithm. Every candidate is a placement of each row's
+/// clues, and its fitness is the number of lines that match their clues. Children take each
+/// row from one of two parents, and sometimes have a row replaced at random.
+/// Each child made counts as one step. Like local_search, this can not show that a solution
+/// is unique.
+/// Returns None if line solving finds the board has no solution.
+pub fn genetic_search(b: &board::Board, config: &ApproximateConfig) -> Option<Approximation> {
+    let start = Instant::now();
+    let placements = RowPlacements::new(b)?;
+    let mut rng = util::Rng::new(config.seed);
+    let height = b.get_height() as usize;
+    let goal = b.get_width() as usize + height;
+    let mut population: Vec<Candidate> = (0..GENETIC_POPULATION)
+        .map(|_| {
+            let rows = (0..height)
+                .map(|row| placements.random_row(row, &mut rng))
+                .collect();
+            Candidate::new(b, rows)
+        })
+        .collect();
+    let mut best = population
+        .iter()
+        .max_by_key(|candidate| candidate.fitness)
+        .unwrap()
+        .clone();
+    let mut steps = 0;
+    while best.fitness < goal
+        && steps < config.max_steps
+        && config
+            .max_duration
+            .is_none_or(|duration| start.elapsed() < duration)
+    {
+        // The best candidate always survives to the next generation
+        let mut next = vec![best.clone()];
+        while next.len() < GENETIC_POPULATION && steps < config.max_steps {
+            steps += 1;
+            let mother = Candidate::tournament(&population, &mut rng);
+            let father = Candidate::tournament(&population, &mut rng);
+            let mut rows: Vec<Vec<board::Cell>> = mother
+                .rows
+                .iter()
+                .zip(father.rows.iter())
+                .map(|(a, b)| {
+                    if rng.chance(0.5) {
+                        a.clone()
+                    } else {
+                        b.clone()
+                    }
+                })
+                .collect();
+            if height > 0 && rng.chance(GENETIC_MUTATION) {
+                let row = rng.below(height as u64) as usize;
+                rows[row] = placements.random_row(row, &mut rng);
+            }
+            let child = Candidate::new(b, rows);
+            if child.fitness > best.fitness {
+                best = child.clone();
+            }
+            next.push(child);
+        }
+        population = next;
+    }
+    Some(placements.approximation(&best.rows, steps))
+}
+
+/// Which rows and columns are searched by lines_satisfiable
+struct LineSelection {
+    rows: Vec<bool>,
+    cols: Vec<bool>,
+}
+
+impl LineSelection {
+    fn contains(&self, line: LineInfo) -> bool {
+        match line.linetype {
+            LineType::Row => self.rows[line.index as usize],
+            LineType::Column => self.cols[line.index as usize],
+        }
+    }
+
+    fn set(&mut self, line: LineInfo, value: bool) {
+        match line.linetype {
+            LineType::Row => self.rows[line.index as usize] = value,
+            LineType::Column => self.cols[line.index as usize] = value,
+        }
+    }
+}
+
+/// Solve the selected lines in to_solve, and every selected line crossing a changed cell, until
+/// no more changes can be made.
+/// Returns false if one of them can not be solved.
+fn solve_selected_lines(
+    b: &mut board::Board,
+    selection: &LineSelection,
+    to_solve: &mut PrioritySet<LineInfo>,
+    nodecache: &mut NodeListCache,
+) -> bool {
+    // nothing is kept from these, so they are not returned
+    let mut stats = SolveStats::default();
+    while let Some(lineid) = to_solve.pop() {
+        let changes = match lineid.linetype {
+            LineType::Row => solve_line(
+                &mut b.get_row_mut(lineid.index),
+                lineid,
+                nodecache,
+                LineSolver::Automaton,
+                &mut stats,
+            ),
+            LineType::Column => solve_line(
+                &mut b.get_col_mut(lineid.index),
+                lineid,
+                nodecache,
+                LineSolver::Automaton,
+                &mut stats,
+            ),
+        };
+        let changes = match changes {
+            Some(changes) => changes,
+            None => return false,
+        };
+        for change in changes.iter() {
+            queue_selected_lines(selection, to_solve, change.get_col(), change.get_row());
+        }
+    }
+    true
+}
+
+/// Queue the selected lines that cross the given cell
+fn queue_selected_lines(
+    selection: &LineSelection,
+    to_solve: &mut PrioritySet<LineInfo>,
+    col: Unit,
+    row: Unit,
+) {
+    let row = LineInfo {
+        index: row,
+        linetype: LineType::Row,
+    };
+    let col = LineInfo {
+        index: col,
+        linetype: LineType::Column,
+    };
+    for &line in [row, col].iter() {
+        if selection.contains(line) {
+            to_solve.insert(line);
+        }
+    }
+}
+
+/// Search for a way to satisfy only the selected lines' constraints, solving and guessing
+/// only cells in those lines. Every other line is treated as if it had no constraints.
+/// Returns Some(true) if the lines can be satisfied, Some(false) if they can not, or None if
+/// that could not be decided before running out of branches.
+fn lines_satisfiable(
+    b: &mut board::Board,
+    selection: &LineSelection,
+    to_solve: &mut PrioritySet<LineInfo>,
+    nodecache: &mut NodeListCache,
+    branches: &mut usize,
+) -> Option<bool> {
+    use board::Cell;
+    if !solve_selected_lines(b, selection, to_solve, nodecache) {
+        return Some(false);
+    }
+    // every selected line can be satisfied on its own, so only the cells shared by two of them
+    // need to be guessed; guess in the lines closest to being solved first
+    let mut row_unknowns = vec![0; b.get_height() as usize];
+    let mut col_unknowns = vec![0; b.get_width() as usize];
+    for i in 0..b.get_num_cells() {
+        if b.get_cell_index(i) == Cell::Unknown {
+            let (col, row) = b.get_coordinate(i);
+            row_unknowns[row as usize] += 1;
+            col_unknowns[col as usize] += 1;
+        }
+    }
+    let index = (0..b.get_num_cells())
+        .filter(|&i| {
+            let (col, row) = b.get_coordinate(i);
+            b.get_cell_index(i) == Cell::Unknown
+                && selection.rows[row as usize]
+                && selection.cols[col as usize]
+        })
+        .min_by_key(|&i| {
+            let (col, row) = b.get_coordinate(i);
+            row_unknowns[row as usize] + col_unknowns[col as usize]
+        });
+    let index = match index {
+        Some(index) => index,
+        None => return Some(true),
+    };
+    let (col, row) = b.get_coordinate(index);
+    for value in guess_values(b, index) {
+        if *branches == 0 {
+            return None;
+        }
+        *branches -= 1;
+        let mut new_board = b.clone();
+        new_board.set_cell_index(index, value);
+        to_solve.clear();
+        queue_selected_lines(selection, to_solve, col, row);
+        if lines_satisfiable(&mut new_board, selection, to_solve, nodecache, branches)? {
+            return Some(true);
+        }
+    }
+    Some(false)
+}
+
+/// Solve every line of a board until a contradiction is found, and select the lines that led
+/// to it: the contradicted line, the lines that deduced any of its cells, the lines that
+/// deduced any of theirs, and so on.
+/// Returns None if line solving alone finds no contradiction.
+fn lines_leading_to_contradiction(
+    b: &board::Board,
+    nodecache: &mut NodeListCache,
+) -> Option<LineSelection> {
+    let mut board = b.clone();
+    // the line that deduced each cell, or None for cells that were known from the start
+    let mut deduced_by: Vec<Option<LineInfo>> = vec![None; b.get_num_cells()];
+    let mut to_solve = all_lines(b, None);
+    let mut stats = SolveStats::default();
+    let contradicted = loop {
+        let lineid = to_solve.pop()?;
+        let changes = match lineid.linetype {
+            LineType::Row => solve_line(
+                &mut board.get_row_mut(lineid.index),
+                lineid,
+                nodecache,
+                LineSolver::Automaton,
+                &mut stats,
+            ),
+            LineType::Column => solve_line(
+                &mut board.get_col_mut(lineid.index),
+                lineid,
+                nodecache,
+                LineSolver::Automaton,
+                &mut stats,
+            ),
+        };
+        let changes = match changes {
+            Some(changes) => changes,
+            None => break lineid,
+        };
+        for change in changes.iter() {
+            let (col, row) = (change.get_col(), change.get_row());
+            deduced_by[board.get_index(col, row)] = Some(lineid);
+            to_solve.insert(LineInfo {
+                index: row,
+                linetype: LineType::Row,
+            });
+            to_solve.insert(LineInfo {
+                index: col,
+                linetype: LineType::Column,
+            });
+        }
+    };
+    let mut selection = LineSelection {
+        rows: vec![false; b.get_height() as usize],
+        cols: vec![false; b.get_width() as usize],
+    };
+    selection.set(contradicted, true);
+    let mut stack = vec![contradicted];
+    while let Some(line) = stack.pop() {
+        let cells: Vec<usize> = match line.linetype {
+            LineType::Row => (0..b.get_width())
+                .map(|col| b.get_index(col, line.index))
+                .collect(),
+            LineType::Column => (0..b.get_height())
+                .map(|row| b.get_index(line.index, row))
+                .collect(),
+        };
+        for other in cells.into_iter().filter_map(|index| deduced_by[index]) {
+            if !selection.contains(other) {
+                selection.set(other, true);
+                stack.push(other);
+            }
+        }
+    }
+    Some(selection)
+}
+
+/// Find a set of lines whose constraints can not all be satisfied, given the cells already
+/// known on the board, as evidence that it has no solution.
+/// If line solving finds a contradiction, this starts from the lines that led to it, and
+/// otherwise from every line. Lines are then dropped one at a time for as long as the rest
+/// still can not be satisfied, so no line in the result can be dropped, though a smaller set
+/// of other lines may exist.
+/// All of the checks together may guess up to max_branches times. Once those run out, the
+/// lines not yet tried are kept, so the result may then be larger than it needs to be.
+/// Returns None if all of the lines can be satisfied together, or if that could not be
+/// ruled out within max_branches.
+pub fn find_conflicting_lines(b: &board::Board, max_branches: usize) -> Option<Vec<LineInfo>> {
+    let mut nodecache = make_node_list_cache(b, 0);
+    let mut selection =
+        lines_leading_to_contradiction(b, &mut nodecache).unwrap_or_else(|| LineSelection {
+            rows: vec![true; b.get_height() as usize],
+            cols: vec![true; b.get_width() as usize],
+        });
+    let rows = (0..b.get_height()).map(|index| LineInfo {
+        index,
+        linetype: LineType::Row,
+    });
+    let cols = (0..b.get_width()).map(|index| LineInfo {
+        index,
+        linetype: LineType::Column,
+    });
+    let lines: Vec<LineInfo> = rows.chain(cols).collect();
+    let mut branches = max_branches;
+    let mut check = |selection: &LineSelection| {
+        let mut to_solve = PrioritySet::new();
+        for &line in lines.iter().filter(|&&line| selection.contains(line)) {
+            to_solve.insert(line);
+        }
+        lines_satisfiable(
+            &mut b.clone(),
+            selection,
+            &mut to_solve,
+            &mut nodecache,
+            &mut branches,
+        )
+    };
+    if check(&selection) != Some(false) {
+        return None;
+    }
+    for &line in lines.iter() {
+        if !selection.contains(line) {
+            continue;
+        }
+        selection.set(line, false);
+        if check(&selection) != Some(false) {
+            // the lines left may be satisfiable without this one, so keep it
+            selection.set(line, true);
+        }
+    }
+    Some(
+        lines
+            .into_iter()
+            .filter(|&line| selection.contains(line))
+            .collect(),
+    )
+}
+
+/// Solve a copy of a board after setting the given cells, given as (col, row, value), to find
+/// out whether those values are consistent with the rest of the board.
+/// Returns SolveResult::Success and the solved board if they are, and
+/// SolveResult::Contradiction if they are not, in which case the returned board is only
+/// partially solved. Assumed values that are Unknown are ignored.
+pub fn solve_with_assumptions(
+    b: &board::Board,
+    assumptions: &[(Unit, Unit, board::Cell)],
+) -> (SolveResult, board::Board) {
+    let (result, board, _stats) =
+        solve_with_assumptions_with(b, assumptions, &SolverConfig::default());
+    (result, board)
+}
+
+/// Same as solve_with_assumptions, but with the given configuration.
+/// If the configuration has limits, the result may also be SolveResult::Timeout or
+/// SolveResult::Exhausted, in which case the assumptions may or may not be consistent.
+pub fn solve_with_assumptions_with(
+    b: &board::Board,
+    assumptions: &[(Unit, Unit, board::Cell)],
+    config: &SolverConfig,
+) -> (SolveResult, board::Board, SolveStats) {
+    use board::LineRef;
+    let mut board = b.clone();
+    let mut scratch = board::LineScratch::new();
+    for &(col, row, value) in assumptions.iter() {
+        if value == board::Cell::Unknown {
+            continue;
+        }
+        let current = board.get_cell(col, row);
+        if current != board::Cell::Unknown && current != value {
+            return (SolveResult::Contradiction, board, SolveStats::default());
+        }
+        board.set_cell(col, row, value);
+        // the solver skips lines without Unknown cells, so check the assumed lines here
+        if !board.get_row_ref(row).is_solvable(&mut scratch)
+            || !board.get_col_ref(col).is_solvable(&mut scratch)
+        {
+            return (SolveResult::Contradiction, board, SolveStats::default());
+        }
+    }
+    let SolveOutcome { result, stats, .. } = stupid_branched_solver_set_with(&mut board, config);
+    (result, board, stats)
+}
+
+/// Cells set together by IncrementalSolver::set_cells, and everything solved from them
+struct Edit {
+    /// The cells that were set, as (col, row, value)
+    cells: Vec<(Unit, Unit, board::Cell)>,
+    /// Every change this edit made to the board, including the cells themselves
+    changes: ChangeSet,
+    /// Whether this edit contradicted the board
+    contradiction: bool,
+}
+
+/// Keeps a board solved by line solving while its cells are edited, as in an editor.
+/// Setting cells only solves the rows and columns they affect, and whatever those lead to.
+/// Changing or clearing a cell undoes only the edits made since that cell was set, and then
+/// repeats the later ones, since nothing solved before the cell was set can depend on it.
+pub struct IncrementalSolver {
+    board: board::Board,
+    meta: BoardMeta,
+    to_solve: PrioritySet<LineInfo>,
+    nodecache: NodeListCache,
+    line_solver: LineSolver,
+    stats: SolveStats,
+    /// Whether solving the board before any edits found a contradiction
+    contradiction: bool,
+    /// Every edit that is in effect, in the order they were made
+    edits: Vec<Edit>,
+}
+
+impl IncrementalSolver {
+    /// Solve as much of the given board as line solving can, to be edited
+    pub fn new(b: board::Board, line_solver: LineSolver) -> IncrementalSolver {
+        let mut solver = IncrementalSolver {
+            meta: BoardMeta::from_board(&b),
+            to_solve: all_lines(&b, None),
+            nodecache: make_node_list_cache(&b, 0),
+            board: b,
+            line_solver,
+            stats: SolveStats::default(),
+            contradiction: false,
+            edits: Vec::new(),
+        };
+        let result = stupid_solver_set(
+            &mut solver.board,
+            &mut solver.meta,
+            &mut solver.to_solve,
+            &mut solver.nodecache,
+            &mut solver.stats,
+            line_solver,
+        );
+        solver.contradiction = result == Some(SolveResult::Contradiction);
+        // the cells solved before any edits are never undone
+        solver.meta.changes.clear();
+        solver
+    }
+
+    /// Get the board, with every edit and every cell solved from them
+    pub fn board(&self) -> &board::Board {
+        &self.board
+    }
+
+    /// Get statistics for all line solving done so far
+    pub fn stats(&self) -> &SolveStats {
+        &self.stats
+    }
+
+    /// Get the board's state: Success if every cell is known, Contradiction if the board or
+    /// any edit in effect led to a contradiction, or None if no more cells can be solved.
+    pub fn status(&self) -> Option<SolveResult> {
+        if self.contradiction || self.edits.iter().any(|edit| edit.contradiction) {
+            Some(SolveResult::Contradiction)
+        } else if self.meta.num_unsolved == 0 {
+            Some(SolveResult::Success)
+        } else {
+            None
+        }
+    }
+
+    /// Set a single cell. See set_cells.
+    pub fn set_cell(&mut self, col: Unit, row: Unit, value: board::Cell) -> Option<SolveResult> {
+        self.set_cells(&[(col, row, value)])
+    }
+
+    /// Set the given cells, given as (col, row, value), and solve the lines they affect.
+    /// Setting a cell to Unknown clears the value an earlier edit gave it.
+    /// If a cell is given more than once, only its last value is used.
+    /// Cells that were known before any edits can not be cleared, and setting one to a
+    /// different value is a contradiction, as is setting a solved cell to a different value.
+    /// Returns the board's new state, as in status.
+    pub fn set_cells(&mut self, cells: &[(Unit, Unit, board::Cell)]) -> Option<SolveResult> {
+        let is_edited = |col: Unit, row: Unit| cells.iter().any(|&(c, r, _)| c == col && r == row);
+        let mut redo = Vec::new();
+        if let Some(first) = self
+            .edits
+            .iter()
+            .position(|edit| edit.cells.iter().any(|&(col, row, _)| is_edited(col, row)))
+        {
+            // Undo every edit since the first one that set one of these cells,
+            // then repeat them without those cells
+            while self.edits.len() > first {
+                let edit = self.edits.pop().unwrap();
+                self.meta.revert(&mut self.board, &edit.changes);
+                redo.push(edit.cells);
+            }
+            redo.reverse();
+            for edit in redo.iter_mut() {
+                edit.retain(|&(col, row, _)| !is_edited(col, row));
+            }
+        }
+        // if a cell is given more than once, its last value is used
+        let mut edit: Vec<(Unit, Unit, board::Cell)> = Vec::new();
+        for &(col, row, value) in cells.iter() {
+            edit.retain(|&(c, r, _)| c != col || r != row);
+            if value != board::Cell::Unknown {
+                edit.push((col, row, value));
+            }
+        }
+        redo.push(edit);
+        for edit in redo {
+            if !edit.is_empty() {
+                self.apply(edit);
+            }
+        }
+        self.status()
+    }
+
+    /// Set the given cells and solve the lines they affect, recording it as a new edit
+    fn apply(&mut self, cells: Vec<(Unit, Unit, board::Cell)>) {
+        use board::LineRef;
+        // an earlier contradiction may have left lines queued
+        self.to_solve.clear();
+        let mut contradiction = false;
+        for &(col, row, value) in cells.iter() {
+            let current = self.board.get_cell(col, row);
+            if current == board::Cell::Unknown {
+                let index = self.board.get_index(col, row);
+                prepare_branch(
+                    &mut self.board,
+                    &mut self.meta,
+                    &mut self.to_solve,
+                    index,
+                    value,
+                );
+                // line solving skips lines without Unknown cells, so check them here
+                let scratch = &mut self.nodecache.scratch;
+                if !self.board.get_row_ref(row).is_solvable(scratch)
+                    || !self.board.get_col_ref(col).is_solvable(scratch)
+                {
+                    contradiction = true;
+                }
+            } else if current != value {
+                contradiction = true;
+            }
+        }
+        if !contradiction {
+            let result = stupid_solver_set(
+                &mut self.board,
+                &mut self.meta,
+                &mut self.to_solve,
+                &mut self.nodecache,
+                &mut self.stats,
+                self.line_solver,
+            );
+            contradiction = result == Some(SolveResult::Contradiction);
+        }
+        self.edits.push(Edit {
+            cells,
+            changes: mem::take(&mut self.meta.changes),
+            contradiction,
+        });
+    }
 }
 
-/// Represents a Change
-#[derive(Copy, Clone, Hash)]
-pub struct Change {
-    previous_value: board::Cell,
-    col: board::Unit,
-    row: board::Unit,
+/// Cells that can be deduced by solving a single line, as found by next_hint
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hint {
+    /// The line the cells are deduced from
+    pub line: LineInfo,
+    /// Each deduced cell, as (col, row, value)
+    pub cells: Vec<(Unit, Unit, board::Cell)>,
 }
 
-/// A set of changes that have been made
-pub struct ChangeSet {
-    pub changes: BTreeSet<Change>,
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.line)?;
+        for (i, (col, row, value)) in self.cells.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{} ({}, {}) = {}", separator, col, row, value)?;
+        }
+        Ok(())
+    }
 }
 
-#[derive(Clone)]
-pub struct BoardMeta {
-    pub num_unsolved: usize,
-    pub unsolved_per_row: Vec<usize>,
-    pub unsolved_per_column: Vec<usize>,
+impl Hint {
+    /// Explain each of this hint's cells, given the board the hint was found for
+    pub fn explain(&self, b: &board::Board) -> Vec<Explanation> {
+        self.cells
+            .iter()
+            .filter_map(|&(col, row, _)| explain_cell(b, self.line, col, row))
+            .collect()
+    }
 }
 
-impl BoardMeta {
-    pub fn solve(&mut self, col: Unit, row: Unit) {
-        self.num_unsolved -= 1;
-        self.unsolved_per_row[row as usize] -= 1;
-        self.unsolved_per_column[col as usize] -= 1;
-    }
+/// Why a cell can be deduced by solving a single line, as found by explain_cell
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    /// The line the cell is deduced from
+    pub line: LineInfo,
+    pub col: Unit,
+    pub row: Unit,
+    /// The value the line forces the cell to have
+    pub value: board::Cell,
+    pub reason: board::CellReason,
+}
 
-    pub fn unsolve(&mut self, col: Unit, row: Unit) {
-        self.num_unsolved += 1;
-        self.unsolved_per_row[row as usize] += 1;
-        self.unsolved_per_column[col as usize] += 1;
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // cells within a row are numbered by column, and cells within a column by row
+        let (linetype, celltype, index) = match self.line.linetype {
+            LineType::Row => ("row", "column", self.col),
+            LineType::Column => ("column", "row", self.row),
+        };
+        let value = match self.value {
+            board::Cell::Empty => "empty".to_string(),
+            board::Cell::Filled => "filled".to_string(),
+            board::Cell::Colored(color) => format!("color {}", color),
+            board::Cell::Unknown => "unknown".to_string(),
+        };
+        match self.reason {
+            board::CellReason::Overlap {
+                length, start, end, ..
+            } => {
+                let cells = if end - start == 1 {
+                    format!("{} {}", celltype, start)
+                } else {
+                    format!("{}s {}-{}", celltype, start, end - 1)
+                };
+                write!(
+                    f,
+                    "{} {}'s {}-clue must overlap {}, so {} {} is {}",
+                    linetype, self.line.index, length, cells, celltype, index, value
+                )
+            }
+            board::CellReason::Unreachable => write!(
+                f,
+                "no clue of {} {} can reach {} {}, so it is {}",
+                linetype, self.line.index, celltype, index, value
+            ),
+            board::CellReason::Placements => write!(
+                f,
+                "every way to place {} {}'s clues makes {} {} {}",
+                linetype, self.line.index, celltype, index, value
+            ),
+        }
     }
+}
 
-    pub fn new(width: usize, height: usize) -> BoardMeta {
-        BoardMeta {
-            num_unsolved: width * height,
-            unsolved_per_row: vec![width; height],
-            unsolved_per_column: vec![height; width],
+/// Explain why the given cell can be deduced by solving the given line of a board.
+/// Returns None if the line does not contain the cell, if the cell is already known, or if
+/// the line alone does not force its value.
+pub fn explain_cell(b: &board::Board, line: LineInfo, col: Unit, row: Unit) -> Option<Explanation> {
+    use board::LineRef;
+    let (value, reason) = match line.linetype {
+        LineType::Row if line.index == row => b.get_row_ref(row).explain_cell(col)?,
+        LineType::Column if line.index == col => b.get_col_ref(col).explain_cell(row)?,
+        _ => return None,
+    };
+    Some(Explanation {
+        line,
+        col,
+        row,
+        value,
+        reason,
+    })
+}
+
+/// Find the next cells that can be deduced by solving a single line of the board, without
+/// changing it. Lines are tried in the order stupid_branched_solver_set would solve them,
+/// and each is solved exactly (with LineSolver::Automaton), so a hint is only missed if it
+/// needs more than one line.
+/// Returns None if no line can deduce any more cells, or if a line contradicts the board.
+pub fn next_hint(b: &board::Board) -> Option<Hint> {
+    let mut board = b.clone();
+    let mut nodecache = make_node_list_cache(b, 0);
+    let mut stats = SolveStats::default();
+    let mut to_solve = all_lines(b, None);
+    while let Some(lineid) = to_solve.pop() {
+        // the board is only changed by a line that deduces something, which is returned
+        let changes = match lineid.linetype {
+            LineType::Row => {
+                let mut row = board.get_row_mut(lineid.index);
+                solve_line(
+                    &mut row,
+                    lineid,
+                    &mut nodecache,
+                    LineSolver::Automaton,
+                    &mut stats,
+                )?
+            }
+            LineType::Column => {
+                let mut col = board.get_col_mut(lineid.index);
+                solve_line(
+                    &mut col,
+                    lineid,
+                    &mut nodecache,
+                    LineSolver::Automaton,
+                    &mut stats,
+                )?
+            }
+        };
+        if !changes.is_empty() {
+            let cells = changes
+                .iter()
+                .map(|change| (change.get_col(), change.get_row(), change.get_value()))
+                .collect();
+            return Some(Hint {
+                line: lineid,
+                cells,
+            });
         }
     }
+    None
+}
 
-    pub fn is_column_solved(&self, index: usize) -> bool {
-        return self.unsolved_per_column[index] == 0;
-    }
+/// The strongest technique a puzzle needed to be solved, as found by rate
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Difficulty {
+    /// Solved by overlapping the leftmost and rightmost placements of each line's clues
+    /// (LineSolver::LeftRight)
+    Overlaps,
+    /// Solved by deducing every cell each line allows on its own (LineSolver::Automaton)
+    LineLogic,
+    /// Solved by line logic and probing, without guessing
+    Probing,
+    /// Solved by guessing, where the deepest guess was nested this many times
+    Backtracking { depth: usize },
+}
 
-    pub fn is_row_solved(&self, index: usize) -> bool {
-        return self.unsolved_per_row[index] == 0;
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Difficulty::Overlaps => write!(f, "overlaps"),
+            Difficulty::LineLogic => write!(f, "line logic"),
+            Difficulty::Probing => write!(f, "probing"),
+            Difficulty::Backtracking { depth } => write!(f, "backtracking (depth {})", depth),
+        }
     }
 }
 
-pub struct NodeListCache {
-    pub rows: Vec<util::NodeList<bool>>,
-    pub cols: Vec<util::NodeList<bool>>,
+/// How hard a puzzle is to solve, as found by rate
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rating {
+    pub difficulty: Difficulty,
+    /// A score for sorting puzzles by difficulty: from 0 to 10 for Overlaps, 10 to 20 for
+    /// LineLogic, 20 to 30 for Probing, and 30 and up for Backtracking.
+    /// Within each range, puzzles that need more passes, probes or branches score higher.
+    pub score: f64,
 }
 
-fn make_node_list_cache(board: &board::Board) -> NodeListCache {
-    use board::LineRef;
-    let mut ret = NodeListCache {
-        rows: Vec::with_capacity(board.get_height() as usize),
-        cols: Vec::with_capacity(board.get_width() as usize),
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, score {:.1}", self.difficulty, self.score)
+    }
+}
+
+/// Rate how hard a board is to solve, by trying stronger techniques until one solves it:
+/// overlaps, then full line logic, then probing, then backtracking.
+/// The configuration's heuristic and limits are used for backtracking; its line solver
+/// and probing are not.
+/// Returns Err with the result of the search if the board could not be solved, which is
+/// SolveResult::Contradiction if it has no solution.
+pub fn rate(b: &board::Board, config: &SolverConfig) -> Result<Rating, SolveResult> {
+    // Scale an amount of work to a score in [0, 10), reaching 5 at the given amount
+    let effort = |amount: usize, half: f64| 10.0 * amount as f64 / (amount as f64 + half);
+    let mut nodecache = make_node_list_cache(b, 0);
+    for &(line_solver, difficulty, base) in [
+        (LineSolver::LeftRight, Difficulty::Overlaps, 0.0),
+        (LineSolver::Automaton, Difficulty::LineLogic, 10.0),
+    ]
+    .iter()
+    {
+        let mut stats = SolveStats::default();
+        match stupid_solver(&mut b.clone(), &mut nodecache, &mut stats, line_solver) {
+            Some(SolveResult::Success) => {
+                return Ok(Rating {
+                    difficulty,
+                    score: base + effort(stats.passes, 10.0),
+                })
+            }
+            Some(result) => return Err(result),
+            None => {}
+        }
+    }
+    let config = SolverConfig {
+        line_solver: LineSolver::Automaton,
+        probing: true,
+        ..config.clone()
     };
-    for i in 0..board.get_width() {
-        ret.cols.push(board.get_col_ref(i).make_empty_node_list());
+    let SolveOutcome { result, stats, .. } =
+        stupid_branched_solver_set_with(&mut b.clone(), &config);
+    if result != SolveResult::Success {
+        return Err(result);
     }
-    for i in 0..board.get_height() {
-        ret.rows.push(board.get_row_ref(i).make_empty_node_list());
+    if stats.max_depth == 0 {
+        Ok(Rating {
+            difficulty: Difficulty::Probing,
+            score: 20.0 + effort(stats.probes, 100.0),
+        })
+    } else {
+        Ok(Rating {
+            difficulty: Difficulty::Backtracking {
+                depth: stats.max_depth,
+            },
+            score: 30.0 + 10.0 * (stats.branches as f64).log2(),
+        })
     }
-    ret
 }
 
-/// Slightly smarter version of stupid_solver.
-pub fn stupid_solver_set(
-    b: &mut board::Board,
-    meta: &mut BoardMeta,
-    to_solve: &mut PrioritySet<LineInfo>,
-    nodecache: &mut NodeListCache,
-) -> Option<SolveResult> {
-    use board::LineMut;
-    use board::LineRef;
-    while to_solve.len() > 0 {
-        let lineid = to_solve.pop().unwrap();
-        match lineid.linetype {
-            LineType::Row => {
-                if meta.is_row_solved(lineid.index as usize) {
-                    continue;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{
+        Board, BoardBuilder, Cell, Constraint, LineAutomaton, LineMasks, LineMut, LineRef,
+        LineScratch, Palette, StandaloneLine,
+    };
+
+    const LINE_SOLVERS: [LineSolver; 3] = [
+        LineSolver::Graph,
+        LineSolver::LeftRight,
+        LineSolver::Automaton,
+    ];
+
+    /// Make a puzzle from a random grid, with the clues that grid has. Cells are filled with
+    /// one of the given number of colors. With totals, some lines of a black and white
+    /// puzzle are given only their number of filled cells.
+    fn random_puzzle(rng: &mut util::Rng, colors: u8, totals: bool, toroidal: bool) -> Board {
+        let width = 2 + rng.below(4) as Unit;
+        let height = 2 + rng.below(4) as Unit;
+        let mut palette = Palette::new();
+        for color in 1..colors {
+            palette.add(&format!("color {}", color), (color, 0, 0));
+        }
+        let mut grid = Board::new_filled(width, height, Cell::Empty);
+        grid.set_palette(palette.clone());
+        grid.set_toroidal(toroidal);
+        for row in 0..height {
+            for col in 0..width {
+                if rng.chance(0.55) {
+                    let color = rng.below(colors as u64) as board::Color;
+                    grid.set_cell(col, row, Cell::with_color(color));
                 }
-                let mut row = b.get_row_mut(lineid.index);
-                // solve this row
-                if let Some(v) =
-                    row.try_solve_line_complete(&mut nodecache.rows[lineid.index as usize])
-                {
-                    // check that no columns are contradicted
-                    for col_i in v.iter() {
-                        let col = b.get_col_ref(*col_i);
-                        if !col.is_solvable(&mut nodecache.cols[*col_i as usize]) {
-                            return Some(SolveResult::Contradiction);
-                        }
-                        // mark this cell as solved
-                        meta.solve(*col_i, lineid.index);
-                        // add column to columns that may now be solvable
-                        if !meta.is_column_solved(*col_i as usize) {
-                            to_solve.insert(LineInfo {
-                                index: *col_i,
-                                linetype: LineType::Column,
-                            });
-                        }
+            }
+        }
+        let rows = grid
+            .rows()
+            .map(|row| line_clues(&row, totals && rng.chance(0.5)))
+            .collect();
+        let cols = grid
+            .cols()
+            .map(|col| line_clues(&col, totals && rng.chance(0.5)))
+            .collect();
+        let mut b = BoardBuilder::new(width, height)
+            .rows(rows)
+            .cols(cols)
+            .palette(palette)
+            .build()
+            .unwrap();
+        b.set_toroidal(toroidal);
+        b
+    }
+
+    /// Get the clues of a solved line: its blocks, or if total is set, its number of filled cells
+    fn line_clues<L: LineRef>(line: &L, total: bool) -> Vec<Constraint> {
+        if total {
+            let filled = (0..line.size()).filter(|&i| line.get_cell(i).is_filled());
+            vec![Constraint::new_total(filled.count() as Unit)]
+        } else {
+            line.generate_new_constraints().unwrap()
+        }
+    }
+
+    /// Check every line solver against brute_force_solutions on random puzzles: line solving
+    /// must only deduce cells that every solution has, and solving must find one of them
+    fn check_line_solvers(colors: u8, totals: bool, toroidal: bool) {
+        let mut rng = util::Rng::new(2092);
+        for _ in 0..60 {
+            let b = random_puzzle(&mut rng, colors, totals, toroidal);
+            let solutions = brute_force_solutions(&b, usize::MAX).unwrap();
+            assert!(!solutions.is_empty());
+            for &line_solver in LINE_SOLVERS.iter() {
+                let mut deduced = b.clone();
+                let mut nodecache = make_node_list_cache(&deduced, 0);
+                let mut stats = SolveStats::default();
+                let result = stupid_solver(&mut deduced, &mut nodecache, &mut stats, line_solver);
+                assert_ne!(
+                    result,
+                    Some(SolveResult::Contradiction),
+                    "{:?}\n{}",
+                    line_solver,
+                    b
+                );
+                for index in 0..b.get_num_cells() {
+                    let value = deduced.get_cell_index(index);
+                    if value != Cell::Unknown {
+                        assert!(
+                            solutions.iter().all(|x| x.get_cell_index(index) == value),
+                            "{:?} deduced a cell no solution has\n{}",
+                            line_solver,
+                            b
+                        );
                     }
-                } else {
-                    return Some(SolveResult::Contradiction);
                 }
+                let mut solved = b.clone();
+                let config = SolverConfig {
+                    line_solver,
+                    ..SolverConfig::default()
+                };
+                let outcome = stupid_branched_solver_set_with(&mut solved, &config);
+                assert_eq!(
+                    outcome.result,
+                    SolveResult::Success,
+                    "{:?}\n{}",
+                    line_solver,
+                    b
+                );
+                assert!(solutions.contains(&solved), "{:?}\n{}", line_solver, b);
             }
-            LineType::Column => {
-                if meta.is_column_solved(lineid.index as usize) {
+        }
+    }
+
+    /// Compare the line solvers with every placement of a line's clues, on random lines with
+    /// some of their cells known. The automaton must deduce exactly the cells that every
+    /// placement agrees on, and the other line solvers only some of them.
+    #[test]
+    fn line_solvers_match_enumeration() {
+        let mut rng = util::Rng::new(2069);
+        for _ in 0..3000 {
+            let size = 1 + rng.below(12) as usize;
+            let solution: Vec<Cell> = (0..size)
+                .map(|_| {
+                    if rng.chance(0.5) {
+                        Cell::Filled
+                    } else {
+                        Cell::Empty
+                    }
+                })
+                .collect();
+            let constraints = StandaloneLine::new(solution.clone(), &Vec::new())
+                .generate_new_constraints()
+                .unwrap();
+            let known: Vec<Cell> = solution
+                .iter()
+                .map(|&x| if rng.chance(0.3) { x } else { Cell::Unknown })
+                .collect();
+            let mut agreed: Option<Vec<Cell>> = None;
+            for bits in 0..1u32 << size {
+                let cells: Vec<Cell> = (0..size)
+                    .map(|i| match bits >> i & 1 {
+                        1 => Cell::Filled,
+                        _ => Cell::Empty,
+                    })
+                    .collect();
+                let fits = cells
+                    .iter()
+                    .zip(&known)
+                    .all(|(&x, &k)| k == Cell::Unknown || x == k);
+                if !fits || !StandaloneLine::new(cells.clone(), &constraints).matches_constraints()
+                {
                     continue;
                 }
-                let mut col = b.get_col_mut(lineid.index);
-                // solve this column
-                if let Some(v) =
-                    col.try_solve_line_complete(&mut nodecache.cols[lineid.index as usize])
-                {
-                    // check that no rows are contradicted
-                    for row_i in v.iter() {
-                        let row = b.get_row_ref(*row_i);
-                        if !row.is_solvable(&mut nodecache.rows[*row_i as usize]) {
-                            return Some(SolveResult::Contradiction);
-                        }
-                        meta.solve(lineid.index, *row_i);
-                        if !meta.is_row_solved(*row_i as usize) {
-                            to_solve.insert(LineInfo {
-                                index: *row_i,
-                                linetype: LineType::Row,
-                            });
-                        }
+                agreed = Some(match agreed {
+                    None => cells,
+                    Some(agreed) => agreed
+                        .iter()
+                        .zip(&cells)
+                        .map(|(&a, &x)| if a == x { a } else { Cell::Unknown })
+                        .collect(),
+                });
+            }
+            let agreed = agreed.unwrap();
+            let automaton = LineAutomaton::new(&constraints, size as Unit);
+            for &line_solver in LINE_SOLVERS.iter() {
+                let mut line = StandaloneLine::new(known.clone(), &constraints);
+                let mut scratch = LineScratch::new();
+                let solved = match line_solver {
+                    LineSolver::Graph => line.try_solve_line_complete(&mut scratch),
+                    LineSolver::LeftRight => line.try_solve_line_left_right(&mut scratch),
+                    LineSolver::Automaton => {
+                        line.try_solve_line_automaton(&automaton, &mut scratch)
                     }
-                } else {
-                    return Some(SolveResult::Contradiction);
+                };
+                assert!(solved.is_some(), "{:?}\n{}", line_solver, line);
+                let cells: Vec<Cell> = (0..size as Unit).map(|i| line.get_cell(i)).collect();
+                for (&x, &a) in cells.iter().zip(&agreed) {
+                    assert!(x == Cell::Unknown || x == a, "{:?}\n{}", line_solver, line);
+                }
+                if line_solver == LineSolver::Automaton {
+                    assert_eq!(cells, agreed, "{}", line);
                 }
             }
         }
-        if meta.num_unsolved == 0 {
-            return Some(SolveResult::Success);
+    }
+
+    /// Compare count_line_placements and line_fill_fractions with listing every way of filling
+    /// random lines that wrap around, with some of their cells known
+    #[test]
+    fn toroidal_placements_match_enumeration() {
+        let mut rng = util::Rng::new(2117);
+        let values = [Cell::Empty, Cell::Filled, Cell::Colored(1)];
+        for _ in 0..2000 {
+            let size = 1 + rng.below(8) as usize;
+            let colors = 2 + rng.below(2) as usize;
+            let solution: Vec<Cell> = (0..size)
+                .map(|_| values[rng.below(colors as u64) as usize])
+                .collect();
+            let constraints = StandaloneLine::new(solution.clone(), &Vec::new())
+                .generate_new_constraints()
+                .unwrap();
+            let known: Vec<Cell> = solution
+                .iter()
+                .map(|&x| if rng.chance(0.3) { x } else { Cell::Unknown })
+                .collect();
+            let mut count = 0u64;
+            let mut filled = vec![0u64; size];
+            for mut code in 0..(colors as u64).pow(size as u32) {
+                let cells: Vec<Cell> = (0..size)
+                    .map(|_| {
+                        let value = values[(code % colors as u64) as usize];
+                        code /= colors as u64;
+                        value
+                    })
+                    .collect();
+                let fits = cells
+                    .iter()
+                    .zip(&known)
+                    .all(|(&x, &k)| k == Cell::Unknown || x == k);
+                let line = StandaloneLine::new(cells.clone(), &constraints).with_toroidal(true);
+                if fits && line.matches_constraints() {
+                    count += 1;
+                    for (ways, x) in filled.iter_mut().zip(&cells) {
+                        *ways += x.is_filled() as u64;
+                    }
+                }
+            }
+            let line = StandaloneLine::new(known, &constraints).with_toroidal(true);
+            assert_eq!(count_line_placements(&line), count, "{}", line);
+            match line_fill_fractions(&line) {
+                None => assert_eq!(count, 0, "{}", line),
+                Some(fractions) => {
+                    for (fraction, &ways) in fractions.iter().zip(&filled) {
+                        let expected = ways as f64 / count as f64;
+                        assert!((fraction - expected).abs() < 1e-9, "{}", line);
+                    }
+                }
+            }
         }
     }
-    if meta.num_unsolved == 0 {
-        Some(SolveResult::Success)
-    } else {
-        None
+
+    /// Compare LineMasks with scanning the cells one at a time, on random lines long enough to
+    /// span several words
+    #[test]
+    fn line_masks_match_cells() {
+        let mut rng = util::Rng::new(2073);
+        for _ in 0..200 {
+            let size = 1 + rng.below(200) as usize;
+            let cells: Vec<Cell> = (0..size)
+                .map(|_| match rng.below(3) {
+                    0 => Cell::Filled,
+                    1 => Cell::Empty,
+                    _ => Cell::Unknown,
+                })
+                .collect();
+            let constraints = Vec::new();
+            let line = StandaloneLine::new(cells.clone(), &constraints);
+            let masks = LineMasks::new(&line);
+            assert_eq!(masks.size(), size);
+            for start in 0..=size {
+                let next = (start..size).find(|&i| cells[i].is_filled());
+                assert_eq!(masks.next_filled(start), next.unwrap_or(size));
+                for end in start..=size {
+                    let range = &cells[start..end];
+                    let filled = range.iter().any(|x| x.is_filled());
+                    let empty = range.contains(&Cell::Empty);
+                    assert_eq!(masks.any_filled(start, end), filled, "{}..{}", start, end);
+                    assert_eq!(masks.any_empty(start, end), empty, "{}..{}", start, end);
+                    assert_eq!(
+                        masks.can_fit_constraint(start, end - start),
+                        line.can_fit_constraint(start as Unit, (end - start) as Unit),
+                        "{}..{}",
+                        start,
+                        end
+                    );
+                }
+            }
+        }
     }
-}
 
-/// A very basic test solving implementation.
-/// Does not always find a solution as it does not branch;
-/// only performs line solving algorithm.
-/// Returns Some(SolveResult) if a success or contradiction was found;
-/// Returns None if the board is in an incomplete solving state.
-pub fn stupid_solver(b: &mut board::Board, nodecache: &mut NodeListCache) -> Option<SolveResult> {
-    use board::LineMut;
-    use board::LineRef;
-    let (width, height) = b.get_size();
-    let mut tiles_to_solve = 0;
-    for i in 0..b.get_num_cells() {
-        if b.get_cell_index(i) == board::Cell::Unknown {
-            tiles_to_solve += 1;
+    /// Line solve a copy of a puzzle with the given cells set, returning the board and its
+    /// state as in IncrementalSolver::status
+    fn solve_with_cells(b: &Board, cells: &[(Unit, Unit, Cell)]) -> (Board, Option<SolveResult>) {
+        let mut fresh = b.clone();
+        let mut nodecache = make_node_list_cache(&fresh, 0);
+        for &(col, row, value) in cells.iter() {
+            match fresh.get_cell(col, row) {
+                Cell::Unknown => fresh.set_cell(col, row, value),
+                current if current != value => return (fresh, Some(SolveResult::Contradiction)),
+                _ => {}
+            }
+        }
+        // line solving stops once every cell is known, so check complete lines here
+        let scratch = &mut nodecache.scratch;
+        let solvable = (0..fresh.get_height())
+            .all(|row| fresh.get_row_ref(row).is_solvable(scratch))
+            && (0..fresh.get_width()).all(|col| fresh.get_col_ref(col).is_solvable(scratch));
+        if !solvable {
+            return (fresh, Some(SolveResult::Contradiction));
+        }
+        let mut stats = SolveStats::default();
+        match stupid_solver(
+            &mut fresh,
+            &mut nodecache,
+            &mut stats,
+            LineSolver::Automaton,
+        ) {
+            Some(SolveResult::Contradiction) => (fresh, Some(SolveResult::Contradiction)),
+            _ if (0..fresh.get_num_cells()).all(|i| fresh.get_cell_index(i) != Cell::Unknown) => {
+                (fresh, Some(SolveResult::Success))
+            }
+            _ => (fresh, None),
         }
     }
-    let mut solved_this_round = 1i64;
-    while solved_this_round > 0 && tiles_to_solve > 0 {
-        solved_this_round = 0;
-        for i in 0..width {
-            let mut col = b.get_col_mut(i);
-            if let Some(v) = col.try_solve_line_complete(&mut nodecache.cols[i as usize]) {
-                // check all rows for contradiction
-                for j in v.iter() {
-                    let row = b.get_row_ref(*j);
-                    if !row.is_solvable(&mut nodecache.rows[*j as usize]) {
-                        // contradiction found :(
-                        return Some(SolveResult::Contradiction);
-                    }
+
+    /// Check IncrementalSolver with random sequences of sets and clears: after every step, the
+    /// board and its state must match a fresh line solve of the puzzle with the same cells set
+    #[test]
+    fn incremental_matches_fresh_solve() {
+        let mut rng = util::Rng::new(2080);
+        for _ in 0..40 {
+            let b = random_puzzle(&mut rng, 1, false, false);
+            let solution = brute_force_solutions(&b, 1).unwrap().remove(0);
+            let mut solver = IncrementalSolver::new(b.clone(), LineSolver::Automaton);
+            let mut cells: Vec<(Unit, Unit, Cell)> = Vec::new();
+            for _ in 0..20 {
+                let col = rng.below(b.get_width() as u64) as Unit;
+                let row = rng.below(b.get_height() as u64) as Unit;
+                let value = match rng.below(4) {
+                    0 => Cell::Unknown,
+                    1 => Cell::Filled,
+                    2 => Cell::Empty,
+                    _ => solution.get_cell(col, row),
+                };
+                let status = solver.set_cell(col, row, value);
+                cells.retain(|&(c, r, _)| c != col || r != row);
+                if value != Cell::Unknown {
+                    cells.push((col, row, value));
+                }
+                let (fresh, fresh_status) = solve_with_cells(&b, &cells);
+                assert_eq!(status, fresh_status, "{:?}\n{}", cells, b);
+                if status != Some(SolveResult::Contradiction) {
+                    assert_eq!(solver.board(), &fresh, "{:?}\n{}", cells, b);
                 }
-                // everything is okily dokily :)
-                solved_this_round += v.len() as i64;
-                tiles_to_solve -= v.len() as i64;
-            } else {
-                // contradiction found :(
-                return Some(SolveResult::Contradiction);
             }
         }
-        for i in 0..height {
-            let mut row = b.get_row_mut(i);
-            if let Some(v) = row.try_solve_line_complete(&mut nodecache.rows[i as usize]) {
-                // check all rows for contradiction
-                for j in v.iter() {
-                    let col = b.get_col_ref(*j);
-                    if !col.is_solvable(&mut nodecache.cols[*j as usize]) {
-                        // contradiction found :(
-                        return Some(SolveResult::Contradiction);
-                    }
+    }
+
+    #[test]
+    fn black_and_white_lines() {
+        check_line_solvers(1, false, false);
+    }
+
+    #[test]
+    fn colored_lines() {
+        check_line_solvers(3, false, false);
+    }
+
+    #[test]
+    fn total_lines() {
+        check_line_solvers(1, true, false);
+    }
+
+    #[test]
+    fn toroidal_lines() {
+        check_line_solvers(1, false, true);
+        check_line_solvers(3, false, true);
+    }
+
+    /// max_branches counts guesses: with 0, a board is only line solved, and a board needing
+    /// one guess is solved with 1
+    #[test]
+    fn max_branches_counts_guesses() {
+        let solvable = BoardBuilder::new(2, 2)
+            .row(0, &[2])
+            .row(1, &[1])
+            .col(0, &[2])
+            .col(1, &[1])
+            .build()
+            .unwrap();
+        // two solutions, so line solving alone deduces nothing
+        let guessing = BoardBuilder::new(2, 2)
+            .row(0, &[1])
+            .row(1, &[1])
+            .col(0, &[1])
+            .col(1, &[1])
+            .build()
+            .unwrap();
+        for &(best_first, threads) in [(false, 1), (true, 1), (false, 2)].iter() {
+            let config = |max| SolverConfig {
+                max_branches: Some(max),
+                best_first,
+                threads,
+                ..SolverConfig::default()
+            };
+            let outcome = stupid_branched_solver_set_with(&mut solvable.clone(), &config(0));
+            assert_eq!(outcome.result, SolveResult::Success);
+            let outcome = stupid_branched_solver_set_with(&mut guessing.clone(), &config(0));
+            assert_eq!(outcome.result, SolveResult::Exhausted(Limit::Branches));
+            let outcome = stupid_branched_solver_set_with(&mut guessing.clone(), &config(1));
+            assert_eq!(outcome.result, SolveResult::Success);
+        }
+    }
+
+    /// forced_cells finds exactly the cells every solution agrees on, and with limits it
+    /// leaves the cells it could not decide Unknown rather than guessing at them
+    #[test]
+    fn forced_cells_match_solutions() {
+        let mut rng = util::Rng::new(2100);
+        for _ in 0..60 {
+            let b = random_puzzle(&mut rng, 2, false, false);
+            let solutions = brute_force_solutions(&b, usize::MAX).unwrap();
+            let expected = solutions
+                .iter()
+                .skip(1)
+                .fold(solutions[0].clone(), |agreed, other| {
+                    agreed.agreement(other).unwrap()
+                });
+            assert_eq!(forced_cells(&b).unwrap(), expected);
+            let config = SolverConfig {
+                max_branches: Some(0),
+                ..SolverConfig::default()
+            };
+            let limited = forced_cells_with(&b, &config).unwrap();
+            let mut unknown = 0;
+            for index in 0..b.get_num_cells() {
+                match limited.board.get_cell_index(index) {
+                    Cell::Unknown => unknown += 1,
+                    value => assert_eq!(value, expected.get_cell_index(index)),
                 }
-                // everything is okily dokily :)
-                solved_this_round += v.len() as i64;
-                tiles_to_solve -= v.len() as i64;
-            } else {
-                // contradiction found :(
-                return Some(SolveResult::Contradiction);
+            }
+            assert!(limited.undecided <= unknown);
+            if limited.undecided == 0 {
+                assert_eq!(limited.board, expected);
             }
         }
     }
-    if tiles_to_solve == 0 {
-        Some(SolveResult::Success)
-    } else {
-        None
+
+    /// find_other_solution_with gives up at its limits rather than saying a solution is unique
+    #[test]
+    fn find_other_solution_limits() {
+        let ambiguous = BoardBuilder::new(2, 2)
+            .row(0, &[1])
+            .row(1, &[1])
+            .col(0, &[1])
+            .col(1, &[1])
+            .build()
+            .unwrap();
+        let mut solution = ambiguous.clone();
+        assert_eq!(
+            stupid_branched_solver_set(&mut solution).result,
+            SolveResult::Success
+        );
+        let config = |max| SolverConfig {
+            max_branches: Some(max),
+            ..SolverConfig::default()
+        };
+        assert_eq!(
+            find_other_solution_with(&ambiguous, &solution, &config(0)),
+            Err(SolveResult::Exhausted(Limit::Branches))
+        );
+        let other = find_other_solution_with(&ambiguous, &solution, &config(2));
+        assert!(matches!(other, Ok(Some(ref other)) if *other != solution));
+        let timeout = SolverConfig {
+            max_duration: Some(Duration::from_secs(0)),
+            ..SolverConfig::default()
+        };
+        assert_eq!(
+            find_other_solution_with(&ambiguous, &solution, &timeout),
+            Err(SolveResult::Timeout)
+        );
     }
-}
 
-/// A very basic solver that utilizes branching when no solution can be found.
-/// Branches are just clones of the Board, which is inefficient.
-/// Will eventually arrive to a solution
-pub fn stupid_branched_solver(
-    b: &mut board::Board,
-    nodecache: &mut NodeListCache,
-) -> (SolveResult, usize) {
-    // use board::LineMut;
-    match stupid_solver(b, nodecache) {
-        Some(SolveResult::Success) => {
-            return (SolveResult::Success, 1);
-        }
-        Some(SolveResult::Contradiction) => {
-            return (SolveResult::Contradiction, 1);
-        }
-        None => {
-            // get first index that is unknown
-            let index =
-                (0..b.get_num_cells()).find(|i| b.get_cell_index(*i) == board::Cell::Unknown);
-            if let Some(index) = index {
-                let mut nbranches = 1;
-                // First, try 0
-                let mut new_board = b.clone();
-                new_board.set_cell_index(index, board::Cell::Empty);
-                let (empty_result, empty_b) = stupid_branched_solver(&mut new_board, nodecache);
-                nbranches += empty_b;
-                if empty_result == SolveResult::Success {
-                    mem::swap(b, &mut new_board);
-                    return (SolveResult::Success, nbranches);
-                } else {
-                    // Now, try 1
-                    let mut new_board = b.clone();
-                    new_board.set_cell_index(index, board::Cell::Filled);
-                    let (filled_result, filled_b) =
-                        stupid_branched_solver(&mut new_board, nodecache);
-                    nbranches += filled_b;
-                    if filled_result == SolveResult::Success {
-                        mem::swap(b, &mut new_board);
-                        return (SolveResult::Success, nbranches);
-                    } else {
-                        return (SolveResult::Contradiction, nbranches);
+    /// Phases are only timed when asked for, and never overlap, so with one thread they add up
+    /// to no more than the time spent solving
+    #[test]
+    fn phases_do_not_overlap() {
+        let mut rng = util::Rng::new(2107);
+        for _ in 0..20 {
+            let b = random_puzzle(&mut rng, 2, false, false);
+            let outcome = stupid_branched_solver_set_with(&mut b.clone(), &SolverConfig::default());
+            assert!(outcome.stats.phases.is_none());
+            let config = SolverConfig {
+                probing: true,
+                crossings: true,
+                time_phases: true,
+                ..SolverConfig::default()
+            };
+            let stats = stupid_branched_solver_set_with(&mut b.clone(), &config).stats;
+            let total: Duration = stats.phases.unwrap().iter().map(|(_, time)| time).sum();
+            assert!(total <= stats.elapsed);
+        }
+    }
+
+    /// The boards stupid_branched_solver keeps for its guesses count towards max_memory
+    #[test]
+    fn max_memory_counts_guesses() {
+        let mut builder = BoardBuilder::new(4, 4);
+        for i in 0..4 {
+            builder = builder.row(i, &[1]).col(i, &[1]);
+        }
+        let mut b = builder.build().unwrap();
+        let config = SolverConfig {
+            max_memory: Some(0),
+            ..SolverConfig::default()
+        };
+        let mut nodecache = make_node_list_cache(&b, 0);
+        let outcome = stupid_branched_solver_with(&mut b, &mut nodecache, &config);
+        assert_eq!(outcome.result, SolveResult::Exhausted(Limit::Memory));
+    }
+
+    /// The cells explored branches agree on include everything deduced before guessing, and
+    /// never contradict the best partial board, which is one of those branches
+    #[test]
+    fn explored_agreement_covers_deduced_cells() {
+        let mut rng = util::Rng::new(2090);
+        let mut checked = 0;
+        for _ in 0..60 {
+            let b = random_puzzle(&mut rng, 2, false, false);
+            let config = |max| SolverConfig {
+                max_branches: Some(max),
+                ..SolverConfig::default()
+            };
+            let deduced = stupid_branched_solver_set_with(&mut b.clone(), &config(0));
+            for max in 1..3 {
+                let outcome = stupid_branched_solver_set_with(&mut b.clone(), &config(max));
+                let agreed = match outcome.stats.explored_agreement.as_ref() {
+                    Some(agreed) => agreed,
+                    None => continue,
+                };
+                assert_eq!(outcome.result, SolveResult::Exhausted(Limit::Branches));
+                checked += 1;
+                let partial = outcome.stats.best_partial.as_ref().unwrap();
+                for index in 0..b.get_num_cells() {
+                    let value = agreed.get_cell_index(index);
+                    if deduced.board.get_cell_index(index) != Cell::Unknown {
+                        assert_eq!(value, deduced.board.get_cell_index(index));
+                    }
+                    if value != Cell::Unknown {
+                        assert_eq!(value, partial.get_cell_index(index));
                     }
                 }
-            } else {
-                panic!("HUH?");
             }
         }
+        assert!(checked > 0);
     }
-}
 
-pub fn stupid_branched_solver_set(b: &mut board::Board) -> (SolveResult, usize) {
-    let mut meta = BoardMeta::new(b.get_width() as usize, b.get_height() as usize);
-    let mut to_solve = PrioritySet::new();
-    for col in 0..b.get_width() {
-        to_solve.insert(LineInfo {
-            index: col,
-            linetype: LineType::Column,
-        });
+    /// A thread that panics must end a parallel search with its panic, rather than leave the
+    /// other threads waiting for its branches
+    #[test]
+    fn parallel_search_panics() {
+        // a board with no solution, which takes several guesses to find
+        let b = BoardBuilder::new(5, 5)
+            .row(0, &[1])
+            .row(1, &[1])
+            .row(2, &[2])
+            .row(3, &[1, 2])
+            .row(4, &[1, 1])
+            .col(0, &[1, 1])
+            .col(1, &[2])
+            .col(2, &[1])
+            .col(3, &[3])
+            .col(4, &[1])
+            .build()
+            .unwrap();
+        let panicked = Arc::new(AtomicBool::new(false));
+        let config = SolverConfig {
+            threads: 2,
+            progress: Some(ProgressCallback::new(move |progress| {
+                // the first branch is searched before the threads start
+                if progress.branches > 1 && !panicked.swap(true, Ordering::Relaxed) {
+                    panic!("progress callback failed");
+                }
+            })),
+            progress_interval: 1,
+            ..SolverConfig::default()
+        };
+        // the other thread keeps going, so this only returns if the panicking thread stops it
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            stupid_branched_solver_set_with(&mut b.clone(), &config)
+        }));
+        assert!(result.is_err());
     }
-    for row in 0..b.get_height() {
-        to_solve.insert(LineInfo {
-            index: row,
-            linetype: LineType::Row,
+
+    #[test]
+    fn total_on_colored_board() {
+        // the palette has a color no clue uses, which makes the board colored
+        let mut palette = Palette::new();
+        palette.add("red", (0xee, 0, 0));
+        let mut b = BoardBuilder::new(4, 1)
+            .rows(vec![vec![Constraint::new_total(2)]])
+            .col(0, &[1])
+            .col(3, &[1])
+            .palette(palette)
+            .build()
+            .unwrap();
+        assert!(b.is_colored());
+        let outcome = stupid_branched_solver_set(&mut b);
+        assert_eq!(outcome.result, SolveResult::Success);
+        let cells: Vec<Cell> = (0..4).map(|col| b.get_cell(col, 0)).collect();
+        assert_eq!(
+            cells,
+            vec![Cell::Filled, Cell::Empty, Cell::Empty, Cell::Filled]
+        );
+    }
+
+    #[test]
+    fn observers_stay_with_their_board() {
+        let mut b = Board::new_filled(2, 2, Cell::Unknown);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        b.add_channel_observer(sender);
+        let mut clone = b.clone();
+        clone.set_cell(0, 0, Cell::Filled);
+        assert!(receiver.try_recv().is_err());
+        b.set_cell(1, 0, Cell::Empty);
+        let change = receiver.try_recv().unwrap();
+        assert_eq!((change.col, change.row, change.after), (1, 0, Cell::Empty));
+
+        // an observer that panicked keeps being notified of later changes
+        let mut panicked = false;
+        b.add_observer(move |_| {
+            if !panicked {
+                panicked = true;
+                panic!("observer failed");
+            }
         });
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            b.set_cell(0, 1, Cell::Filled);
+        }));
+        assert!(result.is_err());
+        while receiver.try_recv().is_ok() {}
+        b.set_cell(1, 1, Cell::Filled);
+        assert_eq!(receiver.try_recv().unwrap().row, 1);
     }
-    let mut n_branches = 0;
-    let mut nodecache = make_node_list_cache(b);
-    let value =
-        _stupid_branched_solver_set(b, &mut meta, &mut to_solve, &mut n_branches, &mut nodecache);
-    (value, n_branches)
-}
 
-fn _stupid_branched_solver_set(
-    b: &mut board::Board,
-    meta: &mut BoardMeta,
-    to_solve: &mut PrioritySet<LineInfo>,
-    num_branches: &mut usize,
-    nodecache: &mut NodeListCache,
-) -> SolveResult {
-    util::inc_maybe_print(num_branches, 1, 100);
-    // use board::LineMut;
-    match stupid_solver_set(b, meta, to_solve, nodecache) {
-        Some(SolveResult::Success) => {
-            return SolveResult::Success;
-        }
-        Some(SolveResult::Contradiction) => {
-            return SolveResult::Contradiction;
-        }
-        None => {
-            // get first index that is unknown
-            let index = (0..b.get_num_cells())
-                .filter(|i| b.get_cell_index(*i) == board::Cell::Unknown)
-                .min_by_key(|i| {
-                    // sum number of known cells in same row and column
-                    let (col, row) = b.get_coordinate(*i);
-                    let mut sum = 0usize;
-                    sum += meta.unsolved_per_row[row as usize];
-                    sum += meta.unsolved_per_column[col as usize];
-                    sum
-                });
-            if let Some(index) = index {
-                // First, insert indices into to_solve
-                let (col_i, row_i) = b.get_coordinate(index);
-                to_solve.insert(LineInfo {
-                    linetype: LineType::Row,
-                    index: row_i,
-                });
-                to_solve.insert(LineInfo {
-                    linetype: LineType::Column,
-                    index: col_i,
-                });
-                meta.solve(col_i, row_i);
-                // Try 0
-                let mut new_board = b.clone();
-                new_board.set_cell_index(index, board::Cell::Empty);
-                let empty_result = _stupid_branched_solver_set(
-                    &mut new_board,
-                    &mut meta.clone(), // clone data
-                    &mut to_solve.clone(),
-                    num_branches,
-                    nodecache,
-                );
-                if empty_result == SolveResult::Success {
-                    mem::swap(b, &mut new_board);
-                    return SolveResult::Success;
-                } else {
-                    // Now, Try 1
-                    let mut new_board = b.clone();
-                    new_board.set_cell_index(index, board::Cell::Filled);
-                    let filled_result = _stupid_branched_solver_set(
-                        &mut new_board,
-                        meta, // no clone needed
-                        to_solve,
-                        num_branches,
-                        nodecache,
-                    );
-                    if filled_result == SolveResult::Success {
-                        mem::swap(b, &mut new_board);
-                        return SolveResult::Success;
-                    } else {
-                        // Neither worked; it's a contradiction
-                        return SolveResult::Contradiction;
-                    }
-                }
-            } else {
-                panic!("HUH?");
+    #[test]
+    fn colored_zero_is_filled() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let hash = |cell: Cell| {
+            let mut hasher = DefaultHasher::new();
+            cell.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(Cell::Colored(0), Cell::Filled);
+        assert_eq!(hash(Cell::Colored(0)), hash(Cell::Filled));
+        assert_ne!(Cell::Colored(1), Cell::Filled);
+        let mut b = Board::new_filled(2, 1, Cell::Colored(0));
+        b.set_cell(1, 0, Cell::Colored(0));
+        assert!(matches!(b.get_cell(0, 0), Cell::Filled));
+        assert!(matches!(b.get_cell(1, 0), Cell::Filled));
+        assert!(!b.is_colored());
+    }
+
+    #[test]
+    fn line_solvers_count_passes_alike() {
+        let mut rng = util::Rng::new(7);
+        for _ in 0..50 {
+            let puzzle = random_puzzle(&mut rng, 1, false, false);
+            let mut swept = puzzle.clone();
+            let mut sweep_stats = SolveStats::default();
+            let mut nodecache = make_node_list_cache(&swept, 0);
+            let sweep_result = stupid_solver(
+                &mut swept,
+                &mut nodecache,
+                &mut sweep_stats,
+                LineSolver::Graph,
+            );
+            let queued = IncrementalSolver::new(puzzle, LineSolver::Graph);
+            let queue_stats = queued.stats();
+            assert_eq!(sweep_stats.passes, 1);
+            assert_eq!(queue_stats.passes, 1);
+            if sweep_result != Some(SolveResult::Contradiction) {
+                assert_eq!(sweep_stats.cells_deduced(), queue_stats.cells_deduced());
+            }
+        }
+    }
+
+    #[test]
+    fn monochrome_heatmap_has_no_escapes() {
+        let b = BoardBuilder::new(2, 2)
+            .row(0, &[2])
+            .col(0, &[1])
+            .col(1, &[1])
+            .build()
+            .unwrap();
+        let grid = fill_probabilities(&b).unwrap();
+        let text = grid.heatmap().monochrome().to_string();
+        assert!(!text.contains('\x1b'));
+        assert_eq!(text, "\u{2588}\u{2588}\u{2588}\u{2588}\n    \n");
+        assert!(grid.heatmap().to_string().contains('\x1b'));
+    }
+
+    #[test]
+    fn probing_only_claims_uniqueness_it_proved() {
+        // every row and column is 1, which has two solutions
+        let square = BoardBuilder::new(2, 2)
+            .rows(vec![vec![Constraint::new(1)]; 2])
+            .cols(vec![vec![Constraint::new(1)]; 2])
+            .build()
+            .unwrap();
+        let mut rng = util::Rng::new(3);
+        let mut puzzles = vec![square];
+        puzzles.extend((0..40).map(|_| random_puzzle(&mut rng, 1, false, false)));
+        let config = SolverConfig {
+            probing: true,
+            ..SolverConfig::default()
+        };
+        for puzzle in puzzles {
+            let outcome = stupid_branched_solver_set_with(&mut puzzle.clone(), &config);
+            assert_eq!(outcome.result, SolveResult::Success);
+            if !outcome.unexplored {
+                assert_eq!(count_solutions(&puzzle, 2), SolutionCount::Exact(1));
+            }
+        }
+    }
+
+    #[test]
+    fn second_solution_search_matches_count() {
+        let mut rng = util::Rng::new(11);
+        for _ in 0..40 {
+            let puzzle = random_puzzle(&mut rng, 1, false, false);
+            let probing = SolverConfig {
+                probing: true,
+                ..SolverConfig::default()
+            };
+            let auto = auto_config(&puzzle, &SolverConfig::default());
+            for config in [probing, auto].iter() {
+                let mut solution = puzzle.clone();
+                let outcome = stupid_branched_solver_set_with(&mut solution, config);
+                assert_eq!(outcome.result, SolveResult::Success);
+                let other = find_other_solution_with(&puzzle, &solution, config).unwrap();
+                let unique = count_solutions(&puzzle, 2) == SolutionCount::Exact(1);
+                assert_eq!(other.is_none(), unique);
             }
         }
     }
@@ -1,5 +1,8 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::hash::Hash;
+use std::sync::{mpsc, Mutex};
+use std::thread;
 
 /// A 2D square list of nodes visualized as such:
 /// A₁,₁ A₁,₂ … A₁,ₙ
@@ -50,7 +53,7 @@ where
                     write!(f, "{:>5} ", "")?;
                 }
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -90,6 +93,28 @@ impl<T> NodeList<T> {
     pub fn set(&mut self, i: usize, j: usize, value: T) {
         self.items[i + j * self.width] = value;
     }
+
+    /// Change this list's size, setting every node to its default value.
+    /// Does not allocate unless the list has never been this large.
+    pub fn reset(&mut self, width: usize, height: usize)
+    where
+        T: Default + Clone,
+    {
+        self.width = width;
+        self.height = height;
+        self.items.clear();
+        self.items.resize(width * height, T::default());
+    }
+}
+
+impl<T> Default for NodeList<T> {
+    fn default() -> Self {
+        NodeList {
+            items: Vec::new(),
+            width: 0,
+            height: 0,
+        }
+    }
 }
 
 impl<T> fmt::Display for NodeList<T>
@@ -101,7 +126,7 @@ where
             for k in 0..self.height {
                 write!(f, "{:>5} ", self.get(j, k))?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -131,12 +156,126 @@ where
 //     }
 // }
 
+/// Mix a seed with a value, giving a well scrambled number that depends only on the two.
+/// This is splitmix64, which, unlike the hashers in std, gives the same numbers on every
+/// platform and Rust version.
+pub fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut x = seed ^ value.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// A stream of pseudo-random numbers drawn from a seed with mix_seed, so that the same seed
+/// gives the same numbers everywhere
+#[derive(Clone, Debug)]
+pub struct Rng {
+    seed: u64,
+    drawn: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { seed, drawn: 0 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.drawn += 1;
+        mix_seed(self.seed, self.drawn)
+    }
+
+    /// Get a number in [0, n). n must not be 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Get true with the given probability, in [0, 1]
+    pub fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+/// Call job with every item, on up to the given number of threads at once, each taking the
+/// next item that no thread has started. Items are only taken from the iterator as threads
+/// become free for them. Results are handed to done on the calling thread in the order of the
+/// items, each as soon as it and every result before it are ready.
+pub fn for_each_parallel<I, R, F, D>(items: I, threads: usize, job: F, mut done: D)
+where
+    I: IntoIterator,
+    I::IntoIter: Send,
+    I::Item: Send,
+    R: Send,
+    F: Fn(I::Item) -> R + Sync,
+    D: FnMut(R),
+{
+    let items = Mutex::new(items.into_iter().enumerate());
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let items = &items;
+            let job = &job;
+            scope.spawn(move || loop {
+                // a thread that panicked while taking an item ends the scope with its panic
+                let next = match items.lock() {
+                    Ok(mut items) => items.next(),
+                    Err(_) => None,
+                };
+                let (i, item) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                if sender.send((i, job(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        // results that finished before some result ahead of them
+        let mut waiting = BTreeMap::new();
+        let mut wanted = 0;
+        for (i, result) in receiver {
+            waiting.insert(i, result);
+            while let Some(result) = waiting.remove(&wanted) {
+                done(result);
+                wanted += 1;
+            }
+        }
+    });
+}
+
+/// Get the nth number, counting from 1, of the Luby sequence: 1, 1, 2, 1, 1, 2, 4, 1, 1, 2, ...
+/// Searches restarted after this many steps, times some unit, are never much slower than
+/// with the best fixed number of steps.
+pub fn luby(n: usize) -> usize {
+    let mut n = n.max(1);
+    loop {
+        // the smallest k with n <= 2^k - 1
+        let mut k = 1;
+        while (1 << k) - 1 < n {
+            k += 1;
+        }
+        if n == (1 << k) - 1 {
+            return 1 << (k - 1);
+        }
+        n -= (1 << (k - 1)) - 1;
+    }
+}
+
+/// A set of values that are popped highest priority first.
+/// Values with the same priority are popped largest first, or, if the set has a seed, in a
+/// pseudo-random order drawn from the seed and the order they were inserted in.
 #[derive(Clone)]
 pub struct PrioritySet<T>
 where
     T: Clone + PartialOrd + Ord + PartialEq + Eq,
 {
-    pub elements: BTreeMap<T, u32>,
+    /// Each value's priority, and the number its ties are broken by
+    elements: BTreeMap<T, (u32, u64)>,
+    seed: Option<u64>,
+    /// Number of values inserted so far, to draw tie breaking numbers from
+    inserted: u64,
 }
 
 impl<T> PrioritySet<T>
@@ -144,13 +283,20 @@ where
     T: Clone + PartialOrd + Ord + PartialEq + Eq,
 {
     pub fn insert(&mut self, value: T) {
-        let entry = self.elements.entry(value).or_insert(0);
-        *entry += 1;
+        self.insert_with_priority(value, 1);
     }
 
+    /// Insert a value with the given priority.
+    /// If the value is already in the set, its priority is raised by p instead, so a value
+    /// inserted several times is popped before one inserted only once.
     pub fn insert_with_priority(&mut self, value: T, p: u32) {
-        let entry = self.elements.entry(value).or_insert(0);
-        *entry = p;
+        let seed = self.seed;
+        let inserted = &mut self.inserted;
+        let entry = self.elements.entry(value).or_insert_with(|| {
+            *inserted += 1;
+            (0, seed.map_or(0, |seed| mix_seed(seed, *inserted)))
+        });
+        entry.0 += p;
     }
 
     pub fn pop(&mut self) -> Option<T> {
@@ -159,9 +305,8 @@ where
             .elements
             .iter()
             .max_by(|(ak, av), (bk, bv)| av.cmp(bv).then_with(|| ak.cmp(bk)));
-        index.map(|i| i.0.clone()).map(|i| {
-            self.elements.remove(&i);
-            i
+        index.map(|i| i.0.clone()).inspect(|i| {
+            self.elements.remove(i);
         })
     }
 
@@ -169,16 +314,125 @@ where
         self.elements.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.elements.clear();
+    }
+
     pub fn new() -> PrioritySet<T> {
+        PrioritySet::with_seed(None)
+    }
+
+    /// Create a set that breaks ties between values with the given seed, if any
+    pub fn with_seed(seed: Option<u64>) -> PrioritySet<T> {
         PrioritySet {
             elements: BTreeMap::new(),
+            seed,
+            inserted: 0,
         }
     }
 }
 
-pub fn inc_maybe_print(value: &mut usize, amt: usize, step: usize) {
-    if (*value + amt) / step != *value / step {
-        println!("{}", *value + amt);
+impl<T> Default for PrioritySet<T>
+where
+    T: Clone + PartialOrd + Ord + PartialEq + Eq,
+{
+    fn default() -> Self {
+        PrioritySet::new()
+    }
+}
+
+/// A map holding at most a fixed number of entries.
+/// When full, inserting a new entry evicts the least recently used one.
+#[derive(Clone)]
+pub struct LruCache<K, V>
+where
+    K: Clone + Hash + Eq,
+{
+    /// Each value, with the time it was last used
+    entries: HashMap<K, (V, u64)>,
+    /// Keys by the time they were last used, oldest first
+    order: BTreeMap<u64, K>,
+    capacity: usize,
+    clock: u64,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Clone + Hash + Eq,
+{
+    /// Create a cache that holds at most capacity entries.
+    /// A cache with a capacity of 0 never stores anything.
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            capacity,
+            clock: 0,
+        }
+    }
+
+    /// Get the value for a key, marking it as recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let (value, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.order.insert(self.clock, key.clone());
+        Some(value)
+    }
+
+    /// Insert a value, replacing any previous value for the key
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some((_, used)) = self.entries.remove(&key) {
+            self.order.remove(&used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.order.insert(self.clock, key.clone());
+        self.entries.insert(key, (value, self.clock));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn for_each_parallel_keeps_order() {
+        let mut results = Vec::new();
+        // later items finish first, so their results must wait for the earlier ones
+        let job = |i: u64| {
+            thread::sleep(std::time::Duration::from_millis(20 - i));
+            i
+        };
+        for_each_parallel(0..20, 4, job, |i| results.push(i));
+        assert_eq!(results, (0..20).collect::<Vec<_>>());
     }
-    *value += amt;
 }